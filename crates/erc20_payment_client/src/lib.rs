@@ -0,0 +1,512 @@
+//! Client for HTTP API of erc20_processor (routes under /api scope).
+//! Request and response types are shared with the server, see [`api`].
+//!
+//! Methods and the OpenAPI document served at /api/openapi.json are maintained by hand,
+//! tests in erc20_payment_lib check that the document covers every served route and
+//! `tests/api_client.rs` runs this client against the real route scope.
+pub use erc20_payment_lib_common::api;
+
+use api::*;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("API error ({status}): {}", .error.error)]
+    Api { status: StatusCode, error: ApiError },
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Invalid response ({status}): {message}")]
+    InvalidResponse { status: StatusCode, message: String },
+}
+
+#[derive(Debug, Clone)]
+enum ClientAuth {
+    None,
+    Bearer(String),
+    Hmac { client: String, secret: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct PaymentClient {
+    http: reqwest::Client,
+    /// For example http://127.0.0.1:8080/erc20/api
+    base_url: String,
+    auth: ClientAuth,
+}
+
+impl PaymentClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth: ClientAuth::None,
+        }
+    }
+
+    pub fn with_bearer_token(mut self, token: &str) -> Self {
+        self.auth = ClientAuth::Bearer(token.to_string());
+        self
+    }
+
+    /// Sign requests with secret of client configured in [api-auth.clients]
+    pub fn with_hmac(mut self, client: &str, secret: &str) -> Self {
+        self.auth = ClientAuth::Hmac {
+            client: client.to_string(),
+            secret: secret.to_string(),
+        };
+        self
+    }
+
+    /// Appends path segments to base url, every segment is percent-encoded
+    fn url(&self, path: &[&str]) -> Result<reqwest::Url, ClientError> {
+        let mut url = reqwest::Url::parse(&self.base_url)
+            .map_err(|err| ClientError::InvalidRequest(format!("Invalid base url: {err}")))?;
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidRequest("Invalid base url".to_string()))?
+            .pop_if_empty()
+            .extend(path);
+        Ok(url)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &[&str],
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<(StatusCode, Vec<u8>), ClientError> {
+        let mut builder = self.http.request(method, self.url(path)?);
+        if !query.is_empty() {
+            builder = builder.query(query);
+        }
+        if let Some(body) = body {
+            builder = builder
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+        let mut request = builder.build()?;
+        match &self.auth {
+            ClientAuth::None => {}
+            ClientAuth::Bearer(token) => {
+                request.headers_mut().insert(
+                    reqwest::header::AUTHORIZATION,
+                    format!("Bearer {token}").parse().map_err(|_| {
+                        ClientError::InvalidRequest("Invalid bearer token".to_string())
+                    })?,
+                );
+            }
+            ClientAuth::Hmac { client, secret } => {
+                let timestamp = chrono::Utc::now().timestamp();
+                let nonce = uuid::Uuid::new_v4().simple().to_string();
+                let url = request.url();
+                let path_and_query = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                let body = request
+                    .body()
+                    .and_then(|b| b.as_bytes())
+                    .unwrap_or_default();
+                let signature = hmac_signature(
+                    secret,
+                    timestamp,
+                    &nonce,
+                    request.method().as_str(),
+                    &path_and_query,
+                    body,
+                );
+                let headers = request.headers_mut();
+                for (name, value) in [
+                    (API_CLIENT_HEADER, client.clone()),
+                    (API_TIMESTAMP_HEADER, timestamp.to_string()),
+                    (API_NONCE_HEADER, nonce),
+                    (API_SIGNATURE_HEADER, signature),
+                ] {
+                    headers.insert(
+                        name,
+                        value.parse().map_err(|_| {
+                            ClientError::InvalidRequest(format!("Invalid value of {name} header"))
+                        })?,
+                    );
+                }
+            }
+        }
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        let body = response.bytes().await?.to_vec();
+        Ok((status, body))
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &[&str],
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<T, ClientError> {
+        let (status, body) = self.send(method, path, query, body).await?;
+        if status.is_success() {
+            serde_json::from_slice(&body).map_err(|err| ClientError::InvalidResponse {
+                status,
+                message: err.to_string(),
+            })
+        } else {
+            Err(api_error(status, &body))
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &[&str]) -> Result<T, ClientError> {
+        self.request(Method::GET, path, &[], None).await
+    }
+
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &[&str],
+        body: Option<&impl Serialize>,
+    ) -> Result<T, ClientError> {
+        let body = body.map(serde_json::to_vec).transpose().map_err(|err| {
+            ClientError::InvalidRequest(format!("Cannot serialize request: {err}"))
+        })?;
+        self.request(Method::POST, path, &[], body).await
+    }
+
+    async fn get_text(&self, path: &[&str]) -> Result<String, ClientError> {
+        let (status, body) = self.send(Method::GET, path, &[], None).await?;
+        if status.is_success() {
+            Ok(String::from_utf8_lossy(&body).to_string())
+        } else {
+            Err(api_error(status, &body))
+        }
+    }
+
+    pub async fn version(&self) -> Result<VersionResponse, ClientError> {
+        self.get(&["version"]).await
+    }
+
+    pub async fn allowances(&self) -> Result<AllowancesResponse, ClientError> {
+        self.get(&["allowances"]).await
+    }
+
+    pub async fn account_balance(
+        &self,
+        account: &str,
+        chain_id: i64,
+        request: &AccountBalanceRequest,
+    ) -> Result<AccountBalanceResponse, ClientError> {
+        let query = request
+            .token
+            .iter()
+            .map(|token| ("token", token.clone()))
+            .collect::<Vec<_>>();
+        self.request(
+            Method::GET,
+            &["balance", account, &chain_id.to_string()],
+            &query,
+            None,
+        )
+        .await
+    }
+
+    pub async fn rpc_pool(&self) -> Result<RpcPoolResponse, ClientError> {
+        self.get(&["rpc_pool"]).await
+    }
+
+    /// RPC pool stats in Prometheus format
+    pub async fn rpc_pool_metrics(&self) -> Result<String, ClientError> {
+        self.get_text(&["rpc_pool", "metrics"]).await
+    }
+
+    pub async fn config(&self) -> Result<ConfigResponse, ClientError> {
+        self.get(&["config"]).await
+    }
+
+    pub async fn stats_transfers(
+        &self,
+        request: &StatsTransferRequest,
+    ) -> Result<StatsTransferResponse, ClientError> {
+        let query = [
+            ("receiver", &request.receiver),
+            ("from", &request.from),
+            ("to", &request.to),
+            ("chain", &request.chain),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.clone().map(|value| (name, value)))
+        .collect::<Vec<_>>();
+        self.request(Method::GET, &["stats", "transfers"], &query, None)
+            .await
+    }
+
+    pub async fn transactions(&self) -> Result<TransactionsResponse, ClientError> {
+        self.get(&["transactions"]).await
+    }
+
+    pub async fn transactions_count(&self) -> Result<TransactionsCountResponse, ClientError> {
+        self.get(&["transactions", "count"]).await
+    }
+
+    pub async fn transactions_next(
+        &self,
+        count: Option<u64>,
+    ) -> Result<TransactionsResponse, ClientError> {
+        match count {
+            Some(count) => {
+                self.get(&["transactions", "next", &count.to_string()])
+                    .await
+            }
+            None => self.get(&["transactions", "next"]).await,
+        }
+    }
+
+    pub async fn transactions_feed(
+        &self,
+        prev: u64,
+        next: u64,
+    ) -> Result<TransactionsFeedResponse, ClientError> {
+        self.get(&["transactions", "feed", &prev.to_string(), &next.to_string()])
+            .await
+    }
+
+    pub async fn transactions_current(&self) -> Result<TransactionsResponse, ClientError> {
+        self.get(&["transactions", "current"]).await
+    }
+
+    pub async fn transactions_last(
+        &self,
+        count: Option<u64>,
+    ) -> Result<TransactionsResponse, ClientError> {
+        match count {
+            Some(count) => {
+                self.get(&["transactions", "last", &count.to_string()])
+                    .await
+            }
+            None => self.get(&["transactions", "last"]).await,
+        }
+    }
+
+    pub async fn skip_tx(&self, tx_id: i64) -> Result<SkipTxResponse, ClientError> {
+        self.post(&["tx", "skip", &tx_id.to_string()], None::<&()>)
+            .await
+    }
+
+    pub async fn tx(&self, tx_id: i64) -> Result<TxResponse, ClientError> {
+        self.get(&["tx", &tx_id.to_string()]).await
+    }
+
+    pub async fn transfers(&self) -> Result<TransfersResponse, ClientError> {
+        self.get(&["transfers"]).await
+    }
+
+    pub async fn tx_transfers(&self, tx_id: i64) -> Result<TransfersResponse, ClientError> {
+        self.get(&["transfers", &tx_id.to_string()]).await
+    }
+
+    pub async fn new_transfer(
+        &self,
+        request: &NewTransferRequest,
+    ) -> Result<NewTransferResponse, ClientError> {
+        self.post(&["transfers", "new"], Some(request)).await
+    }
+
+    /// Insert all transfers or none of them.
+    /// Report is returned also when some rows are invalid (with status 400).
+    pub async fn new_transfers_batch(
+        &self,
+        requests: &[NewTransferRequest],
+    ) -> Result<(StatusCode, TransferBatchResponse), ClientError> {
+        let body = serde_json::to_vec(requests).map_err(|err| {
+            ClientError::InvalidRequest(format!("Cannot serialize request: {err}"))
+        })?;
+        let (status, body) = self
+            .send(Method::POST, &["transfers", "batch"], &[], Some(body))
+            .await?;
+        if status.is_success() || status == StatusCode::BAD_REQUEST {
+            if let Ok(report) = serde_json::from_slice::<TransferBatchResponse>(&body) {
+                return Ok((status, report));
+            }
+        }
+        Err(api_error(status, &body))
+    }
+
+    pub async fn cancel_transfer(
+        &self,
+        payment_id: &str,
+        request: &CancelTransferRequest,
+    ) -> Result<TransferResponse, ClientError> {
+        self.post(&["transfers", payment_id, "cancel"], Some(request))
+            .await
+    }
+
+    pub async fn amend_transfer(
+        &self,
+        payment_id: &str,
+        request: &AmendTransferRequest,
+    ) -> Result<TransferResponse, ClientError> {
+        self.post(&["transfers", payment_id, "amend"], Some(request))
+            .await
+    }
+
+    pub async fn accounts(&self) -> Result<AccountsResponse, ClientError> {
+        self.get(&["accounts"]).await
+    }
+
+    pub async fn account(&self, account: &str) -> Result<AccountResponse, ClientError> {
+        self.get(&["account", account]).await
+    }
+
+    pub async fn account_payments_in(
+        &self,
+        account: &str,
+    ) -> Result<AccountPaymentsInResponse, ClientError> {
+        self.get(&["account", account, "in"]).await
+    }
+
+    /// Processor metrics in Prometheus format
+    pub async fn metrics(&self) -> Result<String, ClientError> {
+        self.get_text(&["metrics"]).await
+    }
+
+    /// Events stored after given id, waits up to timeout for new ones if there are none
+    pub async fn events(&self, request: &EventsRequest) -> Result<EventsResponse, ClientError> {
+        let mut query = vec![("after", request.after.to_string())];
+        if let Some(limit) = request.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(timeout) = request.timeout {
+            query.push(("timeout", timeout.to_string()));
+        }
+        self.request(Method::GET, &["events"], &query, None).await
+    }
+
+    pub async fn faucet_status(&self) -> Result<FaucetStatusResponse, ClientError> {
+        self.get(&["faucet"]).await
+    }
+
+    pub async fn faucet(
+        &self,
+        chain: &str,
+        addr: &str,
+    ) -> Result<FaucetTransferResponse, ClientError> {
+        self.get(&["faucet", chain, addr]).await
+    }
+
+    pub async fn debug(&self) -> Result<DebugResponse, ClientError> {
+        self.get(&["debug"]).await
+    }
+
+    /// OpenAPI document describing the API
+    pub async fn openapi(&self) -> Result<serde_json::Value, ClientError> {
+        self.get(&["openapi.json"]).await
+    }
+}
+
+fn api_error(status: StatusCode, body: &[u8]) -> ClientError {
+    match serde_json::from_slice::<ApiError>(body) {
+        Ok(error) => ClientError::Api { status, error },
+        Err(_) => ClientError::InvalidResponse {
+            status,
+            message: String::from_utf8_lossy(body).to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+    async fn new_transfer(req: HttpRequest, body: web::Bytes) -> HttpResponse {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let expected = hmac_signature(
+            "secret",
+            header(API_TIMESTAMP_HEADER).parse().unwrap_or_default(),
+            &header(API_NONCE_HEADER),
+            req.method().as_str(),
+            req.uri().path_and_query().unwrap().as_str(),
+            &body,
+        );
+        if header(API_CLIENT_HEADER) != "billing" || header(API_SIGNATURE_HEADER) != expected {
+            return HttpResponse::Unauthorized().json(ApiError {
+                error: "Invalid signature".to_string(),
+                transfer: None,
+            });
+        }
+        let request: NewTransferRequest = serde_json::from_slice(&body).unwrap();
+        HttpResponse::Ok().json(NewTransferResponse {
+            payment_id: request.payment_id.unwrap_or_default(),
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_client() {
+        let server = HttpServer::new(|| {
+            App::new().service(
+                web::scope("/erc20/api")
+                    .route(
+                        "/version",
+                        web::get().to(|| async {
+                            HttpResponse::Ok().json(VersionResponse {
+                                name: "erc20_payment_lib".to_string(),
+                                version: "0.4.1".to_string(),
+                            })
+                        }),
+                    )
+                    .route(
+                        "/tx/{tx_id}",
+                        web::get().to(|| async {
+                            HttpResponse::NotFound().json(ApiError {
+                                error: "Transaction not found".to_string(),
+                                transfer: None,
+                            })
+                        }),
+                    )
+                    .route("/transfers/new", web::post().to(new_transfer)),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let base_url = format!("http://{addr}/erc20/api");
+        let client = PaymentClient::new(&base_url);
+        assert_eq!(client.version().await.unwrap().version, "0.4.1");
+
+        match client.tx(1).await {
+            Err(ClientError::Api { status, error }) => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(error.error, "Transaction not found");
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+
+        let request = NewTransferRequest {
+            payment_id: Some("payment-1".to_string()),
+            amount: "1000".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            client.new_transfer(&request).await,
+            Err(ClientError::Api {
+                status: StatusCode::UNAUTHORIZED,
+                ..
+            })
+        ));
+        let client = client.with_hmac("billing", "secret");
+        assert_eq!(
+            client.new_transfer(&request).await.unwrap().payment_id,
+            "payment-1"
+        );
+    }
+}
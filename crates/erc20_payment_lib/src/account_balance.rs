@@ -84,22 +84,38 @@ pub async fn test_balance_loop(
             prev_loop_time = std::time::Instant::now();
         }
 
-        let address = "0x200000000000000000000000".to_string()
-            + format!("{:#018x}", job_no).replace("0x", "").as_str();
-        let address = Address::from_str(&address).map_err(err_from!())?;
+        // one batch request checks tasks addresses at once
+        let batch_end = job_no
+            .saturating_add(account_balance_options.tasks.max(1) as u64)
+            .min(number_of_loops);
+        let mut addresses = Vec::new();
+        for no in job_no..batch_end {
+            let address = "0x200000000000000000000000".to_string()
+                + format!("{:#018x}", no).replace("0x", "").as_str();
+            addresses.push(Address::from_str(&address).map_err(err_from!())?);
+        }
 
-        match web3_pool.clone().eth_balance(address, None).await {
-            Ok(balance) => balance,
+        let balances = match web3_pool
+            .clone()
+            .eth_balance_batch(addresses.iter().map(|address| (*address, None)).collect())
+            .await
+        {
+            Ok(balances) => balances,
             Err(err) => {
+                log::error!("Error getting balances: {}", err);
+                continue;
+            }
+        };
+        for (address, balance) in addresses.iter().zip(balances) {
+            if let Err(err) = balance {
                 log::error!(
                     "Error getting balance for account: {:#x} - {}",
                     address,
                     err
                 );
-                continue;
             }
-        };
-        job_no += 1;
+        }
+        job_no = batch_end;
     }
 
     Ok(())
@@ -1,12 +1,14 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::btree_map::BTreeMap as Map;
 
 use rust_decimal::Decimal;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::err_custom_create;
 use crate::error::*;
 use erc20_payment_lib_common::err_create;
+use erc20_rpc_pool::Web3RpcQuorum;
 use tokio::fs;
 use web3::types::Address;
 
@@ -61,12 +63,167 @@ pub struct Engine {
     pub gather_at_start: bool,
     pub automatic_recover: bool,
     pub ignore_deadlines: bool,
+    #[serde(default)]
+    pub batching: BatchingPolicy,
+    #[serde(default)]
+    pub rpc_stats: RpcStatsSettings,
+    #[serde(default)]
+    pub event_log: EventLogSettings,
+}
+
+/// Trade off between gas cost (bigger batches) and latency of transfers
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct BatchingPolicy {
+    /// Batch is sent when deadline of any transfer in it is closer than this (in seconds)
+    pub deadline_margin: u64,
+    /// Batch containing only low priority transfers (priority below zero) is held
+    /// until it has at least this many transfers
+    pub low_priority_min_batch_size: usize,
+    /// Low priority transfers are not held longer than this (in seconds)
+    pub low_priority_max_wait: u64,
+}
+
+impl Default for BatchingPolicy {
+    fn default() -> Self {
+        BatchingPolicy {
+            deadline_margin: 60,
+            low_priority_min_batch_size: 1,
+            low_priority_max_wait: 3600,
+        }
+    }
+}
+
+/// RPC endpoint scores kept in the database, so they survive restarts
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct RpcStatsSettings {
+    /// How often endpoint stats are saved (in seconds), 0 disables saving and restoring
+    pub save_interval: u64,
+    /// Penalties of restored endpoints are halved after this time (in seconds)
+    pub penalty_half_life: u64,
+}
+
+impl Default for RpcStatsSettings {
+    fn default() -> Self {
+        RpcStatsSettings {
+            save_interval: 60,
+            penalty_half_life: 3600,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub chain: Map<String, Chain>,
     pub engine: Engine,
+    #[serde(default, rename = "remote-signer")]
+    pub remote_signer: Map<String, RemoteSignerSettings>,
+    /// HTTP API is open to anyone who can reach the port if not set
+    #[serde(rename = "api-auth")]
+    pub api_auth: Option<ApiAuthSettings>,
+    #[serde(default)]
+    pub webhook: Map<String, WebhookSettings>,
+}
+
+/// Driver events stored in the database, so consumers can resume from the last seen one
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct EventLogSettings {
+    /// Events older than this (in seconds) are deleted, 0 keeps them forever
+    pub retention: u64,
+}
+
+impl Default for EventLogSettings {
+    fn default() -> Self {
+        EventLogSettings {
+            retention: 7 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiRole {
+    /// Can only read, every authenticated client has this role
+    ReadOnly,
+    /// Can skip transactions and cancel transfers
+    Operator,
+    /// Can create and amend transfers
+    Payer,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiClientSettings {
+    #[serde(default)]
+    pub roles: Vec<ApiRole>,
+    /// Sent as Authorization: Bearer header
+    pub token: Option<String>,
+    /// Key used to sign requests with HMAC-SHA256
+    pub hmac_secret: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiAuthSettings {
+    /// HMAC signed requests older or newer than that (in seconds) are rejected
+    #[serde(default = "default_hmac_max_skew")]
+    pub hmac_max_skew: u64,
+    pub clients: Map<String, ApiClientSettings>,
+}
+
+fn default_hmac_max_skew() -> u64 {
+    300
+}
+
+/// HTTP endpoint receiving driver events, deliveries are retried until it responds with 2xx
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookSettings {
+    pub url: String,
+    /// Key used to sign deliveries with HMAC-SHA256
+    pub secret: String,
+    /// Names of DriverEventContent variants (e.g. TransferFinished), all events if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// First retry delay (in seconds), doubled after every failed attempt
+    #[serde(default = "default_webhook_min_retry_interval")]
+    pub min_retry_interval: u64,
+    /// Retry delay is never longer than this (in seconds)
+    #[serde(default = "default_webhook_max_retry_interval")]
+    pub max_retry_interval: u64,
+    /// Request timeout (in seconds)
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout: u64,
+    /// Delivered events are deleted after this time (in seconds), 0 keeps them forever
+    #[serde(default = "default_webhook_retention")]
+    pub retention: u64,
+}
+
+fn default_webhook_min_retry_interval() -> u64 {
+    5
+}
+
+fn default_webhook_max_retry_interval() -> u64 {
+    3600
+}
+
+fn default_webhook_timeout() -> u64 {
+    10
+}
+
+fn default_webhook_retention() -> u64 {
+    7 * 24 * 3600
+}
+
+/// Signer running in separate process (Web3Signer or any eth_signTransaction JSON-RPC server)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct RemoteSignerSettings {
+    pub url: String,
+    /// Accounts signed by this signer instead of local private keys
+    pub accounts: Vec<Address>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -89,6 +246,47 @@ pub struct LockContractSettings {
     pub address: Address,
 }
 
+/// Multicall3 deployment used to aggregate read calls
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct MulticallContractSettings {
+    pub address: Address,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct MetaTransactionSettings {
+    /// Account holding gas token that submits meta transactions
+    pub relayer: Address,
+    /// Accounts that pay token transfers through the relayer (EIP-712 signed)
+    pub accounts: Vec<Address>,
+}
+
+fn default_fee_history_blocks() -> u64 {
+    10
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeePolicySettings {
+    /// Fees (in gas currency) that single account can spend in a day (UTC)
+    pub daily_budget: Option<Decimal>,
+    /// Fees (in gas currency) that single account can spend in a month (UTC)
+    pub monthly_budget: Option<Decimal>,
+    /// New transactions are not sent while base fee (in Gwei) is above this value,
+    /// unless deadline of one of its transfers is close
+    pub max_base_fee: Option<Decimal>,
+    /// If set max fee per gas is next base fee + priority fee + this value (in Gwei),
+    /// but not higher than max-fee-per-gas of the chain
+    pub base_fee_extra: Option<Decimal>,
+    /// If set priority fee is median of this percentile of priority fees
+    /// paid in recent blocks, but not higher than priority-fee of the chain
+    pub priority_fee_percentile: Option<f64>,
+    /// Number of recent blocks taken from eth_feeHistory
+    #[serde(default = "default_fee_history_blocks")]
+    pub fee_history_blocks: u64,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct FaucetClientSettings {
@@ -110,9 +308,21 @@ pub struct RpcSettings {
     pub backup_level: Option<i64>,
     pub verify_interval_secs: Option<u64>,
     pub min_interval_ms: Option<u64>,
+    /// Token bucket limit of requests sent to every endpoint of this entry
+    pub max_requests_per_second: Option<f64>,
+    /// Max requests sent to every endpoint of this entry during UTC day
+    pub daily_request_quota: Option<u64>,
     pub max_timeout_ms: Option<u64>,
     pub allowed_head_behind_secs: Option<i64>,
     pub max_consecutive_errors: Option<u64>,
+    /// Max number of calls in one JSON-RPC batch request, 1 disables batching
+    pub max_batch_size: Option<usize>,
+    /// Ask that many endpoints for critical calls and compare answers (whole chain pool)
+    pub quorum_size: Option<usize>,
+    /// How many endpoints have to agree, majority of quorum-size by default
+    pub quorum_min_agree: Option<usize>,
+    /// Calls checked by quorum, transaction_receipt, transaction_count and block by default
+    pub quorum_methods: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -125,26 +335,75 @@ pub struct Chain {
     pub priority_fee: Decimal,
     pub max_fee_per_gas: Decimal,
     pub token: Token,
+    #[serde(default)]
+    pub tokens: Vec<Token>,
     pub multi_contract: Option<MultiContractSettings>,
     pub mint_contract: Option<MintContractSettings>,
     pub lock_contract: Option<LockContractSettings>,
+    pub multicall_contract: Option<MulticallContractSettings>,
+    pub meta_transaction: Option<MetaTransactionSettings>,
+    pub fee_policy: Option<FeePolicySettings>,
     pub faucet_client: Option<FaucetClientSettings>,
     pub transaction_timeout: u64,
     pub confirmation_blocks: u64,
+    /// Confirmed transactions are rechecked for reorgs until they are that many blocks deep
+    pub finality_blocks: Option<u64>,
     pub faucet_eth_amount: Option<Decimal>,
     pub faucet_glm_amount: Option<Decimal>,
     pub block_explorer_url: Option<String>,
     pub replacement_timeout: Option<f64>,
     pub external_source_check_interval: Option<u64>,
+    /// Send signed transactions to all healthy endpoints instead of the best one
+    pub broadcast_to_all_endpoints: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Token {
     pub symbol: String,
     pub address: Address,
+    pub decimals: Option<u8>,
     pub faucet: Option<Address>,
 }
 
+impl Chain {
+    /// Default token of the chain first, then additional tokens
+    pub fn all_tokens(&self) -> impl Iterator<Item = &Token> {
+        std::iter::once(&self.token).chain(self.tokens.iter())
+    }
+
+    /// Find token by symbol (case insensitive) or by address
+    pub fn find_token(&self, symbol_or_address: &str) -> Option<&Token> {
+        let address = Address::from_str(symbol_or_address).ok();
+        self.all_tokens().find(|token| {
+            Some(token.address) == address || token.symbol.eq_ignore_ascii_case(symbol_or_address)
+        })
+    }
+
+    /// Tokens have to be unique by symbol (case insensitive) and address
+    pub fn check_tokens(&self) -> Result<(), String> {
+        let tokens = self.all_tokens().collect::<Vec<_>>();
+        for (idx, token) in tokens.iter().enumerate() {
+            if tokens[..idx]
+                .iter()
+                .any(|t| t.address == token.address || t.symbol.eq_ignore_ascii_case(&token.symbol))
+            {
+                return Err(format!(
+                    "Duplicate token {} ({:#x})",
+                    token.symbol, token.address
+                ));
+            }
+            if token.decimals.is_some_and(|decimals| decimals > 18) {
+                return Err(format!(
+                    "Token {} has unsupported number of decimals: {}",
+                    token.symbol,
+                    token.decimals.unwrap_or_default()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Config {
     pub fn default_config_str() -> &'static str {
         //include config.toml
@@ -158,14 +417,35 @@ impl Config {
     }
 
     pub fn load_from_str(str: &str) -> Result<Self, PaymentError> {
-        match toml::from_str(str) {
-            Ok(config) => Ok(config),
+        match toml::from_str::<Self>(str) {
+            Ok(config) => {
+                config
+                    .validate()
+                    .map_err(|err| err_custom_create!("{}", err))?;
+                Ok(config)
+            }
             Err(e) => Err(err_custom_create!("Failed to parse toml {}: {}", str, e)),
         }
     }
 
+    /// Checks settings that cannot be expressed by toml types
+    fn validate(&self) -> Result<(), String> {
+        for (name, chain) in &self.chain {
+            chain
+                .check_tokens()
+                .map_err(|err| format!("Chain {name}: {err}"))?;
+            for rpc_settings in &chain.rpc_endpoints {
+                if let Some(methods) = &rpc_settings.quorum_methods {
+                    Web3RpcQuorum::check_methods(methods)
+                        .map_err(|err| format!("Chain {name}: {err}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaymentError> {
-        match toml::from_str(&String::from_utf8_lossy(&fs::read(&path).await.map_err(
+        match toml::from_str::<Self>(&String::from_utf8_lossy(&fs::read(&path).await.map_err(
             |e| match e.kind() {
                 std::io::ErrorKind::NotFound => {
                     err_create!(e)
@@ -179,7 +459,12 @@ impl Config {
                 }
             },
         )?)) {
-            Ok(config) => Ok(config),
+            Ok(config) => {
+                config.validate().map_err(|err| {
+                    err_custom_create!("Invalid config {}: {}", path.as_ref().display(), err)
+                })?;
+                Ok(config)
+            }
             Err(e) => Err(err_custom_create!(
                 "Failed to parse toml {}: {}",
                 path.as_ref().display(),
@@ -212,3 +497,102 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+
+    #[test]
+    fn test_chain_tokens() {
+        let config = Config::load_from_str(&Config::default_config_str().replace(
+            "# tokens = [",
+            &format!(r#"tokens = [{{ address = "{USDC}", symbol = "USDC", decimals = 6 }}] #"#),
+        ))
+        .unwrap();
+        let polygon = &config.chain["polygon"];
+        assert_eq!(
+            polygon
+                .all_tokens()
+                .map(|t| t.symbol.as_str())
+                .collect::<Vec<_>>(),
+            vec!["GLM", "USDC"]
+        );
+        assert_eq!(
+            polygon.find_token("GLM").unwrap().address,
+            polygon.token.address
+        );
+        assert_eq!(polygon.find_token("usdc").unwrap().decimals, Some(6));
+        assert_eq!(polygon.find_token(USDC).unwrap().symbol, "USDC");
+        assert!(polygon.find_token("DAI").is_none());
+        assert!(polygon
+            .find_token("0x0000000000000000000000000000000000000001")
+            .is_none());
+        //other chains have only default token
+        assert_eq!(config.chain["holesky"].all_tokens().count(), 1);
+    }
+
+    #[test]
+    fn test_chain_tokens_invalid() {
+        let load = |tokens: String| {
+            Config::load_from_str(
+                &Config::default_config_str()
+                    .replace("# tokens = [", &format!("tokens = [{tokens}] #")),
+            )
+        };
+        let err = load(format!(
+            r#"{{ address = "{USDC}", symbol = "glm", decimals = 6 }}"#
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("Duplicate token glm"), "{err}");
+        let err = load(
+            r#"{ address = "0x0B220b82F3eA3B7F6d9A1D8ab58930C064A2b5Bf", symbol = "GLM2" }"#
+                .to_string(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Duplicate token GLM2"), "{err}");
+        let err = load(format!(
+            r#"{{ address = "{USDC}", symbol = "USDC" }}, {{ address = "{USDC}", symbol = "USDC.e" }}"#
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("Duplicate token USDC.e"), "{err}");
+        let err = load(format!(
+            r#"{{ address = "{USDC}", symbol = "USDC", decimals = 19 }}"#
+        ))
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("unsupported number of decimals"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_quorum_methods() {
+        let header = "[[chain.holesky.rpc-endpoints]]";
+        let with_methods = |methods: &str| {
+            Config::default_config_str().replacen(
+                header,
+                &format!("{header}\nquorum-size = 3\nquorum-methods = {methods}"),
+                1,
+            )
+        };
+        let err =
+            Config::load_from_str(&with_methods(r#"["eth_getTransactionReceipt"]"#)).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown quorum method eth_getTransactionReceipt"),
+            "{err}"
+        );
+        let config =
+            Config::load_from_str(&with_methods(r#"["eth_transaction_receipt", "block"]"#))
+                .unwrap();
+        assert_eq!(
+            config.chain["holesky"].rpc_endpoints[0].quorum_methods,
+            Some(vec![
+                "eth_transaction_receipt".to_string(),
+                "block".to_string()
+            ])
+        );
+    }
+}
@@ -5,8 +5,9 @@ use crate::error::PaymentError;
 use std::str::FromStr;
 use web3::contract::tokens::Tokenize;
 use web3::contract::Contract;
+use web3::ethabi::Token;
 use web3::transports::Http;
-use web3::types::{Address, U256};
+use web3::types::{Address, H256, U256};
 use web3::{Transport, Web3};
 
 // todo remove DUMMY_RPC_PROVIDER and use ABI instead
@@ -26,6 +27,12 @@ lazy_static! {
     };
     pub static ref LOCK_CONTRACT_TEMPLATE: Contract<Http> =
         prepare_contract_template(include_bytes!("../contracts/lock_payments.json")).unwrap();
+    pub static ref EIP712_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/eip712.json")).unwrap();
+    pub static ref META_TRANSACTION_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/meta_transaction.json")).unwrap();
+    pub static ref MULTICALL3_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/multicall3.json")).unwrap();
 }
 
 pub fn prepare_contract_template(json_abi: &[u8]) -> Result<Contract<Http>, PaymentError> {
@@ -58,6 +65,10 @@ pub fn encode_erc20_balance_of(address: Address) -> Result<Vec<u8>, web3::ethabi
     contract_encode(&ERC20_CONTRACT_TEMPLATE, "balanceOf", (address,))
 }
 
+pub fn encode_erc20_decimals() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&ERC20_CONTRACT_TEMPLATE, "decimals", ())
+}
+
 pub fn encode_erc20_transfer(
     address: Address,
     amount: U256,
@@ -179,6 +190,29 @@ pub fn encode_create_deposit(
         ),
     )
 }
+
+pub struct ExtendDepositArgs {
+    pub deposit_nonce: u64,
+    pub deposit_extra_amount: U256,
+    pub deposit_extra_fee: U256,
+    pub deposit_timestamp: u64,
+}
+
+pub fn encode_extend_deposit(
+    deposit_args: ExtendDepositArgs,
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(
+        &LOCK_CONTRACT_TEMPLATE,
+        "extendDeposit",
+        (
+            deposit_args.deposit_nonce,
+            deposit_args.deposit_extra_amount,
+            deposit_args.deposit_extra_fee,
+            deposit_args.deposit_timestamp,
+        ),
+    )
+}
+
 pub fn encode_payout_single(
     id: U256,
     recipient: Address,
@@ -206,3 +240,77 @@ pub fn encode_payout_single_and_close(
 pub fn encode_get_deposit_details(id: U256) -> Result<Vec<u8>, web3::ethabi::Error> {
     contract_encode(&LOCK_CONTRACT_TEMPLATE, "getDeposit", (id,))
 }
+
+pub fn encode_get_lock_token() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&LOCK_CONTRACT_TEMPLATE, "GLM", ())
+}
+
+pub fn encode_get_domain_separator() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&EIP712_CONTRACT_TEMPLATE, "getDomainSeperator", ())
+}
+
+pub fn encode_get_meta_transaction_nonce(user: Address) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&META_TRANSACTION_CONTRACT_TEMPLATE, "getNonce", (user,))
+}
+
+pub fn encode_execute_meta_transaction(
+    user: Address,
+    function_signature: Vec<u8>,
+    sig_r: H256,
+    sig_s: H256,
+    sig_v: u8,
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(
+        &META_TRANSACTION_CONTRACT_TEMPLATE,
+        "executeMetaTransaction",
+        (user, function_signature, sig_r, sig_s, U256::from(sig_v)),
+    )
+}
+
+/// Encodes Multicall3 aggregate3 call, every call is allowed to fail
+pub fn encode_multicall3_aggregate3(
+    calls: &[(Address, Vec<u8>)],
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    let calls = calls
+        .iter()
+        .map(|(target, call_data)| {
+            Token::Tuple(vec![
+                Token::Address(*target),
+                Token::Bool(true),
+                Token::Bytes(call_data.clone()),
+            ])
+        })
+        .collect();
+    MULTICALL3_CONTRACT_TEMPLATE
+        .abi()
+        .function("aggregate3")
+        .and_then(|function| function.encode_input(&[Token::Array(calls)]))
+}
+
+/// Decodes aggregate3 result into return data of every call, None for failed calls
+pub fn decode_multicall3_aggregate3(
+    data: &[u8],
+) -> Result<Vec<Option<Vec<u8>>>, web3::ethabi::Error> {
+    let tokens = MULTICALL3_CONTRACT_TEMPLATE
+        .abi()
+        .function("aggregate3")
+        .and_then(|function| function.decode_output(data))?;
+    let Some(Token::Array(results)) = tokens.into_iter().next() else {
+        return Err(web3::ethabi::Error::InvalidData);
+    };
+    results
+        .into_iter()
+        .map(|result| match result {
+            Token::Tuple(fields) => match fields.as_slice() {
+                [Token::Bool(true), Token::Bytes(data)] => Ok(Some(data.clone())),
+                [Token::Bool(false), Token::Bytes(_)] => Ok(None),
+                _ => Err(web3::ethabi::Error::InvalidData),
+            },
+            _ => Err(web3::ethabi::Error::InvalidData),
+        })
+        .collect()
+}
+
+pub fn encode_multicall3_get_eth_balance(address: Address) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&MULTICALL3_CONTRACT_TEMPLATE, "getEthBalance", (address,))
+}
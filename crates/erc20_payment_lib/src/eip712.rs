@@ -0,0 +1,64 @@
+use web3::ethabi::Token;
+use web3::signing::keccak256;
+use web3::types::{Address, H256, U256};
+
+/// Type used by token contracts supporting native meta transactions (i.e. GLM on Polygon)
+pub const META_TRANSACTION_TYPE: &str =
+    "MetaTransaction(uint256 nonce,address from,bytes functionSignature)";
+
+/// EIP-712 struct hash of MetaTransaction
+pub fn meta_transaction_struct_hash(nonce: U256, from: Address, function_signature: &[u8]) -> H256 {
+    let encoded = web3::ethabi::encode(&[
+        Token::FixedBytes(keccak256(META_TRANSACTION_TYPE.as_bytes()).to_vec()),
+        Token::Uint(nonce),
+        Token::Address(from),
+        Token::FixedBytes(keccak256(function_signature).to_vec()),
+    ]);
+    H256(keccak256(&encoded))
+}
+
+/// Digest that has to be signed: keccak256("\x19\x01" ‖ domainSeparator ‖ structHash)
+pub fn typed_data_digest(domain_separator: H256, struct_hash: H256) -> H256 {
+    let mut message = Vec::with_capacity(2 + 32 + 32);
+    message.extend_from_slice(b"\x19\x01");
+    message.extend_from_slice(domain_separator.as_bytes());
+    message.extend_from_slice(struct_hash.as_bytes());
+    H256(keccak256(&message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth::get_eth_addr_from_secret;
+    use crate::signer::{PrivateKeySigner, Signer};
+    use secp256k1::SecretKey;
+    use std::str::FromStr;
+    use web3::signing::recover;
+
+    #[tokio::test]
+    async fn test_sign_meta_transaction() {
+        let secret_key =
+            SecretKey::from_str("c2b876dd5ef1bcab6864249c58dfdcfa9c9b3ee5d5a55bd0b8ac5e4b1f5b1b4f")
+                .unwrap();
+        let address = get_eth_addr_from_secret(&secret_key);
+        let signer = PrivateKeySigner::new(vec![secret_key]);
+
+        let domain_separator = H256(keccak256(b"domain"));
+        let struct_hash = meta_transaction_struct_hash(U256::from(1), address, &[1, 2, 3]);
+        let signature = signer
+            .sign_typed_data(address, domain_separator, struct_hash)
+            .await
+            .unwrap();
+
+        let mut rs = [0u8; 64];
+        rs[..32].copy_from_slice(signature.r.as_bytes());
+        rs[32..].copy_from_slice(signature.s.as_bytes());
+        let recovered = recover(
+            typed_data_digest(domain_separator, struct_hash).as_bytes(),
+            &rs,
+            signature.v as i32 - 27,
+        )
+        .unwrap();
+        assert_eq!(recovered, address);
+    }
+}
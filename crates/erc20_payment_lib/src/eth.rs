@@ -1,17 +1,22 @@
 use crate::contracts::{
-    encode_erc20_allowance, encode_erc20_balance_of, encode_get_deposit_details,
+    decode_multicall3_aggregate3, encode_erc20_allowance, encode_erc20_balance_of,
+    encode_erc20_decimals, encode_get_deposit_details, encode_get_domain_separator,
+    encode_get_lock_token, encode_get_meta_transaction_nonce, encode_multicall3_aggregate3,
+    encode_multicall3_get_eth_balance,
 };
 use crate::error::*;
 use crate::{err_create, err_custom_create, err_from};
 use erc20_payment_lib_common::utils::{datetime_from_u256_timestamp, U256ConvExt};
-use erc20_rpc_pool::Web3RpcPool;
+use erc20_rpc_pool::{RpcErrorKind, Web3RpcPool};
+use lazy_static::lazy_static;
 use secp256k1::{PublicKey, SecretKey};
 use serde::Serialize;
 use sha3::Digest;
 use sha3::Keccak256;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use web3::ethabi;
-use web3::types::{Address, BlockId, BlockNumber, Bytes, CallRequest, U256, U64};
+use web3::types::{Address, BlockId, BlockNumber, Bytes, CallRequest, H256, U256};
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -86,6 +91,25 @@ impl DepositView {
             valid_to: decoded[6].clone().into_uint().unwrap().as_u64(),
         })
     }
+
+    fn into_details(self, block_number: u64) -> Result<DepositDetails, PaymentError> {
+        let amount_u256 = U256::from(self.amount);
+        let fee_amount_u256 = U256::from(self.fee_amount);
+
+        Ok(DepositDetails {
+            deposit_id: format!("{:#x}", self.id),
+            deposit_nonce: self.nonce,
+            funder: self.funder,
+            spender: self.spender,
+            amount: amount_u256.to_string(),
+            fee_amount: fee_amount_u256.to_string(),
+            current_block: block_number,
+            amount_decimal: amount_u256.to_eth().map_err(err_from!())?,
+            fee_amount_decimal: fee_amount_u256.to_eth().map_err(err_from!())?,
+            current_block_datetime: None,
+            valid_to: chrono::DateTime::from_timestamp(self.valid_to as i64, 0).unwrap_or_default(),
+        })
+    }
 }
 
 pub fn deposit_id_from_nonce(funder: Address, nonce: u64) -> U256 {
@@ -101,55 +125,166 @@ pub fn nonce_from_deposit_id(deposit_id: U256) -> u64 {
     u64::from_be_bytes(slice[24..32].try_into().unwrap())
 }
 
+/// Max number of calls aggregated into single multicall request
+const MULTICALL_CHUNK_SIZE: usize = 200;
+
+/// Aggregates calls using Multicall3 aggregate3, every call is allowed to fail (None in result).
+/// Returns None if the multicall contract cannot be used, so caller can fall back to single calls.
+async fn multicall_aggregate3(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Address,
+    calls: &[(Address, Vec<u8>)],
+    block: BlockNumber,
+) -> Option<Vec<Option<Vec<u8>>>> {
+    let mut results = Vec::with_capacity(calls.len());
+    for chunk in calls.chunks(MULTICALL_CHUNK_SIZE) {
+        let call_data = encode_multicall3_aggregate3(chunk).ok()?;
+        let res = match web3
+            .clone()
+            .eth_call(
+                CallRequest {
+                    to: Some(multicall_address),
+                    data: Some(Bytes(call_data)),
+                    ..Default::default()
+                },
+                Some(BlockId::Number(block)),
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                log::warn!(
+                    "Multicall contract {:#x} call failed, falling back to single calls: {}",
+                    multicall_address,
+                    err
+                );
+                return None;
+            }
+        };
+        match decode_multicall3_aggregate3(&res.0) {
+            Ok(chunk_results) if chunk_results.len() == chunk.len() => {
+                results.extend(chunk_results)
+            }
+            _ => {
+                log::warn!(
+                    "Multicall contract {:#x} not found at block {:?}, falling back to single calls",
+                    multicall_address,
+                    block
+                );
+                return None;
+            }
+        }
+    }
+    Some(results)
+}
+
+/// Calls (contract, call data) pairs, aggregated with multicall if available or batched otherwise.
+/// Reverted calls are returned as None.
+async fn eth_call_many(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Option<Address>,
+    calls: Vec<(Address, Vec<u8>)>,
+    block: BlockNumber,
+) -> Result<Vec<Option<Vec<u8>>>, PaymentError> {
+    if let Some(multicall_address) = multicall_address {
+        if let Some(results) =
+            multicall_aggregate3(web3.clone(), multicall_address, &calls, block).await
+        {
+            return Ok(results);
+        }
+    }
+    let calls = calls
+        .into_iter()
+        .map(|(to, data)| {
+            (
+                CallRequest {
+                    to: Some(to),
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                Some(BlockId::Number(block)),
+            )
+        })
+        .collect();
+    let mut results = Vec::new();
+    for res in web3.eth_call_batch(calls).await.map_err(err_from!())? {
+        match res {
+            Ok(res) => results.push(Some(res.0)),
+            Err(err)
+                if matches!(
+                    RpcErrorKind::from_web3_error(&err),
+                    Some(RpcErrorKind::Revert(_))
+                ) =>
+            {
+                results.push(None)
+            }
+            Err(err) => return Err(err_from!()(err)),
+        }
+    }
+    Ok(results)
+}
+
 pub async fn get_deposit_details(
     web3: Arc<Web3RpcPool>,
     deposit_id: U256,
     lock_contract_address: Address,
     block_number: Option<u64>,
 ) -> Result<DepositDetails, PaymentError> {
-    let block_number = if let Some(block_number) = block_number {
-        log::debug!("Checking balance for block number {}", block_number);
-        block_number
-    } else {
-        web3.clone()
+    get_deposits_details(
+        web3,
+        None,
+        &[deposit_id],
+        lock_contract_address,
+        block_number,
+    )
+    .await?
+    .pop()
+    .ok_or_else(|| err_custom_create!("No details returned for deposit {:#x}", deposit_id))
+}
+
+/// Same as get_deposit_details for many deposits at once, aggregated with multicall if available
+pub async fn get_deposits_details(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Option<Address>,
+    deposit_ids: &[U256],
+    lock_contract_address: Address,
+    block_number: Option<u64>,
+) -> Result<Vec<DepositDetails>, PaymentError> {
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => web3
+            .clone()
             .eth_block_number()
             .await
             .map_err(err_from!())?
-            .as_u64()
+            .as_u64(),
     };
+    let mut calls = Vec::with_capacity(deposit_ids.len());
+    for deposit_id in deposit_ids {
+        calls.push((
+            lock_contract_address,
+            encode_get_deposit_details(*deposit_id).map_err(err_from!())?,
+        ));
+    }
+    let results = eth_call_many(
+        web3,
+        multicall_address,
+        calls,
+        BlockNumber::Number(block_number.into()),
+    )
+    .await?;
 
-    let res = web3
-        .eth_call(
-            CallRequest {
-                to: Some(lock_contract_address),
-                data: Some(encode_get_deposit_details(deposit_id).unwrap().into()),
-                ..Default::default()
-            },
-            Some(BlockId::Number(BlockNumber::Number(U64::from(
-                block_number,
-            )))),
-        )
-        .await
-        .map_err(err_from!())?;
-
-    let deposit_view = DepositView::decode_from_bytes(&res.0)?;
-
-    let amount_u256 = U256::from(deposit_view.amount);
-    let fee_amount_u256 = U256::from(deposit_view.fee_amount);
-
-    Ok(DepositDetails {
-        deposit_id: format!("{:#x}", deposit_view.id),
-        deposit_nonce: deposit_view.nonce,
-        funder: deposit_view.funder,
-        spender: deposit_view.spender,
-        amount: amount_u256.to_string(),
-        fee_amount: fee_amount_u256.to_string(),
-        current_block: block_number,
-        amount_decimal: amount_u256.to_eth().map_err(err_from!())?,
-        fee_amount_decimal: fee_amount_u256.to_eth().map_err(err_from!())?,
-        current_block_datetime: None,
-        valid_to: Default::default(),
-    })
+    let mut deposits = Vec::with_capacity(results.len());
+    for (deposit_id, res) in deposit_ids.iter().zip(results) {
+        let Some(res) = res else {
+            return Err(err_custom_create!(
+                "Failed to get details of deposit {:#x}",
+                deposit_id
+            ));
+        };
+        deposits.push(DepositView::decode_from_bytes(&res)?.into_details(block_number)?);
+    }
+    Ok(deposits)
 }
 
 pub async fn get_balance(
@@ -225,6 +360,300 @@ pub async fn get_balance(
     })
 }
 
+fn parse_balance_response(res: Option<Vec<u8>>, address: &Address) -> Result<U256, PaymentError> {
+    match res {
+        Some(res) if res.len() == 32 => Ok(U256::from_big_endian(&res)),
+        res => Err(err_custom_create!(
+            "Invalid balance response for {:#x}: {:?}",
+            address,
+            res
+        )),
+    }
+}
+
+async fn get_balances_multicall(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Address,
+    token_address: Option<Address>,
+    addresses: &[Address],
+    check_gas: bool,
+    block_number: u64,
+) -> Result<Option<Vec<GetBalanceResult>>, PaymentError> {
+    let mut calls = Vec::new();
+    for address in addresses {
+        if check_gas {
+            calls.push((
+                multicall_address,
+                encode_multicall3_get_eth_balance(*address).map_err(err_from!())?,
+            ));
+        }
+        if let Some(token_address) = token_address {
+            calls.push((
+                token_address,
+                encode_erc20_balance_of(*address).map_err(err_from!())?,
+            ));
+        }
+    }
+    let Some(results) = multicall_aggregate3(
+        web3,
+        multicall_address,
+        &calls,
+        BlockNumber::Number(block_number.into()),
+    )
+    .await
+    else {
+        return Ok(None);
+    };
+
+    let mut results = results.into_iter();
+    let mut balances = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let gas_balance = if check_gas {
+            Some(parse_balance_response(results.next().flatten(), address)?)
+        } else {
+            None
+        };
+        let token_balance = if token_address.is_some() {
+            Some(parse_balance_response(results.next().flatten(), address)?)
+        } else {
+            None
+        };
+        balances.push(GetBalanceResult {
+            gas_balance,
+            token_balance,
+            block_number,
+        });
+    }
+    Ok(Some(balances))
+}
+
+/// Same as get_balance for many addresses at once, aggregated with multicall if available
+/// or using JSON-RPC batch requests otherwise
+pub async fn get_balances(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Option<Address>,
+    token_address: Option<Address>,
+    addresses: &[Address],
+    check_gas: bool,
+    block_number: Option<u64>,
+) -> Result<Vec<GetBalanceResult>, PaymentError> {
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => web3
+            .clone()
+            .eth_block_number()
+            .await
+            .map_err(err_from!())?
+            .as_u64(),
+    };
+    let block = BlockNumber::Number(block_number.into());
+
+    if let Some(multicall_address) = multicall_address {
+        if let Some(balances) = get_balances_multicall(
+            web3.clone(),
+            multicall_address,
+            token_address,
+            addresses,
+            check_gas,
+            block_number,
+        )
+        .await?
+        {
+            return Ok(balances);
+        }
+    }
+
+    let gas_balances = if check_gas {
+        let mut gas_balances = Vec::with_capacity(addresses.len());
+        for res in web3
+            .clone()
+            .eth_balance_batch(addresses.iter().map(|a| (*a, Some(block))).collect())
+            .await
+            .map_err(err_from!())?
+        {
+            gas_balances.push(Some(res.map_err(err_from!())?));
+        }
+        gas_balances
+    } else {
+        vec![None; addresses.len()]
+    };
+
+    let token_balances = if let Some(token_address) = token_address {
+        let mut calls = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let call_data = encode_erc20_balance_of(*address).map_err(err_from!())?;
+            calls.push((
+                CallRequest {
+                    to: Some(token_address),
+                    data: Some(Bytes::from(call_data)),
+                    ..Default::default()
+                },
+                Some(BlockId::Number(block)),
+            ));
+        }
+        let mut token_balances = Vec::with_capacity(addresses.len());
+        for res in web3.eth_call_batch(calls).await.map_err(err_from!())? {
+            let res = res.map_err(err_from!())?;
+            if res.0.len() != 32 {
+                return Err(err_create!(TransactionFailedError::new(&format!(
+                    "Invalid balance response: {:?}. Probably not a valid ERC20 contract {:#x}",
+                    res.0, token_address
+                ))));
+            }
+            token_balances.push(Some(U256::from_big_endian(&res.0)));
+        }
+        token_balances
+    } else {
+        vec![None; addresses.len()]
+    };
+
+    Ok(gas_balances
+        .into_iter()
+        .zip(token_balances)
+        .map(|(gas_balance, token_balance)| GetBalanceResult {
+            gas_balance,
+            token_balance,
+            block_number,
+        })
+        .collect())
+}
+
+lazy_static! {
+    // decimals never change for deployed token, so they can be cached for whole process lifetime
+    static ref TOKEN_DECIMALS_CACHE: Mutex<HashMap<(u64, Address), u8>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn set_token_decimals_cache(chain_id: u64, token_address: Address, decimals: u8) {
+    TOKEN_DECIMALS_CACHE
+        .lock()
+        .unwrap()
+        .insert((chain_id, token_address), decimals);
+}
+
+/// Get token decimals from cache or call decimals() on token contract
+pub async fn get_token_decimals(
+    web3: Arc<Web3RpcPool>,
+    token_address: Address,
+) -> Result<u8, PaymentError> {
+    let key = (web3.chain_id, token_address);
+    if let Some(decimals) = TOKEN_DECIMALS_CACHE.lock().unwrap().get(&key) {
+        return Ok(*decimals);
+    }
+    let res = web3
+        .clone()
+        .eth_call(
+            CallRequest {
+                to: Some(token_address),
+                data: Some(Bytes(encode_erc20_decimals().map_err(err_from!())?)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(err_from!())?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid decimals response: {:?}. Probably not a valid ERC20 contract {:#x}",
+            res.0,
+            token_address
+        ));
+    }
+    let decimals = U256::from_big_endian(&res.0);
+    if decimals > U256::from(18) {
+        return Err(err_custom_create!(
+            "Token {:#x} has unsupported number of decimals: {}",
+            token_address,
+            decimals
+        ));
+    }
+    let decimals = decimals.as_u32() as u8;
+    log::debug!("Token {:#x} has {} decimals", token_address, decimals);
+    set_token_decimals_cache(web3.chain_id, token_address, decimals);
+    Ok(decimals)
+}
+
+/// Get token that is locked in deposits of the lock contract
+pub async fn get_lock_contract_token(
+    web3: Arc<Web3RpcPool>,
+    lock_contract_address: Address,
+) -> Result<Address, PaymentError> {
+    let res = web3
+        .eth_call(
+            CallRequest {
+                to: Some(lock_contract_address),
+                data: Some(Bytes(encode_get_lock_token().map_err(err_from!())?)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(err_from!())?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid token response: {:?}. Probably not a valid lock contract {:#x}",
+            res.0,
+            lock_contract_address
+        ));
+    }
+    Ok(Address::from_slice(&res.0[12..]))
+}
+
+/// Get EIP-712 domain separator of token contract supporting meta transactions
+pub async fn get_domain_separator(
+    web3: Arc<Web3RpcPool>,
+    token_address: Address,
+) -> Result<H256, PaymentError> {
+    let res = web3
+        .eth_call(
+            CallRequest {
+                to: Some(token_address),
+                data: Some(Bytes(encode_get_domain_separator().map_err(err_from!())?)),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(err_from!())?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid domain separator response: {:?}. Probably token {:#x} does not support meta transactions",
+            res.0,
+            token_address
+        ));
+    }
+    Ok(H256::from_slice(&res.0))
+}
+
+/// Get meta transaction nonce of the user from token contract
+pub async fn get_meta_transaction_nonce(
+    web3: Arc<Web3RpcPool>,
+    token_address: Address,
+    user: Address,
+) -> Result<U256, PaymentError> {
+    let res = web3
+        .eth_call(
+            CallRequest {
+                to: Some(token_address),
+                data: Some(Bytes(
+                    encode_get_meta_transaction_nonce(user).map_err(err_from!())?,
+                )),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(err_from!())?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid meta transaction nonce response: {:?}. Probably token {:#x} does not support meta transactions",
+            res.0,
+            token_address
+        ));
+    }
+    Ok(U256::from_big_endian(&res.0))
+}
+
 pub struct Web3BlockInfo {
     pub block_number: u64,
     pub block_date: chrono::DateTime<chrono::Utc>,
@@ -269,7 +698,7 @@ pub(crate) async fn get_transaction_count(
     Ok(nonce.as_u64())
 }
 
-pub(crate) fn get_eth_addr_from_secret(secret_key: &SecretKey) -> Address {
+pub fn get_eth_addr_from_secret(secret_key: &SecretKey) -> Address {
     Address::from_slice(
         &Keccak256::digest(
             &PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), secret_key)
@@ -322,10 +751,55 @@ pub async fn check_allowance(
     Ok(allowance)
 }
 
+/// Same as check_allowance for many (owner, token, spender) at once
+pub async fn get_allowances(
+    web3: Arc<Web3RpcPool>,
+    multicall_address: Option<Address>,
+    allowances: &[(Address, Address, Address)],
+) -> Result<Vec<U256>, PaymentError> {
+    let block_number = web3
+        .clone()
+        .eth_block_number()
+        .await
+        .map_err(err_from!())?
+        .as_u64();
+    let mut calls = Vec::with_capacity(allowances.len());
+    for (owner, token, spender) in allowances {
+        calls.push((
+            *token,
+            encode_erc20_allowance(*owner, *spender).map_err(err_from!())?,
+        ));
+    }
+    let results = eth_call_many(
+        web3,
+        multicall_address,
+        calls,
+        BlockNumber::Number(block_number.into()),
+    )
+    .await?;
+    let mut res = Vec::with_capacity(results.len());
+    for ((owner, token, _spender), allowance) in allowances.iter().zip(results) {
+        match allowance {
+            Some(allowance) if allowance.len() == 32 => res.push(U256::from_big_endian(&allowance)),
+            allowance => {
+                return Err(err_custom_create!(
+                    "Invalid response from ERC20 allowance check of owner {:#x}, token {:#x}: {:?}",
+                    owner,
+                    token,
+                    allowance
+                ))
+            }
+        }
+    }
+    Ok(res)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
 
     #[test]
     fn test_get_eth_addr_from_secret() {
@@ -335,4 +809,286 @@ mod tests {
         let addr = format!("{:#x}", get_eth_addr_from_secret(&sk));
         assert_eq!(addr, "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf");
     }
+
+    #[test]
+    fn test_decode_multicall3_aggregate3() {
+        use web3::ethabi::Token;
+        let balance = U256::from(1234);
+        let mut balance_bytes = [0u8; 32];
+        balance.to_big_endian(&mut balance_bytes);
+        let data = ethabi::encode(&[Token::Array(vec![
+            Token::Tuple(vec![
+                Token::Bool(true),
+                Token::Bytes(balance_bytes.to_vec()),
+            ]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![])]),
+        ])]);
+        let results = decode_multicall3_aggregate3(&data).unwrap();
+        assert_eq!(results, vec![Some(balance_bytes.to_vec()), None]);
+
+        // contract not deployed - empty response
+        assert!(decode_multicall3_aggregate3(&[]).is_err());
+
+        let call_data = encode_multicall3_aggregate3(&[(
+            Address::zero(),
+            encode_erc20_balance_of(Address::zero()).unwrap(),
+        )])
+        .unwrap();
+        // aggregate3((address,bool,bytes)[])
+        assert_eq!(call_data[0..4], [0x82, 0xad, 0x56, 0xcb]);
+    }
+
+    type FakeAnswer = fn(Address, &[u8]) -> Option<Vec<u8>>;
+
+    fn fake_rpc_response(
+        request: &serde_json::Value,
+        multicall_address: Address,
+        answer: FakeAnswer,
+        eth_calls: &AtomicUsize,
+    ) -> serde_json::Value {
+        let result = match request["method"].as_str().unwrap_or_default() {
+            "eth_blockNumber" => Ok("0x64".to_string()),
+            "eth_call" => {
+                eth_calls.fetch_add(1, Ordering::SeqCst);
+                let to = Address::from_str(request["params"][0]["to"].as_str().unwrap()).unwrap();
+                let data = hex::decode(
+                    request["params"][0]["data"]
+                        .as_str()
+                        .unwrap()
+                        .trim_start_matches("0x"),
+                )
+                .unwrap();
+                if to == multicall_address {
+                    let calls = ethabi::decode(
+                        &[ethabi::ParamType::Array(Box::new(
+                            ethabi::ParamType::Tuple(vec![
+                                ethabi::ParamType::Address,
+                                ethabi::ParamType::Bool,
+                                ethabi::ParamType::Bytes,
+                            ]),
+                        ))],
+                        &data[4..],
+                    )
+                    .unwrap()
+                    .remove(0)
+                    .into_array()
+                    .unwrap();
+                    let results = calls
+                        .into_iter()
+                        .map(|call| {
+                            let call = call.into_tuple().unwrap();
+                            let res = answer(
+                                call[0].clone().into_address().unwrap(),
+                                &call[2].clone().into_bytes().unwrap(),
+                            );
+                            ethabi::Token::Tuple(vec![
+                                ethabi::Token::Bool(res.is_some()),
+                                ethabi::Token::Bytes(res.unwrap_or_default()),
+                            ])
+                        })
+                        .collect();
+                    Ok(format!(
+                        "0x{}",
+                        hex::encode(ethabi::encode(&[ethabi::Token::Array(results)]))
+                    ))
+                } else {
+                    // contracts without code (not deployed multicall) return empty data
+                    answer(to, &data)
+                        .map(|res| format!("0x{}", hex::encode(res)))
+                        .ok_or("execution reverted")
+                }
+            }
+            _ => Err("method not found"),
+        };
+        match result {
+            Ok(result) => {
+                serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+            }
+            Err(message) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": 3, "message": message}
+            }),
+        }
+    }
+
+    /// JSON-RPC endpoint unpacking multicall requests sent to multicall_address,
+    /// returns pool connected to it and counter of eth_call requests
+    async fn start_fake_rpc(
+        multicall_address: Address,
+        answer: FakeAnswer,
+    ) -> (Arc<Web3RpcPool>, Arc<AtomicUsize>) {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+        use erc20_rpc_pool::{Web3EndpointParams, Web3RpcSingleParams, DEFAULT_MAX_BATCH_SIZE};
+
+        let eth_calls = Arc::new(AtomicUsize::new(0));
+        let eth_calls_ = web::Data::from(eth_calls.clone());
+        let server = HttpServer::new(move || {
+            App::new().app_data(eth_calls_.clone()).route(
+                    "/",
+                    web::post().to(
+                        move |body: web::Json<serde_json::Value>,
+                              eth_calls: web::Data<AtomicUsize>| async move {
+                            let response = match body.into_inner() {
+                                serde_json::Value::Array(requests) => serde_json::Value::Array(
+                                    requests
+                                        .iter()
+                                        .map(|r| {
+                                            fake_rpc_response(
+                                                r,
+                                                multicall_address,
+                                                answer,
+                                                &eth_calls,
+                                            )
+                                        })
+                                        .collect(),
+                                ),
+                                request => fake_rpc_response(
+                                    &request,
+                                    multicall_address,
+                                    answer,
+                                    &eth_calls,
+                                ),
+                            };
+                            HttpResponse::Ok().json(response)
+                        },
+                    ),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        tokio::spawn(server.run());
+
+        let pool = Web3RpcPool::new(
+            1,
+            vec![Web3RpcSingleParams {
+                chain_id: 1,
+                name: "fake".to_string(),
+                endpoint: format!("http://127.0.0.1:{port}/"),
+                web3_endpoint_params: Web3EndpointParams {
+                    backup_level: 0,
+                    skip_validation: true,
+                    max_number_of_consecutive_errors: 5,
+                    verify_interval_secs: 120,
+                    min_interval_requests_ms: None,
+                    max_requests_per_second: None,
+                    daily_request_quota: None,
+                    max_head_behind_secs: None,
+                    max_response_time_ms: 5000,
+                    max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+                },
+                source_id: None,
+            }],
+            vec![],
+            vec![],
+            None,
+            Duration::from_secs(10),
+            Duration::from_secs(300),
+        );
+        (pool, eth_calls)
+    }
+
+    fn u256_bytes(value: U256) -> Vec<u8> {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        bytes.to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_get_allowances_multicall() {
+        let multicall = Address::from_low_u64_be(0xca11);
+        let (pool, eth_calls) = start_fake_rpc(multicall, |to, data| {
+            // allowance(address,address)
+            (data[0..4] == [0xdd, 0x62, 0xed, 0x3e] && to.to_low_u64_be() < 3)
+                .then(|| u256_bytes(U256::from(to.to_low_u64_be() * 1000)))
+        })
+        .await;
+        let owner = Address::from_low_u64_be(0x100);
+        let spender = Address::from_low_u64_be(0x200);
+        let requests = [
+            (owner, Address::from_low_u64_be(1), spender),
+            (owner, Address::from_low_u64_be(2), spender),
+        ];
+
+        let allowances = get_allowances(pool.clone(), Some(multicall), &requests)
+            .await
+            .unwrap();
+        assert_eq!(allowances, vec![U256::from(1000), U256::from(2000)]);
+        assert_eq!(eth_calls.load(Ordering::SeqCst), 1);
+
+        // multicall contract not deployed, falls back to batch of single calls
+        let allowances = get_allowances(
+            pool.clone(),
+            Some(Address::from_low_u64_be(0xdead)),
+            &requests,
+        )
+        .await
+        .unwrap();
+        assert_eq!(allowances, vec![U256::from(1000), U256::from(2000)]);
+        assert_eq!(eth_calls.load(Ordering::SeqCst), 4);
+
+        // reverted allowance call is an error
+        let requests = [(owner, Address::from_low_u64_be(5), spender)];
+        assert!(get_allowances(pool, Some(multicall), &requests)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_deposits_details_multicall() {
+        let multicall = Address::from_low_u64_be(0xca11);
+        let lock_contract = Address::from_low_u64_be(0x10c);
+        let (pool, eth_calls) = start_fake_rpc(multicall, |to, data| {
+            if to != Address::from_low_u64_be(0x10c) || data.len() != 36 {
+                return None;
+            }
+            let id = U256::from_big_endian(&data[4..36]);
+            Some(ethabi::encode(&[
+                ethabi::Token::Uint(id),
+                ethabi::Token::Uint(U256::from(nonce_from_deposit_id(id))),
+                ethabi::Token::Address(Address::from_low_u64_be(0x100)),
+                ethabi::Token::Address(Address::from_low_u64_be(0x200)),
+                ethabi::Token::Uint(U256::from(nonce_from_deposit_id(id)) * U256::exp10(18)),
+                ethabi::Token::Uint(U256::exp10(17)),
+                ethabi::Token::Uint(U256::from(1_700_000_000)),
+            ]))
+        })
+        .await;
+        let funder = Address::from_low_u64_be(0x100);
+        let deposit_ids = [
+            deposit_id_from_nonce(funder, 1),
+            deposit_id_from_nonce(funder, 2),
+        ];
+
+        let details = get_deposits_details(
+            pool.clone(),
+            Some(multicall),
+            &deposit_ids,
+            lock_contract,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(eth_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(details.len(), 2);
+        assert_eq!(details[1].deposit_nonce, 2);
+        assert_eq!(details[1].spender, Address::from_low_u64_be(0x200));
+        assert_eq!(details[0].current_block, 100);
+
+        let details = get_deposit_details(pool.clone(), deposit_ids[0], lock_contract, Some(50))
+            .await
+            .unwrap();
+        assert_eq!(details.deposit_nonce, 1);
+        assert_eq!(details.funder, funder);
+        assert_eq!(details.current_block, 50);
+
+        // deposit of another contract reverts
+        assert!(
+            get_deposit_details(pool, deposit_ids[0], Address::from_low_u64_be(0xbad), None)
+                .await
+                .is_err()
+        );
+    }
 }
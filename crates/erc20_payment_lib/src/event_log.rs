@@ -0,0 +1,207 @@
+use crate::error::PaymentError;
+use crate::error::*;
+use crate::{err_custom_create, err_from};
+use chrono::Utc;
+use erc20_payment_lib_common::api::StoredEvent;
+use erc20_payment_lib_common::model::EventDbObj;
+use erc20_payment_lib_common::ops::{
+    delete_events_older_than, get_events_after, get_last_event_id, insert_event,
+};
+use erc20_payment_lib_common::{DbPool, DriverEvent};
+use futures_util::{Stream, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Every driver event is stored with increasing id, so consumers can continue after the last one they have seen
+#[derive(Clone)]
+pub struct EventLog {
+    conn: DbPool,
+    last_id: Arc<watch::Sender<i64>>,
+}
+
+impl EventLog {
+    pub async fn new(conn: DbPool) -> Result<Self, PaymentError> {
+        let last_id = get_last_event_id(&conn).await.map_err(err_from!())?;
+        Ok(Self {
+            conn,
+            last_id: Arc::new(watch::channel(last_id).0),
+        })
+    }
+
+    pub async fn append(&self, event: &DriverEvent) -> Result<i64, PaymentError> {
+        let payload = serde_json::to_string(event)
+            .map_err(|err| err_custom_create!("Cannot serialize event: {}", err))?;
+        let stored = insert_event(
+            &self.conn,
+            &EventDbObj {
+                id: 0,
+                event_type: event.content.event_type().to_string(),
+                create_date: event.create_date,
+                payload,
+            },
+        )
+        .await
+        .map_err(err_from!())?;
+        self.last_id.send_replace(stored.id);
+        Ok(stored.id)
+    }
+
+    pub fn last_id(&self) -> i64 {
+        *self.last_id.borrow()
+    }
+
+    /// Returns events after given id and id of the last row read.
+    /// Rows with invalid payload are logged and skipped.
+    pub async fn events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<(Vec<StoredEvent>, i64), PaymentError> {
+        let rows = get_events_after(&self.conn, after_id, limit)
+            .await
+            .map_err(err_from!())?;
+        let last_id = rows.last().map(|e| e.id).unwrap_or(after_id);
+        let events = rows
+            .into_iter()
+            .filter_map(|row| match StoredEvent::try_from(row) {
+                Ok(event) => Some(event),
+                Err(err) => {
+                    log::error!("Skipping stored event: {}", err);
+                    None
+                }
+            })
+            .collect();
+        Ok((events, last_id))
+    }
+
+    /// Returns events after given id, waits up to timeout for new ones if there are none yet
+    pub async fn wait_for_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+        timeout: Duration,
+    ) -> Result<(Vec<StoredEvent>, i64), PaymentError> {
+        let mut rx = self.last_id.subscribe();
+        let _ = tokio::time::timeout(timeout, rx.wait_for(|last_id| *last_id > after_id)).await;
+        self.events_after(after_id, limit).await
+    }
+
+    /// Stored events after given id followed by new ones as they come
+    pub fn stream_after(&self, after_id: i64) -> impl Stream<Item = StoredEvent> {
+        let event_log = self.clone();
+        futures_util::stream::unfold(after_id, move |after_id| {
+            let event_log = event_log.clone();
+            async move {
+                let mut after_id = after_id;
+                loop {
+                    match event_log
+                        .wait_for_events_after(after_id, 100, Duration::from_secs(60))
+                        .await
+                    {
+                        Ok((events, last_id)) if !events.is_empty() => {
+                            return Some((futures_util::stream::iter(events), last_id));
+                        }
+                        Ok((_, last_id)) => after_id = last_id,
+                        Err(err) => {
+                            log::error!("Error reading event log: {}", err);
+                            tokio::time::sleep(Duration::from_secs(5)).await;
+                        }
+                    }
+                }
+            }
+        })
+        .flatten()
+    }
+
+    /// Deletes events older than retention every hour, runs until the task is aborted
+    pub async fn prune_loop(self, retention: chrono::Duration) {
+        loop {
+            match delete_events_older_than(&self.conn, Utc::now() - retention).await {
+                Ok(0) => {}
+                Ok(deleted) => log::debug!("Deleted {} old events", deleted),
+                Err(err) => log::warn!("Error deleting old events: {}", err),
+            }
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use erc20_payment_lib_common::DriverEventContent;
+
+    #[tokio::test]
+    async fn test_event_log_resume() {
+        use erc20_payment_lib_common::create_sqlite_connection;
+        let conn = create_sqlite_connection(None, None, false, true)
+            .await
+            .unwrap();
+        let event_log = EventLog::new(conn.clone()).await.unwrap();
+
+        let first = event_log
+            .append(&DriverEvent::now(DriverEventContent::Alive))
+            .await
+            .unwrap();
+        let second = event_log
+            .append(&DriverEvent::now(DriverEventContent::StatusChanged(vec![])))
+            .await
+            .unwrap();
+        assert_eq!(event_log.last_id(), second);
+
+        let (events, last_id) = event_log.events_after(first, 100).await.unwrap();
+        assert_eq!(last_id, second);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, second);
+        assert_eq!(events[0].event_type, "StatusChanged");
+        assert_eq!(
+            events[0].event["content"]["statusChanged"],
+            serde_json::json!([])
+        );
+
+        //nothing new, returns after timeout
+        assert!(event_log
+            .wait_for_events_after(second, 100, Duration::from_millis(10))
+            .await
+            .unwrap()
+            .0
+            .is_empty());
+
+        let mut stream = Box::pin(event_log.stream_after(first));
+        assert_eq!(stream.next().await.unwrap().id, second);
+        let event_log_ = event_log.clone();
+        let third = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            event_log_
+                .append(&DriverEvent::now(DriverEventContent::Alive))
+                .await
+                .unwrap()
+        });
+        assert_eq!(stream.next().await.unwrap().id, third.await.unwrap());
+
+        //rows with broken payload are skipped, but readers move past them
+        let broken = insert_event(
+            &conn,
+            &EventDbObj {
+                id: 0,
+                event_type: "Alive".to_string(),
+                create_date: Utc::now(),
+                payload: "null".to_string(),
+            },
+        )
+        .await
+        .unwrap()
+        .id;
+        let fourth = event_log
+            .append(&DriverEvent::now(DriverEventContent::Alive))
+            .await
+            .unwrap();
+        let (events, last_id) = event_log.events_after(fourth - 2, 1).await.unwrap();
+        assert!(events.is_empty());
+        assert_eq!(last_id, broken);
+        assert_eq!(stream.next().await.unwrap().id, fourth);
+    }
+}
@@ -1,10 +1,13 @@
 mod account_balance;
 pub mod config;
 mod contracts;
+pub mod eip712;
 pub mod eth;
+pub mod event_log;
 pub mod faucet_client;
 pub mod misc;
 mod multi;
+mod rpc_stats;
 pub mod runtime;
 mod sender;
 pub mod server;
@@ -12,6 +15,7 @@ pub mod service;
 pub mod setup;
 pub mod signer;
 pub mod transaction;
+pub mod webhook;
 
 pub use contracts::DUMMY_RPC_PROVIDER;
 use erc20_payment_lib_common::*;
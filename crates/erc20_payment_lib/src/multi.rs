@@ -7,12 +7,17 @@ pub(crate) fn pack_transfers_for_multi_contract(
     receivers: Vec<Address>,
     amounts: Vec<U256>,
 ) -> Result<(Vec<[u8; 32]>, U256), PaymentError> {
+    //amount is packed into 96 bits of raw token units, whatever the token decimals are
+    //(7.9 billions tokens for 18 decimals, 7.9 * 10^16 tokens for 6 decimals)
     let max_value = U256::from(2).pow(U256::from(96));
-    //Assuming 18 decimals it is sufficient up to: 7.9 billions tokens
     let mut sum = U256::from(0);
     for amount in &amounts {
-        if amount > &max_value {
-            return Err(err_custom_create!("Amount is too big to use packed error",));
+        if amount >= &max_value {
+            return Err(err_custom_create!(
+                "Amount {} is too big to use packed transfer, max allowed is {}",
+                amount,
+                max_value - 1
+            ));
         }
         sum += *amount;
     }
@@ -0,0 +1,97 @@
+use crate::err_from;
+use crate::error::PaymentError;
+use crate::error::*;
+use crate::setup::PaymentSetup;
+use erc20_payment_lib_common::model::RpcEndpointStatsDbObj;
+use erc20_payment_lib_common::ops::{get_rpc_endpoint_stats, upsert_rpc_endpoint_stats};
+use erc20_payment_lib_common::DbPool;
+use erc20_rpc_pool::{Web3RpcInfo, Web3RpcPool};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Loads endpoint stats saved by the previous run into the pools
+pub async fn restore_rpc_endpoint_stats(
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+) -> Result<(), PaymentError> {
+    if payment_setup.rpc_stats.save_interval == 0 {
+        return Ok(());
+    }
+    let half_life = chrono::Duration::try_seconds(payment_setup.rpc_stats.penalty_half_life as i64)
+        .unwrap_or_default();
+    for (chain_id, chain_setup) in &payment_setup.chain_setup {
+        let saved = get_rpc_endpoint_stats(conn, *chain_id)
+            .await
+            .map_err(err_from!())?;
+        let mut restored = 0;
+        for stats in saved {
+            let info = match serde_json::from_str::<Web3RpcInfo>(&stats.info) {
+                Ok(info) => info,
+                Err(err) => {
+                    log::warn!("Skipping saved stats of endpoint {}: {}", stats.name, err);
+                    continue;
+                }
+            };
+            if chain_setup.provider.restore_endpoint_info(
+                &stats.endpoint,
+                info,
+                stats.updated_date,
+                half_life,
+            ) {
+                restored += 1;
+            }
+        }
+        if restored > 0 {
+            log::info!(
+                "Restored stats of {} RPC endpoints for chain {}",
+                restored,
+                chain_id
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn save_rpc_endpoint_stats(
+    conn: &DbPool,
+    chain_id: i64,
+    pool: &Web3RpcPool,
+) -> Result<(), PaymentError> {
+    let now = chrono::Utc::now();
+    for (_idx, params, info) in pool.get_endpoints_info() {
+        // endpoints from external sources come and go, only verified ones are worth keeping
+        if info.removed_date.is_some() || info.last_verified.is_none() {
+            continue;
+        }
+        let stats = RpcEndpointStatsDbObj {
+            chain_id,
+            endpoint: params.endpoint,
+            name: params.name,
+            info: serde_json::to_string(&info).unwrap_or_default(),
+            updated_date: now,
+        };
+        upsert_rpc_endpoint_stats(conn, &stats)
+            .await
+            .map_err(err_from!())?;
+    }
+    Ok(())
+}
+
+pub async fn save_rpc_endpoint_stats_loop(
+    conn: DbPool,
+    pools: Vec<(i64, Arc<Web3RpcPool>)>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        for (chain_id, pool) in &pools {
+            if let Err(err) = save_rpc_endpoint_stats(&conn, *chain_id, pool).await {
+                log::warn!(
+                    "Failed to save RPC endpoint stats for chain {}: {}",
+                    chain_id,
+                    err
+                );
+            }
+        }
+    }
+}
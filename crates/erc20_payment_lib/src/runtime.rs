@@ -1,15 +1,16 @@
-use crate::signer::{Signer, SignerAccount};
+use crate::signer::{RemoteSigner, Signer, SignerAccount};
 use crate::transaction::{
-    create_create_deposit, create_faucet_mint, create_terminate_deposit, create_token_transfer,
-    find_receipt_extended, FindReceiptParseResult,
+    create_create_deposit, create_extend_deposit, create_faucet_mint, create_terminate_deposit,
+    create_token_transfer, find_receipt_extended, FindReceiptParseResult,
 };
-use crate::{err_custom_create, err_from};
-use erc20_payment_lib_common::create_sqlite_connection;
+use crate::{err_create, err_custom_create, err_from};
+use erc20_payment_lib_common::create_db_connection;
 use erc20_payment_lib_common::ops::{
-    cleanup_allowance_tx, cleanup_token_transfer_tx, delete_tx, get_last_unsent_tx,
+    amend_token_transfer, cancel_token_transfer, cleanup_allowance_tx, cleanup_token_transfer_tx,
+    delete_tx, get_last_unsent_tx, get_token_transfer_by_payment_id,
     get_token_transfers_by_deposit_id, get_transaction_chain, get_transactions,
     get_unpaid_token_transfers, insert_token_transfer, insert_token_transfer_with_deposit_check,
-    insert_tx, update_token_transfer,
+    insert_token_transfers_with_deposit_check, insert_tx, update_token_transfer,
 };
 use std::collections::BTreeMap;
 use std::ops::DerefMut;
@@ -21,19 +22,25 @@ use crate::error::{ErrorBag, PaymentError};
 use crate::setup::{ChainSetup, ExtraOptionsForTesting, PaymentSetup};
 
 use crate::config::{self, Config};
+use erc20_payment_lib_common::DbPool;
 use secp256k1::SecretKey;
-use sqlx::SqlitePool;
 
 use crate::account_balance::{test_balance_loop, BalanceOptions2};
 use crate::config::AdditionalOptions;
-use crate::contracts::CreateDepositArgs;
+use crate::contracts::{CreateDepositArgs, ExtendDepositArgs};
 use crate::eth::{
-    get_eth_addr_from_secret, get_latest_block_info, nonce_from_deposit_id, DepositDetails,
+    get_eth_addr_from_secret, get_latest_block_info, get_lock_contract_token, get_token_decimals,
+    nonce_from_deposit_id, DepositDetails,
 };
+use crate::event_log::EventLog;
+use crate::rpc_stats::{restore_rpc_endpoint_stats, save_rpc_endpoint_stats_loop};
 use crate::sender::service_loop;
 use crate::utils::{DecimalConvExt, StringConvExt, U256ConvExt};
+use crate::webhook::WebhookOutbox;
 use chrono::{DateTime, Utc};
-use erc20_payment_lib_common::model::TokenTransferDbObj;
+use erc20_payment_lib_common::model::{
+    check_transfer_priority, TokenTransferDbObj, TokenTransferStatus,
+};
 use erc20_payment_lib_common::{
     DriverEvent, DriverEventContent, FaucetData, SharedInfoTx, StatusProperty,
     TransactionStuckReason, Web3RpcPoolContent,
@@ -188,6 +195,30 @@ impl StatusTracker {
                 (TxStuck { chain_id: id1 }, TxStuck { chain_id: id2 }) if id1 == id2 => {
                     return false;
                 }
+
+                (
+                    FeeBudgetExhausted {
+                        chain_id: id1,
+                        address: addr1,
+                        period: period1,
+                        budget: old_budget,
+                        spent: old_spent,
+                    },
+                    FeeBudgetExhausted {
+                        chain_id: id2,
+                        address: addr2,
+                        period: period2,
+                        budget: new_budget,
+                        spent: new_spent,
+                    },
+                ) if id1 == id2 && addr1 == addr2 && period1 == period2 => {
+                    if old_budget == new_budget && old_spent == new_spent {
+                        return false;
+                    }
+                    *old_budget = *new_budget;
+                    *old_spent = *new_spent;
+                    return true;
+                }
                 _ => {}
             }
         }
@@ -210,6 +241,9 @@ impl StatusTracker {
             StatusProperty::NoToken { chain_id, .. } if *chain_id == ok_chain_id => false,
             StatusProperty::TxStuck { chain_id, .. } if *chain_id == ok_chain_id => false,
             StatusProperty::Web3RpcError { chain_id, .. } if *chain_id == ok_chain_id => false,
+            StatusProperty::FeeBudgetExhausted { chain_id, .. } if *chain_id == ok_chain_id => {
+                false
+            }
             _ => true,
         });
 
@@ -220,6 +254,8 @@ impl StatusTracker {
         mut broadcast_sender: Option<broadcast::Sender<DriverEvent>>,
         mut mpsc_sender: Option<mpsc::Sender<DriverEvent>>,
         mut status_rx: mpsc::Receiver<DriverEvent>,
+        event_log: EventLog,
+        webhook_outbox: Option<WebhookOutbox>,
     ) -> Self {
         let status = Arc::new(Mutex::new(Vec::new()));
         let status_ = Arc::clone(&status);
@@ -263,6 +299,18 @@ impl StatusTracker {
                             },
                         )
                     }
+                    DriverEventContent::TransactionStuck(
+                        TransactionStuckReason::FeeBudgetExceeded(details),
+                    ) => Self::update(
+                        status.lock().await.deref_mut(),
+                        StatusProperty::FeeBudgetExhausted {
+                            chain_id: details.tx.chain_id,
+                            address: details.tx.from_addr.clone(),
+                            period: details.period.clone(),
+                            budget: details.budget,
+                            spent: details.spent,
+                        },
+                    ),
                     DriverEventContent::TransactionStuck(TransactionStuckReason::GasPriceLow(
                         details,
                     )) => Self::update(
@@ -305,30 +353,43 @@ impl StatusTracker {
                 };
 
                 if pass_raw_message {
-                    if let Some(sender) = &mut mpsc_sender {
-                        if let Err(err) = sender.send(ev.clone()).await {
-                            log::warn!("Error resending driver event: {}", err);
+                    //the same snapshot goes to every consumer
+                    let status_changed = if emit_changed {
+                        Some(DriverEvent::now(DriverEventContent::StatusChanged(
+                            status.lock().await.clone(),
+                        )))
+                    } else {
+                        None
+                    };
+                    let events = std::iter::once(ev)
+                        .chain(status_changed)
+                        .collect::<Vec<_>>();
+
+                    for event in &events {
+                        if let Err(err) = event_log.append(event).await {
+                            log::error!("Error storing driver event: {}", err);
                         }
-                        if emit_changed {
-                            if let Err(err) = sender
-                                .send(DriverEvent::now(DriverEventContent::StatusChanged(
-                                    status.lock().await.clone(),
-                                )))
-                                .await
-                            {
-                                log::warn!("Error resending driver status changed event: {}", err);
+                    }
+
+                    if let Some(outbox) = &webhook_outbox {
+                        for event in &events {
+                            if let Err(err) = outbox.enqueue(event).await {
+                                log::error!("Error storing webhook delivery: {}", err);
                             }
                         }
                     }
 
-                    if let Some(sender) = &mut broadcast_sender {
-                        if let Err(_err) = sender.send(ev) {
-                            //channel closed - it's normal
+                    if let Some(sender) = &mut mpsc_sender {
+                        for event in &events {
+                            if let Err(err) = sender.send(event.clone()).await {
+                                log::warn!("Error resending driver event: {}", err);
+                            }
                         }
-                        if emit_changed {
-                            if let Err(_err) = sender.send(DriverEvent::now(
-                                DriverEventContent::StatusChanged(status.lock().await.clone()),
-                            )) {
+                    }
+
+                    if let Some(sender) = &mut broadcast_sender {
+                        for event in events {
+                            if let Err(_err) = sender.send(event) {
                                 //channel closed - it's normal
                             }
                         }
@@ -360,16 +421,19 @@ pub struct PaymentRuntime {
     pub driver_broadcast_sender: Option<broadcast::Sender<DriverEvent>>,
     pub driver_mpsc_sender: Option<mpsc::Sender<DriverEvent>>,
     pub raw_event_sender: mpsc::Sender<DriverEvent>,
-    conn: SqlitePool,
+    pub event_log: EventLog,
+    conn: DbPool,
     status_tracker: StatusTracker,
-    config: Config,
+    /// Loops not bound to any account, aborted when runtime is stopped or dropped
+    background_tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
+    pub(crate) config: Config,
 }
 
 pub struct PaymentRuntimeArgs {
     pub secret_keys: Vec<SecretKey>,
     pub db_filename: PathBuf,
     pub config: config::Config,
-    pub conn: Option<SqlitePool>,
+    pub conn: Option<DbPool>,
     pub options: Option<AdditionalOptions>,
     pub broadcast_sender: Option<broadcast::Sender<DriverEvent>>,
     pub mspc_sender: Option<mpsc::Sender<DriverEvent>>,
@@ -382,10 +446,14 @@ pub struct TransferArgs {
     pub from: Address,
     pub receiver: Address,
     pub tx_type: TransferType,
+    /// Token used for TransferType::Token, default token of the chain if not set
+    pub token: Option<Address>,
     pub amount: U256,
     pub payment_id: String,
     pub deadline: Option<DateTime<Utc>>,
     pub deposit_id: Option<String>,
+    /// Higher is sent first, transfers with priority below zero can be held to build bigger batches
+    pub priority: i64,
 }
 
 impl PaymentRuntime {
@@ -487,28 +555,81 @@ impl PaymentRuntime {
             conn
         } else {
             log::info!(
-                "connecting to sqlite file db: {}",
+                "connecting to db: {}",
                 payment_runtime_args.db_filename.display()
             );
-            create_sqlite_connection(Some(&payment_runtime_args.db_filename), None, false, true)
-                .await?
+            create_db_connection(
+                &payment_runtime_args.db_filename.to_string_lossy(),
+                false,
+                true,
+            )
+            .await?
         };
 
+        let mut background_tasks = Vec::new();
+        restore_rpc_endpoint_stats(&conn, &payment_setup).await?;
+        if payment_setup.rpc_stats.save_interval > 0 {
+            let pools = payment_setup
+                .chain_setup
+                .iter()
+                .map(|(chain_id, chain_setup)| (*chain_id, chain_setup.provider.clone()))
+                .collect();
+            background_tasks.push(tokio::spawn(save_rpc_endpoint_stats_loop(
+                conn.clone(),
+                pools,
+                Duration::from_secs(payment_setup.rpc_stats.save_interval),
+            )));
+        }
+
         let driver_broadcast_sender = payment_runtime_args.broadcast_sender.clone();
         let driver_mpsc_sender = payment_runtime_args.mspc_sender.clone();
 
+        let webhook_outbox =
+            WebhookOutbox::new(conn.clone(), payment_runtime_args.config.webhook.clone());
+        if let Some(webhook_outbox) = &webhook_outbox {
+            background_tasks.push(tokio::spawn(webhook_outbox.clone().delivery_loop()));
+        }
+
+        let event_log = EventLog::new(conn.clone()).await?;
+        let event_log_retention = payment_runtime_args.config.engine.event_log.retention;
+        if event_log_retention > 0 {
+            background_tasks.push(tokio::spawn(event_log.clone().prune_loop(
+                chrono::Duration::try_seconds(event_log_retention as i64).unwrap_or_default(),
+            )));
+        }
+
         let status_tracker = StatusTracker::new(
             payment_runtime_args.broadcast_sender,
             payment_runtime_args.mspc_sender,
             status_rx,
+            event_log.clone(),
+            webhook_outbox,
         );
 
-        let accounts = payment_runtime_args
+        let mut accounts = payment_runtime_args
             .secret_keys
             .iter()
             .map(|s| SignerAccount::new(get_eth_addr_from_secret(s), signer.clone()))
             .collect::<Vec<SignerAccount>>();
 
+        for (name, remote_signer_settings) in &payment_runtime_args.config.remote_signer {
+            let remote_signer: Arc<Box<dyn Signer + Send + Sync>> = Arc::new(Box::new(
+                RemoteSigner::new(&remote_signer_settings.url).map_err(|err| {
+                    err_custom_create!("Remote signer {} setup failed: {}", name, err.message)
+                })?,
+            ));
+            for address in &remote_signer_settings.accounts {
+                log::info!(
+                    "Account {:#x} is signed by remote signer {} ({})",
+                    address,
+                    name,
+                    remote_signer_settings.url
+                );
+                accounts.retain(|account| account.address != *address);
+                accounts.push(SignerAccount::new(*address, remote_signer.clone()));
+            }
+        }
+
         let shared_state = Arc::new(std::sync::Mutex::new(SharedState {
             accounts: vec![],
             inserted: 0,
@@ -527,9 +648,11 @@ impl PaymentRuntime {
             wake: notify.clone(),
             conn,
             status_tracker,
+            background_tasks: std::sync::Mutex::new(background_tasks),
             driver_broadcast_sender,
             driver_mpsc_sender,
             raw_event_sender,
+            event_log,
             config: payment_runtime_args.config,
         };
 
@@ -615,6 +738,13 @@ impl PaymentRuntime {
         for handle in handles {
             handle.abort();
         }
+        self.abort_background_tasks();
+    }
+
+    fn abort_background_tasks(&self) {
+        for handle in self.background_tasks.lock().unwrap().drain(..) {
+            handle.abort();
+        }
     }
 
     pub fn add_account(
@@ -900,11 +1030,12 @@ impl PaymentRuntime {
         }
     }
 
-    pub async fn transfer_with_account(
+    fn create_transfer(
         &self,
-        account: &SignerAccount,
-        transfer_args: TransferArgs,
-    ) -> Result<(), PaymentError> {
+        transfer_args: &TransferArgs,
+    ) -> Result<TokenTransferDbObj, PaymentError> {
+        check_transfer_priority(transfer_args.priority)
+            .map_err(|err| err_custom_create!("{err}"))?;
         let chain_cfg = self
             .config
             .chain
@@ -914,43 +1045,94 @@ impl PaymentRuntime {
                 transfer_args.network
             ))?;
 
-        let token_addr = match transfer_args.tx_type {
-            TransferType::Token => {
-                let address = chain_cfg.token.address;
-                Some(address)
-            }
-            TransferType::Gas => None,
-        };
+        let token_addr =
+            match transfer_args.tx_type {
+                TransferType::Token => {
+                    let chain_setup = self.setup.chain_setup.get(&chain_cfg.chain_id).ok_or(
+                        err_custom_create!("Chain {} not found in setup", chain_cfg.chain_id),
+                    )?;
+                    let address = transfer_args.token.unwrap_or(chain_setup.glm_address);
+                    if chain_setup.get_token_by_address(address).is_none() {
+                        return Err(err_custom_create!(
+                            "Token {:#x} is not configured for chain {}",
+                            address,
+                            transfer_args.network
+                        ));
+                    }
+                    Some(address)
+                }
+                TransferType::Gas => None,
+            };
 
-        let token_transfer = create_token_transfer(
+        let mut token_transfer = create_token_transfer(
             transfer_args.from,
             transfer_args.receiver,
             chain_cfg.chain_id,
             Some(&transfer_args.payment_id),
             token_addr,
             transfer_args.amount,
-            transfer_args.deposit_id,
+            transfer_args.deposit_id.clone(),
         );
+        token_transfer.priority = transfer_args.priority;
+        token_transfer.deadline = transfer_args.deadline;
+        Ok(token_transfer)
+    }
 
-        insert_token_transfer_with_deposit_check(&self.conn, &token_transfer).await?;
-
-        if !self.setup.ignore_deadlines {
-            if let Some(deadline) = transfer_args.deadline {
-                let mut ext_gath_time_guard = account.external_gather_time.lock().unwrap();
-                let new_time = ext_gath_time_guard
-                    .map(|t| t.min(deadline))
-                    .unwrap_or(deadline);
-
-                if Some(new_time) != *ext_gath_time_guard {
-                    *ext_gath_time_guard = Some(new_time);
-                    self.wake.notify_one();
-                }
+    fn schedule_gather(&self, account: &SignerAccount, transfer_args: &TransferArgs) {
+        let gather_time = if transfer_args.priority > 0 {
+            Some(Utc::now())
+        } else if self.setup.ignore_deadlines {
+            None
+        } else {
+            transfer_args.deadline.map(|deadline| {
+                deadline
+                    - chrono::Duration::try_seconds(
+                        self.setup.batching_policy.deadline_margin as i64,
+                    )
+                    .unwrap_or_default()
+            })
+        };
+        if let Some(gather_time) = gather_time {
+            let mut ext_gath_time_guard = account.external_gather_time.lock().unwrap();
+            let new_time = ext_gath_time_guard
+                .map(|t| t.min(gather_time))
+                .unwrap_or(gather_time);
+
+            if Some(new_time) != *ext_gath_time_guard {
+                *ext_gath_time_guard = Some(new_time);
+                self.wake.notify_one();
             }
         }
+    }
 
+    pub async fn transfer_with_account(
+        &self,
+        account: &SignerAccount,
+        transfer_args: TransferArgs,
+    ) -> Result<(), PaymentError> {
+        let token_transfer = self.create_transfer(&transfer_args)?;
+        insert_token_transfer_with_deposit_check(&self.conn, &token_transfer).await?;
+        self.schedule_gather(account, &transfer_args);
         Ok(())
     }
 
+    /// Inserts all transfers in one database transaction, nothing is inserted if any of them fails
+    pub async fn transfers_with_accounts(
+        &self,
+        transfers: &[(SignerAccount, TransferArgs)],
+    ) -> Result<Vec<TokenTransferDbObj>, PaymentError> {
+        let mut token_transfers = Vec::with_capacity(transfers.len());
+        for (_, transfer_args) in transfers {
+            token_transfers.push(self.create_transfer(transfer_args)?);
+        }
+        let inserted =
+            insert_token_transfers_with_deposit_check(&self.conn, &token_transfers).await?;
+        for (account, transfer_args) in transfers {
+            self.schedule_gather(account, transfer_args);
+        }
+        Ok(inserted)
+    }
+
     pub async fn mint_golem_token(
         &self,
         chain_name: &str,
@@ -977,6 +1159,44 @@ impl PaymentRuntime {
         res
     }
 
+    pub async fn extend_deposit(
+        &self,
+        chain_name: &str,
+        from: Address,
+        opt: ExtendDepositOptionsInt,
+    ) -> Result<(), PaymentError> {
+        let chain_cfg = self.config.chain.get(chain_name).ok_or(err_custom_create!(
+            "Chain {} not found in config file",
+            chain_name
+        ))?;
+        let web3 = self.setup.get_provider(chain_cfg.chain_id)?;
+
+        let res = extend_deposit(web3, &self.conn, chain_cfg.chain_id as u64, from, opt).await;
+        self.wake.notify_one();
+        res
+    }
+
+    pub async fn cancel_transfer(
+        &self,
+        chain_id: i64,
+        from: Address,
+        payment_id: &str,
+    ) -> Result<TokenTransferDbObj, PaymentError> {
+        cancel_transfer(&self.conn, chain_id, from, payment_id).await
+    }
+
+    pub async fn amend_transfer(
+        &self,
+        chain_id: i64,
+        from: Address,
+        payment_id: &str,
+        args: AmendTransferArgs,
+    ) -> Result<TokenTransferDbObj, PaymentError> {
+        let res = amend_transfer(&self.conn, chain_id, from, payment_id, args).await;
+        self.wake.notify_one();
+        res
+    }
+
     pub async fn get_status(&self) -> Vec<StatusProperty> {
         self.status_tracker.get_status().await
     }
@@ -1005,6 +1225,29 @@ impl PaymentRuntime {
             .get_chain(chain_id)
             .ok_or(err_custom_create!("Chain {} not found", chain_id))?
             .glm_address;
+        self.verify_token_transaction(chain_id, glm_address, tx_hash, sender, receiver, amount)
+            .await
+    }
+
+    pub async fn verify_token_transaction(
+        &self,
+        chain_id: i64,
+        token_address: Address,
+        tx_hash: H256,
+        sender: Address,
+        receiver: Address,
+        amount: U256,
+    ) -> Result<VerifyTransactionResult, PaymentError> {
+        let chain = self
+            .get_chain(chain_id)
+            .ok_or(err_custom_create!("Chain {} not found", chain_id))?;
+        if chain.get_token_by_address(token_address).is_none() {
+            return Err(err_custom_create!(
+                "Token {:#x} is not configured for chain {}",
+                token_address,
+                chain_id
+            ));
+        }
         let prov = self.setup.get_provider(chain_id)?;
         verify_transaction(
             prov,
@@ -1013,7 +1256,7 @@ impl PaymentRuntime {
             sender,
             receiver,
             amount,
-            glm_address,
+            token_address,
         )
         .await
     }
@@ -1023,6 +1266,12 @@ impl PaymentRuntime {
     }
 }
 
+impl Drop for PaymentRuntime {
+    fn drop(&mut self) {
+        self.abort_background_tasks();
+    }
+}
+
 pub enum VerifyTransactionResult {
     Verified { amount: U256 },
     Rejected(String),
@@ -1040,7 +1289,7 @@ impl VerifyTransactionResult {
 
 pub async fn mint_golem_token(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: u64,
     from: Address,
     glm_address: Address,
@@ -1086,10 +1335,10 @@ pub async fn mint_golem_token(
     }
 
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-    let filter = "method=\"FAUCET.create\" AND fee_paid is NULL";
+    let filter = "method='FAUCET.create' AND fee_paid is NULL";
 
     let tx_existing = get_transactions(
-        &mut *db_transaction,
+        &mut db_transaction,
         Some(from),
         Some(filter),
         None,
@@ -1107,7 +1356,7 @@ pub async fn mint_golem_token(
     }
 
     let faucet_mint_tx = create_faucet_mint(from, faucet_contract_address, chain_id, None)?;
-    let mint_tx = insert_tx(&mut *db_transaction, &faucet_mint_tx)
+    let mint_tx = insert_tx(&mut db_transaction, &faucet_mint_tx)
         .await
         .map_err(err_from!())?;
     db_transaction.commit().await.map_err(err_from!())?;
@@ -1138,6 +1387,16 @@ pub async fn deposit_details(
     Ok(result)
 }
 
+/// Token locked by the lock contract together with its decimals
+pub async fn deposit_token(
+    web3: Arc<Web3RpcPool>,
+    lock_contract_address: Address,
+) -> Result<(Address, u8), PaymentError> {
+    let token_address = get_lock_contract_token(web3.clone(), lock_contract_address).await?;
+    let decimals = get_token_decimals(web3, token_address).await?;
+    Ok((token_address, decimals))
+}
+
 pub struct CloseDepositOptionsInt {
     pub lock_contract_address: Address,
     pub skip_deposit_check: bool,
@@ -1153,7 +1412,7 @@ pub struct TerminateDepositOptionsInt {
 
 pub async fn close_deposit(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: u64,
     from: Address,
     opt: CloseDepositOptionsInt,
@@ -1179,7 +1438,7 @@ pub async fn close_deposit(
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
 
     let current_token_transfers = get_token_transfers_by_deposit_id(
-        &mut *db_transaction,
+        &mut db_transaction,
         chain_id as i64,
         &format!("{:#x}", opt.deposit_id),
     )
@@ -1212,14 +1471,15 @@ pub async fn close_deposit(
     if let Some(tt) = candidate_for_mark_close {
         let mut tt = tt.clone();
         tt.deposit_finish = 1;
-        update_token_transfer(&mut *db_transaction, &tt)
+        update_token_transfer(&mut db_transaction, &tt)
             .await
             .map_err(err_from!())?;
     } else {
         //empty transfer is just a marker that we need deposit to be closed
+        //it has no payment_id, so it cannot collide with payment ids given by the user
         let new_tt = TokenTransferDbObj {
             id: 0,
-            payment_id: Some(format!("close_deposit_{:#x}", opt.deposit_id)),
+            payment_id: None,
             from_addr: format!("{:#x}", from),
             receiver_addr: format!("{:#x}", Address::zero()),
             chain_id: chain_id as i64,
@@ -1232,8 +1492,11 @@ pub async fn close_deposit(
             paid_date: None,
             fee_paid: None,
             error: None,
+            status: TokenTransferStatus::Queued,
+            priority: 0,
+            deadline: None,
         };
-        insert_token_transfer(&mut *db_transaction, &new_tt)
+        insert_token_transfer(&mut db_transaction, &new_tt)
             .await
             .map_err(err_from!())?;
     }
@@ -1250,7 +1513,7 @@ pub async fn close_deposit(
 
 pub async fn terminate_deposit(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: u64,
     from: Address,
     opt: TerminateDepositOptionsInt,
@@ -1296,7 +1559,7 @@ pub async fn terminate_deposit(
     )?;
 
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-    let make_deposit_tx = insert_tx(&mut *db_transaction, &free_deposit_tx_id)
+    let make_deposit_tx = insert_tx(&mut db_transaction, &free_deposit_tx_id)
         .await
         .map_err(err_from!())?;
     db_transaction.commit().await.map_err(err_from!())?;
@@ -1305,6 +1568,117 @@ pub async fn terminate_deposit(
     Ok(())
 }
 
+pub struct ExtendDepositOptionsInt {
+    pub lock_contract_address: Address,
+    pub skip_deposit_check: bool,
+    pub skip_balance_check: bool,
+    pub deposit_id: U256,
+    pub extra_amount: Option<Decimal>,
+    pub extra_fee: Option<Decimal>,
+    /// New valid to timestamp, if not set current one is kept
+    pub timestamp: Option<u64>,
+}
+
+pub async fn extend_deposit(
+    web3: Arc<Web3RpcPool>,
+    conn: &DbPool,
+    chain_id: u64,
+    from: Address,
+    opt: ExtendDepositOptionsInt,
+) -> Result<(), PaymentError> {
+    let (token_address, token_decimals) =
+        deposit_token(web3.clone(), opt.lock_contract_address).await?;
+    let extra_amount = opt
+        .extra_amount
+        .unwrap_or_default()
+        .to_u256_from_token_decimal(token_decimals)
+        .map_err(err_from!())?;
+    let extra_fee = opt
+        .extra_fee
+        .unwrap_or_default()
+        .to_u256_from_token_decimal(token_decimals)
+        .map_err(err_from!())?;
+
+    let mut timestamp = opt.timestamp;
+    if !opt.skip_deposit_check || timestamp.is_none() {
+        let deposit_details =
+            deposit_details(web3.clone(), opt.deposit_id, opt.lock_contract_address).await?;
+        if deposit_details.spender == Address::zero() {
+            log::error!("Deposit {} not found", opt.deposit_id);
+            return Err(err_custom_create!("Deposit {} not found", opt.deposit_id));
+        }
+        if deposit_details.funder != from {
+            log::error!("You are not the funder of deposit {}", opt.deposit_id);
+            return Err(err_custom_create!(
+                "You are not the funder of deposit {}",
+                opt.deposit_id
+            ));
+        }
+        let current_valid_to = deposit_details.valid_to.timestamp() as u64;
+        if let Some(timestamp) = timestamp {
+            if timestamp < current_valid_to {
+                return Err(err_custom_create!(
+                    "Deposit {} cannot be shortened, current valid to: {}",
+                    opt.deposit_id,
+                    deposit_details.valid_to
+                ));
+            }
+        } else {
+            timestamp = Some(current_valid_to);
+        }
+    }
+    let timestamp = timestamp.ok_or(err_custom_create!("Timestamp not specified"))?;
+
+    if extra_amount.is_zero() && extra_fee.is_zero() && opt.timestamp.is_none() {
+        return Err(err_custom_create!(
+            "Nothing to extend. Specify extra amount, extra fee or new valid to timestamp"
+        ));
+    }
+
+    if !opt.skip_balance_check && !(extra_amount + extra_fee).is_zero() {
+        let block_info = get_latest_block_info(web3.clone()).await?;
+        let token_balance = get_token_balance(
+            web3.clone(),
+            token_address,
+            from,
+            Some(block_info.block_number),
+        )
+        .await?;
+
+        if token_balance < extra_amount + extra_fee {
+            return Err(err_custom_create!(
+                "You don't have enough: {} of token {:#x} on network with chain id: {} and account {:#x}",
+                token_balance,
+                token_address,
+                chain_id,
+                from
+            ));
+        };
+    }
+
+    let extend_deposit_tx = create_extend_deposit(
+        from,
+        opt.lock_contract_address,
+        chain_id,
+        None,
+        ExtendDepositArgs {
+            deposit_nonce: nonce_from_deposit_id(opt.deposit_id),
+            deposit_extra_amount: extra_amount,
+            deposit_extra_fee: extra_fee,
+            deposit_timestamp: timestamp,
+        },
+    )?;
+
+    let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+    let extend_deposit_tx = insert_tx(&mut db_transaction, &extend_deposit_tx)
+        .await
+        .map_err(err_from!())?;
+    db_transaction.commit().await.map_err(err_from!())?;
+
+    log::info!("Extend deposit added to queue: {}", extend_deposit_tx.id);
+    Ok(())
+}
+
 pub struct CreateDepositOptionsInt {
     pub lock_contract_address: Address,
     pub spender: Address,
@@ -1318,19 +1692,24 @@ pub struct CreateDepositOptionsInt {
 
 pub async fn make_deposit(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: u64,
     from: Address,
-    glm_address: Address,
     opt: CreateDepositOptionsInt,
 ) -> Result<(), PaymentError> {
+    let (token_address, token_decimals) =
+        deposit_token(web3.clone(), opt.lock_contract_address).await?;
     let amount = if let Some(amount) = opt.amount {
-        amount.to_u256_from_eth().map_err(err_from!())?
+        amount
+            .to_u256_from_token_decimal(token_decimals)
+            .map_err(err_from!())?
     } else {
         return Err(err_custom_create!("Amount not specified. Use --amount"));
     };
     let fee_amount = if let Some(fee_amount) = opt.fee_amount {
-        fee_amount.to_u256_from_eth().map_err(err_from!())?
+        fee_amount
+            .to_u256_from_token_decimal(token_decimals)
+            .map_err(err_from!())?
     } else {
         return Err(err_custom_create!(
             "Fee amount not specified. Use --fee-amount"
@@ -1341,7 +1720,7 @@ pub async fn make_deposit(
         let block_info = get_latest_block_info(web3.clone()).await?;
         let token_balance = get_token_balance(
             web3.clone(),
-            glm_address,
+            token_address,
             from,
             Some(block_info.block_number),
         )
@@ -1349,8 +1728,9 @@ pub async fn make_deposit(
 
         if token_balance < amount + fee_amount {
             return Err(err_custom_create!(
-                "You don't have enough: {} GLM on network with chain id: {} and account {:#x}",
+                "You don't have enough: {} of token {:#x} on network with chain id: {} and account {:#x}",
                 token_balance,
+                token_address,
                 chain_id,
                 from
             ));
@@ -1358,9 +1738,9 @@ pub async fn make_deposit(
     }
 
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-    let filter = "method=\"LOCK.deposit\" AND fee_paid is NULL";
+    let filter = "method='LOCK.deposit' AND fee_paid is NULL";
     let tx_existing = get_transactions(
-        &mut *db_transaction,
+        &mut db_transaction,
         Some(from),
         Some(filter),
         None,
@@ -1392,7 +1772,7 @@ pub async fn make_deposit(
         },
     )?;
 
-    let deposit_tx = insert_tx(&mut *db_transaction, &deposit_tx)
+    let deposit_tx = insert_tx(&mut db_transaction, &deposit_tx)
         .await
         .map_err(err_from!())?;
     db_transaction.commit().await.map_err(err_from!())?;
@@ -1418,7 +1798,7 @@ pub async fn get_token_balance(
 }
 
 pub async fn get_unpaid_token_amount(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     token_address: Address,
     sender: Address,
@@ -1431,12 +1811,7 @@ pub async fn get_unpaid_token_amount(
         if let Some(token_addr) = transfer.token_addr {
             let token_addr = Address::from_str(&token_addr).map_err(err_from!())?;
             if token_addr != token_address {
-                return Err(err_custom_create!(
-                    "Token address mismatch table token_transfer: {} != {}, id: {}",
-                    transfer.id,
-                    token_addr,
-                    token_address
-                ));
+                continue;
             }
             sum += transfer.token_amount.to_u256().map_err(err_from!())?
         }
@@ -1452,10 +1827,10 @@ pub async fn verify_transaction(
     sender: Address,
     receiver: Address,
     amount: U256,
-    glm_address: Address,
+    token_address: Address,
 ) -> Result<VerifyTransactionResult, PaymentError> {
     let (chain_tx_dao, transfers) =
-        match find_receipt_extended(web3, tx_hash, chain_id, glm_address).await? {
+        match find_receipt_extended(web3, tx_hash, chain_id, &[token_address]).await? {
             FindReceiptParseResult::Success((chain_tx_dao, transfers)) => (chain_tx_dao, transfers),
             FindReceiptParseResult::Failure(str) => {
                 return Ok(VerifyTransactionResult::Rejected(format!(
@@ -1503,8 +1878,105 @@ pub async fn verify_transaction(
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct AmendTransferArgs {
+    pub receiver: Option<Address>,
+    pub amount: Option<U256>,
+}
+
+async fn get_queued_transfer_by_payment_id(
+    conn: &DbPool,
+    chain_id: i64,
+    from: Address,
+    payment_id: &str,
+) -> Result<TokenTransferDbObj, PaymentError> {
+    let transfer =
+        get_token_transfer_by_payment_id(conn, chain_id, &format!("{:#x}", from), payment_id)
+            .await
+            .map_err(err_from!())?
+            .ok_or(err_create!(ErrorBag::TransferNotFound(
+                payment_id.to_string()
+            )))?;
+    if transfer.status != TokenTransferStatus::Queued {
+        return Err(err_create!(ErrorBag::TransferNotQueued(Box::new(transfer))));
+    }
+    Ok(transfer)
+}
+
+/// Error for transfer that was changed by someone else between reading and updating it
+async fn transfer_changed_error(
+    conn: &DbPool,
+    chain_id: i64,
+    from: Address,
+    payment_id: &str,
+) -> PaymentError {
+    match get_queued_transfer_by_payment_id(conn, chain_id, from, payment_id).await {
+        Ok(transfer) => err_create!(ErrorBag::TransferNotQueued(Box::new(transfer))),
+        Err(err) => err,
+    }
+}
+
+/// Cancel transfer that is not yet batched into a transaction
+pub async fn cancel_transfer(
+    conn: &DbPool,
+    chain_id: i64,
+    from: Address,
+    payment_id: &str,
+) -> Result<TokenTransferDbObj, PaymentError> {
+    let transfer = get_queued_transfer_by_payment_id(conn, chain_id, from, payment_id).await?;
+    let Some(transfer) = cancel_token_transfer(conn, transfer.id)
+        .await
+        .map_err(err_from!())?
+    else {
+        return Err(transfer_changed_error(conn, chain_id, from, payment_id).await);
+    };
+    log::info!("Transfer {} cancelled", payment_id);
+    Ok(transfer)
+}
+
+/// Change receiver and/or amount of transfer that is not yet batched into a transaction
+pub async fn amend_transfer(
+    conn: &DbPool,
+    chain_id: i64,
+    from: Address,
+    payment_id: &str,
+    args: AmendTransferArgs,
+) -> Result<TokenTransferDbObj, PaymentError> {
+    let transfer = get_queued_transfer_by_payment_id(conn, chain_id, from, payment_id).await?;
+    if transfer.deposit_finish > 0 {
+        return Err(err_custom_create!(
+            "Transfer {} is closing deposit and cannot be amended",
+            payment_id
+        ));
+    }
+    let receiver_addr = match args.receiver {
+        Some(receiver) if receiver == Address::zero() => {
+            return Err(err_custom_create!("Receiver cannot be zero address"));
+        }
+        Some(receiver) => format!("{:#x}", receiver),
+        None => transfer.receiver_addr,
+    };
+    let token_amount = match args.amount {
+        Some(amount) => amount.to_string(),
+        None => transfer.token_amount,
+    };
+    let Some(transfer) = amend_token_transfer(conn, transfer.id, &receiver_addr, &token_amount)
+        .await
+        .map_err(err_from!())?
+    else {
+        return Err(transfer_changed_error(conn, chain_id, from, payment_id).await);
+    };
+    log::info!(
+        "Transfer {} amended, receiver: {}, amount: {}",
+        payment_id,
+        transfer.receiver_addr,
+        transfer.token_amount
+    );
+    Ok(transfer)
+}
+
 pub async fn remove_transaction_force(
-    conn: &SqlitePool,
+    conn: &DbPool,
     tx_id: i64,
 ) -> Result<Option<Vec<i64>>, PaymentError> {
     let mut db_transaction = conn
@@ -1516,14 +1988,14 @@ pub async fn remove_transaction_force(
         Ok(txs) => {
             for tx in &txs {
                 //if tx is allowance then remove all references to it
-                cleanup_allowance_tx(&mut *db_transaction, tx.id)
+                cleanup_allowance_tx(&mut db_transaction, tx.id)
                     .await
                     .map_err(err_from!())?;
                 //if tx is token_transfer then remove all references to it
-                cleanup_token_transfer_tx(&mut *db_transaction, tx.id)
+                cleanup_token_transfer_tx(&mut db_transaction, tx.id)
                     .await
                     .map_err(err_from!())?;
-                delete_tx(&mut *db_transaction, tx.id)
+                delete_tx(&mut db_transaction, tx.id)
                     .await
                     .map_err(err_from!())?;
             }
@@ -1537,20 +2009,18 @@ pub async fn remove_transaction_force(
     }
 }
 
-pub async fn remove_last_unsent_transactions(
-    conn: SqlitePool,
-) -> Result<Option<i64>, PaymentError> {
+pub async fn remove_last_unsent_transactions(conn: DbPool) -> Result<Option<i64>, PaymentError> {
     let mut db_transaction = conn
         .begin()
         .await
         .map_err(|err| err_custom_create!("Error beginning transaction {err}"))?;
-    match get_last_unsent_tx(&mut *db_transaction, 0).await {
+    match get_last_unsent_tx(&mut db_transaction, 0).await {
         Ok(tx) => {
             if let Some(tx) = tx {
-                cleanup_token_transfer_tx(&mut *db_transaction, tx.id)
+                cleanup_token_transfer_tx(&mut db_transaction, tx.id)
                     .await
                     .map_err(err_from!())?;
-                delete_tx(&mut *db_transaction, tx.id)
+                delete_tx(&mut db_transaction, tx.id)
                     .await
                     .map_err(err_from!())?;
                 db_transaction.commit().await.map_err(err_from!())?;
@@ -1,7 +1,10 @@
 mod allowance;
 mod batching;
+mod fee_policy;
 pub mod process;
+mod reorg;
 mod service;
 
 pub use allowance::*;
+pub use reorg::*;
 pub use service::*;
@@ -7,25 +7,118 @@ use crate::transaction::create_erc20_approve;
 use erc20_payment_lib_common::ops::*;
 
 use crate::setup::PaymentSetup;
-use crate::{err_create, err_from};
+use crate::{err_create, err_custom_create, err_from};
 
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{CantSignContent, DriverEvent, DriverEventContent};
-use sqlx::SqlitePool;
 
 use crate::error::TransactionFailedError;
-use crate::eth::check_allowance;
+use crate::eth::get_allowances;
 use erc20_payment_lib_common::model::AllowanceDbObj;
 use web3::types::{Address, U256};
 
+/// Reads allowance of the requested token from chain. Allowances of other tokens of the chain
+/// for the same owner and spender are read in the same multicall and stored if already given.
+async fn check_chain_allowances(
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+    allowance_request: &AllowanceRequest,
+    minimum_allowance: U256,
+) -> Result<U256, PaymentError> {
+    let chain_setup = payment_setup
+        .chain_setup
+        .get(&allowance_request.chain_id)
+        .ok_or_else(|| {
+            err_custom_create!(
+                "No chain setup for chain id: {}",
+                allowance_request.chain_id
+            )
+        })?;
+    let owner = Address::from_str(&allowance_request.owner).map_err(err_from!())?;
+    let token = Address::from_str(&allowance_request.token_addr).map_err(err_from!())?;
+    let spender = Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?;
+
+    let mut other_tokens = Vec::new();
+    for other_token in chain_setup.tokens.iter().map(|t| t.address) {
+        if other_token == token || other_tokens.iter().any(|(t, _)| *t == other_token) {
+            continue;
+        }
+        let db_allowance = find_allowance(
+            conn,
+            &allowance_request.owner,
+            &format!("{other_token:#x}"),
+            &allowance_request.spender_addr,
+            allowance_request.chain_id,
+        )
+        .await
+        .map_err(err_from!())?;
+        if db_allowance
+            .as_ref()
+            .map(|a| a.confirm_date.is_none())
+            .unwrap_or(true)
+        {
+            other_tokens.push((other_token, db_allowance));
+        }
+    }
+
+    let requests = std::iter::once(token)
+        .chain(other_tokens.iter().map(|(t, _)| *t))
+        .map(|token| (owner, token, spender))
+        .collect::<Vec<_>>();
+    let allowances = get_allowances(
+        chain_setup.provider.clone(),
+        chain_setup.multicall_contract_address,
+        &requests,
+    )
+    .await?;
+
+    for ((other_token, db_allowance), allowance) in other_tokens.into_iter().zip(&allowances[1..]) {
+        if *allowance <= minimum_allowance {
+            continue;
+        }
+        log::info!(
+            "Allowance for token {:#x} found on chain, store it in db",
+            other_token
+        );
+        match db_allowance {
+            Some(mut db_allowance) => {
+                db_allowance.confirm_date = Some(chrono::Utc::now());
+                update_allowance(conn, &db_allowance)
+                    .await
+                    .map_err(err_from!())?;
+            }
+            None => {
+                insert_allowance(
+                    conn,
+                    &AllowanceDbObj {
+                        id: 0,
+                        owner: allowance_request.owner.clone(),
+                        token_addr: format!("{other_token:#x}"),
+                        spender: allowance_request.spender_addr.clone(),
+                        chain_id: allowance_request.chain_id,
+                        tx_id: None,
+                        allowance: allowance.to_string(),
+                        confirm_date: Some(chrono::Utc::now()),
+                        fee_paid: None,
+                        error: None,
+                    },
+                )
+                .await
+                .map_err(err_from!())?;
+            }
+        }
+    }
+    Ok(allowances[0])
+}
+
 pub async fn process_allowance(
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     allowance_request: &AllowanceRequest,
     signer: Arc<Box<dyn Signer + Send + Sync + 'static>>,
     event_sender: Option<&tokio::sync::mpsc::Sender<DriverEvent>>,
 ) -> Result<u32, PaymentError> {
     let minimum_allowance: U256 = U256::max_value() / U256::from(2);
-    let web3 = payment_setup.get_provider(allowance_request.chain_id)?;
 
     let mut db_allowance = find_allowance(
         conn,
@@ -48,11 +141,11 @@ pub async fn process_allowance(
                     "Checking allowance on chain owner: {}",
                     &allowance_request.owner
                 );
-                let allowance = check_allowance(
-                    web3,
-                    Address::from_str(&allowance_request.owner).map_err(err_from!())?,
-                    Address::from_str(&allowance_request.token_addr).map_err(err_from!())?,
-                    Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
+                let allowance = check_chain_allowances(
+                    conn,
+                    payment_setup,
+                    allowance_request,
+                    minimum_allowance,
                 )
                 .await?;
                 log::info!("Allowance on chain: {}", allowance);
@@ -71,13 +164,9 @@ pub async fn process_allowance(
         },
         None => {
             log::info!("No db entry, check allowance on chain");
-            let allowance = check_allowance(
-                web3,
-                Address::from_str(&allowance_request.owner).map_err(err_from!())?,
-                Address::from_str(&allowance_request.token_addr).map_err(err_from!())?,
-                Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
-            )
-            .await?;
+            let allowance =
+                check_chain_allowances(conn, payment_setup, allowance_request, minimum_allowance)
+                    .await?;
             if allowance > minimum_allowance {
                 log::info!("Allowance found on chain, add entry to db");
                 let db_allowance = AllowanceDbObj {
@@ -144,11 +233,11 @@ pub async fn process_allowance(
             None,
         )?;
         let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-        let web3_tx_dao = insert_tx(&mut *db_transaction, &approve_tx)
+        let web3_tx_dao = insert_tx(&mut db_transaction, &approve_tx)
             .await
             .map_err(err_from!())?;
         allowance.tx_id = Some(web3_tx_dao.id);
-        insert_allowance(&mut *db_transaction, &allowance)
+        insert_allowance(&mut db_transaction, &allowance)
             .await
             .map_err(err_from!())?;
 
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Duration, Utc};
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 
@@ -7,18 +9,21 @@ use crate::error::{AllowanceRequest, ErrorBag, PaymentError};
 
 use crate::transaction::{
     create_close_deposit, create_erc20_deposit_transfer, create_erc20_transfer,
-    create_erc20_transfer_multi, create_erc20_transfer_multi_deposit, create_eth_transfer,
-    MultiTransferArgs, MultiTransferDepositArgs, SingleTransferDepositArgs,
+    create_erc20_transfer_meta, create_erc20_transfer_multi, create_erc20_transfer_multi_deposit,
+    create_eth_transfer, MetaTransferArgs, MultiTransferArgs, MultiTransferDepositArgs,
+    SingleTransferDepositArgs,
 };
 
+use crate::config::BatchingPolicy;
+use crate::eth::get_meta_transaction_nonce;
 use crate::setup::PaymentSetup;
 use crate::{err_create, err_custom_create, err_from};
 
-use sqlx::SqlitePool;
+use erc20_payment_lib_common::{DbPool, DbTransaction};
 use tokio::sync::mpsc;
 
 use crate::signer::SignerAccount;
-use erc20_payment_lib_common::model::TokenTransferDbObj;
+use erc20_payment_lib_common::model::{TokenTransferDbObj, TokenTransferStatus};
 use erc20_payment_lib_common::DriverEvent;
 use web3::types::{Address, U256};
 
@@ -47,10 +52,102 @@ pub struct TokenTransferMultiOrder {
     token_transfers: Vec<TokenTransferDbObj>,
 }
 
+/// Transfers can be cancelled or amended while they are gathered, so tx_id is set only
+/// when the transfer is unchanged. Returns false in that case, the caller should drop
+/// the db transaction and let the next gathering pick up the current state.
+async fn assign_transfer_to_tx(
+    db_transaction: &mut DbTransaction<'_>,
+    token_transfer: &mut TokenTransferDbObj,
+    tx_id: i64,
+) -> Result<bool, PaymentError> {
+    if !assign_token_transfer_to_tx(&mut *db_transaction, token_transfer, tx_id)
+        .await
+        .map_err(err_from!())?
+    {
+        log::warn!(
+            "Token transfer {} was changed while gathering, skipping batch",
+            token_transfer.id
+        );
+        return Ok(false);
+    }
+    token_transfer.tx_id = Some(tx_id);
+    token_transfer.status = TokenTransferStatus::Batched;
+    Ok(true)
+}
+
+/// Removes from the map groups of transfers that are worth holding to build bigger batches.
+/// Returns the time when held transfers have to be gathered at the latest.
+fn hold_low_priority_transfers(
+    transfer_map: &mut TokenTransferMap,
+    policy: &BatchingPolicy,
+    ignore_deadlines: bool,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let mut groups = HashMap::<TokenTransferMultiKey, Vec<TokenTransferKey>>::new();
+    for key in transfer_map.keys() {
+        groups
+            .entry(TokenTransferMultiKey {
+                from_addr: key.from_addr.clone(),
+                chain_id: key.chain_id,
+                token_addr: key.token_addr.clone(),
+                deposit_id: key.deposit_id.clone(),
+            })
+            .or_default()
+            .push(key.clone());
+    }
+
+    let max_wait = Duration::try_seconds(policy.low_priority_max_wait as i64).unwrap_or_default();
+    let deadline_margin = Duration::try_seconds(policy.deadline_margin as i64).unwrap_or_default();
+    let mut next_gather_time: Option<DateTime<Utc>> = None;
+    for (multi_key, keys) in groups {
+        let transfers = keys
+            .iter()
+            .filter_map(|key| transfer_map.get(key))
+            .flatten()
+            .collect::<Vec<_>>();
+        if transfers.len() >= policy.low_priority_min_batch_size
+            || transfers.iter().any(|t| t.priority >= 0)
+        {
+            continue;
+        }
+        let release_time = transfers
+            .iter()
+            .map(|t| match t.deadline {
+                Some(deadline) if !ignore_deadlines => {
+                    (t.create_date + max_wait).min(deadline - deadline_margin)
+                }
+                _ => t.create_date + max_wait,
+            })
+            .min();
+        let Some(release_time) = release_time else {
+            continue;
+        };
+        if release_time <= now {
+            continue;
+        }
+        log::info!(
+            "Holding {} low priority transfers from {} on chain {} until {}",
+            transfers.len(),
+            multi_key.from_addr,
+            multi_key.chain_id,
+            release_time
+        );
+        for key in keys {
+            transfer_map.remove(&key);
+        }
+        next_gather_time = Some(
+            next_gather_time
+                .map(|t| t.min(release_time))
+                .unwrap_or(release_time),
+        );
+    }
+    next_gather_time
+}
+
 pub async fn gather_transactions_pre(
     account: &SignerAccount,
     chain_id: i64,
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     process_tx_needed: &mut bool,
 ) -> Result<TokenTransferMap, PaymentError> {
@@ -65,6 +162,7 @@ pub async fn gather_transactions_pre(
             Ok(from_addr) => {
                 if from_addr == Address::zero() {
                     f.error = Some("from_addr is zero".to_string());
+                    f.status = TokenTransferStatus::Failed;
                     update_token_transfer(conn, f).await.map_err(err_from!())?;
                     continue;
                 }
@@ -78,6 +176,7 @@ pub async fn gather_transactions_pre(
             }
             Err(_err) => {
                 f.error = Some("Invalid from address".to_string());
+                f.status = TokenTransferStatus::Failed;
                 update_token_transfer(conn, f).await.map_err(err_from!())?;
                 continue;
             }
@@ -120,15 +219,19 @@ pub async fn gather_transactions_pre(
 
                 let mut transaction = conn.begin().await.map_err(err_from!())?;
 
-                let new_tx = insert_tx(&mut *transaction, &close_deposit_tx_id)
+                let new_tx = insert_tx(&mut transaction, &close_deposit_tx_id)
                     .await
                     .map_err(err_from!())?;
 
-                let mut tt = f.clone();
-                tt.tx_id = Some(new_tx.id);
-                update_token_transfer(&mut *transaction, &tt)
+                if !assign_token_transfer_to_tx(&mut transaction, f, new_tx.id)
                     .await
-                    .map_err(err_from!())?;
+                    .map_err(err_from!())?
+                {
+                    return Err(err_custom_create!(
+                        "Token transfer {} was changed while gathering",
+                        f.id
+                    ));
+                }
 
                 transaction.commit().await.map_err(err_from!())?;
                 *process_tx_needed = true;
@@ -139,12 +242,14 @@ pub async fn gather_transactions_pre(
             Ok(rec_address) => {
                 if rec_address == Address::zero() {
                     f.error = Some("receiver_addr is zero".to_string());
+                    f.status = TokenTransferStatus::Failed;
                     update_token_transfer(conn, f).await.map_err(err_from!())?;
                     continue;
                 }
             }
             Err(_err) => {
                 f.error = Some("Invalid receiver address".to_string());
+                f.status = TokenTransferStatus::Failed;
                 update_token_transfer(conn, f).await.map_err(err_from!())?;
                 continue;
             }
@@ -167,11 +272,28 @@ pub async fn gather_transactions_pre(
             }
         }
     }
+
+    // when finishing after the work is done there is no later gathering, so nothing is held
+    if !payment_setup.finish_when_done {
+        if let Some(release_time) = hold_low_priority_transfers(
+            &mut transfer_map,
+            &payment_setup.batching_policy,
+            payment_setup.ignore_deadlines,
+            Utc::now(),
+        ) {
+            let mut external_gather_time = account.external_gather_time.lock().unwrap();
+            *external_gather_time = Some(
+                external_gather_time
+                    .map(|t| t.min(release_time))
+                    .unwrap_or(release_time),
+            );
+        }
+    }
     Ok(transfer_map)
 }
 
 pub async fn gather_transactions_batch_multi(
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     multi_order_vector: &mut [TokenTransferMultiOrder],
     token_transfer: &TokenTransferMultiKey,
@@ -360,16 +482,17 @@ pub async fn gather_transactions_batch_multi(
                 ));
             };
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let web3_tx_dao = insert_tx(&mut *db_transaction, &web3tx)
+            let web3_tx_dao = insert_tx(&mut db_transaction, &web3tx)
                 .await
                 .map_err(err_from!())?;
 
             for token_t in &mut *smaller_order {
                 for token_transfer in &mut token_t.token_transfers {
-                    token_transfer.tx_id = Some(web3_tx_dao.id);
-                    update_token_transfer(&mut *db_transaction, token_transfer)
-                        .await
-                        .map_err(err_from!())?;
+                    if !assign_transfer_to_tx(&mut db_transaction, token_transfer, web3_tx_dao.id)
+                        .await?
+                    {
+                        return Ok(0);
+                    }
                 }
             }
             db_transaction.commit().await.map_err(err_from!())?;
@@ -381,9 +504,131 @@ pub async fn gather_transactions_batch_multi(
     Ok(1)
 }
 
+pub async fn gather_transactions_batch_meta(
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+    account: &SignerAccount,
+    relayer: Address,
+    multi_order_vector: &mut [TokenTransferMultiOrder],
+    token_transfer: &TokenTransferMultiKey,
+) -> Result<u32, PaymentError> {
+    let chain_setup = payment_setup
+        .chain_setup
+        .get(&token_transfer.chain_id)
+        .ok_or(err_custom_create!(
+            "No setup found for chain id: {}",
+            token_transfer.chain_id
+        ))?;
+    let token_addr = token_transfer
+        .token_addr
+        .as_ref()
+        .ok_or(err_custom_create!(
+            "Meta transactions are supported only for token transfers"
+        ))?;
+    let from_addr = Address::from_str(&token_transfer.from_addr).map_err(err_from!())?;
+    if from_addr != account.address {
+        return Err(err_custom_create!(
+            "Meta transaction has to be signed by sender {:#x}, got account {:#x}",
+            from_addr,
+            account.address
+        ));
+    }
+
+    //nonce of meta transaction is read from token contract, so new ones are created
+    //only when previous are done, nonces of orders gathered together are consecutive
+    let in_progress =
+        get_meta_transactions_in_progress_count(conn, from_addr, token_transfer.chain_id)
+            .await
+            .map_err(err_from!())?;
+    if in_progress > 0 {
+        log::debug!(
+            "Meta transaction for {:#x} still in progress, skipping until it is done",
+            from_addr
+        );
+        return Ok(0);
+    }
+    let token = Address::from_str(token_addr).map_err(err_from!())?;
+    let mut nonce =
+        get_meta_transaction_nonce(chain_setup.provider.clone(), token, from_addr).await?;
+
+    let mut inserted = 0;
+    for order in multi_order_vector.iter_mut() {
+        let mut sum = U256::zero();
+        for token_transfer in &order.token_transfers {
+            sum += U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+        }
+
+        log::info!(
+            "Inserting meta transaction stub for ERC20 transfer to: {:?} relayed by {:#x}",
+            order.receiver,
+            relayer
+        );
+        let web3tx = create_erc20_transfer_meta(
+            chain_setup.provider.clone(),
+            account,
+            MetaTransferArgs {
+                relayer,
+                token,
+                erc20_to: order.receiver,
+                erc20_amount: sum,
+                chain_id: token_transfer.chain_id as u64,
+                gas_limit: None,
+                nonce: Some(nonce),
+            },
+        )
+        .await?;
+
+        let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+        let web3_tx_dao = insert_tx(&mut db_transaction, &web3tx)
+            .await
+            .map_err(err_from!())?;
+        for token_transfer in &mut order.token_transfers {
+            if !assign_transfer_to_tx(&mut db_transaction, token_transfer, web3_tx_dao.id).await? {
+                //next orders would leave gap in nonces, they are gathered again in next pass
+                return Ok(inserted);
+            }
+        }
+        db_transaction.commit().await.map_err(err_from!())?;
+        inserted += 1;
+        nonce += U256::one();
+    }
+    Ok(inserted)
+}
+
+/// Relayer has to keep running while accounts it is relaying for have pending transfers
+pub async fn is_relayer_work_pending(
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+    account: Address,
+    chain_id: i64,
+) -> Result<bool, PaymentError> {
+    let Some(meta_transaction) = payment_setup
+        .chain_setup
+        .get(&chain_id)
+        .and_then(|chain_setup| chain_setup.meta_transaction.as_ref())
+    else {
+        return Ok(false);
+    };
+    if meta_transaction.relayer != account {
+        return Ok(false);
+    }
+    for sender in &meta_transaction.accounts {
+        let pending = get_pending_token_transfers(conn, *sender, chain_id)
+            .await
+            .map_err(err_from!())?;
+        if !pending.is_empty() {
+            return Ok(true);
+        }
+    }
+    let to_process = get_next_transactions_to_process(conn, Some(account), 1, chain_id)
+        .await
+        .map_err(err_from!())?;
+    Ok(!to_process.is_empty())
+}
+
 pub async fn gather_transactions_batch(
     _event_sender: Option<mpsc::Sender<DriverEvent>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     token_transfers: &mut [TokenTransferDbObj],
     token_transfer: &TokenTransferKey,
@@ -443,14 +688,13 @@ pub async fn gather_transactions_batch(
         )
     };
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-    let web3_tx_dao = insert_tx(&mut *db_transaction, &web3tx)
+    let web3_tx_dao = insert_tx(&mut db_transaction, &web3tx)
         .await
         .map_err(err_from!())?;
     for token_transfer in token_transfers.iter_mut() {
-        token_transfer.tx_id = Some(web3_tx_dao.id);
-        update_token_transfer(&mut *db_transaction, token_transfer)
-            .await
-            .map_err(err_from!())?;
+        if !assign_transfer_to_tx(&mut db_transaction, token_transfer, web3_tx_dao.id).await? {
+            return Ok(0);
+        }
     }
     db_transaction.commit().await.map_err(err_from!())?;
     Ok(1)
@@ -458,13 +702,15 @@ pub async fn gather_transactions_batch(
 
 pub async fn gather_transactions_post(
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
-    conn: &SqlitePool,
+    account: &SignerAccount,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     token_transfer_map: &mut TokenTransferMap,
 ) -> Result<u32, PaymentError> {
     let mut inserted_tx_count = 0;
 
-    let mut sorted_order = BTreeMap::<i64, TokenTransferKey>::new();
+    // higher priority first, so they land in the first batch when batch size is limited
+    let mut sorted_order = BTreeMap::<(Reverse<i64>, i64), TokenTransferKey>::new();
 
     for pair in token_transfer_map.iter() {
         let token_transfers = pair.1;
@@ -474,7 +720,12 @@ pub async fn gather_transactions_post(
             .map(|f| f.id)
             .min()
             .ok_or_else(|| err_custom_create!("Failed algorithm when searching min"))?;
-        sorted_order.insert(min_id, token_transfer.clone());
+        let max_priority = token_transfers
+            .iter()
+            .map(|f| f.priority)
+            .max()
+            .unwrap_or_default();
+        sorted_order.insert((Reverse(max_priority), min_id), token_transfer.clone());
     }
     let use_multi = true;
     if use_multi {
@@ -503,11 +754,11 @@ pub async fn gather_transactions_post(
                 )
                 .await
                 {
-                    Ok(_) => {
-                        inserted_tx_count += 1;
+                    Ok(count) => {
+                        inserted_tx_count += count;
                     }
                     Err(e) => {
-                        match &e.inner {
+                        match e.inner.as_ref() {
                             ErrorBag::NoAllowanceFound(_allowance_request) => {
                                 //pass allowance error up
                                 return Err(e);
@@ -536,7 +787,6 @@ pub async fn gather_transactions_post(
                         }
                     }
                 }
-                inserted_tx_count += 1;
                 continue;
             }
 
@@ -566,20 +816,43 @@ pub async fn gather_transactions_post(
         for key in multi_key_map {
             let token_transfer = key.0;
             let mut token_transfers = key.1.clone();
+            let meta_transaction_relayer = if token_transfer.deposit_id.is_none() {
+                payment_setup
+                    .chain_setup
+                    .get(&token_transfer.chain_id)
+                    .zip(Address::from_str(&token_transfer.from_addr).ok())
+                    .and_then(|(chain_setup, from_addr)| {
+                        chain_setup.meta_transaction_relayer(from_addr)
+                    })
+            } else {
+                None
+            };
             //todo fix clones
-            match gather_transactions_batch_multi(
-                conn,
-                payment_setup,
-                &mut token_transfers,
-                &token_transfer,
-            )
-            .await
-            {
-                Ok(_) => {
-                    inserted_tx_count += 1;
+            let res = if let Some(relayer) = meta_transaction_relayer {
+                gather_transactions_batch_meta(
+                    conn,
+                    payment_setup,
+                    account,
+                    relayer,
+                    &mut token_transfers,
+                    &token_transfer,
+                )
+                .await
+            } else {
+                gather_transactions_batch_multi(
+                    conn,
+                    payment_setup,
+                    &mut token_transfers,
+                    &token_transfer,
+                )
+                .await
+            };
+            match res {
+                Ok(count) => {
+                    inserted_tx_count += count;
                 }
                 Err(e) => {
-                    match &e.inner {
+                    match e.inner.as_ref() {
                         ErrorBag::NoAllowanceFound(_allowance_request) => {
                             //pass allowance error up
                             return Err(e);
@@ -590,6 +863,7 @@ pub async fn gather_transactions_post(
                                 for token_transfer in multi.token_transfers {
                                     let mut tt = token_transfer.clone();
                                     tt.error = Some("Error in gathering transactions".to_string());
+                                    tt.status = TokenTransferStatus::Failed;
                                     update_token_transfer(conn, &tt)
                                         .await
                                         .map_err(err_from!())?;
@@ -604,9 +878,100 @@ pub async fn gather_transactions_post(
                     }
                 }
             }
-            inserted_tx_count += 1;
         }
     }
 
     Ok(inserted_tx_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(id: i64, receiver: &str, priority: i64) -> TokenTransferDbObj {
+        TokenTransferDbObj {
+            id,
+            payment_id: None,
+            from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+            receiver_addr: receiver.to_string(),
+            chain_id: 987789,
+            token_addr: None,
+            token_amount: "1".to_string(),
+            deposit_id: None,
+            deposit_finish: 0,
+            create_date: Utc::now(),
+            tx_id: None,
+            paid_date: None,
+            fee_paid: None,
+            error: None,
+            status: TokenTransferStatus::Queued,
+            priority,
+            deadline: None,
+        }
+    }
+
+    fn transfer_map(transfers: Vec<TokenTransferDbObj>) -> TokenTransferMap {
+        let mut map = TokenTransferMap::new();
+        for t in transfers {
+            map.entry(TokenTransferKey {
+                from_addr: t.from_addr.clone(),
+                receiver_addr: t.receiver_addr.clone(),
+                chain_id: t.chain_id,
+                token_addr: t.token_addr.clone(),
+                deposit_id: t.deposit_id.clone(),
+            })
+            .or_default()
+            .push(t);
+        }
+        map
+    }
+
+    #[test]
+    fn test_hold_low_priority_transfers() {
+        let policy = BatchingPolicy {
+            deadline_margin: 60,
+            low_priority_min_batch_size: 3,
+            low_priority_max_wait: 3600,
+        };
+        let now = Utc::now();
+
+        //small batch of low priority transfers is held
+        let mut map = transfer_map(vec![transfer(1, "0x01", -1), transfer(2, "0x02", -1)]);
+        let release_time = hold_low_priority_transfers(&mut map, &policy, false, now).unwrap();
+        assert!(map.is_empty());
+        assert!(release_time > now + Duration::try_seconds(3500).unwrap());
+
+        //normal priority transfer takes low priority ones with it
+        let mut map = transfer_map(vec![transfer(1, "0x01", -1), transfer(2, "0x02", 0)]);
+        assert_eq!(
+            hold_low_priority_transfers(&mut map, &policy, false, now),
+            None
+        );
+        assert_eq!(map.len(), 2);
+
+        //batch is big enough
+        let mut map = transfer_map(vec![
+            transfer(1, "0x01", -1),
+            transfer(2, "0x02", -1),
+            transfer(3, "0x02", -1),
+        ]);
+        assert_eq!(
+            hold_low_priority_transfers(&mut map, &policy, false, now),
+            None
+        );
+        assert_eq!(map.len(), 2);
+
+        //deadline is close
+        let mut close_deadline = transfer(1, "0x01", -1);
+        close_deadline.deadline = Some(now + Duration::try_seconds(30).unwrap());
+        let mut map = transfer_map(vec![close_deadline.clone()]);
+        assert_eq!(
+            hold_low_priority_transfers(&mut map, &policy, false, now),
+            None
+        );
+        assert_eq!(map.len(), 1);
+        let mut map = transfer_map(vec![close_deadline]);
+        assert!(hold_low_priority_transfers(&mut map, &policy, true, now).is_some());
+        assert!(map.is_empty());
+    }
+}
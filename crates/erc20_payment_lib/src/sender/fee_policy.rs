@@ -0,0 +1,293 @@
+use crate::error::PaymentError;
+use crate::error::*;
+use crate::setup::{FeePolicySetup, PaymentSetup};
+use crate::{err_custom_create, err_from};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use erc20_payment_lib_common::model::TxDbObj;
+use erc20_payment_lib_common::ops::{get_token_transfers_by_tx, get_transactions_for_fee_budget};
+use erc20_payment_lib_common::utils::{StringConvExt, U256ConvExt};
+use erc20_payment_lib_common::{DbPool, FeeBudgetDetails};
+use erc20_rpc_pool::Web3RpcPool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use web3::types::{BlockId, BlockNumber, U256};
+
+fn start_of_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+        .unwrap()
+}
+
+fn start_of_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .unwrap()
+}
+
+/// Median of priority fees (first percentile of each block), empty blocks are skipped
+fn priority_fee_from_rewards(rewards: &[Vec<U256>]) -> Option<U256> {
+    let mut fees = rewards
+        .iter()
+        .filter_map(|r| r.first().copied())
+        .filter(|fee| !fee.is_zero())
+        .collect::<Vec<_>>();
+    fees.sort();
+    fees.get(fees.len() / 2).copied()
+}
+
+/// Replacement transaction reuses nonce of the replaced one and only one of them can be mined,
+/// so for every nonce only the confirmed transaction, or the latest one if none is confirmed, is kept
+fn latest_per_nonce(txs: &[TxDbObj]) -> Vec<&TxDbObj> {
+    let mut by_nonce: BTreeMap<i64, &TxDbObj> = BTreeMap::new();
+    let mut latest = Vec::new();
+    for tx in txs {
+        let Some(nonce) = tx.nonce else {
+            latest.push(tx);
+            continue;
+        };
+        let current = by_nonce.entry(nonce).or_insert(tx);
+        if (tx.confirm_date.is_some(), tx.id) > (current.confirm_date.is_some(), current.id) {
+            *current = tx;
+        }
+    }
+    latest.extend(by_nonce.into_values());
+    latest
+}
+
+/// Fees paid since given date plus maximum fees pending transactions can take
+fn fees_spent_since(txs: &[TxDbObj], since: DateTime<Utc>) -> Result<U256, PaymentError> {
+    let mut spent = U256::zero();
+    for tx in latest_per_nonce(txs) {
+        match tx.confirm_date {
+            Some(confirm_date) => {
+                if confirm_date >= since {
+                    if let Some(fee_paid) = &tx.fee_paid {
+                        spent += fee_paid.to_u256().map_err(err_from!())?;
+                    }
+                }
+            }
+            None => {
+                let max_fee_per_gas = tx
+                    .max_fee_per_gas
+                    .as_ref()
+                    .map(|v| v.to_u256())
+                    .transpose()
+                    .map_err(err_from!())?
+                    .unwrap_or_default();
+                spent += max_fee_per_gas * U256::from(tx.gas_limit.unwrap_or_default());
+            }
+        }
+    }
+    Ok(spent)
+}
+
+/// Gas prices taken from recent blocks (eth_feeHistory), capped by the values from chain config
+pub async fn gas_prices_from_fee_history(
+    web3: Arc<Web3RpcPool>,
+    fee_policy: &FeePolicySetup,
+    max_fee_per_gas: U256,
+    priority_fee: U256,
+) -> Result<(U256, U256), PaymentError> {
+    if fee_policy.priority_fee_percentile.is_none() && fee_policy.base_fee_extra.is_none() {
+        return Ok((max_fee_per_gas, priority_fee));
+    }
+    let fee_history = web3
+        .eth_fee_history(
+            U256::from(fee_policy.fee_history_blocks),
+            BlockNumber::Latest,
+            fee_policy.priority_fee_percentile.map(|p| vec![p]),
+        )
+        .await
+        .map_err(err_from!())?;
+
+    let mut new_priority_fee = priority_fee;
+    if fee_policy.priority_fee_percentile.is_some() {
+        if let Some(fee) = priority_fee_from_rewards(&fee_history.reward.unwrap_or_default()) {
+            new_priority_fee = fee.min(priority_fee);
+        }
+    }
+    let mut new_max_fee_per_gas = max_fee_per_gas;
+    if let Some(base_fee_extra) = fee_policy.base_fee_extra {
+        // last entry is base fee of the next block
+        if let Some(next_base_fee) = fee_history.base_fee_per_gas.last() {
+            new_max_fee_per_gas =
+                (*next_base_fee + new_priority_fee + base_fee_extra).min(max_fee_per_gas);
+        }
+    }
+    let new_priority_fee = new_priority_fee.min(new_max_fee_per_gas);
+    if new_max_fee_per_gas != max_fee_per_gas || new_priority_fee != priority_fee {
+        log::info!(
+            "Gas prices from fee history - max fee per gas: {} Gwei, priority fee: {} Gwei",
+            new_max_fee_per_gas.to_gwei_str(),
+            new_priority_fee.to_gwei_str(),
+        );
+    }
+    Ok((new_max_fee_per_gas, new_priority_fee))
+}
+
+/// Returns current base fee if it is above the limit set in the policy
+pub async fn base_fee_above_limit(
+    web3: Arc<Web3RpcPool>,
+    fee_policy: &FeePolicySetup,
+) -> Result<Option<U256>, PaymentError> {
+    let Some(max_base_fee) = fee_policy.max_base_fee else {
+        return Ok(None);
+    };
+    let base_fee = web3
+        .eth_block(BlockId::Number(BlockNumber::Latest))
+        .await
+        .map_err(err_from!())?
+        .ok_or(err_custom_create!(
+            "Failed to get latest block from RPC node"
+        ))?
+        .base_fee_per_gas
+        .ok_or(err_custom_create!(
+            "Failed to get base_fee_per_gas from RPC node"
+        ))?;
+    if base_fee > max_base_fee {
+        Ok(Some(base_fee))
+    } else {
+        Ok(None)
+    }
+}
+
+/// True if any transfer sent with the transaction has to be sent now because of its deadline
+pub async fn is_deadline_close(
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+    tx_id: i64,
+) -> Result<bool, PaymentError> {
+    if payment_setup.ignore_deadlines {
+        return Ok(false);
+    }
+    let deadline_limit = Utc::now()
+        + chrono::Duration::try_seconds(payment_setup.batching_policy.deadline_margin as i64)
+            .unwrap_or_default();
+    let token_transfers = get_token_transfers_by_tx(conn, tx_id)
+        .await
+        .map_err(err_from!())?;
+    Ok(token_transfers
+        .iter()
+        .any(|tt| tt.deadline.map(|d| d <= deadline_limit).unwrap_or(false)))
+}
+
+/// Returns details of the exceeded budget if sending the transaction can exceed it
+pub async fn check_fee_budget(
+    conn: &DbPool,
+    fee_policy: &FeePolicySetup,
+    tx: &TxDbObj,
+    tx_max_fee: U256,
+) -> Result<Option<FeeBudgetDetails>, PaymentError> {
+    if fee_policy.daily_budget.is_none() && fee_policy.monthly_budget.is_none() {
+        return Ok(None);
+    }
+    let now = Utc::now();
+    let month_start = start_of_month(now);
+    let txs = get_transactions_for_fee_budget(conn, tx.chain_id, &tx.from_addr, month_start)
+        .await
+        .map_err(err_from!())?
+        .into_iter()
+        .filter(|t| t.id != tx.id)
+        .collect::<Vec<_>>();
+
+    for (period, budget, since) in [
+        ("daily", fee_policy.daily_budget, start_of_day(now)),
+        ("monthly", fee_policy.monthly_budget, month_start),
+    ] {
+        let Some(budget) = budget else {
+            continue;
+        };
+        let spent = fees_spent_since(&txs, since)?;
+        if spent + tx_max_fee > budget {
+            return Ok(Some(FeeBudgetDetails {
+                tx: tx.clone(),
+                period: period.to_string(),
+                budget: budget.to_eth().map_err(err_from!())?,
+                spent: spent.to_eth().map_err(err_from!())?,
+                tx_max_fee: tx_max_fee.to_eth().map_err(err_from!())?,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_fee_from_rewards() {
+        let rewards = vec![
+            vec![U256::from(3)],
+            vec![U256::zero()],
+            vec![U256::from(1)],
+            vec![],
+            vec![U256::from(2)],
+        ];
+        assert_eq!(priority_fee_from_rewards(&rewards), Some(U256::from(2)));
+        assert_eq!(priority_fee_from_rewards(&[vec![U256::zero()]]), None);
+    }
+
+    #[test]
+    fn test_fees_spent_since() {
+        let now = Utc.with_ymd_and_hms(2024, 4, 15, 12, 0, 0).unwrap();
+        let confirmed_today = TxDbObj {
+            confirm_date: Some(now),
+            fee_paid: Some("100".to_string()),
+            ..Default::default()
+        };
+        let confirmed_this_month = TxDbObj {
+            confirm_date: Some(Utc.with_ymd_and_hms(2024, 4, 2, 12, 0, 0).unwrap()),
+            fee_paid: Some("1000".to_string()),
+            ..Default::default()
+        };
+        let pending = TxDbObj {
+            max_fee_per_gas: Some("5".to_string()),
+            gas_limit: Some(2),
+            ..Default::default()
+        };
+        let txs = vec![confirmed_today, confirmed_this_month, pending];
+        assert_eq!(
+            fees_spent_since(&txs, start_of_day(now)).unwrap(),
+            U256::from(110)
+        );
+        assert_eq!(
+            fees_spent_since(&txs, start_of_month(now)).unwrap(),
+            U256::from(1110)
+        );
+
+        // pending transaction replaced twice, only the latest replacement can be mined
+        let replaced = TxDbObj {
+            id: 1,
+            nonce: Some(7),
+            max_fee_per_gas: Some("5".to_string()),
+            gas_limit: Some(2),
+            ..Default::default()
+        };
+        let replacement = TxDbObj {
+            id: 2,
+            max_fee_per_gas: Some("6".to_string()),
+            ..replaced.clone()
+        };
+        let latest_replacement = TxDbObj {
+            id: 3,
+            max_fee_per_gas: Some("7".to_string()),
+            ..replaced.clone()
+        };
+        let txs = vec![latest_replacement.clone(), replaced.clone(), replacement];
+        assert_eq!(
+            fees_spent_since(&txs, start_of_day(now)).unwrap(),
+            U256::from(14)
+        );
+
+        // replaced transaction got mined before the replacement
+        let confirmed_replaced = TxDbObj {
+            confirm_date: Some(now),
+            fee_paid: Some("8".to_string()),
+            ..replaced
+        };
+        let txs = vec![confirmed_replaced, latest_replacement];
+        assert_eq!(
+            fees_spent_since(&txs, start_of_day(now)).unwrap(),
+            U256::from(8)
+        );
+    }
+}
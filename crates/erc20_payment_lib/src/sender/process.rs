@@ -1,37 +1,37 @@
 use crate::error::PaymentError;
 use crate::error::*;
 use crate::{err_create, err_custom_create, err_from};
-use erc20_payment_lib_common::model::TxDbObj;
+use erc20_payment_lib_common::model::{TokenTransferStatus, TxDbObj};
 use erc20_payment_lib_common::ops::{
     delete_tx, get_transaction, get_transaction_highest_nonce, insert_tx, remap_allowance_tx,
-    remap_token_transfer_tx, update_processing_and_first_processed_tx, update_tx,
-    update_tx_stuck_date,
+    remap_token_transfer_tx, update_processing_and_first_processed_tx,
+    update_token_transfers_status_by_tx, update_tx, update_tx_stuck_date,
 };
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{
     CantSignContent, DriverEvent, DriverEventContent, GasLowInfo, NoGasDetails,
     TransactionStuckReason,
 };
-use rust_decimal::prelude::Zero;
 use rust_decimal::Decimal;
-use sqlx::SqlitePool;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use web3::transports::Http;
-use web3::types::{Address, BlockId, BlockNumber, U256, U64};
+use web3::types::{Address, BlockId, BlockNumber, U256};
 use web3::Web3;
 
 use crate::eth::get_transaction_count;
 use crate::runtime::{remove_transaction_force, send_driver_event, SharedState};
+use crate::sender::fee_policy::{
+    base_fee_above_limit, check_fee_budget, gas_prices_from_fee_history, is_deadline_close,
+};
 use crate::setup::PaymentSetup;
 use crate::signer::Signer;
 use crate::transaction::check_transaction;
 use crate::transaction::find_receipt;
 use crate::transaction::send_transaction;
 use crate::transaction::sign_transaction_with_callback;
-use crate::utils::{
-    datetime_from_u256_timestamp, get_env_bool_value, DecimalConvExt, StringConvExt, U256ConvExt,
-};
+use crate::utils::{datetime_from_u256_timestamp, StringConvExt, U256ConvExt};
 
 #[derive(Debug)]
 pub enum ProcessTransactionResult {
@@ -54,7 +54,7 @@ pub async fn get_provider(url: &str) -> Result<Web3<Http>, PaymentError> {
 pub async fn process_transaction(
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
     shared_state: Arc<std::sync::Mutex<SharedState>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     web3_tx_dao: &mut TxDbObj,
     payment_setup: &PaymentSetup,
     signer: Arc<Box<dyn Signer + Send + Sync + 'static>>,
@@ -65,8 +65,6 @@ pub async fn process_transaction(
         return Ok((web3_tx_dao.clone(), ProcessTransactionResult::DoNotSave));
     };
 
-    let is_polygon_eco_mode = chain_setup.chain_id == 137 && get_env_bool_value("POLYGON_ECO_MODE");
-
     let web3 = payment_setup.get_provider(chain_id).map_err(|_e| {
         err_create!(TransactionFailedError::new(&format!(
             "Failed to get provider for chain id: {chain_id}"
@@ -87,6 +85,38 @@ pub async fn process_transaction(
         ))));
     }
 
+    if let Some(fee_policy) = &chain_setup.fee_policy {
+        // only new transactions wait, replacements have to be sent anyway
+        if web3_tx_dao.signed_raw_data.is_none() && web3_tx_dao.orig_tx_id.is_none() {
+            if let Some(base_fee) = base_fee_above_limit(web3.clone(), fee_policy).await? {
+                if is_deadline_close(conn, payment_setup, web3_tx_dao.id).await? {
+                    log::warn!(
+                        "Base fee {} Gwei is above the limit, but deadline of transfers in tx {} is close",
+                        base_fee.to_gwei_str(),
+                        web3_tx_dao.id
+                    );
+                } else {
+                    log::info!(
+                        "Base fee {} Gwei is above the limit, waiting with tx {}",
+                        base_fee.to_gwei_str(),
+                        web3_tx_dao.id
+                    );
+                    shared_state.lock().unwrap().set_tx_message(
+                        web3_tx_dao.id,
+                        format!(
+                            "Waiting for base fee to drop, now {} Gwei",
+                            base_fee.to_gwei_str()
+                        ),
+                    );
+                    return Ok((
+                        web3_tx_dao.clone(),
+                        ProcessTransactionResult::DoNotSaveWaitForGasOrToken,
+                    ));
+                }
+            }
+        }
+    }
+
     let transaction_nonce = if let Some(nonce) = web3_tx_dao.nonce {
         nonce
     } else {
@@ -139,72 +169,20 @@ pub async fn process_transaction(
         } else {
             chain_setup.max_fee_per_gas
         };
-        let max_priority_fee = if let Some(priority_fee) = &web3_tx_dao.priority_fee {
+        let mut max_priority_fee = if let Some(priority_fee) = &web3_tx_dao.priority_fee {
             priority_fee.to_u256().map_err(err_from!())?
         } else {
             chain_setup.priority_fee
         };
 
-        if is_polygon_eco_mode {
-            let blockchain_gas_price = web3
-                .clone()
-                .eth_block(BlockId::Number(BlockNumber::Latest))
-                .await
-                .map_err(err_from!())?
-                .ok_or(err_create!(TransactionFailedError::new(
-                    "Failed to get latest block from RPC node"
-                )))?
-                .base_fee_per_gas
-                .ok_or(err_create!(TransactionFailedError::new(
-                    "Failed to get base_fee_per_gas from RPC node"
-                )))?;
-
-            let extra_gas = if let Ok(extra_gas) = std::env::var("POLYGON_ECO_MODE_EXTRA_GAS") {
-                let mut extra_gas = Decimal::from_str_exact(&extra_gas).map_err(|err| {
-                    err_custom_create!("POLYGON_ECO_MODE_EXTRA_GAS has to be decimal format {err}")
-                })?;
-
-                if extra_gas > Decimal::from(30) {
-                    log::warn!("Extra gas is too high, setting to 30");
-                    extra_gas = Decimal::from(30);
-                }
-                if extra_gas < Decimal::from(-10) {
-                    log::warn!("Extra gas is too low, setting to -10");
-                    extra_gas = Decimal::from(-10);
-                }
-                extra_gas
-            } else {
-                Decimal::zero()
-            };
-
-            let mut new_target_gas_u256 = if extra_gas >= Decimal::zero() {
-                let extra_gas_u256 = extra_gas.to_u256_from_gwei().map_err(err_from!())?;
-                blockchain_gas_price + extra_gas_u256
-            } else {
-                let extra_gas_u256 = extra_gas.to_u256_from_gwei().map_err(err_from!())?;
-                let min_base_price = U256::from(1_000_000_000u64);
-
-                let mut new_target_gas_u256 = blockchain_gas_price - extra_gas_u256;
-                if blockchain_gas_price < min_base_price + extra_gas_u256 {
-                    new_target_gas_u256 = min_base_price;
-                }
-                new_target_gas_u256
-            };
-
-            //max_fee_per_gas cannot be lower than priority fee
-            if new_target_gas_u256 < max_priority_fee {
-                new_target_gas_u256 = max_priority_fee;
-            }
-
-            if new_target_gas_u256 * 11 < max_fee_per_gas * 10 {
-                log::warn!(
-                    "Eco mode activated. Sending transaction with lower base fee. Blockchain base fee: {} Gwei, Tx base fee: {} Gwei",
-                    blockchain_gas_price.to_gwei().map_err(err_from!())?,
-                    new_target_gas_u256.to_gwei().map_err(err_from!())?,
-                );
-
-                max_fee_per_gas = new_target_gas_u256;
-            }
+        if let Some(fee_policy) = &chain_setup.fee_policy {
+            (max_fee_per_gas, max_priority_fee) = gas_prices_from_fee_history(
+                web3.clone(),
+                fee_policy,
+                max_fee_per_gas,
+                max_priority_fee,
+            )
+            .await?;
         }
         web3_tx_dao.max_fee_per_gas = Some(max_fee_per_gas.to_string());
         web3_tx_dao.priority_fee = Some(max_priority_fee.to_string());
@@ -225,9 +203,16 @@ pub async fn process_transaction(
     } else {
         web3_tx_dao.first_processed = Some(chrono::Utc::now());
         web3_tx_dao.processing = 1;
-        update_processing_and_first_processed_tx(conn, web3_tx_dao)
+        if !update_processing_and_first_processed_tx(conn, web3_tx_dao)
             .await
-            .map_err(err_from!())?;
+            .map_err(err_from!())?
+        {
+            log::warn!(
+                "Transaction id: {} already taken by other processor",
+                web3_tx_dao.id
+            );
+            return Ok((web3_tx_dao.clone(), ProcessTransactionResult::DoNotSave));
+        }
     }
 
     if web3_tx_dao.signed_raw_data.is_none() {
@@ -284,23 +269,50 @@ pub async fn process_transaction(
                     }
                 }
                 Err(err) => {
-                    let err_msg = format!("{err}");
-                    if err_msg
-                        .to_lowercase()
-                        .contains("insufficient funds for transfer")
-                    {
-                        log::error!(
-                            "Insufficient {} for tx id: {}",
-                            chain_setup.currency_gas_symbol,
-                            web3_tx_dao.id
-                        );
-                        return Err(err);
-                    }
                     log::error!("Error while checking transaction: {}", err);
                     return Err(err);
                 }
             }
             log::debug!("web3_tx_dao after check_transaction: {:?}", web3_tx_dao);
+
+            if let Some(fee_policy) = &chain_setup.fee_policy {
+                let max_fee_per_gas = web3_tx_dao
+                    .max_fee_per_gas
+                    .as_ref()
+                    .map(|v| v.to_u256())
+                    .transpose()
+                    .map_err(err_from!())?
+                    .unwrap_or(chain_setup.max_fee_per_gas);
+                let tx_max_fee =
+                    max_fee_per_gas * U256::from(web3_tx_dao.gas_limit.unwrap_or_default());
+                if let Some(details) =
+                    check_fee_budget(conn, fee_policy, web3_tx_dao, tx_max_fee).await?
+                {
+                    log::warn!(
+                        "Fee budget ({}) exhausted for account {} - spent: {}, budget: {}, tx max fee: {}",
+                        details.period,
+                        web3_tx_dao.from_addr,
+                        details.spent,
+                        details.budget,
+                        details.tx_max_fee
+                    );
+                    shared_state.lock().unwrap().set_tx_message(
+                        web3_tx_dao.id,
+                        format!("Waiting, {} fee budget exhausted", details.period),
+                    );
+                    send_driver_event(
+                        &event_sender,
+                        DriverEventContent::TransactionStuck(
+                            TransactionStuckReason::FeeBudgetExceeded(details),
+                        ),
+                    )
+                    .await;
+                    return Ok((
+                        web3_tx_dao.clone(),
+                        ProcessTransactionResult::DoNotSaveWaitForGasOrToken,
+                    ));
+                }
+            }
         }
         shared_state
             .lock()
@@ -308,6 +320,9 @@ pub async fn process_transaction(
             .set_tx_message(web3_tx_dao.id, "Signing transaction".to_string());
         sign_transaction_with_callback(&event_sender, web3_tx_dao, from_addr, signer).await?;
         update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        update_token_transfers_status_by_tx(conn, web3_tx_dao.id, TokenTransferStatus::Signed)
+            .await
+            .map_err(err_from!())?;
     }
 
     if web3_tx_dao.broadcast_date.is_none() {
@@ -328,9 +343,13 @@ pub async fn process_transaction(
             event_sender.clone(),
             web3.clone(),
             web3_tx_dao,
+            chain_setup.broadcast_to_all_endpoints,
         )
         .await?;
         update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        update_token_transfers_status_by_tx(conn, web3_tx_dao.id, TokenTransferStatus::Broadcast)
+            .await
+            .map_err(err_from!())?;
         log::info!(
             "Transaction {} sent, tx hash: {}",
             web3_tx_dao.id,
@@ -412,7 +431,7 @@ pub async fn process_transaction(
                 }
             };
 
-            if let Some(effective_gas_price) = res {
+            if res.is_some() {
                 let Some(block_number) = current_tx.block_number.map(|bn| bn as u64) else {
                     return Err(err_custom_create!(
                         "Block number not found on dao for tx: {}",
@@ -433,37 +452,6 @@ pub async fn process_transaction(
                         current_tx.tx_hash.clone().unwrap_or_default()
                     );
 
-                    if is_polygon_eco_mode {
-                        let blockchain_gas_price = web3
-                            .eth_block(BlockId::Number(BlockNumber::Number(U64::from(
-                                block_number,
-                            ))))
-                            .await;
-                        if let Ok(Some(block)) = blockchain_gas_price {
-                            if let Some(base_fee_per_gas_u256) = block.base_fee_per_gas {
-                                if effective_gas_price > base_fee_per_gas_u256 {
-                                    let base_fee_per_gas =
-                                        base_fee_per_gas_u256.to_gwei().unwrap_or_default();
-                                    let effective_priority_fee = (effective_gas_price
-                                        - base_fee_per_gas_u256)
-                                        .to_gwei()
-                                        .unwrap_or_default();
-                                    log::info!(
-                                        "Effective priority fee: {}",
-                                        effective_priority_fee
-                                    );
-                                    log::info!(
-                                        "Saved: {:.2} Gwei ({:.1}%)",
-                                        Decimal::from(30) - effective_priority_fee,
-                                        Decimal::from(100)
-                                            * (Decimal::from(30) - effective_priority_fee)
-                                            / (base_fee_per_gas + Decimal::from(30))
-                                    );
-                                }
-                            }
-                        };
-                    }
-
                     //cleanup txs
                     //let confirmed_tx = current_tx.clone();
                     let mut orig_tx = current_tx.clone();
@@ -485,12 +473,12 @@ pub async fn process_transaction(
                             current_tx.id
                         );
                         //handle case when transaction is allowance
-                        remap_allowance_tx(&mut *db_transaction, orig_tx.id, current_tx.id)
+                        remap_allowance_tx(&mut db_transaction, orig_tx.id, current_tx.id)
                             .await
                             .map_err(err_from!())?;
 
                         //handle case when transaction is token transfer
-                        remap_token_transfer_tx(&mut *db_transaction, orig_tx.id, current_tx.id)
+                        remap_token_transfer_tx(&mut db_transaction, orig_tx.id, current_tx.id)
                             .await
                             .map_err(err_from!())?;
                     }
@@ -499,12 +487,12 @@ pub async fn process_transaction(
                     loop {
                         if process_tx.id != current_tx.id {
                             log::info!("Deleting tx: {}", process_tx.id);
-                            delete_tx(&mut *db_transaction, process_tx.id)
+                            delete_tx(&mut db_transaction, process_tx.id)
                                 .await
                                 .map_err(err_from!())?;
                         }
                         if let Some(next_tx) = process_tx.orig_tx_id {
-                            process_tx = get_transaction(&mut *db_transaction, next_tx)
+                            process_tx = get_transaction(&mut db_transaction, next_tx)
                                 .await
                                 .map_err(err_from!())?;
                         } else {
@@ -512,9 +500,13 @@ pub async fn process_transaction(
                         }
                     }
                     current_tx.orig_tx_id = None;
-                    //signed raw data is no longer needed
-                    current_tx.signed_raw_data = None;
-                    update_tx(&mut *db_transaction, &current_tx)
+                    //signed raw data is no longer needed, unless transaction can still be reorged out
+                    if !chain_setup.finality_blocks.is_some_and(|finality_blocks| {
+                        block_number + finality_blocks > current_block_number
+                    }) {
+                        current_tx.signed_raw_data = None;
+                    }
+                    update_tx(&mut db_transaction, &current_tx)
                         .await
                         .map_err(err_from!())?;
                     db_transaction.commit().await.map_err(err_from!())?;
@@ -733,6 +725,8 @@ pub async fn process_transaction(
                         blockchain_date: None,
                         gas_used: None,
                         block_number: None,
+                        block_hash: None,
+                        final_date: None,
                         chain_status: None,
                         block_gas_price: None,
                         effective_gas_price: None,
@@ -741,6 +735,7 @@ pub async fn process_transaction(
                         engine_message: None,
                         engine_error: None,
                         orig_tx_id: Some(tx.id),
+                        broadcast_endpoints: None,
                     };
                     // used only for specific case testing
                     if let Some(Some(erc20_lib_test_replacement_timeout)) = payment_setup
@@ -755,11 +750,11 @@ pub async fn process_transaction(
                         tokio::time::sleep(erc20_lib_test_replacement_timeout).await;
                     }
                     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-                    let new_tx_dao = insert_tx(&mut *db_transaction, &new_tx_dao)
+                    let new_tx_dao = insert_tx(&mut db_transaction, &new_tx_dao)
                         .await
                         .map_err(err_from!())?;
                     tx.processing = 0;
-                    update_tx(&mut *db_transaction, &tx)
+                    update_tx(&mut db_transaction, &tx)
                         .await
                         .map_err(err_from!())?;
                     db_transaction.commit().await.map_err(err_from!())?;
@@ -794,6 +789,7 @@ pub async fn process_transaction(
                 event_sender.clone(),
                 web3.clone(),
                 web3_tx_dao,
+                chain_setup.broadcast_to_all_endpoints,
             )
             .await?;
             web3_tx_dao.broadcast_count += 1;
@@ -881,6 +877,9 @@ pub async fn process_transaction(
         if !wait_for_confirmation {
             return Ok((web3_tx_dao.clone(), ProcessTransactionResult::Unknown));
         }
-        tokio::time::sleep(Duration::from_secs(payment_setup.process_interval)).await;
+        // wakes up on new block when subscription is available (ws:// or IPC endpoint)
+        web3.clone()
+            .wait_for_new_head(Duration::from_secs(payment_setup.process_interval))
+            .await;
     }
 }
@@ -0,0 +1,271 @@
+use crate::err_from;
+use crate::error::PaymentError;
+use crate::error::*;
+use crate::runtime::send_driver_event;
+use crate::setup::ChainSetup;
+use erc20_payment_lib_common::model::{TokenTransferStatus, TxDbObj};
+use erc20_payment_lib_common::ops::{
+    get_token_transfers_by_tx, get_transactions_awaiting_finality, update_token_transfer, update_tx,
+};
+use erc20_payment_lib_common::DbPool;
+use erc20_payment_lib_common::{DriverEvent, DriverEventContent, TransactionReorgedInfo};
+use erc20_rpc_pool::Web3RpcPool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use web3::types::{Address, BlockId, BlockNumber, U64};
+
+/// Clears all data gathered after the transaction was mined, so it is broadcast and confirmed again
+fn revert_confirmation(tx: &mut TxDbObj) {
+    tx.processing = 1;
+    tx.broadcast_date = None;
+    tx.first_stuck_date = None;
+    tx.confirm_date = None;
+    tx.blockchain_date = None;
+    tx.block_number = None;
+    tx.block_hash = None;
+    tx.chain_status = None;
+    tx.gas_used = None;
+    tx.block_gas_price = None;
+    tx.effective_gas_price = None;
+    tx.fee_paid = None;
+    tx.error = None;
+    tx.final_date = None;
+}
+
+/// Reverts confirmation of the transaction and moves its token transfers back to broadcast state
+async fn revert_reorged_transaction(conn: &DbPool, tx: &mut TxDbObj) -> Result<(), PaymentError> {
+    revert_confirmation(tx);
+
+    let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+    let token_transfers = get_token_transfers_by_tx(&mut db_transaction, tx.id)
+        .await
+        .map_err(err_from!())?;
+    for mut token_transfer in token_transfers {
+        token_transfer.status = TokenTransferStatus::Broadcast;
+        token_transfer.paid_date = None;
+        token_transfer.fee_paid = None;
+        token_transfer.error = None;
+        update_token_transfer(&mut db_transaction, &token_transfer)
+            .await
+            .map_err(err_from!())?;
+    }
+    update_tx(&mut db_transaction, tx)
+        .await
+        .map_err(err_from!())?;
+    db_transaction.commit().await.map_err(err_from!())?;
+    Ok(())
+}
+
+/// Rechecks block hash of confirmed transactions until they are finality_blocks deep.
+/// Transactions whose block is no longer part of the chain are reverted to pending state
+/// together with their token transfers and broadcast again using stored signed data.
+pub async fn check_reorged_transactions(
+    event_sender: &Option<tokio::sync::mpsc::Sender<DriverEvent>>,
+    conn: &DbPool,
+    web3: Arc<Web3RpcPool>,
+    chain_setup: &ChainSetup,
+    from_addr: Address,
+) -> Result<(), PaymentError> {
+    let Some(finality_blocks) = chain_setup.finality_blocks else {
+        return Ok(());
+    };
+    let txs = get_transactions_awaiting_finality(
+        conn,
+        chain_setup.chain_id,
+        &format!("{:#x}", from_addr),
+    )
+    .await
+    .map_err(err_from!())?;
+    if txs.is_empty() {
+        return Ok(());
+    }
+
+    let current_block_number = web3
+        .clone()
+        .eth_block_number()
+        .await
+        .map_err(err_from!())?
+        .as_u64();
+
+    //transactions are often confirmed in the same block, ask for every block only once
+    let mut canonical_hashes: BTreeMap<u64, Option<String>> = BTreeMap::new();
+    for tx in &txs {
+        let Some(block_number) = tx.block_number.map(|bn| bn as u64) else {
+            continue;
+        };
+        if canonical_hashes.contains_key(&block_number) {
+            continue;
+        }
+        let block = web3
+            .clone()
+            .eth_block(BlockId::Number(BlockNumber::Number(U64::from(
+                block_number,
+            ))))
+            .await
+            .map_err(err_from!())?;
+        canonical_hashes.insert(
+            block_number,
+            block.and_then(|b| b.hash).map(|h| format!("{:#x}", h)),
+        );
+    }
+
+    for mut tx in txs {
+        let (Some(block_number), Some(block_hash)) = (tx.block_number, tx.block_hash.clone())
+        else {
+            continue;
+        };
+        let Some(canonical_hash) = canonical_hashes
+            .get(&(block_number as u64))
+            .cloned()
+            .flatten()
+        else {
+            //endpoint is behind, check again later
+            log::debug!(
+                "Block {} of tx {} not found, skipping reorg check",
+                block_number,
+                tx.id
+            );
+            continue;
+        };
+
+        if canonical_hash == block_hash {
+            if block_number as u64 + finality_blocks <= current_block_number {
+                log::debug!("Transaction {} reached finality", tx.id);
+                tx.final_date = Some(chrono::Utc::now());
+                //signed raw data is no longer needed
+                tx.signed_raw_data = None;
+                update_tx(conn, &tx).await.map_err(err_from!())?;
+            }
+            continue;
+        }
+
+        log::warn!(
+            "Transaction {} tx_hash: {} was confirmed in block {} ({}), which is no longer part of the chain ({}). Broadcasting it again",
+            tx.id,
+            tx.tx_hash.clone().unwrap_or_default(),
+            block_number,
+            block_hash,
+            canonical_hash
+        );
+        revert_reorged_transaction(conn, &mut tx).await?;
+
+        send_driver_event(
+            event_sender,
+            DriverEventContent::TransactionReorged(TransactionReorgedInfo {
+                tx_dao: tx,
+                block_number,
+                block_hash,
+            }),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revert_confirmation() {
+        let mut tx = TxDbObj {
+            processing: 0,
+            signed_raw_data: Some("0x02f8".to_string()),
+            broadcast_date: Some(chrono::Utc::now()),
+            confirm_date: Some(chrono::Utc::now()),
+            block_number: Some(100),
+            block_hash: Some("0xabc".to_string()),
+            chain_status: Some(1),
+            fee_paid: Some("21000".to_string()),
+            ..Default::default()
+        };
+        revert_confirmation(&mut tx);
+        assert_eq!(tx.processing, 1);
+        assert!(tx.broadcast_date.is_none());
+        assert!(tx.confirm_date.is_none());
+        assert!(tx.block_number.is_none());
+        assert!(tx.block_hash.is_none());
+        assert!(tx.chain_status.is_none());
+        assert!(tx.fee_paid.is_none());
+        //signed data is needed to broadcast the transaction again
+        assert_eq!(tx.signed_raw_data, Some("0x02f8".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_revert_reorged_transaction() {
+        use erc20_payment_lib_common::create_sqlite_connection;
+        use erc20_payment_lib_common::model::TokenTransferDbObj;
+        use erc20_payment_lib_common::ops::{get_transaction, insert_token_transfer, insert_tx};
+
+        let conn = create_sqlite_connection(None, None, false, true)
+            .await
+            .unwrap();
+
+        let confirmed_tx = TxDbObj {
+            from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+            chain_id: 987789,
+            signed_raw_data: Some("0x02f8".to_string()),
+            broadcast_date: Some(chrono::Utc::now()),
+            confirm_date: Some(chrono::Utc::now()),
+            block_number: Some(100),
+            block_hash: Some("0xabc".to_string()),
+            chain_status: Some(1),
+            fee_paid: Some("21000".to_string()),
+            ..Default::default()
+        };
+        let mut reorged_tx = insert_tx(&conn, &confirmed_tx).await.unwrap();
+        let other_tx = insert_tx(&conn, &confirmed_tx).await.unwrap();
+
+        let confirmed_transfer = |tx_id: i64| TokenTransferDbObj {
+            id: -1,
+            payment_id: None,
+            from_addr: confirmed_tx.from_addr.clone(),
+            receiver_addr: "0x0000000000000000000000000000000000000001".to_string(),
+            chain_id: 987789,
+            token_addr: None,
+            token_amount: "1".to_string(),
+            deposit_id: None,
+            deposit_finish: 0,
+            create_date: chrono::Utc::now(),
+            tx_id: Some(tx_id),
+            paid_date: Some(chrono::Utc::now()),
+            fee_paid: Some("10500".to_string()),
+            error: None,
+            status: TokenTransferStatus::Confirmed,
+            priority: 0,
+            deadline: None,
+        };
+        for tx_id in [reorged_tx.id, reorged_tx.id, other_tx.id] {
+            insert_token_transfer(&conn, &confirmed_transfer(tx_id))
+                .await
+                .unwrap();
+        }
+
+        revert_reorged_transaction(&conn, &mut reorged_tx)
+            .await
+            .unwrap();
+
+        let reverted = get_transaction(&conn, reorged_tx.id).await.unwrap();
+        assert_eq!(reverted.processing, 1);
+        assert!(reverted.confirm_date.is_none());
+        assert!(reverted.block_hash.is_none());
+        assert!(reverted.fee_paid.is_none());
+        assert_eq!(reverted.signed_raw_data, Some("0x02f8".to_string()));
+        let reverted_transfers = get_token_transfers_by_tx(&conn, reorged_tx.id)
+            .await
+            .unwrap();
+        assert_eq!(reverted_transfers.len(), 2);
+        for transfer in reverted_transfers {
+            assert_eq!(transfer.status, TokenTransferStatus::Broadcast);
+            assert!(transfer.paid_date.is_none());
+            assert!(transfer.fee_paid.is_none());
+        }
+
+        assert_eq!(get_transaction(&conn, other_tx.id).await.unwrap(), other_tx);
+        let other_transfers = get_token_transfers_by_tx(&conn, other_tx.id).await.unwrap();
+        assert_eq!(other_transfers.len(), 1);
+        assert_eq!(other_transfers[0].status, TokenTransferStatus::Confirmed);
+        assert!(other_transfers[0].paid_date.is_some());
+        assert_eq!(other_transfers[0].fee_paid, Some("10500".to_string()));
+    }
+}
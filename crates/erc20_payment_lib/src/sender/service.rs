@@ -9,21 +9,24 @@ use crate::sender::process::{process_transaction, ProcessTransactionResult};
 use crate::utils::ConversionError;
 
 use crate::runtime::{send_driver_event, SharedState};
-use crate::sender::batching::{gather_transactions_post, gather_transactions_pre};
+use crate::sender::batching::{
+    gather_transactions_post, gather_transactions_pre, is_relayer_work_pending,
+};
+use crate::sender::check_reorged_transactions;
 use crate::sender::process_allowance;
 use crate::setup::PaymentSetup;
 use crate::signer::{Signer, SignerAccount};
 use crate::{err_create, err_custom_create, err_from};
-use erc20_payment_lib_common::model::TxDbObj;
+use erc20_payment_lib_common::model::{TokenTransferStatus, TxDbObj};
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{DriverEvent, DriverEventContent, TransactionFinishedInfo};
-use sqlx::SqlitePool;
 use tokio::select;
 use tokio::time::Instant;
 use web3::types::{Address, U256};
 
 pub async fn update_token_transfer_result(
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     tx: &mut TxDbObj,
     process_t_res: &ProcessTransactionResult,
 ) -> Result<(), PaymentError> {
@@ -32,7 +35,7 @@ pub async fn update_token_transfer_result(
             tx.processing = 0;
 
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let mut token_transfers = get_token_transfers_by_tx(&mut *db_transaction, tx.id)
+            let mut token_transfers = get_token_transfers_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
 
@@ -80,12 +83,17 @@ pub async fn update_token_transfer_result(
             for (token_transfer, fee_paid) in token_transfers.iter_mut().zip(distribute_fee) {
                 token_transfer.fee_paid = fee_paid.map(|v| v.to_string());
                 token_transfer.paid_date = Some(chrono::Utc::now());
+                token_transfer.status = if tx.chain_status == Some(0) {
+                    TokenTransferStatus::Failed
+                } else {
+                    TokenTransferStatus::Confirmed
+                };
 
-                update_token_transfer(&mut *db_transaction, token_transfer)
+                update_token_transfer(&mut db_transaction, token_transfer)
                     .await
                     .map_err(err_from!())?;
             }
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -105,19 +113,20 @@ pub async fn update_token_transfer_result(
             tx.processing = 0;
 
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let token_transfers = get_token_transfers_by_tx(&mut *db_transaction, tx.id)
+            let token_transfers = get_token_transfers_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
             for mut token_transfer in token_transfers {
                 token_transfer.fee_paid = Some("0".to_string());
                 token_transfer.error = Some(err.clone());
-                update_token_transfer(&mut *db_transaction, &token_transfer)
+                token_transfer.status = TokenTransferStatus::Failed;
+                update_token_transfer(&mut db_transaction, &token_transfer)
                     .await
                     .map_err(err_from!())?;
             }
             tx.error = Some(err.clone());
 
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -126,19 +135,20 @@ pub async fn update_token_transfer_result(
             tx.processing = 0;
 
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let token_transfers = get_token_transfers_by_tx(&mut *db_transaction, tx.id)
+            let token_transfers = get_token_transfers_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
             for mut token_transfer in token_transfers {
                 token_transfer.fee_paid = Some("0".to_string());
                 token_transfer.error = Some(err.clone());
-                update_token_transfer(&mut *db_transaction, &token_transfer)
+                token_transfer.status = TokenTransferStatus::Failed;
+                update_token_transfer(&mut db_transaction, &token_transfer)
                     .await
                     .map_err(err_from!())?;
             }
             tx.error = Some(err.clone());
 
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -154,7 +164,7 @@ pub async fn update_token_transfer_result(
 
 pub async fn update_approve_result(
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     tx: &mut TxDbObj,
     process_t_res: &ProcessTransactionResult,
 ) -> Result<(), PaymentError> {
@@ -163,14 +173,14 @@ pub async fn update_approve_result(
             tx.processing = 0;
 
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let mut allowance = get_allowance_by_tx(&mut *db_transaction, tx.id)
+            let mut allowance = get_allowance_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
             allowance.fee_paid = tx.fee_paid.clone();
-            update_allowance(&mut *db_transaction, &allowance)
+            update_allowance(&mut db_transaction, &allowance)
                 .await
                 .map_err(err_from!())?;
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -184,16 +194,16 @@ pub async fn update_approve_result(
         ProcessTransactionResult::NeedRetry(err) => {
             tx.processing = 0;
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let mut allowance = get_allowance_by_tx(&mut *db_transaction, tx.id)
+            let mut allowance = get_allowance_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
             allowance.fee_paid = Some("0".to_string());
             allowance.error = Some(err.clone());
             tx.error = Some(err.clone());
-            update_allowance(&mut *db_transaction, &allowance)
+            update_allowance(&mut db_transaction, &allowance)
                 .await
                 .map_err(err_from!())?;
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -201,16 +211,16 @@ pub async fn update_approve_result(
         ProcessTransactionResult::InternalError(err) => {
             tx.processing = 0;
             let mut db_transaction = conn.begin().await.map_err(err_from!())?;
-            let mut allowance = get_allowance_by_tx(&mut *db_transaction, tx.id)
+            let mut allowance = get_allowance_by_tx(&mut db_transaction, tx.id)
                 .await
                 .map_err(err_from!())?;
             allowance.fee_paid = Some("0".to_string());
             allowance.error = Some(err.clone());
             tx.error = Some(err.clone());
-            update_allowance(&mut *db_transaction, &allowance)
+            update_allowance(&mut db_transaction, &allowance)
                 .await
                 .map_err(err_from!())?;
-            update_tx(&mut *db_transaction, tx)
+            update_tx(&mut db_transaction, tx)
                 .await
                 .map_err(err_from!())?;
             db_transaction.commit().await.map_err(err_from!())?;
@@ -225,7 +235,7 @@ pub async fn update_approve_result(
 }
 
 pub async fn update_tx_result(
-    conn: &SqlitePool,
+    conn: &DbPool,
     tx: &mut TxDbObj,
     process_t_res: &ProcessTransactionResult,
 ) -> Result<(), PaymentError> {
@@ -258,12 +268,27 @@ pub async fn process_transactions(
     chain_id: i64,
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
     shared_state: Arc<std::sync::Mutex<SharedState>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     signer: Arc<Box<dyn Signer + Send + Sync + 'static>>,
 ) -> Result<(), PaymentError> {
     //remove tx from current processing infos
 
+    if let Some(chain_setup) = payment_setup.chain_setup.get(&chain_id) {
+        let web3 = payment_setup.get_provider(chain_id)?;
+        if let Err(err) = check_reorged_transactions(
+            &event_sender,
+            conn,
+            web3,
+            chain_setup,
+            signer_account.address,
+        )
+        .await
+        {
+            log::warn!("Failed to check confirmed transactions for reorgs: {}", err);
+        }
+    }
+
     let mut current_wait_time_no_gas_token: f64 = 0.0;
     loop {
         let mut transactions =
@@ -298,7 +323,7 @@ pub async fn process_transactions(
             .await
             {
                 Ok((tx_dao, process_result)) => (tx_dao, process_result),
-                Err(err) => match err.inner {
+                Err(err) => match *err.inner {
                     ErrorBag::TransactionFailedError(err2) => {
                         shared_state
                             .lock()
@@ -330,6 +355,7 @@ pub async fn process_transactions(
         };
         if tx.method.starts_with("MULTI.golemTransfer")
             || tx.method == "ERC20.transfer"
+            || tx.method == "ERC20.executeMetaTransaction"
             || tx.method == "transfer"
         {
             log::debug!("Updating token transfer result");
@@ -407,6 +433,42 @@ fn get_next_gather_time(
     }
 }
 
+/// Transfers inserted outside of the runtime (CLI, import) do not schedule gathering,
+/// so deadlines stored in db are checked too. Deadlines flushed by the last gathering are skipped.
+async fn schedule_gather_for_stored_deadlines(
+    account: &SignerAccount,
+    chain_id: i64,
+    conn: &DbPool,
+    payment_setup: &PaymentSetup,
+    last_gather_time: chrono::DateTime<chrono::Utc>,
+) {
+    let deadlines =
+        match get_pending_token_transfer_deadlines(conn, account.address, chain_id).await {
+            Ok(deadlines) => deadlines,
+            Err(err) => {
+                log::warn!("Failed to get deadlines of pending transfers: {}", err);
+                return;
+            }
+        };
+    let deadline_margin =
+        chrono::Duration::try_seconds(payment_setup.batching_policy.deadline_margin as i64)
+            .unwrap_or_default();
+    let Some(flush_time) = deadlines
+        .into_iter()
+        .map(|deadline| deadline - deadline_margin)
+        .filter(|flush_time| *flush_time > last_gather_time)
+        .min()
+    else {
+        return;
+    };
+    let mut external_gather_time = account.external_gather_time.lock().unwrap();
+    *external_gather_time = Some(
+        external_gather_time
+            .map(|t| t.min(flush_time))
+            .unwrap_or(flush_time),
+    );
+}
+
 fn get_next_gather_time_and_clear_if_success(
     account: &SignerAccount,
     last_gather_time: chrono::DateTime<chrono::Utc>,
@@ -473,7 +535,7 @@ pub async fn service_loop(
     chain_id: i64,
     account: Address,
     wake: Arc<tokio::sync::Notify>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     payment_setup: &PaymentSetup,
     event_sender: Option<tokio::sync::mpsc::Sender<DriverEvent>>,
 ) {
@@ -553,6 +615,17 @@ pub async fn service_loop(
 
         //we should be here only when all pending transactions are processed
 
+        if !payment_setup.ignore_deadlines {
+            schedule_gather_for_stored_deadlines(
+                &signer_account,
+                chain_id,
+                conn,
+                payment_setup,
+                last_gather_time,
+            )
+            .await;
+        }
+
         let next_gather_time = get_next_gather_time_and_clear_if_success(
             &signer_account,
             last_gather_time,
@@ -612,6 +685,7 @@ pub async fn service_loop(
 
         match gather_transactions_post(
             event_sender.clone(),
+            &signer_account,
             conn,
             payment_setup,
             &mut token_transfer_map,
@@ -624,7 +698,7 @@ pub async fn service_loop(
                 }
             }
             Err(e) => {
-                match &e.inner {
+                match e.inner.as_ref() {
                     ErrorBag::NoAllowanceFound(allowance_request) => {
                         log::info!(
                             "No allowance found for contract {} to spend token {} for owner: {}",
@@ -669,6 +743,17 @@ pub async fn service_loop(
         };
         last_gather_time = current_time;
         if payment_setup.finish_when_done && !process_tx_needed {
+            match is_relayer_work_pending(conn, payment_setup, account, chain_id).await {
+                Ok(true) => {
+                    log::debug!("Waiting for meta transactions to relay...");
+                    tokio::time::sleep(Duration::from_secs(payment_setup.process_interval)).await;
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    log::error!("Error when checking relayer work: {}", e);
+                }
+            }
             log::info!("No more work to do, exiting...");
             break;
         }
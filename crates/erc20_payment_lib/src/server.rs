@@ -1,2 +1,4 @@
+pub mod auth;
+pub mod openapi;
 pub mod web;
 pub mod ws;
@@ -0,0 +1,445 @@
+use crate::config::{ApiAuthSettings, ApiClientSettings, ApiRole};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::HttpMessage;
+use actix_web::{web, HttpResponse};
+pub use erc20_payment_lib_common::api::{
+    hmac_signature, API_CLIENT_HEADER, API_MAX_NONCE_LENGTH, API_NONCE_HEADER,
+    API_SIGNATURE_HEADER, API_TIMESTAMP_HEADER,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use futures_util::{FutureExt, StreamExt};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+const MAX_SIGNED_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Query parameter accepted instead of bearer header in GET requests,
+/// browsers cannot set headers when opening websocket (/event_stream)
+pub const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+/// Nonces of accepted HMAC signed requests, shared by all server workers.
+/// Nonce is remembered until its timestamp leaves hmac-max-skew window,
+/// later the request is rejected because of the timestamp anyway.
+#[derive(Clone, Default)]
+pub struct ApiNonceCache {
+    seen: Arc<Mutex<HashMap<(String, String), i64>>>,
+}
+
+impl ApiNonceCache {
+    /// Returns false if the client already used the nonce
+    fn insert(&self, client_name: &str, nonce: &str, timestamp: i64, max_skew: u64) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, ts| ts.saturating_add(max_skew as i64) >= now);
+        seen.insert((client_name.to_string(), nonce.to_string()), timestamp)
+            .is_none()
+    }
+}
+
+/// Role needed to call endpoint, None for endpoints open to everyone.
+/// Path is relative to /api scope.
+pub fn required_role(method: &Method, path: &str) -> Option<ApiRole> {
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    match (method.as_str(), segments.as_slice()) {
+        ("GET", [] | ["version"] | ["openapi.json"]) => None,
+        ("POST", ["transfers", "new" | "batch"] | ["transfers", _, "amend"]) => {
+            Some(ApiRole::Payer)
+        }
+        ("POST", ["tx", "skip", _] | ["transfers", _, "cancel"]) => Some(ApiRole::Operator),
+        ("GET", ["faucet", ..] | ["config"] | ["debug"]) => Some(ApiRole::Operator),
+        ("GET" | "HEAD" | "OPTIONS", _) => Some(ApiRole::ReadOnly),
+        _ => Some(ApiRole::Operator),
+    }
+}
+
+fn has_role(client: &ApiClientSettings, role: ApiRole) -> bool {
+    role == ApiRole::ReadOnly || client.roles.contains(&role)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn header_str<'a>(req: &'a ServiceRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn client_by_token(
+    settings: &ApiAuthSettings,
+    token: &str,
+) -> Result<(String, ApiClientSettings), String> {
+    settings
+        .clients
+        .iter()
+        .find(|(_, client)| {
+            client
+                .token
+                .as_ref()
+                .is_some_and(|t| constant_time_eq(t.as_bytes(), token.trim().as_bytes()))
+        })
+        .map(|(name, client)| (name.clone(), client.clone()))
+        .ok_or("Invalid bearer token".to_string())
+}
+
+async fn authenticate(
+    settings: &ApiAuthSettings,
+    nonces: &ApiNonceCache,
+    req: &mut ServiceRequest,
+) -> Result<(String, ApiClientSettings), String> {
+    if let Some(token) = header_str(req, "Authorization").and_then(|h| h.strip_prefix("Bearer ")) {
+        return client_by_token(settings, token);
+    }
+    if req.method() == Method::GET {
+        if let Some(token) = web::Query::<BTreeMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.get(ACCESS_TOKEN_PARAM).cloned())
+        {
+            return client_by_token(settings, &token);
+        }
+    }
+
+    let Some(client_name) = header_str(req, API_CLIENT_HEADER).map(|s| s.to_string()) else {
+        return Err("Missing credentials".to_string());
+    };
+    let client = settings
+        .clients
+        .get(&client_name)
+        .ok_or(format!("Unknown client {client_name}"))?;
+    let secret = client
+        .hmac_secret
+        .as_ref()
+        .ok_or(format!("Client {client_name} cannot sign requests"))?;
+    let timestamp = header_str(req, API_TIMESTAMP_HEADER)
+        .and_then(|t| t.parse::<i64>().ok())
+        .ok_or("Missing or invalid timestamp".to_string())?;
+    if (chrono::Utc::now().timestamp() - timestamp).unsigned_abs() > settings.hmac_max_skew {
+        return Err("Request timestamp out of range".to_string());
+    }
+    let nonce = header_str(req, API_NONCE_HEADER)
+        .filter(|n| !n.is_empty() && n.len() <= API_MAX_NONCE_LENGTH)
+        .ok_or("Missing or invalid nonce".to_string())?
+        .to_string();
+    let signature = header_str(req, API_SIGNATURE_HEADER)
+        .ok_or("Missing signature".to_string())?
+        .to_lowercase();
+
+    //signature covers request body, read it and put it back for the handler
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|err| format!("Cannot read request body: {err}"))?;
+        if body.len() + chunk.len() > MAX_SIGNED_BODY_SIZE {
+            return Err("Request body too large".to_string());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/")
+        .to_string();
+    let expected = hmac_signature(
+        secret,
+        timestamp,
+        &nonce,
+        req.method().as_str(),
+        &path_and_query,
+        &body,
+    );
+    let payload_body = body.clone();
+    req.set_payload(Payload::Stream {
+        payload: Box::pin(futures_util::stream::once(async move { Ok(payload_body) })),
+    });
+    if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+        return Err("Invalid signature".to_string());
+    }
+    //only requests with valid signature are remembered, so unknown callers cannot fill the cache
+    if !nonces.insert(&client_name, &nonce, timestamp, settings.hmac_max_skew) {
+        return Err("Nonce already used".to_string());
+    }
+    Ok((client_name, client.clone()))
+}
+
+/// Middleware checking bearer token or HMAC signature of API requests and role of the caller.
+/// Does nothing when auth is not configured.
+#[derive(Clone)]
+pub struct ApiAuth {
+    settings: Option<Arc<ApiAuthSettings>>,
+    nonces: ApiNonceCache,
+}
+
+impl ApiAuth {
+    /// Nonce cache has to be shared by all workers, otherwise request can be replayed on other worker
+    pub fn new(settings: Option<ApiAuthSettings>, nonces: ApiNonceCache) -> Self {
+        Self {
+            settings: settings.map(Arc::new),
+            nonces,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = ApiAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiAuthMiddleware {
+            service: Rc::new(service),
+            settings: self.settings.clone(),
+            nonces: self.nonces.clone(),
+        }))
+    }
+}
+
+pub struct ApiAuthMiddleware<S> {
+    service: Rc<S>,
+    settings: Option<Arc<ApiAuthSettings>>,
+    nonces: ApiNonceCache,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let settings = self.settings.clone();
+        let nonces = self.nonces.clone();
+        async move {
+            let required = settings
+                .as_ref()
+                .and_then(|_| required_role(req.method(), req.match_info().unprocessed()));
+            if let (Some(settings), Some(required)) = (settings, required) {
+                match authenticate(&settings, &nonces, &mut req).await {
+                    Ok((client_name, client)) => {
+                        if !has_role(&client, required) {
+                            log::warn!(
+                                "API client {} without {:?} role tried {} {}",
+                                client_name,
+                                required,
+                                req.method(),
+                                req.path()
+                            );
+                            let response = HttpResponse::Forbidden()
+                                .json(json!({"error": format!("{:?} role required", required)}));
+                            return Ok(req.into_response(response).map_into_right_body());
+                        }
+                    }
+                    Err(err) => {
+                        log::debug!("Unauthorized API request {}: {}", req.path(), err);
+                        let response = HttpResponse::Unauthorized()
+                            .insert_header(("WWW-Authenticate", "Bearer"))
+                            .json(json!({ "error": err }));
+                        return Ok(req.into_response(response).map_into_right_body());
+                    }
+                }
+            }
+            service.call(req).await.map(|res| res.map_into_left_body())
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, Scope};
+
+    fn auth_settings() -> ApiAuthSettings {
+        let mut clients = BTreeMap::new();
+        clients.insert(
+            "monitoring".to_string(),
+            ApiClientSettings {
+                roles: vec![],
+                token: Some("read-token".to_string()),
+                hmac_secret: None,
+            },
+        );
+        clients.insert(
+            "billing".to_string(),
+            ApiClientSettings {
+                roles: vec![ApiRole::Payer],
+                token: None,
+                hmac_secret: Some("billing-secret".to_string()),
+            },
+        );
+        ApiAuthSettings {
+            hmac_max_skew: 300,
+            clients,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_nonce_cache() {
+        let nonces = ApiNonceCache::default();
+        let now = chrono::Utc::now().timestamp();
+        assert!(nonces.insert("billing", "nonce-1", now, 300));
+        assert!(!nonces.insert("billing", "nonce-1", now, 300));
+        assert!(nonces.insert("monitoring", "nonce-1", now, 300));
+        // timestamp out of skew window, request would be rejected before checking nonce
+        assert!(nonces.insert("billing", "nonce-2", now - 1000, 300));
+        assert!(nonces.insert("billing", "nonce-2", now - 1000, 300));
+        assert_eq!(nonces.seen.lock().unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_api_auth() {
+        let app = test::init_service(
+            App::new().service(
+                Scope::new("erc20").service(
+                    Scope::new("/api")
+                        .route("/version", web::get().to(HttpResponse::Ok))
+                        .route("/transfers", web::get().to(HttpResponse::Ok))
+                        .route(
+                            "/transfers/new",
+                            web::post()
+                                .to(|body: String| async move { HttpResponse::Ok().body(body) }),
+                        )
+                        .route("/tx/skip/{tx_id}", web::post().to(HttpResponse::Ok))
+                        .wrap(ApiAuth::new(
+                            Some(auth_settings()),
+                            ApiNonceCache::default(),
+                        )),
+                ),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/erc20/api/version")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/erc20/api/transfers")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/erc20/api/transfers")
+            .insert_header(("Authorization", "Bearer read-token"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        // token in query is accepted only for GET requests (websocket)
+        let req = test::TestRequest::get()
+            .uri("/erc20/api/transfers?access_token=read-token")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::get()
+            .uri("/erc20/api/transfers?access_token=wrong-token")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/tx/skip/1?access_token=read-token")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/tx/skip/1")
+            .insert_header(("Authorization", "Bearer read-token"))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let body = r#"{"amount":"1"}"#;
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = hmac_signature(
+            "billing-secret",
+            timestamp,
+            "nonce-1",
+            "POST",
+            "/erc20/api/transfers/new",
+            body.as_bytes(),
+        );
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/transfers/new")
+            .insert_header((API_CLIENT_HEADER, "billing"))
+            .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((API_NONCE_HEADER, "nonce-1"))
+            .insert_header((API_SIGNATURE_HEADER, signature.clone()))
+            .set_payload(body)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, body.as_bytes());
+
+        // the same request sent again
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/transfers/new")
+            .insert_header((API_CLIENT_HEADER, "billing"))
+            .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((API_NONCE_HEADER, "nonce-1"))
+            .insert_header((API_SIGNATURE_HEADER, signature.clone()))
+            .set_payload(body)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // nonce is part of the signature, cannot be replaced
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/transfers/new")
+            .insert_header((API_CLIENT_HEADER, "billing"))
+            .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((API_NONCE_HEADER, "nonce-2"))
+            .insert_header((API_SIGNATURE_HEADER, signature.clone()))
+            .set_payload(body)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // body changed after signing
+        let req = test::TestRequest::post()
+            .uri("/erc20/api/transfers/new")
+            .insert_header((API_CLIENT_HEADER, "billing"))
+            .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((API_NONCE_HEADER, "nonce-3"))
+            .insert_header((API_SIGNATURE_HEADER, signature))
+            .set_payload(r#"{"amount":"1000"}"#)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
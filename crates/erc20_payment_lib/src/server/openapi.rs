@@ -0,0 +1,1134 @@
+use crate::server::auth::{
+    required_role, ACCESS_TOKEN_PARAM, API_CLIENT_HEADER, API_SIGNATURE_HEADER,
+};
+use actix_web::http::Method;
+use erc20_payment_lib_common::model::{MAX_TRANSFER_PRIORITY, MIN_TRANSFER_PRIORITY};
+use serde_json::{json, Map, Value};
+
+fn string() -> Value {
+    json!({"type": "string"})
+}
+
+fn integer() -> Value {
+    json!({"type": "integer", "format": "int64"})
+}
+
+fn number() -> Value {
+    json!({"type": "number"})
+}
+
+fn boolean() -> Value {
+    json!({"type": "boolean"})
+}
+
+fn date_time() -> Value {
+    json!({"type": "string", "format": "date-time"})
+}
+
+fn any_object() -> Value {
+    json!({"type": "object", "additionalProperties": true})
+}
+
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{name}")})
+}
+
+fn nullable(schema: Value) -> Value {
+    match schema {
+        Value::Object(mut obj) if !obj.contains_key("$ref") => {
+            obj.insert("nullable".to_string(), Value::Bool(true));
+            Value::Object(obj)
+        }
+        schema => json!({"allOf": [schema], "nullable": true}),
+    }
+}
+
+/// Object schema, every property that is not nullable is required
+fn object(props: &[(&str, Value)]) -> Value {
+    let required = props
+        .iter()
+        .filter(|(_, schema)| schema.get("nullable").is_none())
+        .map(|(name, _)| Value::String(name.to_string()))
+        .collect::<Vec<_>>();
+    let properties = props
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect::<Map<_, _>>();
+    json!({"type": "object", "required": required, "properties": properties})
+}
+
+fn schemas() -> Map<String, Value> {
+    let schemas = [
+        (
+            "ApiError",
+            object(&[
+                ("error", string()),
+                ("transfer", nullable(schema_ref("TokenTransfer"))),
+            ]),
+        ),
+        (
+            "VersionResponse",
+            object(&[("name", string()), ("version", string())]),
+        ),
+        (
+            "Tx",
+            object(&[
+                ("id", integer()),
+                ("method", string()),
+                ("fromAddr", string()),
+                ("toAddr", string()),
+                ("chainId", integer()),
+                ("gasLimit", nullable(integer())),
+                ("maxFeePerGas", nullable(string())),
+                ("priorityFee", nullable(string())),
+                ("val", string()),
+                ("nonce", nullable(integer())),
+                ("processing", integer()),
+                ("createdDate", date_time()),
+                ("firstProcessed", nullable(date_time())),
+                ("txHash", nullable(string())),
+                ("signedDate", nullable(date_time())),
+                ("broadcastDate", nullable(date_time())),
+                ("broadcastCount", integer()),
+                ("firstStuckDate", nullable(date_time())),
+                ("confirmDate", nullable(date_time())),
+                ("blockchainDate", nullable(date_time())),
+                ("gasUsed", nullable(integer())),
+                ("blockNumber", nullable(integer())),
+                ("blockHash", nullable(string())),
+                ("finalDate", nullable(date_time())),
+                ("chainStatus", nullable(integer())),
+                ("blockGasPrice", nullable(string())),
+                ("effectiveGasPrice", nullable(string())),
+                ("feePaid", nullable(string())),
+                ("error", nullable(string())),
+                ("origTxId", nullable(integer())),
+                ("broadcastEndpoints", nullable(string())),
+                ("engineMessage", nullable(string())),
+                ("engineError", nullable(string())),
+            ]),
+        ),
+        (
+            "TokenTransfer",
+            object(&[
+                ("id", integer()),
+                ("paymentId", nullable(string())),
+                ("fromAddr", string()),
+                ("receiverAddr", string()),
+                ("chainId", integer()),
+                ("tokenAddr", nullable(string())),
+                ("tokenAmount", string()),
+                ("depositId", nullable(string())),
+                ("depositFinish", integer()),
+                ("createDate", date_time()),
+                ("txId", nullable(integer())),
+                ("paidDate", nullable(date_time())),
+                ("feePaid", nullable(string())),
+                ("error", nullable(string())),
+                (
+                    "status",
+                    json!({"type": "string", "enum": ["queued", "batched", "signed", "broadcast", "confirmed", "failed", "cancelled"]}),
+                ),
+                ("priority", integer()),
+                ("deadline", nullable(date_time())),
+            ]),
+        ),
+        (
+            "Allowance",
+            object(&[
+                ("id", integer()),
+                ("owner", string()),
+                ("tokenAddr", string()),
+                ("spender", string()),
+                ("allowance", string()),
+                ("chainId", integer()),
+                ("txId", nullable(integer())),
+                ("feePaid", nullable(string())),
+                ("confirmDate", nullable(date_time())),
+                ("error", nullable(string())),
+            ]),
+        ),
+        (
+            "TransferIn",
+            object(&[
+                ("id", integer()),
+                ("paymentId", string()),
+                ("fromAddr", string()),
+                ("receiverAddr", string()),
+                ("chainId", integer()),
+                ("tokenAddr", nullable(string())),
+                ("tokenAmount", string()),
+                ("txHash", nullable(string())),
+                ("requestedDate", date_time()),
+                ("receivedDate", nullable(date_time())),
+            ]),
+        ),
+        (
+            "SharedInfoTx",
+            object(&[
+                ("message", string()),
+                ("error", nullable(string())),
+                ("skip", boolean()),
+            ]),
+        ),
+        (
+            "AllowancesResponse",
+            object(&[("allowances", array(schema_ref("Allowance")))]),
+        ),
+        ("TxResponse", object(&[("tx", schema_ref("Tx"))])),
+        (
+            "TransactionsResponse",
+            object(&[("txs", array(schema_ref("Tx")))]),
+        ),
+        (
+            "TransactionsFeedResponse",
+            object(&[
+                ("txs", array(schema_ref("Tx"))),
+                (
+                    "current",
+                    json!({"type": "object", "additionalProperties": schema_ref("SharedInfoTx")}),
+                ),
+            ]),
+        ),
+        (
+            "TransactionsCountResponse",
+            object(&[
+                ("transfersQueued", integer()),
+                ("transfersProcessing", integer()),
+                ("transfersDone", integer()),
+                ("txQueued", integer()),
+                ("txDone", integer()),
+            ]),
+        ),
+        ("SkipTxResponse", object(&[("success", boolean())])),
+        (
+            "TransfersResponse",
+            object(&[("transfers", array(schema_ref("TokenTransfer")))]),
+        ),
+        (
+            "TransferResponse",
+            object(&[("transfer", schema_ref("TokenTransfer"))]),
+        ),
+        (
+            "NewTransferRequest",
+            object(&[
+                ("from", string()),
+                ("to", string()),
+                ("token", nullable(string())),
+                ("amount", string()),
+                ("chain", integer()),
+                ("dueDate", nullable(date_time())),
+                ("paymentId", nullable(string())),
+                ("depositId", nullable(string())),
+                (
+                    "priority",
+                    json!({"type": "integer", "format": "int64", "nullable": true,
+                        "minimum": MIN_TRANSFER_PRIORITY, "maximum": MAX_TRANSFER_PRIORITY}),
+                ),
+            ]),
+        ),
+        ("NewTransferResponse", object(&[("paymentId", string())])),
+        (
+            "TransferBatchRowResult",
+            object(&[
+                ("row", integer()),
+                ("paymentId", nullable(string())),
+                ("error", nullable(string())),
+            ]),
+        ),
+        (
+            "TransferBatchResponse",
+            object(&[
+                ("inserted", integer()),
+                ("invalid", integer()),
+                ("results", array(schema_ref("TransferBatchRowResult"))),
+            ]),
+        ),
+        (
+            "CancelTransferRequest",
+            object(&[("from", string()), ("chain", integer())]),
+        ),
+        (
+            "AmendTransferRequest",
+            object(&[
+                ("from", string()),
+                ("chain", integer()),
+                ("to", nullable(string())),
+                ("amount", nullable(string())),
+            ]),
+        ),
+        (
+            "ChainTransfer",
+            object(&[
+                ("id", integer()),
+                ("fromAddr", string()),
+                ("receiverAddr", string()),
+                ("chainId", integer()),
+                ("tokenAddr", nullable(string())),
+                ("tokenAmount", string()),
+                ("txHash", string()),
+                ("blockNumber", integer()),
+                ("feePaid", nullable(string())),
+                ("blockDate", date_time()),
+                ("blockTimestamp", integer()),
+                ("toAddr", string()),
+                ("callerAddr", string()),
+            ]),
+        ),
+        (
+            "StatsTransferResponse",
+            object(&[
+                ("request_time", number()),
+                ("transfers", array(schema_ref("ChainTransfer"))),
+            ]),
+        ),
+        (
+            "AccountsResponse",
+            object(&[("publicAddr", array(string()))]),
+        ),
+        (
+            "AccountResponse",
+            object(&[
+                ("account", string()),
+                ("allowances", array(schema_ref("Allowance"))),
+                ("transfersQueued", integer()),
+                ("transfersProcessing", integer()),
+                ("transfersDone", integer()),
+                ("receivedTransfers", integer()),
+            ]),
+        ),
+        (
+            "AccountPaymentsInResponse",
+            object(&[("transfersIn", array(schema_ref("TransferIn")))]),
+        ),
+        (
+            "AccountBalanceResponse",
+            object(&[
+                ("networkId", integer()),
+                ("account", string()),
+                ("gasBalance", string()),
+                ("tokenSymbol", string()),
+                ("tokenAddress", string()),
+                ("tokenDecimals", integer()),
+                ("tokenBalance", string()),
+                ("tokenBalanceDecimal", string()),
+                ("blockNumber", integer()),
+                ("blockDate", date_time()),
+            ]),
+        ),
+        (
+            "StoredEvent",
+            object(&[
+                ("id", integer()),
+                ("eventType", string()),
+                ("createDate", date_time()),
+                ("content", any_object()),
+            ]),
+        ),
+        (
+            "EventsResponse",
+            object(&[
+                ("events", array(schema_ref("StoredEvent"))),
+                ("lastEventId", integer()),
+            ]),
+        ),
+        (
+            "RpcPoolNetwork",
+            object(&[
+                ("chainId", integer()),
+                ("chainNetwork", string()),
+                ("endpoints", array(any_object())),
+            ]),
+        ),
+        (
+            "RpcPoolResponse",
+            object(&[("networks", array(schema_ref("RpcPoolNetwork")))]),
+        ),
+        ("ConfigResponse", object(&[("config", any_object())])),
+        ("DebugResponse", object(&[("sharedState", any_object())])),
+        ("FaucetStatusResponse", object(&[("status", string())])),
+        (
+            "FaucetTransferResponse",
+            object(&[
+                ("transfer_gas_id", integer()),
+                ("transfer_gas_payment_id", nullable(string())),
+                ("transfer_glm_id", integer()),
+                ("transfer_glm_payment_id", nullable(string())),
+            ]),
+        ),
+    ];
+    schemas
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect()
+}
+
+enum Body {
+    Json(&'static str),
+    Text,
+    Websocket(&'static str),
+}
+
+struct Operation {
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    query: &'static [(&'static str, &'static str)],
+    request: Option<&'static str>,
+    response: Body,
+    /// Error statuses with specific meaning, other ones are covered by 4XX/5XX
+    errors: &'static [(&'static str, &'static str)],
+}
+
+const QUEUED_TRANSFER_ERRORS: &[(&str, &str)] = &[
+    ("404", "Transfer not found for given chain and sender"),
+    (
+        "409",
+        "Transfer is not queued anymore, current transfer is returned",
+    ),
+];
+
+fn op(method: Method, path: &'static str, summary: &'static str, response: Body) -> Operation {
+    Operation {
+        method,
+        path,
+        summary,
+        query: &[],
+        request: None,
+        response,
+        errors: &[],
+    }
+}
+
+fn operations() -> Vec<Operation> {
+    use Body::*;
+    vec![
+        op(Method::GET, "/", "Version of the processor", Json("VersionResponse")),
+        op(Method::GET, "/version", "Version of the processor", Json("VersionResponse")),
+        op(Method::GET, "/openapi.json", "This document", Text),
+        op(Method::GET, "/allowances", "All allowances", Json("AllowancesResponse")),
+        Operation {
+            query: &[("token", "Token symbol or address, GLM if not set")],
+            ..op(
+                Method::GET,
+                "/balance/{account}/{chain}",
+                "Gas and token balance of the account",
+                Json("AccountBalanceResponse"),
+            )
+        },
+        op(Method::GET, "/rpc_pool", "RPC endpoints and their stats", Json("RpcPoolResponse")),
+        op(Method::GET, "/rpc_pool/metrics", "RPC endpoint stats in Prometheus format", Text),
+        op(Method::GET, "/config", "Payment setup", Json("ConfigResponse")),
+        Operation {
+            query: &[
+                ("receiver", "Receiver address or all"),
+                ("from", "Unix timestamp"),
+                ("to", "Unix timestamp"),
+                ("chain", "Chain id"),
+            ],
+            ..op(
+                Method::GET,
+                "/stats/transfers",
+                "Transfers found on chain",
+                Json("StatsTransferResponse"),
+            )
+        },
+        op(Method::GET, "/transactions", "All transactions", Json("TransactionsResponse")),
+        op(
+            Method::GET,
+            "/transactions/count",
+            "Number of transactions and transfers",
+            Json("TransactionsCountResponse"),
+        ),
+        op(Method::GET, "/transactions/next", "Queued transactions", Json("TransactionsResponse")),
+        op(
+            Method::GET,
+            "/transactions/next/{count}",
+            "Queued transactions",
+            Json("TransactionsResponse"),
+        ),
+        op(
+            Method::GET,
+            "/transactions/feed/{prev}/{next}",
+            "Recently processed, current and queued transactions",
+            Json("TransactionsFeedResponse"),
+        ),
+        op(
+            Method::GET,
+            "/transactions/current",
+            "Transactions being processed",
+            Json("TransactionsResponse"),
+        ),
+        op(
+            Method::GET,
+            "/transactions/last",
+            "Recently processed transactions",
+            Json("TransactionsResponse"),
+        ),
+        op(
+            Method::GET,
+            "/transactions/last/{count}",
+            "Recently processed transactions",
+            Json("TransactionsResponse"),
+        ),
+        op(
+            Method::POST,
+            "/tx/skip/{tx_id}",
+            "Skip transaction being processed",
+            Json("SkipTxResponse"),
+        ),
+        op(Method::GET, "/tx/{tx_id}", "Transaction details", Json("TxResponse")),
+        op(Method::GET, "/transfers", "All transfers", Json("TransfersResponse")),
+        op(
+            Method::GET,
+            "/transfers/{tx_id}",
+            "Transfers sent in transaction",
+            Json("TransfersResponse"),
+        ),
+        Operation {
+            request: Some("NewTransferRequest"),
+            errors: &[("409", "Transfer with given payment id already exists, existing transfer is returned")],
+            ..op(
+                Method::POST,
+                "/transfers/new",
+                "Schedule transfer",
+                Json("NewTransferResponse"),
+            )
+        },
+        Operation {
+            request: Some("NewTransferRequest"),
+            ..op(
+                Method::POST,
+                "/transfers/batch",
+                "Schedule transfers atomically (JSON array or CSV), 400 with per-row errors if any is invalid",
+                Json("TransferBatchResponse"),
+            )
+        },
+        Operation {
+            request: Some("CancelTransferRequest"),
+            errors: QUEUED_TRANSFER_ERRORS,
+            ..op(
+                Method::POST,
+                "/transfers/{payment_id}/cancel",
+                "Cancel queued transfer",
+                Json("TransferResponse"),
+            )
+        },
+        Operation {
+            request: Some("AmendTransferRequest"),
+            errors: QUEUED_TRANSFER_ERRORS,
+            ..op(
+                Method::POST,
+                "/transfers/{payment_id}/amend",
+                "Change receiver or amount of queued transfer",
+                Json("TransferResponse"),
+            )
+        },
+        op(Method::GET, "/accounts", "Sender accounts", Json("AccountsResponse")),
+        op(Method::GET, "/account/{account}", "Account details", Json("AccountResponse")),
+        op(
+            Method::GET,
+            "/account/{account}/in",
+            "Transfers expected by the account",
+            Json("AccountPaymentsInResponse"),
+        ),
+        op(Method::GET, "/metrics", "Processor metrics in Prometheus format", Text),
+        Operation {
+            query: &[
+                ("after", "Id of the last event seen, 0 to read from the oldest stored event"),
+                ("limit", "Max number of events (default 100)"),
+                ("timeout", "Seconds to wait for new events (default 30, max 60)"),
+            ],
+            ..op(
+                Method::GET,
+                "/events",
+                "Stored driver events after given id, waits for new ones if there are none",
+                Json("EventsResponse"),
+            )
+        },
+        Operation {
+            query: &[(
+                "after",
+                "Resume from stored events after this id, only new events are sent if not set",
+            )],
+            ..op(
+                Method::GET,
+                "/event_stream",
+                "Websocket with driver events",
+                Websocket("StoredEvent json per message, DriverEvent json if after is not set"),
+            )
+        },
+        op(Method::GET, "/faucet", "Faucet status", Json("FaucetStatusResponse")),
+        op(
+            Method::GET,
+            "/faucet/{chain}/{addr}",
+            "Send test gas and tokens to the address",
+            Json("FaucetTransferResponse"),
+        ),
+        op(Method::GET, "/debug", "Engine state", Json("DebugResponse")),
+    ]
+}
+
+fn path_params(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| json!({"name": name, "in": "path", "required": true, "schema": string()}))
+        .collect()
+}
+
+fn operation_object(operation: &Operation) -> Value {
+    let mut parameters = path_params(operation.path);
+    parameters.extend(operation.query.iter().map(|(name, description)| {
+        json!({"name": name, "in": "query", "required": false, "description": description, "schema": string()})
+    }));
+    let (code, success) = match operation.response {
+        Body::Json(schema) => (
+            "200",
+            json!({"description": "Success", "content": {"application/json": {"schema": schema_ref(schema)}}}),
+        ),
+        Body::Text => (
+            "200",
+            json!({"description": "Success", "content": {"text/plain": {"schema": string()}}}),
+        ),
+        Body::Websocket(messages) => (
+            "101",
+            json!({"description": format!("Switching to websocket, {messages}")}),
+        ),
+    };
+    let error = json!({"description": "Error", "content": {"application/json": {"schema": schema_ref("ApiError")}}});
+    let mut obj = json!({
+        "summary": operation.summary,
+        "parameters": parameters,
+        "responses": {
+            code: success,
+            "4XX": error,
+            "5XX": error,
+        },
+    });
+    for (code, description) in operation.errors {
+        let mut response = error.clone();
+        response["description"] = json!(description);
+        obj["responses"][code] = response;
+    }
+    if let Some(request) = operation.request {
+        obj["requestBody"] = json!({
+            "required": true,
+            "content": {"application/json": {"schema": schema_ref(request)}},
+        });
+    }
+    match required_role(&operation.method, operation.path) {
+        Some(role) => {
+            let mut security = vec![
+                json!({"bearerAuth": []}),
+                json!({"hmacAuth": [], "hmacClient": []}),
+            ];
+            if operation.method == Method::GET {
+                security.push(json!({"accessToken": []}));
+            }
+            obj["security"] = Value::Array(security);
+            obj["x-required-role"] = serde_json::to_value(role).unwrap_or_default();
+        }
+        None => obj["security"] = json!([]),
+    }
+    obj
+}
+
+/// OpenAPI 3.0 description of routes in /api scope, served at /api/openapi.json
+pub fn openapi_document(api_base_url: &str) -> Value {
+    let mut paths = Map::new();
+    for operation in operations() {
+        let path = paths
+            .entry(operation.path.to_string())
+            .or_insert_with(|| json!({}));
+        path[operation.method.as_str().to_lowercase()] = operation_object(&operation);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "erc20_payment_lib",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{"url": api_base_url}],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "bearerAuth": {"type": "http", "scheme": "bearer"},
+                "hmacAuth": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": API_SIGNATURE_HEADER,
+                    "description": "hex(HMAC-SHA256(\"<timestamp>\\n<nonce>\\n<METHOD>\\n<path and query>\\n<body>\")), timestamp sent in X-Api-Timestamp header, unique nonce in X-Api-Nonce header",
+                },
+                "hmacClient": {"type": "apiKey", "in": "header", "name": API_CLIENT_HEADER},
+                "accessToken": {
+                    "type": "apiKey",
+                    "in": "query",
+                    "name": ACCESS_TOKEN_PARAM,
+                    "description": "Bearer token passed in query, GET requests only (websocket)",
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(obj) => {
+                if let Some(Value::String(r)) = obj.get("$ref") {
+                    refs.push(r.clone());
+                }
+                obj.values().for_each(|v| collect_refs(v, refs));
+            }
+            Value::Array(arr) => arr.iter().for_each(|v| collect_refs(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_openapi_document() {
+        let doc = openapi_document("/erc20/api");
+        let mut refs = Vec::new();
+        collect_refs(&doc, &mut refs);
+        assert!(!refs.is_empty());
+        for r in refs {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(
+                doc["components"]["schemas"].get(name).is_some(),
+                "missing schema {name}"
+            );
+        }
+        assert_eq!(
+            doc["paths"]["/transfers/new"]["post"]["x-required-role"],
+            "payer"
+        );
+        assert_eq!(doc["paths"]["/version"]["get"]["security"], json!([]));
+        assert_eq!(
+            doc["paths"]["/event_stream"]["get"]["security"][2],
+            json!({"accessToken": []})
+        );
+        for code in ["200", "404", "409"] {
+            assert!(
+                doc["paths"]["/transfers/{payment_id}/cancel"]["post"]["responses"]
+                    .get(code)
+                    .is_some()
+            );
+        }
+        assert_eq!(
+            doc["paths"]["/tx/skip/{tx_id}"]["post"]["parameters"][0]["name"],
+            "tx_id"
+        );
+    }
+
+    /// Routes registered in runtime_web_scope (method and path), read from its source
+    fn scope_routes() -> Vec<(String, String)> {
+        let source = include_str!("web.rs");
+        let start = source.find("Scope::new(\"/api\")").unwrap();
+        let end = start + source[start..].find(".wrap(ApiAuth").unwrap();
+        let code = source[start..end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        let mut routes = Vec::new();
+        let mut rest = code.as_str();
+        while let Some(pos) = rest.find(".route(") {
+            rest = &rest[pos + ".route(".len()..];
+            //resource routes take path from web::resource
+            let (path, after_path) = match rest.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"').unwrap();
+                    (quoted[..end].to_string(), &quoted[end + 2..])
+                }
+                None => {
+                    let resource = code[..code.len() - rest.len()]
+                        .rfind("web::resource(\"")
+                        .unwrap();
+                    let path = &code[resource + "web::resource(\"".len()..];
+                    (path[..path.find('"').unwrap()].to_string(), rest)
+                }
+            };
+            let method = after_path
+                .strip_prefix("web::")
+                .and_then(|m| m.split('(').next())
+                .unwrap();
+            routes.push((method.to_uppercase(), path));
+        }
+        routes
+    }
+
+    #[test]
+    fn test_openapi_covers_all_routes() {
+        let routes = scope_routes();
+        assert!(routes.contains(&("POST".to_string(), "/transfers/batch".to_string())));
+        assert!(routes.contains(&("GET".to_string(), "/faucet/{chain}/{addr}".to_string())));
+        let doc = openapi_document("/erc20/api");
+        for (method, path) in &routes {
+            assert!(
+                doc["paths"][path].get(method.to_lowercase()).is_some(),
+                "{method} {path} is not documented"
+            );
+        }
+        let documented = operations()
+            .iter()
+            .map(|op| (op.method.to_string(), op.path.to_string()))
+            .collect::<Vec<_>>();
+        for route in &documented {
+            assert!(routes.contains(route), "{route:?} is not served");
+        }
+    }
+
+    /// Checks serialized value against schema, nested schemas are followed by $ref
+    fn check_value(
+        schemas: &Map<String, Value>,
+        checked: &mut std::collections::BTreeSet<String>,
+        schema: &Value,
+        value: &Value,
+        at: &str,
+    ) {
+        if value.is_null() {
+            assert_eq!(schema["nullable"], json!(true), "{at} is null");
+            return;
+        }
+        if let Some(inner) = schema.get("allOf") {
+            return check_value(schemas, checked, &inner[0], value, at);
+        }
+        if let Some(Value::String(r)) = schema.get("$ref") {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            checked.insert(name.to_string());
+            return check_value(schemas, checked, &schemas[name], value, name);
+        }
+        match schema["type"].as_str().unwrap() {
+            "string" => assert!(value.is_string(), "{at} is not string: {value}"),
+            "integer" => assert!(value.is_i64() || value.is_u64(), "{at} is not integer"),
+            "number" => assert!(value.is_number(), "{at} is not number"),
+            "boolean" => assert!(value.is_boolean(), "{at} is not boolean"),
+            "array" => {
+                let items = value.as_array().unwrap();
+                assert!(!items.is_empty(), "{at} has no items to check");
+                for item in items {
+                    check_value(schemas, checked, &schema["items"], item, at);
+                }
+            }
+            "object" => {
+                let obj = value.as_object().unwrap();
+                if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                    for key in obj.keys() {
+                        assert!(properties.contains_key(key), "{at} has no property {key}");
+                    }
+                    for (key, property) in properties {
+                        match obj.get(key) {
+                            Some(v) => check_value(schemas, checked, property, v, key),
+                            None => assert!(
+                                !schema["required"].as_array().unwrap().contains(&json!(key)),
+                                "{at} requires {key} which is not serialized"
+                            ),
+                        }
+                    }
+                }
+                if let Some(additional @ Value::Object(_)) = schema.get("additionalProperties") {
+                    for v in obj.values() {
+                        check_value(schemas, checked, additional, v, at);
+                    }
+                }
+            }
+            other => panic!("Unexpected type {other} in {at}"),
+        }
+    }
+
+    /// Schema properties have to match what the server sends
+    #[test]
+    fn test_openapi_schema_matches_serialization() {
+        use erc20_payment_lib_common::api::*;
+        use erc20_payment_lib_common::model::{
+            AllowanceDbObj, TokenTransferDbObj, TransferInDbObj, TxDbObj,
+        };
+        use erc20_payment_lib_common::SharedInfoTx;
+        use std::collections::BTreeMap;
+
+        let date = chrono::DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let tx = TxDbObj {
+            gas_limit: Some(21000),
+            nonce: Some(1),
+            tx_hash: Some("0x1".to_string()),
+            ..Default::default()
+        };
+        let transfer: TokenTransferDbObj = serde_json::from_value(json!({
+            "id": 1, "paymentId": null, "fromAddr": "0x0", "receiverAddr": "0x1", "chainId": 1,
+            "tokenAddr": null, "tokenAmount": "1", "depositId": null, "depositFinish": 0,
+            "createDate": "2024-05-01T00:00:00Z", "txId": null, "paidDate": null, "feePaid": null,
+            "error": null,
+        }))
+        .unwrap();
+        let allowance = AllowanceDbObj {
+            id: 1,
+            owner: "0x0".to_string(),
+            token_addr: "0x1".to_string(),
+            spender: "0x2".to_string(),
+            allowance: "1".to_string(),
+            chain_id: 1,
+            tx_id: None,
+            fee_paid: None,
+            confirm_date: Some(date),
+            error: None,
+        };
+        let transfer_in = TransferInDbObj {
+            id: 1,
+            payment_id: "p1".to_string(),
+            from_addr: "0x0".to_string(),
+            receiver_addr: "0x1".to_string(),
+            chain_id: 1,
+            token_addr: None,
+            token_amount: "1".to_string(),
+            tx_hash: None,
+            requested_date: date,
+            received_date: None,
+        };
+        let samples = [
+            (
+                "ApiError",
+                serde_json::to_value(ApiError {
+                    error: "already exists".to_string(),
+                    transfer: Some(Box::new(transfer.clone())),
+                }),
+            ),
+            (
+                "VersionResponse",
+                serde_json::to_value(VersionResponse {
+                    name: "erc20_payment_lib".to_string(),
+                    version: "0.4.1".to_string(),
+                }),
+            ),
+            (
+                "AllowancesResponse",
+                serde_json::to_value(AllowancesResponse {
+                    allowances: vec![allowance.clone()],
+                }),
+            ),
+            (
+                "TxResponse",
+                serde_json::to_value(TxResponse { tx: tx.clone() }),
+            ),
+            (
+                "TransactionsResponse",
+                serde_json::to_value(TransactionsResponse {
+                    txs: vec![tx.clone()],
+                }),
+            ),
+            (
+                "TransactionsFeedResponse",
+                serde_json::to_value(TransactionsFeedResponse {
+                    txs: vec![tx],
+                    current: BTreeMap::from([(
+                        1,
+                        SharedInfoTx {
+                            message: "Sending".to_string(),
+                            error: None,
+                            skip: false,
+                        },
+                    )]),
+                }),
+            ),
+            (
+                "TransactionsCountResponse",
+                serde_json::to_value(TransactionsCountResponse {
+                    transfers_queued: 1,
+                    transfers_processing: 2,
+                    transfers_done: 3,
+                    tx_queued: 4,
+                    tx_done: 5,
+                }),
+            ),
+            (
+                "SkipTxResponse",
+                serde_json::to_value(SkipTxResponse { success: true }),
+            ),
+            (
+                "TransfersResponse",
+                serde_json::to_value(TransfersResponse {
+                    transfers: vec![transfer.clone()],
+                }),
+            ),
+            (
+                "TransferResponse",
+                serde_json::to_value(TransferResponse { transfer }),
+            ),
+            (
+                "NewTransferRequest",
+                serde_json::to_value(NewTransferRequest {
+                    from: "0x0".to_string(),
+                    to: "0x1".to_string(),
+                    amount: "1".to_string(),
+                    chain: 1,
+                    priority: Some(1),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "NewTransferResponse",
+                serde_json::to_value(NewTransferResponse {
+                    payment_id: "p1".to_string(),
+                }),
+            ),
+            (
+                "TransferBatchResponse",
+                serde_json::to_value(TransferBatchResponse {
+                    inserted: 1,
+                    invalid: 0,
+                    results: vec![TransferBatchRowResult {
+                        row: 1,
+                        payment_id: Some("p1".to_string()),
+                        error: None,
+                    }],
+                }),
+            ),
+            (
+                "CancelTransferRequest",
+                serde_json::to_value(CancelTransferRequest {
+                    from: "0x0".to_string(),
+                    chain: 1,
+                }),
+            ),
+            (
+                "AmendTransferRequest",
+                serde_json::to_value(AmendTransferRequest {
+                    from: "0x0".to_string(),
+                    chain: 1,
+                    to: Some("0x1".to_string()),
+                    amount: None,
+                }),
+            ),
+            (
+                "StatsTransferResponse",
+                serde_json::to_value(StatsTransferResponse {
+                    request_time: 0.5,
+                    transfers: vec![ChainTransferRespObj {
+                        id: 1,
+                        from_addr: "0x0".to_string(),
+                        receiver_addr: "0x1".to_string(),
+                        chain_id: 1,
+                        token_addr: None,
+                        token_amount: "1".to_string(),
+                        tx_hash: "0x2".to_string(),
+                        block_number: 1,
+                        fee_paid: None,
+                        block_date: date,
+                        block_timestamp: 1,
+                        to_addr: "0x3".to_string(),
+                        caller_addr: "0x4".to_string(),
+                    }],
+                }),
+            ),
+            (
+                "AccountsResponse",
+                serde_json::to_value(AccountsResponse {
+                    public_addr: vec!["0x0".to_string()],
+                }),
+            ),
+            (
+                "AccountResponse",
+                serde_json::to_value(AccountResponse {
+                    account: "0x0".to_string(),
+                    allowances: vec![allowance],
+                    transfers_queued: 1,
+                    transfers_processing: 2,
+                    transfers_done: 3,
+                    received_transfers: 4,
+                }),
+            ),
+            (
+                "AccountPaymentsInResponse",
+                serde_json::to_value(AccountPaymentsInResponse {
+                    transfers_in: vec![transfer_in],
+                }),
+            ),
+            (
+                "AccountBalanceResponse",
+                serde_json::to_value(AccountBalanceResponse {
+                    network_id: 1,
+                    account: "0x0".to_string(),
+                    gas_balance: "1".to_string(),
+                    token_symbol: "GLM".to_string(),
+                    token_address: "0x1".to_string(),
+                    token_decimals: 18,
+                    token_balance: "1".to_string(),
+                    token_balance_decimal: "0.000000000000000001".to_string(),
+                    block_number: 1,
+                    block_date: date,
+                }),
+            ),
+            (
+                "EventsResponse",
+                serde_json::to_value(EventsResponse {
+                    events: vec![StoredEvent {
+                        id: 1,
+                        event_type: "StatusChanged".to_string(),
+                        event: json!({"createDate": "2024-05-01T00:00:00Z", "content": {"statusChanged": []}}),
+                    }],
+                    last_event_id: 1,
+                }),
+            ),
+            (
+                "RpcPoolResponse",
+                serde_json::to_value(RpcPoolResponse {
+                    networks: vec![RpcPoolNetwork {
+                        chain_id: 1,
+                        chain_network: "mainnet".to_string(),
+                        endpoints: vec![json!({"name": "rpc"})],
+                    }],
+                }),
+            ),
+            (
+                "ConfigResponse",
+                serde_json::to_value(ConfigResponse {
+                    config: json!({"chain": {}}),
+                }),
+            ),
+            (
+                "DebugResponse",
+                serde_json::to_value(DebugResponse {
+                    shared_state: json!({"inserted": 0}),
+                }),
+            ),
+            (
+                "FaucetStatusResponse",
+                serde_json::to_value(FaucetStatusResponse {
+                    status: "ok".to_string(),
+                }),
+            ),
+            (
+                "FaucetTransferResponse",
+                serde_json::to_value(FaucetTransferResponse {
+                    transfer_gas_id: 1,
+                    transfer_gas_payment_id: Some("p1".to_string()),
+                    transfer_glm_id: 2,
+                    transfer_glm_payment_id: None,
+                }),
+            ),
+        ];
+
+        let schemas = schemas();
+        let mut checked = std::collections::BTreeSet::new();
+        for (name, value) in samples {
+            check_value(
+                &schemas,
+                &mut checked,
+                &schema_ref(name),
+                &value.unwrap(),
+                name,
+            );
+        }
+        for name in schemas.keys() {
+            assert!(checked.contains(name), "{name} is not checked");
+        }
+    }
+}
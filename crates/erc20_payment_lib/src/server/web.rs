@@ -1,22 +1,26 @@
-use crate::eth::get_balance;
-use crate::runtime::{PaymentRuntime, SharedState, TransferArgs, TransferType};
+use crate::eth::{get_balance, get_deposits_details};
+use crate::runtime::{AmendTransferArgs, PaymentRuntime, SharedState, TransferArgs, TransferType};
+use crate::server::auth::{ApiAuth, ApiNonceCache};
+use crate::server::openapi::openapi_document;
 use crate::server::ws::event_stream_websocket_endpoint;
 use crate::setup::{ChainSetup, PaymentSetup};
+use crate::signer::SignerAccount;
 use crate::transaction::create_token_transfer;
 use actix_files::NamedFile;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::http::header::HeaderValue;
 use actix_web::http::{header, StatusCode};
 use actix_web::web::Data;
-use actix_web::{web, HttpRequest, HttpResponse, Responder, Scope};
-use chrono::{DateTime, Utc};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError, Scope};
+use erc20_payment_lib_common::api::*;
+use erc20_payment_lib_common::error::{ErrorBag, PaymentError};
+use erc20_payment_lib_common::model::{check_transfer_priority, TokenTransferDbObj};
 use erc20_payment_lib_common::ops::*;
-use erc20_payment_lib_common::utils::datetime_from_u256_timestamp;
+use erc20_payment_lib_common::utils::{datetime_from_u256_timestamp, U256ConvExt};
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{export_metrics_to_prometheus, FaucetData};
 use erc20_rpc_pool::VerifyEndpointResult;
-use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::SqlitePool;
 use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -26,97 +30,126 @@ use web3::types::{Address, BlockId, BlockNumber, U256};
 
 pub struct ServerData {
     pub shared_state: Arc<std::sync::Mutex<SharedState>>,
-    pub db_connection: Arc<Mutex<SqlitePool>>,
+    pub db_connection: Arc<Mutex<DbPool>>,
     pub payment_setup: PaymentSetup,
     pub payment_runtime: PaymentRuntime,
+    /// Shared by all workers, see [`ApiAuth::new`]
+    pub api_nonces: ApiNonceCache,
 }
 
-macro_rules! return_on_error {
-    ( $e:expr ) => {
-        match $e {
-            Ok(x) => x,
-            Err(err) => {
-                return web::Json(json!({
-                    "error": err.to_string()
-                }));
-            }
+/// Error returned by API handlers, body is always ApiError json
+#[derive(Debug)]
+pub struct ApiHttpError {
+    status: StatusCode,
+    body: ApiError,
+}
+
+impl ApiHttpError {
+    pub fn new(status: StatusCode, error: impl ToString) -> Self {
+        Self {
+            status,
+            body: ApiError {
+                error: error.to_string(),
+                transfer: None,
+            },
         }
     }
-}
 
-pub async fn tx_details(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let tx_id = req
-        .match_info()
-        .get("tx_id")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(None);
+    pub fn bad_request(error: impl ToString) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, error)
+    }
 
-    let tx_id = match tx_id {
-        Some(tx_id) => tx_id,
-        None => return web::Json(json!({"error": "failed to parse tx_id"})),
-    };
+    pub fn not_found(error: impl ToString) -> Self {
+        Self::new(StatusCode::NOT_FOUND, error)
+    }
 
-    let tx = {
-        let db_conn = data.db_connection.lock().await;
-        match get_transaction(&*db_conn, tx_id).await {
-            Ok(allowances) => allowances,
-            Err(err) => {
-                return web::Json(json!({
-                    "error": err.to_string()
-                }));
-                //return format!("Error getting allowances: {:?}", err);
-            }
+    pub fn internal(error: impl ToString) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, error)
+    }
+
+    /// Maps errors of operations allowed only on queued transfer
+    fn transfer_update_failed(action: &str, err: PaymentError) -> Self {
+        let message = format!("Failed to {action} transfer: {}", err.inner);
+        match *err.inner {
+            ErrorBag::TransferNotFound(_) => Self::not_found(message),
+            ErrorBag::TransferNotQueued(transfer) => Self {
+                status: StatusCode::CONFLICT,
+                body: ApiError {
+                    error: message,
+                    transfer: Some(transfer),
+                },
+            },
+            ErrorBag::SQLxError(_) => Self::internal(message),
+            _ => Self::bad_request(message),
         }
-    };
+    }
 
-    /*
-    let transfers = {
-        let db_conn = data.db_connection.lock().await;
-        match get_token_transfers_by_tx(&db_conn, tx_id).await {
-            Ok(allowances) => allowances,
-            Err(err) => {
-                return web::Json(json!({
-                    "error": err.to_string()
-                }))
-            }
+    fn transfer_already_exists(transfer: TokenTransferDbObj) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            body: ApiError {
+                error: "already exists".to_string(),
+                transfer: Some(Box::new(transfer)),
+            },
         }
-    };*/
-    /*let json_transfers = transfers
-    .iter()
-    .map(|transfer| {
-        json!({
-            "id": transfer.id,
-            "chain_id": transfer.chain_id,
-            "tx_id": transfer.tx_id,
-            "from": transfer.from_addr,
-            "receiver": transfer.receiver_addr,
-            "token": transfer.token_addr,
-            "amount": transfer.token_amount,
-            "fee_paid": transfer.fee_paid,
+    }
+}
+
+impl std::fmt::Display for ApiHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.body.error)
+    }
+}
+
+impl ResponseError for ApiHttpError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(&self.body)
+    }
+}
+
+type ApiResult<T> = Result<web::Json<T>, ApiHttpError>;
+
+fn path_param<T: FromStr>(req: &HttpRequest, name: &str) -> Result<Option<T>, ApiHttpError>
+where
+    T::Err: std::fmt::Display,
+{
+    req.match_info()
+        .get(name)
+        .map(|value| {
+            T::from_str(value)
+                .map_err(|err| ApiHttpError::bad_request(format!("failed to parse {name}: {err}")))
         })
-    })
-    .collect::<Vec<_>>();*/
+        .transpose()
+}
 
-    web::Json(json!({
-        "tx": tx,
-    }))
+pub async fn tx_details(data: Data<Box<ServerData>>, req: HttpRequest) -> ApiResult<TxResponse> {
+    let tx_id = path_param::<i64>(&req, "tx_id")?
+        .ok_or(ApiHttpError::bad_request("failed to parse tx_id"))?;
+
+    let tx = {
+        let db_conn = data.db_connection.lock().await;
+        get_transaction(&*db_conn, tx_id)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound => {
+                    ApiHttpError::not_found(format!("Tx {tx_id} not found"))
+                }
+                err => ApiHttpError::internal(err),
+            })?
+    };
+
+    Ok(web::Json(TxResponse { tx }))
 }
 
-pub async fn rpc_pool(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
+pub async fn rpc_pool(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+) -> ApiResult<RpcPoolResponse> {
     let my_data = data.shared_state.lock().unwrap();
-    //synchronize rpc_pool statistics with server
-    /*shared_state.lock().await.web3_rpc_pool.insert(
-        chain_id,
-        web3.endpoints
-            .iter()
-            .map(|e| {
-                (
-                    e.read().unwrap().web3_rpc_params.clone(),
-                    e.read().unwrap().web3_rpc_info.clone(),
-                )
-            })
-            .collect::<Vec<(Web3RpcParams, Web3RpcInfo)>>(),
-    );*/
     // Convert BTreeMap of Arenas to BTreeMap of Vec because serde can't serialize Arena
     let web3_rpc_pool_info = my_data
         .web3_pool_ref
@@ -135,10 +168,10 @@ pub async fn rpc_pool(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Re
         })
         .collect::<BTreeMap<_, _>>();
 
-    let mut array = Vec::with_capacity(web3_rpc_pool_info.len());
+    let mut networks = Vec::with_capacity(web3_rpc_pool_info.len());
 
     for (idx, val) in web3_rpc_pool_info {
-        let val = val
+        let endpoints = val
             .iter()
             .map(|v| json!(*v.try_read_for(Duration::from_secs(5)).unwrap()))
             .collect::<Vec<_>>();
@@ -148,17 +181,13 @@ pub async fn rpc_pool(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Re
             .get(&idx)
             .map(|s| s.network.clone())
             .unwrap_or("unknown".to_string());
-        array.push(json!(
-            {
-                "chainId": idx,
-                "chainNetwork": chain_network,
-                "endpoints": val,
-            }
-        ));
+        networks.push(RpcPoolNetwork {
+            chain_id: idx,
+            chain_network,
+            endpoints,
+        });
     }
-    web::Json(json!({
-        "networks": array,
-    }))
+    Ok(web::Json(RpcPoolResponse { networks }))
 }
 
 struct MetricGroup {
@@ -219,6 +248,34 @@ pub async fn rpc_pool_metrics(data: Data<Box<ServerData>>, _req: HttpRequest) ->
         metrics: Vec::new(),
     });
 
+    metrics.push(MetricGroup {
+        metric_help:
+            "# HELP rpc_endpoint_requests_available Requests available in token bucket (-1 if not limited)"
+                .to_string(),
+        metric_type: "# TYPE rpc_endpoint_requests_available gauge".to_string(),
+        metrics: Vec::new(),
+    });
+    metrics.push(MetricGroup {
+        metric_help:
+            "# HELP rpc_endpoint_daily_requests_remaining Requests left from daily quota (-1 if no quota)"
+                .to_string(),
+        metric_type: "# TYPE rpc_endpoint_daily_requests_remaining gauge".to_string(),
+        metrics: Vec::new(),
+    });
+    metrics.push(MetricGroup {
+        metric_help:
+            "# HELP rpc_endpoint_backoff_ms Time left until rate limited endpoint is used again"
+                .to_string(),
+        metric_type: "# TYPE rpc_endpoint_backoff_ms gauge".to_string(),
+        metrics: Vec::new(),
+    });
+    metrics.push(MetricGroup {
+        metric_help: "# HELP rpc_endpoint_rate_limited_count Number of rate limit responses"
+            .to_string(),
+        metric_type: "# TYPE rpc_endpoint_rate_limited_count counter".to_string(),
+        metrics: Vec::new(),
+    });
+
     for (_idx, vec) in pool_ref {
         for (_idx, endpoint) in vec.try_lock_for(Duration::from_secs(5)).unwrap().iter() {
             let endpoint = endpoint
@@ -287,6 +344,40 @@ pub async fn rpc_pool_metrics(data: Data<Box<ServerData>>, _req: HttpRequest) ->
                 value: head_behind.to_string(),
             };
             metrics[5].metrics.push(new_metric);
+
+            if let Some(budget) = endpoint.rate_limit_budget() {
+                let new_metric = Metric {
+                    name: "rpc_endpoint_requests_available".into(),
+                    params: params.clone(),
+                    value: budget.requests_available.unwrap_or(-1.0).to_string(),
+                };
+                metrics[6].metrics.push(new_metric);
+
+                let new_metric = Metric {
+                    name: "rpc_endpoint_daily_requests_remaining".into(),
+                    params: params.clone(),
+                    value: budget
+                        .daily_requests_remaining
+                        .map(|remaining| remaining as i64)
+                        .unwrap_or(-1)
+                        .to_string(),
+                };
+                metrics[7].metrics.push(new_metric);
+
+                let new_metric = Metric {
+                    name: "rpc_endpoint_backoff_ms".into(),
+                    params: params.clone(),
+                    value: budget.backoff_remaining_ms.to_string(),
+                };
+                metrics[8].metrics.push(new_metric);
+
+                let new_metric = Metric {
+                    name: "rpc_endpoint_rate_limited_count".into(),
+                    params: params.clone(),
+                    value: budget.rate_limited_count.to_string(),
+                };
+                metrics[9].metrics.push(new_metric);
+            }
         }
     }
 
@@ -316,241 +407,202 @@ pub async fn rpc_pool_metrics(data: Data<Box<ServerData>>, _req: HttpRequest) ->
     resp
 }
 
-pub async fn allowances(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
+pub async fn allowances(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+) -> ApiResult<AllowancesResponse> {
     data.shared_state.lock().unwrap().inserted += 1;
     let allowances = {
         let db_conn = data.db_connection.lock().await;
-        match get_all_allowances(&db_conn).await {
-            Ok(allowances) => allowances,
-            Err(err) => {
-                return web::Json(json!({
-                    "error": err.to_string()
-                }));
-                //return format!("Error getting allowances: {:?}", err);
-            }
-        }
+        get_all_allowances(&db_conn)
+            .await
+            .map_err(ApiHttpError::internal)?
     };
 
-    web::Json(json!({
-        "allowances": allowances,
-    }))
+    Ok(web::Json(AllowancesResponse { allowances }))
 }
 
-pub async fn transactions_count(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
-    let queued_tx_count = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_transaction_count(&db_conn, Some(TRANSACTION_FILTER_QUEUED)).await)
-    };
-    let done_tx_count = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_transaction_count(&db_conn, Some(TRANSACTION_FILTER_DONE)).await)
-    };
-
-    let queued_transfer_count = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(
-            get_transfer_count(&db_conn, Some(TRANSFER_FILTER_QUEUED), None, None).await
-        )
-    };
-    let processed_transfer_count = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(
-            get_transfer_count(&db_conn, Some(TRANSFER_FILTER_PROCESSING), None, None).await
-        )
+pub async fn transactions_count(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+) -> ApiResult<TransactionsCountResponse> {
+    let db_conn = data.db_connection.lock().await.clone();
+    let tx_count = |filter| {
+        let db_conn = db_conn.clone();
+        async move {
+            get_transaction_count(&db_conn, Some(filter))
+                .await
+                .map(|count| count as u64)
+                .map_err(ApiHttpError::internal)
+        }
     };
-    let done_transfer_count = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_transfer_count(&db_conn, Some(TRANSFER_FILTER_DONE), None, None).await)
+    let transfer_count = |filter| {
+        let db_conn = db_conn.clone();
+        async move {
+            get_transfer_count(&db_conn, Some(filter), None, None)
+                .await
+                .map(|count| count as u64)
+                .map_err(ApiHttpError::internal)
+        }
     };
 
-    web::Json(json!({
-        "transfersQueued": queued_transfer_count,
-        "transfersProcessing": processed_transfer_count,
-        "transfersDone": done_transfer_count,
-        "txQueued": queued_tx_count,
-        "txDone": done_tx_count,
+    Ok(web::Json(TransactionsCountResponse {
+        transfers_queued: transfer_count(TRANSFER_FILTER_QUEUED).await?,
+        transfers_processing: transfer_count(TRANSFER_FILTER_PROCESSING).await?,
+        transfers_done: transfer_count(TRANSFER_FILTER_DONE).await?,
+        tx_queued: tx_count(TRANSACTION_FILTER_QUEUED).await?,
+        tx_done: tx_count(TRANSACTION_FILTER_DONE).await?,
     }))
 }
 
-pub async fn config_endpoint(data: Data<Box<ServerData>>) -> impl Responder {
-    let payment_setup = data.payment_setup.clone();
-
-    web::Json(json!({
-        "config": payment_setup,
+pub async fn config_endpoint(data: Data<Box<ServerData>>) -> ApiResult<ConfigResponse> {
+    Ok(web::Json(ConfigResponse {
+        config: serde_json::to_value(&data.payment_setup).map_err(ApiHttpError::internal)?,
     }))
 }
 
-pub async fn debug_endpoint(data: Data<Box<ServerData>>) -> impl Responder {
+pub async fn debug_endpoint(data: Data<Box<ServerData>>) -> ApiResult<DebugResponse> {
     let shared_state = data.shared_state.lock().unwrap().clone();
 
-    web::Json(json!({
-        "sharedState": shared_state,
+    Ok(web::Json(DebugResponse {
+        shared_state: serde_json::to_value(&shared_state).map_err(ApiHttpError::internal)?,
     }))
 }
 
-pub async fn transactions(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
+pub async fn transactions(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+) -> ApiResult<TransactionsResponse> {
     //todo: add limits
     let txs = {
         let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_transactions(&*db_conn, None, None, None, None, None).await)
+        get_transactions(&*db_conn, None, None, None, None, None)
+            .await
+            .map_err(ApiHttpError::internal)?
     };
-    web::Json(json!({
-        "txs": txs,
-    }))
+    Ok(web::Json(TransactionsResponse { txs }))
 }
 
 pub async fn skip_pending_operation(
     data: Data<Box<ServerData>>,
     req: HttpRequest,
-) -> impl Responder {
-    let tx_id = req
-        .match_info()
-        .get("tx_id")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(None);
-    if let Some(tx_id) = tx_id {
-        if data.shared_state.lock().unwrap().skip_tx(tx_id) {
-            web::Json(json!({
-                "success": "true",
-            }))
-        } else {
-            web::Json(json!({
-                "error": "Tx not found",
-            }))
-        }
+) -> ApiResult<SkipTxResponse> {
+    let tx_id = path_param::<i64>(&req, "tx_id")?
+        .ok_or(ApiHttpError::bad_request("failed to parse tx_id"))?;
+    if data.shared_state.lock().unwrap().skip_tx(tx_id) {
+        Ok(web::Json(SkipTxResponse { success: true }))
     } else {
-        web::Json(json!({
-            "error": "failed to parse tx_id",
-        }))
+        Err(ApiHttpError::not_found("Tx not found"))
     }
 }
 
-pub async fn transactions_next(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let limit = req
-        .match_info()
-        .get("count")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(Some(10));
+pub async fn transactions_next(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<TransactionsResponse> {
+    let limit = path_param::<i64>(&req, "count")?.unwrap_or(10);
 
     let txs = {
         let db_conn = data.db_connection.lock().await;
-        return_on_error!(
-            get_transactions(
-                &*db_conn,
-                None,
-                Some(TRANSACTION_FILTER_QUEUED),
-                limit,
-                Some(TRANSACTION_ORDER_BY_CREATE_DATE),
-                None
-            )
-            .await
+        get_transactions(
+            &*db_conn,
+            None,
+            Some(TRANSACTION_FILTER_QUEUED),
+            Some(limit),
+            Some(TRANSACTION_ORDER_BY_CREATE_DATE),
+            None,
         )
+        .await
+        .map_err(ApiHttpError::internal)?
     };
-    web::Json(json!({
-        "txs": txs,
-    }))
+    Ok(web::Json(TransactionsResponse { txs }))
 }
 
 pub async fn transactions_current(
     data: Data<Box<ServerData>>,
     _req: HttpRequest,
-) -> impl Responder {
+) -> ApiResult<TransactionsResponse> {
     let txs = {
         let db_conn = data.db_connection.lock().await;
-        return_on_error!(
-            get_transactions(
-                &*db_conn,
-                None,
-                Some(TRANSACTION_FILTER_PROCESSING),
-                None,
-                Some(TRANSACTION_ORDER_BY_CREATE_DATE),
-                None
-            )
-            .await
+        get_transactions(
+            &*db_conn,
+            None,
+            Some(TRANSACTION_FILTER_PROCESSING),
+            None,
+            Some(TRANSACTION_ORDER_BY_CREATE_DATE),
+            None,
         )
+        .await
+        .map_err(ApiHttpError::internal)?
     };
-    web::Json(json!({
-        "txs": txs,
-    }))
+    Ok(web::Json(TransactionsResponse { txs }))
 }
 
 pub async fn transactions_last_processed(
     data: Data<Box<ServerData>>,
     req: HttpRequest,
-) -> impl Responder {
-    let limit = req
-        .match_info()
-        .get("count")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(Some(10));
+) -> ApiResult<TransactionsResponse> {
+    let limit = path_param::<i64>(&req, "count")?.unwrap_or(10);
 
     let txs = {
         let db_conn = data.db_connection.lock().await;
-        return_on_error!(
-            get_transactions(
-                &*db_conn,
-                None,
-                Some(TRANSACTION_FILTER_DONE),
-                limit,
-                Some(TRANSACTION_ORDER_BY_FIRST_PROCESSED_DATE_DESC),
-                None
-            )
-            .await
+        get_transactions(
+            &*db_conn,
+            None,
+            Some(TRANSACTION_FILTER_DONE),
+            Some(limit),
+            Some(TRANSACTION_ORDER_BY_FIRST_PROCESSED_DATE_DESC),
+            None,
         )
+        .await
+        .map_err(ApiHttpError::internal)?
     };
-    web::Json(json!({
-        "txs": txs,
-    }))
+    Ok(web::Json(TransactionsResponse { txs }))
 }
 
-pub async fn transactions_feed(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let limit_prev = req
-        .match_info()
-        .get("prev")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(Some(10));
-    let limit_next = req
-        .match_info()
-        .get("next")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(Some(10));
+pub async fn transactions_feed(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<TransactionsFeedResponse> {
+    let limit_prev = path_param::<i64>(&req, "prev")?.unwrap_or(10);
+    let limit_next = path_param::<i64>(&req, "next")?.unwrap_or(10);
     let mut txs = {
         let db_conn = data.db_connection.lock().await;
-        let mut db_transaction = return_on_error!(db_conn.begin().await);
-        let mut txs = return_on_error!(
-            get_transactions(
-                &mut *db_transaction,
-                None,
-                Some(TRANSACTION_FILTER_DONE),
-                limit_prev,
-                Some(TRANSACTION_ORDER_BY_FIRST_PROCESSED_DATE_DESC),
-                None
-            )
-            .await
-        );
-        let txs_current = return_on_error!(
-            get_transactions(
-                &mut *db_transaction,
-                None,
-                Some(TRANSACTION_FILTER_PROCESSING),
-                None,
-                Some(TRANSACTION_ORDER_BY_CREATE_DATE),
-                None
-            )
-            .await
-        );
-        let tx_next = return_on_error!(
-            get_transactions(
-                &mut *db_transaction,
-                None,
-                Some(TRANSACTION_FILTER_QUEUED),
-                limit_next,
-                Some(TRANSACTION_ORDER_BY_CREATE_DATE),
-                None
-            )
+        let mut db_transaction = db_conn.begin().await.map_err(ApiHttpError::internal)?;
+        let mut txs = get_transactions(
+            &mut db_transaction,
+            None,
+            Some(TRANSACTION_FILTER_DONE),
+            Some(limit_prev),
+            Some(TRANSACTION_ORDER_BY_FIRST_PROCESSED_DATE_DESC),
+            None,
+        )
+        .await
+        .map_err(ApiHttpError::internal)?;
+        let txs_current = get_transactions(
+            &mut db_transaction,
+            None,
+            Some(TRANSACTION_FILTER_PROCESSING),
+            None,
+            Some(TRANSACTION_ORDER_BY_CREATE_DATE),
+            None,
+        )
+        .await
+        .map_err(ApiHttpError::internal)?;
+        let tx_next = get_transactions(
+            &mut db_transaction,
+            None,
+            Some(TRANSACTION_FILTER_QUEUED),
+            Some(limit_next),
+            Some(TRANSACTION_ORDER_BY_CREATE_DATE),
+            None,
+        )
+        .await
+        .map_err(ApiHttpError::internal)?;
+        db_transaction
+            .commit()
             .await
-        );
-        return_on_error!(db_transaction.commit().await);
+            .map_err(ApiHttpError::internal)?;
         //join transactions
         txs.reverse();
         txs.extend(txs_current);
@@ -566,51 +618,36 @@ pub async fn transactions_feed(data: Data<Box<ServerData>>, req: HttpRequest) ->
         }
     }
 
-    web::Json(json!({
-        "txs": txs,
-        "current": current_tx,
+    Ok(web::Json(TransactionsFeedResponse {
+        txs,
+        current: current_tx,
     }))
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TransactionRequest {
-    from: String,
-    to: String,
-    token: Option<String>,
-    amount: String,
-    chain: i64,
-    due_date: Option<String>,
-    payment_id: Option<String>,
-    deposit_id: Option<String>,
-}
-
-async fn new_transfer(
-    data: Data<Box<ServerData>>,
-    _req: HttpRequest,
-    new_transfer: web::Json<TransactionRequest>,
-) -> actix_web::Result<String> {
-    //println!("new_transfer: {:?}", new_transfer);
-
+/// Validates transfer request and finds the account sending it
+fn parse_transfer_request(
+    data: &ServerData,
+    new_transfer: &NewTransferRequest,
+) -> Result<(SignerAccount, TransferArgs), String> {
     let chain = data
         .payment_setup
         .chain_setup
         .get(&new_transfer.chain)
-        .ok_or(actix_web::error::ErrorBadRequest("No config found"))?
-        .clone();
+        .ok_or(format!("No config found for chain {}", new_transfer.chain))?;
 
-    let tx_type = if let Some(_token) = &new_transfer.token {
-        TransferType::Token
+    let (tx_type, token) = if let Some(token) = &new_transfer.token {
+        let token = chain
+            .get_token(token)
+            .ok_or_else(|| format!("Unknown token: {}", token))?;
+        (TransferType::Token, Some(token.address))
     } else {
-        TransferType::Gas
+        (TransferType::Gas, None)
     };
 
     let due_date = if let Some(due_date) = &new_transfer.due_date {
         Some(
             chrono::DateTime::parse_from_rfc3339(due_date)
-                .map_err(|err| {
-                    actix_web::error::ErrorBadRequest(format!("Invalid due_date: {}", err))
-                })?
+                .map_err(|err| format!("Invalid due_date: {}", err))?
                 .naive_utc()
                 .and_utc(),
         )
@@ -624,85 +661,373 @@ async fn new_transfer(
         uuid::Uuid::new_v4().to_string()
     };
 
-    let transfer_args = TransferArgs {
-        network: chain.network,
-        from: Address::from_str(&new_transfer.from).unwrap(),
-        receiver: Address::from_str(&new_transfer.to).unwrap(),
-        tx_type,
-        amount: U256::from_dec_str(&new_transfer.amount).unwrap(),
-        payment_id,
-        deadline: due_date,
-        deposit_id: new_transfer.deposit_id.clone(),
-    };
+    let from = Address::from_str(&new_transfer.from)
+        .map_err(|err| format!("Invalid from address {}: {}", new_transfer.from, err))?;
+    let receiver = Address::from_str(&new_transfer.to)
+        .map_err(|err| format!("Invalid to address {}: {}", new_transfer.to, err))?;
+    let amount = U256::from_dec_str(&new_transfer.amount)
+        .map_err(|err| format!("Invalid amount {}: {:?}", new_transfer.amount, err))?;
+
+    if let Some(deposit_id) = &new_transfer.deposit_id {
+        U256::from_str_radix(deposit_id, 16)
+            .map_err(|err| format!("Invalid deposit id {}: {}", deposit_id, err))?;
+        if chain.lock_contract_address.is_none() {
+            return Err(format!(
+                "Deposits are not supported on chain {}",
+                new_transfer.chain
+            ));
+        }
+    }
+
+    let priority = new_transfer.priority.unwrap_or_default();
+    check_transfer_priority(priority)?;
 
-    let account = match data
+    let account = data
         .shared_state
         .lock()
         .unwrap()
         .accounts
         .iter()
-        .find(|acc| acc.address == transfer_args.from)
-    {
-        Some(acc) => acc.clone(),
-        None => {
-            return Err(actix_web::error::ErrorBadRequest(format!(
-                "Account not found: {:#x}",
-                transfer_args.from
-            )));
-        }
+        .find(|acc| acc.address == from)
+        .cloned()
+        .ok_or(format!("Account not found: {:#x}", from))?;
+
+    let transfer_args = TransferArgs {
+        network: chain.network.clone(),
+        from,
+        receiver,
+        tx_type,
+        token,
+        amount,
+        payment_id,
+        deadline: due_date,
+        deposit_id: new_transfer.deposit_id.clone(),
+        priority,
     };
+    Ok((account, transfer_args))
+}
+
+async fn new_transfer(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+    new_transfer: web::Json<NewTransferRequest>,
+) -> ApiResult<NewTransferResponse> {
+    let (account, transfer_args) =
+        parse_transfer_request(&data, &new_transfer).map_err(ApiHttpError::bad_request)?;
 
     if let Err(err) = data
         .payment_runtime
         .transfer_with_account(&account, transfer_args.clone())
         .await
     {
-        return Err(actix_web::error::ErrorInternalServerError(format!(
+        if let ErrorBag::TransferAlreadyExists(token_transfer) = *err.inner {
+            return Err(ApiHttpError::transfer_already_exists(*token_transfer));
+        }
+        return Err(ApiHttpError::internal(format!(
             "Failed to create transfer: {}",
             err
         )));
     };
     log::warn!("Created transfer: {:?}", transfer_args);
 
-    Ok("success".to_string())
+    Ok(web::Json(NewTransferResponse {
+        payment_id: transfer_args.payment_id,
+    }))
 }
 
-#[derive(Deserialize)]
-pub struct StatsTransferRequest {
-    receiver: Option<String>,
-    from: Option<String>,
-    to: Option<String>,
-    chain: Option<String>,
+const MAX_BATCH_TRANSFERS: usize = 1000;
+const MAX_BATCH_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Parses JSON array or CSV (with header) of transfer requests, every row is parsed separately
+fn parse_transfer_batch(
+    req: &HttpRequest,
+    body: &[u8],
+) -> Result<Vec<Result<NewTransferRequest, String>>, String> {
+    let is_csv = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.contains("csv"));
+    if is_csv {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body);
+        Ok(rdr
+            .deserialize::<NewTransferRequest>()
+            .map(|row| row.map_err(|err| format!("Invalid CSV row: {}", err)))
+            .collect())
+    } else {
+        let rows = serde_json::from_slice::<Vec<serde_json::Value>>(body)
+            .map_err(|err| format!("Expected JSON array of transfers: {}", err))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                serde_json::from_value::<NewTransferRequest>(row)
+                    .map_err(|err| format!("Invalid transfer: {}", err))
+            })
+            .collect())
+    }
 }
 
-#[derive(Debug, Serialize)]
-pub struct StatsTransferResult {
-    request_time: f64,
-    transfers: Vec<ChainTransferRespObj>,
+/// Checks that deposits used by transfers exist, are not closed and can be spent by the sender
+async fn check_batch_deposits(
+    data: &ServerData,
+    conn: &DbPool,
+    rows: &mut [Result<(SignerAccount, TransferArgs), String>],
+) {
+    let mut deposits: BTreeMap<(i64, U256), Vec<usize>> = BTreeMap::new();
+    for (idx, row) in rows.iter().enumerate() {
+        if let Ok((_, transfer_args)) = row {
+            if let Some(deposit_id) = &transfer_args.deposit_id {
+                let chain_id = data
+                    .payment_setup
+                    .chain_setup
+                    .values()
+                    .find(|c| c.network == transfer_args.network)
+                    .map(|c| c.chain_id)
+                    .unwrap_or_default();
+                if let Ok(deposit_id) = U256::from_str_radix(deposit_id, 16) {
+                    deposits
+                        .entry((chain_id, deposit_id))
+                        .or_default()
+                        .push(idx);
+                }
+            }
+        }
+    }
+    let mut by_chain: BTreeMap<i64, Vec<U256>> = BTreeMap::new();
+    for (chain_id, deposit_id) in deposits.keys() {
+        by_chain.entry(*chain_id).or_default().push(*deposit_id);
+    }
+
+    for (chain_id, deposit_ids) in by_chain {
+        let Some(chain) = data.payment_setup.chain_setup.get(&chain_id) else {
+            continue;
+        };
+        let Some(lock_contract_address) = chain.lock_contract_address else {
+            continue;
+        };
+        let details = get_deposits_details(
+            chain.provider.clone(),
+            chain.multicall_contract_address,
+            &deposit_ids,
+            lock_contract_address,
+            None,
+        )
+        .await
+        .map_err(|err| format!("Cannot check deposit: {}", err));
+        for (idx, deposit_id) in deposit_ids.iter().enumerate() {
+            let deposit_str = format!("{:#x}", deposit_id);
+            let closed = check_if_deposit_closed(conn, chain_id, &deposit_str)
+                .await
+                .map_err(|err| format!("Cannot check deposit: {}", err));
+            for row_idx in &deposits[&(chain_id, *deposit_id)] {
+                let Ok((_, transfer_args)) = &rows[*row_idx] else {
+                    continue;
+                };
+                let error = match (&details, &closed) {
+                    (Err(err), _) | (_, Err(err)) => Some(err.clone()),
+                    (_, Ok(true)) => Some(format!("Deposit {} is already closed", deposit_str)),
+                    (Ok(details), Ok(false)) => {
+                        let details = &details[idx];
+                        if details.amount_decimal.is_zero() {
+                            Some(format!("Deposit {} not found", deposit_str))
+                        } else if details.spender != transfer_args.from {
+                            Some(format!(
+                                "Account {:#x} is not spender of deposit {}",
+                                transfer_args.from, deposit_str
+                            ))
+                        } else {
+                            None
+                        }
+                    }
+                };
+                if let Some(error) = error {
+                    rows[*row_idx] = Err(error);
+                }
+            }
+        }
+    }
 }
 
-#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct ChainTransferRespObj {
-    pub id: i64,
-    pub from_addr: String,
-    pub receiver_addr: String,
-    pub chain_id: i64,
-    pub token_addr: Option<String>,
-    pub token_amount: String,
-    pub tx_hash: String,
-    pub block_number: i64,
-    pub fee_paid: Option<String>,
-    pub block_date: DateTime<Utc>,
-    pub block_timestamp: i64,
-    pub to_addr: String,
-    pub caller_addr: String,
+async fn new_transfers_batch(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiHttpError> {
+    let requests = parse_transfer_batch(&req, &body).map_err(ApiHttpError::bad_request)?;
+    if requests.is_empty() {
+        return Err(ApiHttpError::bad_request("No transfers in batch"));
+    }
+    if requests.len() > MAX_BATCH_TRANSFERS {
+        return Err(ApiHttpError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Too many transfers in batch: {}, max {}",
+                requests.len(),
+                MAX_BATCH_TRANSFERS
+            ),
+        ));
+    }
+    let conn = data.db_connection.lock().await.clone();
+
+    let mut rows = requests
+        .iter()
+        .map(|request| {
+            request
+                .as_ref()
+                .map_err(|err| err.clone())
+                .and_then(|request| parse_transfer_request(&data, request))
+        })
+        .collect::<Vec<_>>();
+
+    let mut payment_ids: BTreeMap<(String, Address, String), usize> = BTreeMap::new();
+    for (idx, row) in rows.iter_mut().enumerate() {
+        let Ok((_, transfer_args)) = row else {
+            continue;
+        };
+        let key = (
+            transfer_args.network.clone(),
+            transfer_args.from,
+            transfer_args.payment_id.clone(),
+        );
+        if let Some(first_idx) = payment_ids.get(&key) {
+            *row = Err(format!(
+                "Payment id {} already used in row {}",
+                transfer_args.payment_id,
+                first_idx + 1
+            ));
+            continue;
+        }
+        payment_ids.insert(key, idx);
+        let chain_id = data
+            .payment_setup
+            .chain_setup
+            .values()
+            .find(|c| c.network == transfer_args.network)
+            .map(|c| c.chain_id)
+            .unwrap_or_default();
+        match get_token_transfer_by_payment_id(
+            &conn,
+            chain_id,
+            &format!("{:#x}", transfer_args.from),
+            &transfer_args.payment_id,
+        )
+        .await
+        {
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                *row = Err(format!(
+                    "Payment id {} already exists",
+                    transfer_args.payment_id
+                ))
+            }
+            Err(err) => *row = Err(format!("Cannot check payment id: {}", err)),
+        }
+    }
+    check_batch_deposits(&data, &conn, &mut rows).await;
+
+    let results = rows
+        .iter()
+        .enumerate()
+        .map(|(idx, row)| TransferBatchRowResult {
+            row: idx + 1,
+            payment_id: row.as_ref().ok().map(|(_, args)| args.payment_id.clone()),
+            error: row.as_ref().err().cloned(),
+        })
+        .collect::<Vec<_>>();
+    let invalid = results.iter().filter(|r| r.error.is_some()).count();
+    if invalid > 0 {
+        return Ok(HttpResponse::BadRequest().json(TransferBatchResponse {
+            inserted: 0,
+            invalid,
+            results,
+        }));
+    }
+
+    let transfers = rows.into_iter().flatten().collect::<Vec<_>>();
+    match data
+        .payment_runtime
+        .transfers_with_accounts(&transfers)
+        .await
+    {
+        Ok(inserted) => {
+            log::info!("Created batch of {} transfers", inserted.len());
+            Ok(HttpResponse::Ok().json(TransferBatchResponse {
+                inserted: inserted.len(),
+                invalid: 0,
+                results,
+            }))
+        }
+        Err(err) => {
+            if let ErrorBag::TransferAlreadyExists(token_transfer) = *err.inner {
+                return Err(ApiHttpError::transfer_already_exists(*token_transfer));
+            }
+            Err(ApiHttpError::internal(format!(
+                "Failed to create transfers: {}",
+                err
+            )))
+        }
+    }
+}
+
+//web::Path decodes the whole segment, match_info keeps %2F encoded
+async fn cancel_transfer(
+    data: Data<Box<ServerData>>,
+    payment_id: web::Path<String>,
+    cancel_transfer: web::Json<CancelTransferRequest>,
+) -> ApiResult<TransferResponse> {
+    let from = Address::from_str(&cancel_transfer.from)
+        .map_err(|err| ApiHttpError::bad_request(format!("Invalid from address: {}", err)))?;
+    let transfer = data
+        .payment_runtime
+        .cancel_transfer(cancel_transfer.chain, from, &payment_id)
+        .await
+        .map_err(|err| ApiHttpError::transfer_update_failed("cancel", err))?;
+
+    Ok(web::Json(TransferResponse { transfer }))
+}
+
+async fn amend_transfer(
+    data: Data<Box<ServerData>>,
+    payment_id: web::Path<String>,
+    amend_transfer: web::Json<AmendTransferRequest>,
+) -> ApiResult<TransferResponse> {
+    let from = Address::from_str(&amend_transfer.from)
+        .map_err(|err| ApiHttpError::bad_request(format!("Invalid from address: {}", err)))?;
+    let receiver = match &amend_transfer.to {
+        Some(to) => Some(
+            Address::from_str(to)
+                .map_err(|err| ApiHttpError::bad_request(format!("Invalid to address: {}", err)))?,
+        ),
+        None => None,
+    };
+    let amount = match &amend_transfer.amount {
+        Some(amount) => Some(
+            U256::from_dec_str(amount)
+                .map_err(|err| ApiHttpError::bad_request(format!("Invalid amount: {:?}", err)))?,
+        ),
+        None => None,
+    };
+
+    let transfer = data
+        .payment_runtime
+        .amend_transfer(
+            amend_transfer.chain,
+            from,
+            &payment_id,
+            AmendTransferArgs { receiver, amount },
+        )
+        .await
+        .map_err(|err| ApiHttpError::transfer_update_failed("amend", err))?;
+
+    Ok(web::Json(TransferResponse { transfer }))
 }
 
 pub async fn stats_transfers(
     data: Data<Box<ServerData>>,
     info: web::Query<StatsTransferRequest>,
-) -> actix_web::Result<web::Json<StatsTransferResult>> {
+) -> ApiResult<StatsTransferResponse> {
     let time_start = std::time::Instant::now();
     let receiver = if info.receiver.clone() == Some("all".to_string()) {
         None
@@ -711,10 +1036,10 @@ pub async fn stats_transfers(
             &info
                 .receiver
                 .clone()
-                .ok_or(actix_web::error::ErrorBadRequest("account not found"))?,
+                .ok_or(ApiHttpError::bad_request("account not found"))?,
         )
         .map_err(|err| {
-            actix_web::error::ErrorBadRequest(format!("account has to be valid address {err}"))
+            ApiHttpError::bad_request(format!("account has to be valid address {err}"))
         })?;
         Some(account)
     };
@@ -725,53 +1050,41 @@ pub async fn stats_transfers(
             &info
                 .from
                 .clone()
-                .ok_or(actix_web::error::ErrorBadRequest("From not found"))?,
+                .ok_or(ApiHttpError::bad_request("From not found"))?,
         )
-        .map_err(|err| {
-            actix_web::error::ErrorBadRequest(format!("From is not a valid timestamp {err}"))
-        })?,
+        .map_err(|err| ApiHttpError::bad_request(format!("From is not a valid timestamp {err}")))?,
         0,
     )
-    .ok_or(actix_web::error::ErrorBadRequest(
-        "From is not a valid timestamp.",
-    ))?;
+    .ok_or(ApiHttpError::bad_request("From is not a valid timestamp."))?;
     let to = chrono::DateTime::from_timestamp(
         i64::from_str(
             &info
                 .to
                 .clone()
-                .ok_or(actix_web::error::ErrorBadRequest("To not found"))?,
+                .ok_or(ApiHttpError::bad_request("To not found"))?,
         )
-        .map_err(|err| {
-            actix_web::error::ErrorBadRequest(format!("To is not a valid timestamp {err}"))
-        })?,
+        .map_err(|err| ApiHttpError::bad_request(format!("To is not a valid timestamp {err}")))?,
         0,
     )
-    .ok_or(actix_web::error::ErrorBadRequest(
-        "To is not a valid timestamp.",
-    ))?;
+    .ok_or(ApiHttpError::bad_request("To is not a valid timestamp."))?;
 
     let chain_id = i64::from_str(
         &info
             .chain
             .clone()
-            .ok_or(actix_web::error::ErrorBadRequest("Chain id not found"))?,
+            .ok_or(ApiHttpError::bad_request("Chain id not found"))?,
     )
-    .map_err(|err| actix_web::error::ErrorBadRequest(format!("Chain id a valid {err}")))?;
+    .map_err(|err| ApiHttpError::bad_request(format!("Chain id a valid {err}")))?;
 
     let conn = data.db_connection.lock().await.clone();
     let transf = if let Some(receiver) = account_str.as_ref() {
         let transf =
             get_all_chain_transfers_by_receiver_ext(&conn, chain_id, from, to, receiver, None)
                 .await;
-        transf.map_err(|err| {
-            actix_web::error::ErrorBadRequest(format!("Unknown server error: {}", err))
-        })?
+        transf.map_err(|err| ApiHttpError::bad_request(format!("Unknown server error: {}", err)))?
     } else {
         let transf = get_all_chain_transfers_ext(&conn, chain_id, from, to, None).await;
-        transf.map_err(|err| {
-            actix_web::error::ErrorBadRequest(format!("Unknown server error: {}", err))
-        })?
+        transf.map_err(|err| ApiHttpError::bad_request(format!("Unknown server error: {}", err)))?
     };
 
     let mut resp = Vec::new();
@@ -808,135 +1121,119 @@ pub async fn stats_transfers(
 
     let time_end = time_start.elapsed().as_secs_f64();
     //serialize
-    Ok(web::Json(StatsTransferResult {
+    Ok(web::Json(StatsTransferResponse {
         request_time: time_end,
         transfers: resp,
     }))
 }
 
-pub async fn transfers(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let tx_id = req
-        .match_info()
-        .get("tx_id")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(None);
-
-    //let my_data = data.shared_state.lock().await;
+pub async fn transfers(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<TransfersResponse> {
+    let tx_id = path_param::<i64>(&req, "tx_id")?;
 
     let transfers = {
         let db_conn = data.db_connection.lock().await;
         if let Some(tx_id) = tx_id {
-            match get_token_transfers_by_tx(&*db_conn, tx_id).await {
-                Ok(allowances) => allowances,
-                Err(err) => {
-                    return web::Json(json!({
-                        "error": err.to_string()
-                    }));
-                }
-            }
+            get_token_transfers_by_tx(&*db_conn, tx_id).await
         } else {
-            match get_all_token_transfers(&db_conn, None).await {
-                Ok(allowances) => allowances,
-                Err(err) => {
-                    return web::Json(json!({
-                        "error": err.to_string()
-                    }));
-                }
-            }
+            get_all_token_transfers(&db_conn, None).await
         }
+        .map_err(ApiHttpError::internal)?
     };
 
-    /*
-        let json_transfers = transfers
-            .iter()
-            .map(|transfer| {
-                json!({
-                    "id": transfer.id,
-                    "chain_id": transfer.chain_id,
-                    "tx_id": transfer.tx_id,
-                    "from": transfer.from_addr,
-                    "receiver": transfer.receiver_addr,
-                    "token": transfer.token_addr,
-                    "amount": transfer.token_amount,
-                    "fee_paid": transfer.fee_paid,
-                })
-            })
-            .collect::<Vec<_>>();
-    */
-    web::Json(json!({
-        "transfers": transfers,
-    }))
+    Ok(web::Json(TransfersResponse { transfers }))
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct AccountBalanceResponse {
-    network_id: i64,
-    account: String,
-    gas_balance: String,
-    token_balance: String,
-    block_number: u64,
-    block_date: chrono::DateTime<chrono::Utc>,
+const MAX_EVENTS_AT_ONCE: i64 = 1000;
+const MAX_EVENTS_WAIT: u64 = 60;
+
+async fn events(
+    data: Data<Box<ServerData>>,
+    info: web::Query<EventsRequest>,
+) -> ApiResult<EventsResponse> {
+    let limit = info.limit.unwrap_or(100).clamp(1, MAX_EVENTS_AT_ONCE);
+    let timeout = Duration::from_secs(info.timeout.unwrap_or(30).min(MAX_EVENTS_WAIT));
+    let (events, last_event_id) = data
+        .payment_runtime
+        .event_log
+        .wait_for_events_after(info.after, limit, timeout)
+        .await
+        .map_err(ApiHttpError::internal)?;
+    Ok(web::Json(EventsResponse {
+        events,
+        last_event_id,
+    }))
 }
 
 async fn account_balance(
     data: Data<Box<ServerData>>,
     req: HttpRequest,
-) -> actix_web::Result<web::Json<AccountBalanceResponse>> {
+    info: web::Query<AccountBalanceRequest>,
+) -> ApiResult<AccountBalanceResponse> {
     let account = Address::from_str(
         req.match_info()
             .get("account")
-            .ok_or(actix_web::error::ErrorBadRequest("account not found"))?,
+            .ok_or(ApiHttpError::bad_request("account not found"))?,
     )
-    .map_err(|err| {
-        actix_web::error::ErrorBadRequest(format!("account has to be valid address {err}"))
-    })?;
+    .map_err(|err| ApiHttpError::bad_request(format!("account has to be valid address {err}")))?;
     let network_id = i64::from_str(
         req.match_info()
             .get("chain")
-            .ok_or(actix_web::error::ErrorBadRequest("chain-id not found"))?,
+            .ok_or(ApiHttpError::bad_request("chain-id not found"))?,
     )
-    .map_err(|err| actix_web::error::ErrorBadRequest(format!("chain-id has to be int {err}")))?;
+    .map_err(|err| ApiHttpError::bad_request(format!("chain-id has to be int {err}")))?;
 
     let chain = data
         .payment_setup
         .chain_setup
         .get(&network_id)
-        .ok_or(actix_web::error::ErrorBadRequest("No config found"))?;
+        .ok_or(ApiHttpError::bad_request("No config found"))?;
+
+    let token = match &info.token {
+        Some(token) => chain
+            .get_token(token)
+            .ok_or_else(|| ApiHttpError::bad_request(format!("Unknown token: {}", token)))?,
+        None => chain
+            .get_token_by_address(chain.glm_address)
+            .ok_or(ApiHttpError::internal("Default token not found"))?,
+    };
 
     let block_info = chain
         .provider
         .clone()
         .eth_block(BlockId::Number(BlockNumber::Latest))
         .await
-        .map_err(|err| {
-            actix_web::error::ErrorInternalServerError(format!("Failed to get latest block {err}"))
-        })?
-        .ok_or(actix_web::error::ErrorInternalServerError(
-            "Failed to found block info",
-        ))?;
-
-    let block_number = block_info
-        .number
-        .ok_or(actix_web::error::ErrorInternalServerError(
-            "Failed to found block number in block info",
-        ))?;
+        .map_err(|err| ApiHttpError::internal(format!("Failed to get latest block {err}")))?
+        .ok_or(ApiHttpError::internal("Failed to found block info"))?;
+
+    let block_number = block_info.number.ok_or(ApiHttpError::internal(
+        "Failed to found block number in block info",
+    ))?;
 
     let block_date = datetime_from_u256_timestamp(block_info.timestamp).ok_or(
-        actix_web::error::ErrorInternalServerError("Failed to found block date in block info"),
+        ApiHttpError::internal("Failed to found block date in block info"),
     )?;
 
     let balance = get_balance(
         chain.provider.clone(),
-        Some(chain.glm_address),
+        Some(token.address),
         account,
         true,
         Some(block_number.as_u64()),
     )
     .await
-    .map_err(|err| {
-        actix_web::error::ErrorInternalServerError(format!("Failed to get balance {err}"))
-    })?;
+    .map_err(|err| ApiHttpError::internal(format!("Failed to get balance {err}")))?;
+
+    let token_decimals = chain
+        .token_decimals(token.address)
+        .await
+        .map_err(|err| ApiHttpError::internal(format!("Failed to get token decimals {err}")))?;
+    let token_balance = balance.token_balance.unwrap_or_default();
+    let token_balance_decimal = token_balance
+        .to_token_decimal(token_decimals)
+        .map_err(|err| ApiHttpError::internal(format!("Failed to convert token balance {err}")))?;
 
     Ok(web::Json(AccountBalanceResponse {
         network_id,
@@ -945,20 +1242,20 @@ async fn account_balance(
             .gas_balance
             .map(|b| b.to_string())
             .unwrap_or("0".to_string()),
-        token_balance: balance
-            .token_balance
-            .map(|b| b.to_string())
-            .unwrap_or("0".to_string()),
+        token_symbol: token.symbol.clone(),
+        token_address: format!("{:#x}", token.address),
+        token_decimals,
+        token_balance: token_balance.to_string(),
+        token_balance_decimal: token_balance_decimal.to_string(),
         block_number: block_number.as_u64(),
         block_date,
     }))
 }
 
-pub async fn accounts(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
-    //let name = req.match_info().get("name").unwrap_or("World");
-    //let mut my_data = data.shared_state.lock().await;
-    //my_data.inserted += 1;
-
+pub async fn accounts(
+    data: Data<Box<ServerData>>,
+    _req: HttpRequest,
+) -> ApiResult<AccountsResponse> {
     let public_addr = data
         .shared_state
         .lock()
@@ -968,37 +1265,36 @@ pub async fn accounts(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Re
         .map(|sk| format!("{:#x}", sk.address))
         .collect::<Vec<String>>();
 
-    web::Json(json!({
-        "publicAddr": public_addr
-    }))
+    Ok(web::Json(AccountsResponse { public_addr }))
+}
+
+fn account_param(req: &HttpRequest) -> Result<String, ApiHttpError> {
+    let account = path_param::<Address>(req, "account")?
+        .ok_or(ApiHttpError::bad_request("No account provided"))?;
+    Ok(format!("{account:#x}"))
 }
 
-pub async fn account_payments_in(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let account = return_on_error!(req.match_info().get("account").ok_or("No account provided"));
-    let web3_account = return_on_error!(Address::from_str(account));
-    let account = format!("{web3_account:#x}");
+pub async fn account_payments_in(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<AccountPaymentsInResponse> {
+    let account = account_param(&req)?;
 
     let transfers_in = {
         let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_account_transfers_in(&db_conn, &account, None).await)
+        get_account_transfers_in(&db_conn, &account, None)
+            .await
+            .map_err(ApiHttpError::internal)?
     };
-    /*let chain_transfers = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_account_chain_transfers(&db_conn, &account).await)
-    };*/
 
-    web::Json(json!({
-        "transfersIn": transfers_in,
-     //   "chainTransfers": chain_transfers,
-    }))
+    Ok(web::Json(AccountPaymentsInResponse { transfers_in }))
 }
 
-pub async fn account_details(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let account = return_on_error!(req.match_info().get("account").ok_or("No account provided"));
-
-    let web3_account = return_on_error!(Address::from_str(account));
-
-    let account = format!("{web3_account:#x}");
+pub async fn account_details(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<AccountResponse> {
+    let account = account_param(&req)?;
 
     let is_sender = if let Some(addr) = data
         .shared_state
@@ -1014,9 +1310,26 @@ pub async fn account_details(data: Data<Box<ServerData>>, req: HttpRequest) -> i
     } else {
         false
     };
-    let allowances = {
-        let db_conn = data.db_connection.lock().await;
-        return_on_error!(get_allowances_by_owner(&db_conn, &account).await)
+    let db_conn = data.db_connection.lock().await.clone();
+    let allowances = get_allowances_by_owner(&db_conn, &account)
+        .await
+        .map_err(ApiHttpError::internal)?;
+
+    let transfer_count = |filter, sender: Option<&str>, receiver: Option<&str>| {
+        let db_conn = db_conn.clone();
+        let sender = sender.map(|s| s.to_string());
+        let receiver = receiver.map(|s| s.to_string());
+        async move {
+            get_transfer_count(
+                &db_conn,
+                Some(filter),
+                sender.as_deref(),
+                receiver.as_deref(),
+            )
+            .await
+            .map(|count| count as u64)
+            .map_err(ApiHttpError::internal)
+        }
     };
 
     let mut queued_transfer_count = 0;
@@ -1024,48 +1337,21 @@ pub async fn account_details(data: Data<Box<ServerData>>, req: HttpRequest) -> i
     let mut done_transfer_count = 0;
 
     if is_sender {
-        queued_transfer_count = {
-            let db_conn = data.db_connection.lock().await;
-            return_on_error!(
-                get_transfer_count(&db_conn, Some(TRANSFER_FILTER_QUEUED), Some(&account), None)
-                    .await
-            )
-        };
-        processed_transfer_count = {
-            let db_conn = data.db_connection.lock().await;
-            return_on_error!(
-                get_transfer_count(
-                    &db_conn,
-                    Some(TRANSFER_FILTER_PROCESSING),
-                    Some(&account),
-                    None
-                )
-                .await
-            )
-        };
-        done_transfer_count = {
-            let db_conn = data.db_connection.lock().await;
-            return_on_error!(
-                get_transfer_count(&db_conn, Some(TRANSFER_FILTER_DONE), Some(&account), None)
-                    .await
-            )
-        };
+        queued_transfer_count =
+            transfer_count(TRANSFER_FILTER_QUEUED, Some(&account), None).await?;
+        processed_transfer_count =
+            transfer_count(TRANSFER_FILTER_PROCESSING, Some(&account), None).await?;
+        done_transfer_count = transfer_count(TRANSFER_FILTER_DONE, Some(&account), None).await?;
     }
-    let received_transfer_count = {
-        let db_conn = data.db_connection.lock().await;
-
-        return_on_error!(
-            get_transfer_count(&db_conn, Some(TRANSFER_FILTER_ALL), None, Some(&account)).await
-        )
-    };
+    let received_transfer_count = transfer_count(TRANSFER_FILTER_ALL, None, Some(&account)).await?;
 
-    web::Json(json!({
-        "account": account,
-        "allowances": allowances,
-        "transfersQueued": queued_transfer_count,
-        "transfersProcessing": processed_transfer_count,
-        "transfersDone": done_transfer_count,
-        "receivedTransfers": received_transfer_count,
+    Ok(web::Json(AccountResponse {
+        account,
+        allowances,
+        transfers_queued: queued_transfer_count,
+        transfers_processing: processed_transfer_count,
+        transfers_done: done_transfer_count,
+        received_transfers: received_transfer_count,
     }))
 }
 
@@ -1091,27 +1377,39 @@ pub async fn metrics(_req: HttpRequest) -> impl Responder {
     })
 }
 
-pub async fn greet(_req: HttpRequest) -> impl Responder {
+pub async fn greet(_req: HttpRequest) -> web::Json<VersionResponse> {
     const VERSION: &str = env!("CARGO_PKG_VERSION");
-    web::Json(json!({
-        "name": "erc20_payment_lib",
-        "version": VERSION,
-    }))
+    web::Json(VersionResponse {
+        name: "erc20_payment_lib".to_string(),
+        version: VERSION.to_string(),
+    })
 }
 
-pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
-    let target_addr = req.match_info().get("addr").unwrap_or("");
-    let chain_id = req.match_info().get("chain").unwrap_or("");
-    if !target_addr.is_empty() {
-        let receiver_addr = return_on_error!(web3::types::Address::from_str(target_addr));
+pub async fn openapi_json(req: HttpRequest) -> web::Json<serde_json::Value> {
+    let api_base_url = req.path().trim_end_matches("/openapi.json");
+    web::Json(openapi_document(api_base_url))
+}
 
-        let chain_id = return_on_error!(i64::from_str(chain_id));
+pub async fn faucet_status(_req: HttpRequest) -> web::Json<FaucetStatusResponse> {
+    web::Json(FaucetStatusResponse {
+        status: "faucet enabled".to_string(),
+    })
+}
 
-        let chain: &ChainSetup = return_on_error!(data
+pub async fn faucet(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+) -> ApiResult<FaucetTransferResponse> {
+    let receiver_addr = path_param::<Address>(&req, "addr")?
+        .ok_or(ApiHttpError::bad_request("No address provided"))?;
+    let chain_id =
+        path_param::<i64>(&req, "chain")?.ok_or(ApiHttpError::bad_request("No chain provided"))?;
+    {
+        let chain: &ChainSetup = data
             .payment_setup
             .chain_setup
             .get(&(chain_id))
-            .ok_or("No config for given chain id"));
+            .ok_or(ApiHttpError::bad_request("No config for given chain id"))?;
         let faucet_event_idx = format!("{receiver_addr:#x}_{chain_id}");
 
         {
@@ -1134,9 +1432,10 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
             if let Some(el) = faucet_data.faucet_events.get(&faucet_event_idx) {
                 let ago = (chrono::Utc::now().time() - el.time()).num_seconds();
                 if ago < MIN_SECONDS {
-                    return web::Json(json!({
-                        "error": format!("Already sent to this address {ago} seconds ago. Try again after {MIN_SECONDS} seconds")
-                    }));
+                    return Err(ApiHttpError::new(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        format!("Already sent to this address {ago} seconds ago. Try again after {MIN_SECONDS} seconds"),
+                    ));
                 } else {
                     faucet_data
                         .faucet_events
@@ -1172,12 +1471,12 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
             .unwrap()
             .address;
 
-        let faucet_eth_amount = return_on_error!(chain
+        let faucet_eth_amount = chain
             .faucet_eth_amount
-            .ok_or("Faucet amount not set on chain"));
-        let faucet_glm_amount = return_on_error!(chain
+            .ok_or(ApiHttpError::internal("Faucet amount not set on chain"))?;
+        let faucet_glm_amount = chain
             .faucet_glm_amount
-            .ok_or("Faucet GLM amount not set on chain"));
+            .ok_or(ApiHttpError::internal("Faucet GLM amount not set on chain"))?;
 
         let token_transfer_eth = {
             let tt = create_token_transfer(
@@ -1190,7 +1489,9 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
                 None,
             );
             let db_conn = data.db_connection.lock().await;
-            return_on_error!(insert_token_transfer(&*db_conn, &tt).await)
+            insert_token_transfer(&*db_conn, &tt)
+                .await
+                .map_err(ApiHttpError::internal)?
         };
         let token_transfer_glm = {
             let tt = create_token_transfer(
@@ -1203,20 +1504,18 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
                 None,
             );
             let db_conn = data.db_connection.lock().await;
-            return_on_error!(insert_token_transfer(&*db_conn, &tt).await)
+            insert_token_transfer(&*db_conn, &tt)
+                .await
+                .map_err(ApiHttpError::internal)?
         };
 
-        return web::Json(json!({
-        "transfer_gas_id": token_transfer_eth.id,
-        "transfer_gas_payment_id": token_transfer_eth.payment_id,
-        "transfer_glm_id": token_transfer_glm.id,
-        "transfer_glm_payment_id": token_transfer_glm.payment_id,
-                }));
+        Ok(web::Json(FaucetTransferResponse {
+            transfer_gas_id: token_transfer_eth.id,
+            transfer_gas_payment_id: token_transfer_eth.payment_id,
+            transfer_glm_id: token_transfer_glm.id,
+            transfer_glm_payment_id: token_transfer_glm.payment_id,
+        }))
     }
-
-    web::Json(json!({
-        "status": "faucet enabled"
-    }))
 }
 
 pub fn runtime_web_scope(
@@ -1227,9 +1526,17 @@ pub fn runtime_web_scope(
     debug: bool,
     frontend: bool,
 ) -> Scope {
+    let api_auth = server_data.payment_runtime.config.api_auth.clone();
+    let api_nonces = server_data.api_nonces.clone();
     let api_scope = Scope::new("/api");
     let mut api_scope = api_scope
         .app_data(server_data)
+        .app_data(web::JsonConfig::default().error_handler(|err, _req| {
+            ApiHttpError::bad_request(format!("Invalid JSON body: {err}")).into()
+        }))
+        .app_data(web::QueryConfig::default().error_handler(|err, _req| {
+            ApiHttpError::bad_request(format!("Invalid query: {err}")).into()
+        }))
         .route("/allowances", web::get().to(allowances))
         .route("/balance/{account}/{chain}", web::get().to(account_balance))
         .route("/rpc_pool", web::get().to(rpc_pool))
@@ -1264,19 +1571,35 @@ pub fn runtime_web_scope(
         .route("/account/{account}", web::get().to(account_details))
         .route("/account/{account}/in", web::get().to(account_payments_in))
         .route("/metrics", web::get().to(metrics))
+        .route("/events", web::get().to(events))
         .route("/", web::get().to(greet))
         .route(
             "/event_stream",
             web::get().to(event_stream_websocket_endpoint),
         )
-        .route("/version", web::get().to(greet));
+        .route("/version", web::get().to(greet))
+        .route("/openapi.json", web::get().to(openapi_json));
 
     if enable_transfers {
-        api_scope = api_scope.route("/transfers/new", web::post().to(new_transfer))
+        api_scope = api_scope
+            .route("/transfers/new", web::post().to(new_transfer))
+            .service(
+                web::resource("/transfers/batch")
+                    .app_data(web::PayloadConfig::new(MAX_BATCH_BODY_SIZE))
+                    .route(web::post().to(new_transfers_batch)),
+            )
+            .route(
+                "/transfers/{payment_id}/cancel",
+                web::post().to(cancel_transfer),
+            )
+            .route(
+                "/transfers/{payment_id}/amend",
+                web::post().to(amend_transfer),
+            );
     }
     if enable_faucet {
         log::info!("Faucet endpoints enabled");
-        api_scope = api_scope.route("/faucet", web::get().to(faucet));
+        api_scope = api_scope.route("/faucet", web::get().to(faucet_status));
         api_scope = api_scope.route("/faucet/{chain}/{addr}", web::get().to(faucet));
     }
     if debug {
@@ -1284,6 +1607,11 @@ pub fn runtime_web_scope(
         api_scope = api_scope.route("/debug", web::get().to(debug_endpoint));
     }
 
+    if api_auth.is_some() {
+        log::info!("API authentication enabled");
+    }
+    let api_scope = api_scope.wrap(ApiAuth::new(api_auth, api_nonces));
+
     // Add version endpoint to /api, /api/ and /api/version
     let scope = scope.route("/api", web::get().to(greet));
     let mut scope = scope.service(api_scope);
@@ -1309,3 +1637,39 @@ pub fn runtime_web_scope(
     }
     scope
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_parse_transfer_batch() {
+        let req = TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .to_http_request();
+        let body = r#"[
+            {"from": "0x001066290077e38f222cc6009c0c7a91d5192303", "to": "0x0000000000000000000000000000000000000001", "amount": "100", "chain": 17000},
+            {"from": "0x001066290077e38f222cc6009c0c7a91d5192303", "amount": "100", "chain": 17000}
+        ]"#;
+        let rows = parse_transfer_batch(&req, body.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].as_ref().unwrap().amount, "100");
+        assert!(rows[1].is_err());
+        assert!(parse_transfer_batch(&req, b"{}").is_err());
+
+        let req = TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "text/csv"))
+            .to_http_request();
+        let body = "from,to,token,amount,chain,paymentId\n\
+            0x001066290077e38f222cc6009c0c7a91d5192303, 0x0000000000000000000000000000000000000001,tGLM,5,17000,p1\n\
+            0x001066290077e38f222cc6009c0c7a91d5192303,0x0000000000000000000000000000000000000001,,7,holesky,p2\n";
+        let rows = parse_transfer_batch(&req, body.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.to, "0x0000000000000000000000000000000000000001");
+        assert_eq!(first.token.as_deref(), Some("tGLM"));
+        assert_eq!(first.payment_id.as_deref(), Some("p1"));
+        assert!(rows[1].is_err());
+    }
+}
@@ -1,20 +1,31 @@
 use super::web::ServerData;
+use crate::event_log::EventLog;
 use actix::{Actor, StreamHandler};
 use actix_web::web::Data;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use erc20_payment_lib_common::api::StoredEvent;
 use erc20_payment_lib_common::DriverEvent;
+use serde::Deserialize;
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_stream::wrappers::BroadcastStream;
 
+enum EventSource {
+    Broadcast(broadcast::Receiver<DriverEvent>),
+    /// Stored events after given id, then new ones as they are stored
+    EventLog(EventLog, i64),
+}
+
 struct MainWebsocketActor {
-    rx: Option<broadcast::Receiver<DriverEvent>>,
+    source: Option<EventSource>,
 }
 
 impl MainWebsocketActor {
-    pub fn new(rec: broadcast::Receiver<DriverEvent>) -> Self {
-        Self { rx: Some(rec) }
+    fn new(source: EventSource) -> Self {
+        Self {
+            source: Some(source),
+        }
     }
 }
 
@@ -33,12 +44,24 @@ impl StreamHandler<Result<DriverEvent, BroadcastStreamRecvError>> for MainWebsoc
                 ctx.text(serde_json::to_string(&event).expect("Failed to serialize DriverEvent"));
             }
             Err(BroadcastStreamRecvError::Lagged(n)) => {
-                log::warn!("Websocket actor skipped {} messages", n);
+                log::warn!(
+                    "Websocket actor skipped {} messages, connect with ?after=<id> to read stored events",
+                    n
+                );
             }
         }
     }
 }
 
+impl StreamHandler<StoredEvent> for MainWebsocketActor {
+    fn handle(&mut self, event: StoredEvent, ctx: &mut Self::Context) {
+        match serde_json::to_string(&event) {
+            Ok(text) => ctx.text(text),
+            Err(err) => log::error!("Failed to serialize event {}: {}", event.id, err),
+        }
+    }
+}
+
 /// Handler for ws::Message message
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MainWebsocketActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
@@ -55,23 +78,48 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MainWebsocketActo
     }
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        let stream_wrapper: BroadcastStream<DriverEvent> =
-            BroadcastStream::new(self.rx.take().unwrap());
-        <Self as StreamHandler<Result<DriverEvent, BroadcastStreamRecvError>>>::add_stream(
-            stream_wrapper,
-            ctx,
-        );
+        match self.source.take().unwrap() {
+            EventSource::Broadcast(rx) => {
+                let stream_wrapper: BroadcastStream<DriverEvent> = BroadcastStream::new(rx);
+                <Self as StreamHandler<Result<DriverEvent, BroadcastStreamRecvError>>>::add_stream(
+                    stream_wrapper,
+                    ctx,
+                );
+            }
+            EventSource::EventLog(event_log, after) => {
+                <Self as StreamHandler<StoredEvent>>::add_stream(
+                    event_log.stream_after(after),
+                    ctx,
+                );
+            }
+        }
     }
 }
 
+#[derive(Deserialize)]
+pub struct EventStreamRequest {
+    /// Resume from stored events after this id instead of streaming only new events
+    after: Option<i64>,
+}
+
 pub async fn event_stream_websocket_endpoint(
     data: Data<Box<ServerData>>,
     req: HttpRequest,
     stream: web::Payload,
+    info: web::Query<EventStreamRequest>,
 ) -> Result<HttpResponse, Error> {
-    if let Some(driver_broadcast_sender) = &data.payment_runtime.driver_broadcast_sender {
+    if let Some(after) = info.after {
+        ws::start(
+            MainWebsocketActor::new(EventSource::EventLog(
+                data.payment_runtime.event_log.clone(),
+                after,
+            )),
+            &req,
+            stream,
+        )
+    } else if let Some(driver_broadcast_sender) = &data.payment_runtime.driver_broadcast_sender {
         ws::start(
-            MainWebsocketActor::new(driver_broadcast_sender.subscribe()),
+            MainWebsocketActor::new(EventSource::Broadcast(driver_broadcast_sender.subscribe())),
             &req,
             stream,
         )
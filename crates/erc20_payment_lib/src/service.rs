@@ -4,20 +4,20 @@ use std::sync::Arc;
 use crate::error::{ErrorBag, PaymentError};
 use erc20_payment_lib_common::ops::*;
 
-use crate::transaction::{find_receipt_extended, FindReceiptParseResult};
+use crate::transaction::{find_receipts_extended, FindReceiptParseResult};
 use crate::utils::{ConversionError, U256ConvExt};
 
 use crate::err_from;
 use crate::setup::ChainSetup;
 
 use crate::contracts::encode_erc20_balance_of;
-use erc20_payment_lib_common::model::{ChainTxDbObj, TransferInDbObj};
+use erc20_payment_lib_common::model::{ChainTransferDbObj, ChainTxDbObj, TransferInDbObj};
+use erc20_payment_lib_common::DbPool;
 use erc20_rpc_pool::Web3RpcPool;
-use sqlx::SqlitePool;
-use web3::types::{Address, BlockNumber, CallRequest, U256};
+use web3::types::{Address, BlockId, BlockNumber, Bytes, CallRequest, H256, U256};
 
 pub async fn add_payment_request_2(
-    conn: &SqlitePool,
+    conn: &DbPool,
     token_address: Option<Address>,
     token_amount: U256,
     payment_id: &str,
@@ -43,7 +43,7 @@ pub async fn add_payment_request_2(
 }
 
 pub async fn add_glm_request(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_setup: &ChainSetup,
     token_amount: U256,
     payment_id: &str,
@@ -69,124 +69,157 @@ pub async fn add_glm_request(
 
 pub async fn transaction_from_chain_and_into_db(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     tx_hash: &str,
-    glm_address: Address,
+    token_addresses: &[Address],
     get_balances: bool,
 ) -> Result<Option<ChainTxDbObj>, PaymentError> {
     log::debug!("tx_hash: {tx_hash}");
-    let tx_hash = web3::types::H256::from_str(tx_hash)
+    let tx_hash = H256::from_str(tx_hash)
         .map_err(|_err| ConversionError::from("Cannot parse tx_hash".to_string()))
         .map_err(err_from!())?;
 
-    if let Some(chain_tx) = get_chain_tx_hash(conn, format!("{:#x}", tx_hash))
-        .await
-        .map_err(err_from!())?
-    {
-        log::warn!("Transaction already in DB: {}, skipping...", chain_tx.id);
-        return Ok(Some(chain_tx));
+    transactions_from_chain_and_into_db(
+        web3,
+        conn,
+        chain_id,
+        &[tx_hash],
+        token_addresses,
+        get_balances,
+    )
+    .await?
+    .pop()
+    .unwrap_or(Ok(None))
+}
+
+/// Imports transactions from chain fetching their data with batch requests.
+/// Returns result for every given transaction, None if transaction is failed or cannot be parsed.
+/// Token balance is read for the first of token_addresses (main token of the chain).
+pub async fn transactions_from_chain_and_into_db(
+    web3: Arc<Web3RpcPool>,
+    conn: &DbPool,
+    chain_id: i64,
+    tx_hashes: &[H256],
+    token_addresses: &[Address],
+    get_balances: bool,
+) -> Result<Vec<Result<Option<ChainTxDbObj>, PaymentError>>, PaymentError> {
+    let mut results = Vec::with_capacity(tx_hashes.len());
+    let mut positions = Vec::new();
+    let mut to_fetch = Vec::new();
+    for tx_hash in tx_hashes {
+        if let Some(chain_tx) = get_chain_tx_hash(conn, format!("{:#x}", tx_hash))
+            .await
+            .map_err(err_from!())?
+        {
+            log::warn!("Transaction already in DB: {}, skipping...", chain_tx.id);
+            results.push(Some(Ok(Some(chain_tx))));
+        } else {
+            positions.push(results.len());
+            results.push(None);
+            to_fetch.push(*tx_hash);
+        }
+    }
+    if to_fetch.is_empty() {
+        return Ok(results.into_iter().flatten().collect());
     }
 
-    let (mut chain_tx_dao, transfers) =
-        match find_receipt_extended(web3.clone(), tx_hash, chain_id, glm_address).await? {
-            FindReceiptParseResult::Success((c, t)) => (c, t),
-            FindReceiptParseResult::Failure(str) => {
+    let found = find_receipts_extended(web3.clone(), &to_fetch, chain_id, token_addresses).await?;
+    let mut parsed = Vec::new();
+    for (pos, res) in positions.into_iter().zip(found) {
+        match res {
+            Ok(FindReceiptParseResult::Success((chain_tx_dao, transfers))) => {
+                if chain_tx_dao.chain_status == 1 {
+                    parsed.push((pos, chain_tx_dao, transfers));
+                } else {
+                    results[pos] = Some(Ok(None));
+                }
+            }
+            Ok(FindReceiptParseResult::Failure(str)) => {
                 log::warn!("Transaction cannot be parsed: {}", str);
-                return Ok(None);
+                results[pos] = Some(Ok(None));
             }
-        };
-
-    if chain_tx_dao.chain_status != 1 {
-        return Ok(None);
+            Err(err) => results[pos] = Some(Err(err)),
+        }
     }
 
-    if get_balances {
-        let mut loop_no = 0;
-
-        let balance = loop {
-            loop_no += 1;
-            match web3
-                .clone()
-                .eth_balance(
-                    Address::from_str(&chain_tx_dao.from_addr).unwrap(),
-                    Some(BlockNumber::Number(chain_tx_dao.block_number.into())),
-                )
-                .await
-            {
-                Ok(v) => break Some(v),
-                Err(e) => {
-                    log::debug!("Error getting balance: {}", e);
-                    if loop_no > 1000 {
-                        break None;
-                    }
-                }
-            };
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        };
-
-        log::debug!(
-            "Balance: {:.5} for block {}",
-            balance.unwrap_or_default().to_eth().unwrap(),
-            chain_tx_dao.block_number
-        );
+    if let Some(&main_token) = token_addresses
+        .first()
+        .filter(|_| get_balances && !parsed.is_empty())
+    {
+        let mut balance_calls = Vec::with_capacity(parsed.len());
+        let mut token_balance_calls = Vec::with_capacity(parsed.len());
+        for (_, chain_tx_dao, _) in &parsed {
+            let from_addr = Address::from_str(&chain_tx_dao.from_addr).map_err(err_from!())?;
+            let block = BlockNumber::Number(chain_tx_dao.block_number.into());
+            balance_calls.push((from_addr, Some(block)));
+            let call_data = encode_erc20_balance_of(from_addr).map_err(err_from!())?;
+            token_balance_calls.push((
+                CallRequest {
+                    to: Some(main_token),
+                    data: Some(Bytes::from(call_data)),
+                    ..Default::default()
+                },
+                Some(BlockId::Number(block)),
+            ));
+        }
+        let balances = web3
+            .clone()
+            .eth_balance_batch(balance_calls)
+            .await
+            .map_err(err_from!())?;
+        let token_balances = web3
+            .eth_call_batch(token_balance_calls)
+            .await
+            .map_err(err_from!())?;
 
-        loop_no = 0;
-        let token_balance = loop {
-            let call_data =
-                encode_erc20_balance_of(Address::from_str(&chain_tx_dao.from_addr).unwrap())
-                    .map_err(err_from!())?;
-            match web3
-                .clone()
-                .eth_call(
-                    CallRequest {
-                        from: None,
-                        to: Some(glm_address),
-                        gas: None,
-                        gas_price: None,
-                        value: None,
-                        data: Some(web3::types::Bytes::from(call_data)),
-                        transaction_type: None,
-                        access_list: None,
-                        max_fee_per_gas: None,
-                        max_priority_fee_per_gas: None,
-                    },
-                    Some(web3::types::BlockId::Number(BlockNumber::Number(
-                        chain_tx_dao.block_number.into(),
-                    ))),
-                )
+        for ((_, chain_tx_dao, _), (balance, token_balance)) in parsed
+            .iter_mut()
+            .zip(balances.into_iter().zip(token_balances))
+        {
+            let balance = balance
+                .map_err(|e| log::debug!("Error getting balance: {}", e))
+                .ok();
+            let token_balance = token_balance
+                .map_err(|e| log::debug!("Error getting token balance: {}", e))
+                .ok()
+                .filter(|v| v.0.len() == 32)
+                .map(|v| U256::from_big_endian(&v.0));
+            log::debug!(
+                "Balance: {:.5} for block {}",
+                balance.unwrap_or_default().to_eth().unwrap_or_default(),
+                chain_tx_dao.block_number
+            );
+            log::info!(
+                "Token balance: {:.5} for block {}",
+                token_balance
+                    .map(|v| v.to_eth().unwrap_or_default())
+                    .unwrap_or_default(),
+                chain_tx_dao.block_number
+            );
+            chain_tx_dao.balance_eth = balance.map(|b| b.to_string());
+            chain_tx_dao.balance_glm = token_balance.map(|v| v.to_string());
+        }
+    }
+
+    for (pos, chain_tx_dao, transfers) in parsed {
+        results[pos] = Some(
+            insert_chain_tx_with_transfers(conn, &chain_tx_dao, transfers)
                 .await
-            {
-                Ok(v) => {
-                    if v.0.len() == 32 {
-                        break Some(U256::from_big_endian(&v.0));
-                    }
-                }
-                Err(e) => {
-                    log::debug!("Error getting token balance: {}", e);
-                }
-            };
-            if loop_no > 1000 {
-                break None;
-            }
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        };
-
-        log::info!(
-            "Token balance: {:.5} for block {}",
-            token_balance
-                .map(|v| v.to_eth().unwrap())
-                .unwrap_or_default(),
-            chain_tx_dao.block_number
+                .map(Some),
         );
-
-        chain_tx_dao.balance_eth = balance.map(|b| b.to_string());
-        chain_tx_dao.balance_glm = token_balance.map(|v| v.to_string());
     }
+    Ok(results.into_iter().flatten().collect())
+}
 
+async fn insert_chain_tx_with_transfers(
+    conn: &DbPool,
+    chain_tx_dao: &ChainTxDbObj,
+    transfers: Vec<ChainTransferDbObj>,
+) -> Result<ChainTxDbObj, PaymentError> {
     let mut db_transaction = conn.begin().await.map_err(err_from!())?;
 
-    let tx = insert_chain_tx(&mut *db_transaction, &chain_tx_dao)
+    let tx = insert_chain_tx(&mut db_transaction, chain_tx_dao)
         .await
         .map_err(err_from!())?;
 
@@ -221,7 +254,7 @@ pub async fn transaction_from_chain_and_into_db(
         for (mut transfer, fee_paid) in transfers.into_iter().zip(distribute_fee) {
             transfer.chain_tx_id = tx.id;
             transfer.fee_paid = fee_paid.map(|v| v.to_string());
-            insert_chain_transfer(&mut *db_transaction, &transfer)
+            insert_chain_transfer(&mut db_transaction, &transfer)
                 .await
                 .map_err(err_from!())?;
         }
@@ -229,13 +262,13 @@ pub async fn transaction_from_chain_and_into_db(
 
     db_transaction.commit().await.map_err(err_from!())?;
     log::debug!("Transaction found and parsed successfully: {}", tx.id);
-    Ok(Some(tx))
+    Ok(tx)
 }
 
 /*
 pub async fn confirm_loop(
     _shared_state: Arc<Mutex<SharedState>>,
-    _conn: &SqlitePool,
+    _conn: &DbPool,
     payment_setup: &PaymentSetup,
 ) {
     loop {
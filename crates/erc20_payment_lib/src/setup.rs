@@ -1,17 +1,19 @@
-use crate::config::{AdditionalOptions, Config};
+use crate::config::{AdditionalOptions, BatchingPolicy, Config, RpcStatsSettings};
 use crate::error::ErrorBag;
 use crate::error::PaymentError;
+use crate::eth::{get_token_decimals, set_token_decimals_cache};
 
 use crate::utils::DecimalConvExt;
 use crate::{err_custom_create, err_from};
 use erc20_payment_lib_common::DriverEvent;
 use erc20_rpc_pool::{
     Web3EndpointParams, Web3ExternalDnsSource, Web3ExternalJsonSource, Web3PoolType, Web3RpcPool,
-    Web3RpcSingleParams,
+    Web3RpcQuorum, Web3RpcSingleParams, DEFAULT_MAX_BATCH_SIZE,
 };
 use rust_decimal::Decimal;
 use serde::Serialize;
 use std::collections::BTreeMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
@@ -36,6 +38,33 @@ pub struct FaucetSetup {
     pub mint_max_glm_allowed: Option<Decimal>,
 }
 
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenSetup {
+    pub symbol: String,
+    pub address: Address,
+    /// Decimals from config, if not set they are read from the token contract
+    pub decimals: Option<u8>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MetaTransactionSetup {
+    pub relayer: Address,
+    pub accounts: Vec<Address>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FeePolicySetup {
+    pub daily_budget: Option<U256>,
+    pub monthly_budget: Option<U256>,
+    pub max_base_fee: Option<U256>,
+    pub base_fee_extra: Option<U256>,
+    pub priority_fee_percentile: Option<f64>,
+    pub fee_history_blocks: u64,
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ChainSetup {
@@ -49,18 +78,29 @@ pub struct ChainSetup {
     pub max_fee_per_gas: U256,
     pub priority_fee: U256,
     pub glm_address: Address,
+    /// All tokens that can be paid on this chain, default token (glm_address) is first
+    pub tokens: Vec<TokenSetup>,
     pub multi_contract_address: Option<Address>,
     pub lock_contract_address: Option<Address>,
+    /// Balance, allowance and deposit lookups are aggregated using this contract if set
+    pub multicall_contract_address: Option<Address>,
+    /// Token transfers of these accounts are sent as meta transactions by the relayer
+    pub meta_transaction: Option<MetaTransactionSetup>,
+    pub fee_policy: Option<FeePolicySetup>,
     pub faucet_setup: FaucetSetup,
     pub multi_contract_max_at_once: usize,
     pub transaction_timeout: u64,
     pub skip_multi_contract_check: bool,
     pub confirmation_blocks: u64,
+    /// Block hash of confirmed transactions is rechecked until this depth
+    pub finality_blocks: Option<u64>,
     pub faucet_eth_amount: Option<U256>,
     pub faucet_glm_amount: Option<U256>,
     pub block_explorer_url: Option<String>,
     pub replacement_timeout: Option<f64>,
     pub external_source_check_interval: Option<u64>,
+    /// Signed transactions are sent to all allowed endpoints at once
+    pub broadcast_to_all_endpoints: bool,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -89,6 +129,8 @@ pub struct PaymentSetup {
     pub gather_at_start: bool,
     pub mark_as_unrecoverable_after_seconds: u64,
     pub ignore_deadlines: bool,
+    pub batching_policy: BatchingPolicy,
+    pub rpc_stats: RpcStatsSettings,
     pub automatic_recover: bool,
     pub contract_use_direct_method: bool,
     pub contract_use_unpacked_method: bool,
@@ -96,6 +138,42 @@ pub struct PaymentSetup {
     pub extra_options_for_testing: Option<ExtraOptionsForTesting>,
 }
 
+impl ChainSetup {
+    /// Find token by symbol (case insensitive) or by address
+    pub fn get_token(&self, symbol_or_address: &str) -> Option<&TokenSetup> {
+        let address = Address::from_str(symbol_or_address).ok();
+        self.tokens.iter().find(|token| {
+            Some(token.address) == address || token.symbol.eq_ignore_ascii_case(symbol_or_address)
+        })
+    }
+
+    pub fn get_token_by_address(&self, address: Address) -> Option<&TokenSetup> {
+        self.tokens.iter().find(|token| token.address == address)
+    }
+
+    pub fn token_addresses(&self) -> Vec<Address> {
+        self.tokens.iter().map(|token| token.address).collect()
+    }
+
+    /// Relayer that sends token transfers of given sender as meta transactions
+    pub fn meta_transaction_relayer(&self, sender: Address) -> Option<Address> {
+        self.meta_transaction
+            .as_ref()
+            .filter(|meta| meta.accounts.contains(&sender))
+            .map(|meta| meta.relayer)
+    }
+
+    pub async fn token_decimals(&self, token_address: Address) -> Result<u8, PaymentError> {
+        match self
+            .get_token_by_address(token_address)
+            .and_then(|token| token.decimals)
+        {
+            Some(decimals) => Ok(decimals),
+            None => get_token_decimals(self.provider.clone(), token_address).await,
+        }
+    }
+}
+
 const MARK_AS_UNRECOVERABLE_AFTER_SECONDS: u64 = 300;
 
 fn split_string_by_coma(s: &Option<String>) -> Option<Vec<String>> {
@@ -140,6 +218,8 @@ impl PaymentSetup {
                 .mark_as_unrecoverable_after_seconds
                 .unwrap_or(MARK_AS_UNRECOVERABLE_AFTER_SECONDS),
             ignore_deadlines: config.engine.ignore_deadlines,
+            batching_policy: config.engine.batching.clone(),
+            rpc_stats: config.engine.rpc_stats.clone(),
             automatic_recover: config.engine.automatic_recover,
             contract_use_direct_method: false,
             contract_use_unpacked_method: false,
@@ -176,6 +256,11 @@ impl PaymentSetup {
                                     .max_consecutive_errors
                                     .unwrap_or(5),
                                 min_interval_requests_ms: rpc_settings.min_interval_ms,
+                                max_requests_per_second: rpc_settings.max_requests_per_second,
+                                daily_request_quota: rpc_settings.daily_request_quota,
+                                max_batch_size: rpc_settings
+                                    .max_batch_size
+                                    .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                             },
                             source_id: None,
                         };
@@ -196,6 +281,11 @@ impl PaymentSetup {
                                 .max_consecutive_errors
                                 .unwrap_or(5),
                             min_interval_requests_ms: rpc_settings.min_interval_ms,
+                            max_requests_per_second: rpc_settings.max_requests_per_second,
+                            daily_request_quota: rpc_settings.daily_request_quota,
+                            max_batch_size: rpc_settings
+                                .max_batch_size
+                                .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                         },
                     });
                 } else if let Some(json_source) = &rpc_settings.json_source {
@@ -213,10 +303,28 @@ impl PaymentSetup {
                                 .max_consecutive_errors
                                 .unwrap_or(5),
                             min_interval_requests_ms: rpc_settings.min_interval_ms,
+                            max_requests_per_second: rpc_settings.max_requests_per_second,
+                            daily_request_quota: rpc_settings.daily_request_quota,
+                            max_batch_size: rpc_settings
+                                .max_batch_size
+                                .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                         },
                     });
                 }
             }
+            let quorum = chain_config
+                .1
+                .rpc_endpoints
+                .iter()
+                .find_map(|rpc_settings| {
+                    rpc_settings.quorum_size.map(|size| {
+                        Web3RpcQuorum::new(
+                            size,
+                            rpc_settings.quorum_min_agree,
+                            rpc_settings.quorum_methods.clone(),
+                        )
+                    })
+                });
             let web3_pool = Web3RpcPool::new(
                 chain_config.1.chain_id as u64,
                 single_endpoints,
@@ -227,6 +335,16 @@ impl PaymentSetup {
                 Duration::from_secs(chain_config.1.external_source_check_interval.unwrap_or(300)),
             );
 
+            if let Some(quorum) = &quorum {
+                log::info!(
+                    "Using quorum of {} out of {} endpoints for chain {}",
+                    quorum.min_agree,
+                    quorum.size,
+                    chain_config.1.chain_id
+                );
+            }
+            web3_pool.set_quorum(quorum);
+
             web3_rpc_pool_info
                 .lock()
                 .unwrap()
@@ -271,6 +389,81 @@ impl PaymentSetup {
                 mint_glm_address: chain_config.1.mint_contract.clone().map(|mc| mc.address),
             };
 
+            chain_config
+                .1
+                .check_tokens()
+                .map_err(|err| err_custom_create!("Chain {}: {}", chain_config.0, err))?;
+            let mut tokens: Vec<TokenSetup> = Vec::new();
+            for token in chain_config.1.all_tokens() {
+                if let Some(decimals) = token.decimals {
+                    set_token_decimals_cache(
+                        chain_config.1.chain_id as u64,
+                        token.address,
+                        decimals,
+                    );
+                }
+                tokens.push(TokenSetup {
+                    symbol: token.symbol.clone(),
+                    address: token.address,
+                    decimals: token.decimals,
+                });
+            }
+
+            let meta_transaction = match chain_config.1.meta_transaction.as_ref() {
+                Some(meta) => {
+                    if meta.accounts.contains(&meta.relayer) {
+                        return Err(err_custom_create!(
+                            "Meta transaction relayer {:#x} cannot be on the list of accounts on chain {}",
+                            meta.relayer,
+                            chain_config.0
+                        ));
+                    }
+                    Some(MetaTransactionSetup {
+                        relayer: meta.relayer,
+                        accounts: meta.accounts.clone(),
+                    })
+                }
+                None => None,
+            };
+
+            let fee_policy = match chain_config.1.fee_policy.as_ref() {
+                Some(fee_policy) => {
+                    if let Some(percentile) = fee_policy.priority_fee_percentile {
+                        if !(0.0..=100.0).contains(&percentile) {
+                            return Err(err_custom_create!(
+                                "Priority fee percentile has to be between 0 and 100 on chain {}",
+                                chain_config.0
+                            ));
+                        }
+                    }
+                    Some(FeePolicySetup {
+                        daily_budget: fee_policy
+                            .daily_budget
+                            .map(|v| v.to_u256_from_eth())
+                            .transpose()
+                            .map_err(err_from!())?,
+                        monthly_budget: fee_policy
+                            .monthly_budget
+                            .map(|v| v.to_u256_from_eth())
+                            .transpose()
+                            .map_err(err_from!())?,
+                        max_base_fee: fee_policy
+                            .max_base_fee
+                            .map(|v| v.to_u256_from_gwei())
+                            .transpose()
+                            .map_err(err_from!())?,
+                        base_fee_extra: fee_policy
+                            .base_fee_extra
+                            .map(|v| v.to_u256_from_gwei())
+                            .transpose()
+                            .map_err(err_from!())?,
+                        priority_fee_percentile: fee_policy.priority_fee_percentile,
+                        fee_history_blocks: fee_policy.fee_history_blocks.max(1),
+                    })
+                }
+                None => None,
+            };
+
             ps.chain_setup.insert(
                 chain_config.1.chain_id,
                 ChainSetup {
@@ -289,6 +482,7 @@ impl PaymentSetup {
                         .map_err(err_from!())?,
                     glm_address: chain_config.1.token.address,
                     currency_glm_symbol: chain_config.1.token.symbol.clone(),
+                    tokens,
                     multi_contract_address: chain_config
                         .1
                         .multi_contract
@@ -301,11 +495,19 @@ impl PaymentSetup {
                         .map(|m| m.max_at_once)
                         .unwrap_or(1),
                     lock_contract_address: chain_config.1.lock_contract.clone().map(|m| m.address),
+                    multicall_contract_address: chain_config
+                        .1
+                        .multicall_contract
+                        .clone()
+                        .map(|m| m.address),
+                    meta_transaction,
+                    fee_policy,
                     faucet_setup,
 
                     transaction_timeout: chain_config.1.transaction_timeout,
                     skip_multi_contract_check: options.skip_multi_contract_check,
                     confirmation_blocks: chain_config.1.confirmation_blocks,
+                    finality_blocks: chain_config.1.finality_blocks,
                     currency_gas_symbol: chain_config.1.currency_symbol.clone(),
                     faucet_eth_amount,
                     faucet_glm_amount,
@@ -313,6 +515,10 @@ impl PaymentSetup {
                     chain_id: chain_config.1.chain_id,
                     replacement_timeout: chain_config.1.replacement_timeout,
                     external_source_check_interval: chain_config.1.external_source_check_interval,
+                    broadcast_to_all_endpoints: chain_config
+                        .1
+                        .broadcast_to_all_endpoints
+                        .unwrap_or(false),
                 },
             );
         }
@@ -337,3 +543,43 @@ impl PaymentSetup {
         Ok(chain_setup.provider.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_chain_setup_tokens() {
+        let usdc = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+        let config = Config::load_from_str(&Config::default_config_str().replace(
+            "# tokens = [",
+            &format!(r#"tokens = [{{ address = "{usdc}", symbol = "USDC", decimals = 6 }}] #"#),
+        ))
+        .unwrap();
+        let setup = PaymentSetup::new(
+            &config,
+            &AdditionalOptions::default(),
+            Arc::new(std::sync::Mutex::new(BTreeMap::new())),
+            None,
+        )
+        .unwrap();
+        let polygon = setup.chain_setup.get(&137).unwrap();
+        assert_eq!(polygon.tokens.len(), 2);
+        assert_eq!(polygon.tokens[0].address, polygon.glm_address);
+        let usdc_address = Address::from_str(usdc).unwrap();
+        assert_eq!(polygon.get_token("Usdc").unwrap().address, usdc_address);
+        assert_eq!(polygon.get_token(usdc).unwrap().decimals, Some(6));
+        assert_eq!(
+            polygon.get_token_by_address(usdc_address).unwrap().symbol,
+            "USDC"
+        );
+        assert!(polygon.get_token("DAI").is_none());
+        assert_eq!(polygon.token_decimals(usdc_address).await.unwrap(), 6);
+        assert!(setup
+            .chain_setup
+            .get(&17000)
+            .unwrap()
+            .get_token("USDC")
+            .is_none());
+    }
+}
@@ -1,7 +1,9 @@
 mod account;
 mod external;
 mod private;
+mod remote;
 
 pub use account::*;
 pub use external::*;
 pub use private::*;
+pub use remote::*;
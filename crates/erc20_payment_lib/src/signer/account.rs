@@ -8,7 +8,8 @@ use tokio::task::JoinHandle;
 use tokio::time::timeout;
 
 use super::Signer;
-use web3::types::{Address, SignedTransaction, TransactionParameters, H160};
+use web3::signing::Signature;
+use web3::types::{Address, SignedTransaction, TransactionParameters, H160, H256};
 
 #[derive(Clone, Serialize)]
 pub struct SignerAccount {
@@ -79,4 +80,22 @@ impl SignerAccount {
             Err(err) => Err(err_custom_create!("Sign check timed out {err:?}")),
         }
     }
+
+    pub async fn sign_typed_data(
+        &self,
+        domain_separator: H256,
+        struct_hash: H256,
+    ) -> Result<Signature, PaymentError> {
+        match timeout(
+            std::time::Duration::from_secs(5),
+            self.signer
+                .sign_typed_data(self.address, domain_separator, struct_hash),
+        )
+        .await
+        {
+            Ok(Ok(signature)) => Ok(signature),
+            Ok(Err(err)) => Err(err_custom_create!("Sign typed data returned error {err:?}")),
+            Err(err) => Err(err_custom_create!("Sign typed data timed out {err:?}")),
+        }
+    }
 }
@@ -1,7 +1,9 @@
 use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
 use std::fmt::Debug;
 
-use web3::types::{SignedTransaction, TransactionParameters, H160};
+use web3::signing::Signature;
+use web3::types::{SignedTransaction, TransactionParameters, H160, H256};
 
 #[derive(Debug)]
 pub struct SignerError {
@@ -18,4 +20,19 @@ pub trait Signer: Send + Sync {
         pub_address: H160,
         tp: TransactionParameters,
     ) -> BoxFuture<'_, Result<SignedTransaction, SignerError>>;
+
+    /// Sign EIP-712 typed data given by domain separator and struct hash (used for meta transactions)
+    fn sign_typed_data(
+        &self,
+        _pub_address: H160,
+        _domain_separator: H256,
+        _struct_hash: H256,
+    ) -> BoxFuture<'_, Result<Signature, SignerError>> {
+        async move {
+            Err(SignerError {
+                message: "Typed data signing is not supported by this signer".to_string(),
+            })
+        }
+        .boxed()
+    }
 }
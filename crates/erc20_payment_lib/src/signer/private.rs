@@ -1,11 +1,13 @@
 use crate::contracts::DUMMY_RPC_PROVIDER;
+use crate::eip712::typed_data_digest;
 use crate::eth::get_eth_addr_from_secret;
 use futures_util::future::BoxFuture;
 use futures_util::FutureExt;
 use secp256k1::SecretKey;
 
 use super::{Signer, SignerError};
-use web3::types::{SignedTransaction, TransactionParameters, H160};
+use web3::signing::{Key, SecretKeyRef, Signature};
+use web3::types::{SignedTransaction, TransactionParameters, H160, H256};
 
 /// PrivateKeySigner is implementation of Signer trait that stores private keys in memory and use
 /// them to sign transactions matching them by public addresses
@@ -55,4 +57,22 @@ impl Signer for PrivateKeySigner {
         }
         .boxed()
     }
+
+    fn sign_typed_data(
+        &self,
+        pub_address: H160,
+        domain_separator: H256,
+        struct_hash: H256,
+    ) -> BoxFuture<'_, Result<Signature, SignerError>> {
+        async move {
+            let secret_key = self.get_private_key(pub_address)?;
+            let digest = typed_data_digest(domain_separator, struct_hash);
+            SecretKeyRef::new(secret_key)
+                .sign(digest.as_bytes(), None)
+                .map_err(|err| SignerError {
+                    message: format!("Error when signing typed data in PrivateKeySigner {err}"),
+                })
+        }
+        .boxed()
+    }
 }
@@ -0,0 +1,398 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use rlp::{Rlp, RlpStream};
+use serde_json::{json, Map, Value};
+
+use super::{Signer, SignerError};
+use web3::signing::{keccak256, recover};
+use web3::transports::Http;
+use web3::types::{Bytes, SignedTransaction, TransactionParameters, H160, H256, U256};
+use web3::Transport;
+
+/// RemoteSigner is implementation of Signer trait that keeps no keys and asks separate process
+/// to sign transactions using Web3Signer compatible JSON-RPC (eth_accounts, eth_signTransaction)
+pub struct RemoteSigner {
+    url: String,
+    transport: Http,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str) -> Result<Self, SignerError> {
+        let transport = Http::new(url).map_err(|err| SignerError {
+            message: format!("Invalid remote signer url {url}: {err}"),
+        })?;
+        Ok(Self {
+            url: url.to_string(),
+            transport,
+        })
+    }
+
+    async fn call(&self, method: &str, params: Vec<Value>) -> Result<Value, SignerError> {
+        self.transport
+            .execute(method, params)
+            .await
+            .map_err(|err| SignerError {
+                message: format!("Remote signer {} failed on {method}: {err}", self.url),
+            })
+    }
+
+    async fn accounts(&self) -> Result<Vec<H160>, SignerError> {
+        let res = self.call("eth_accounts", vec![]).await?;
+        serde_json::from_value(res).map_err(|err| SignerError {
+            message: format!("Invalid eth_accounts response from {}: {err}", self.url),
+        })
+    }
+}
+
+fn transaction_request(pub_address: H160, tp: &TransactionParameters) -> Value {
+    let mut req = Map::new();
+    req.insert("from".to_string(), json!(pub_address));
+    if let Some(to) = tp.to {
+        req.insert("to".to_string(), json!(to));
+    }
+    req.insert("gas".to_string(), json!(tp.gas));
+    if let Some(gas_price) = tp.gas_price {
+        req.insert("gasPrice".to_string(), json!(gas_price));
+    }
+    if let Some(max_fee_per_gas) = tp.max_fee_per_gas {
+        req.insert("maxFeePerGas".to_string(), json!(max_fee_per_gas));
+    }
+    if let Some(max_priority_fee_per_gas) = tp.max_priority_fee_per_gas {
+        req.insert(
+            "maxPriorityFeePerGas".to_string(),
+            json!(max_priority_fee_per_gas),
+        );
+    }
+    req.insert("value".to_string(), json!(tp.value));
+    req.insert("data".to_string(), json!(tp.data));
+    if let Some(nonce) = tp.nonce {
+        req.insert("nonce".to_string(), json!(nonce));
+    }
+    if let Some(chain_id) = tp.chain_id {
+        req.insert("chainId".to_string(), json!(U256::from(chain_id)));
+    }
+    if let Some(transaction_type) = tp.transaction_type {
+        req.insert("type".to_string(), json!(transaction_type));
+    }
+    if let Some(access_list) = &tp.access_list {
+        req.insert("accessList".to_string(), json!(access_list));
+    }
+    Value::Object(req)
+}
+
+fn rlp_error(err: rlp::DecoderError) -> SignerError {
+    SignerError {
+        message: format!("Cannot decode signed transaction: {err}"),
+    }
+}
+
+fn check_field<T: PartialEq + std::fmt::Debug>(
+    field: &str,
+    expected: Option<T>,
+    signed: T,
+) -> Result<(), SignerError> {
+    match expected {
+        Some(expected) if expected != signed => Err(SignerError {
+            message: format!(
+                "Remote signer changed {field} of transaction: requested {expected:?}, signed {signed:?}"
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that fields of signed transaction are the same as requested,
+/// fields not set in request (None) are not checked
+fn check_signed_fields(
+    rlp: &Rlp,
+    tx_type: Option<u8>,
+    legacy_chain_id: Option<u64>,
+    tp: &TransactionParameters,
+) -> Result<(), SignerError> {
+    check_field(
+        "type",
+        Some(tp.transaction_type.map(|t| t.as_u64()).unwrap_or(0)),
+        tx_type.map(u64::from).unwrap_or(0),
+    )?;
+    // legacy: nonce, gasPrice, gas, to, value, data
+    // EIP-2930: chainId, nonce, gasPrice, gas, to, value, data, accessList
+    // EIP-1559: chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gas, to, value, data, accessList
+    let (chain_id, nonce_pos) = match tx_type {
+        None => (legacy_chain_id, 0),
+        Some(1) | Some(2) => (Some(rlp.val_at::<u64>(0).map_err(rlp_error)?), 1),
+        Some(tx_type) => {
+            return Err(SignerError {
+                message: format!("Unsupported signed transaction type {tx_type}"),
+            })
+        }
+    };
+    check_field("chain id", tp.chain_id.map(Some), chain_id)?;
+    check_field("nonce", tp.nonce, rlp.val_at(nonce_pos).map_err(rlp_error)?)?;
+    let gas_pos = if tx_type == Some(2) {
+        check_field(
+            "max priority fee",
+            tp.max_priority_fee_per_gas,
+            rlp.val_at(nonce_pos + 1).map_err(rlp_error)?,
+        )?;
+        check_field(
+            "max fee",
+            tp.max_fee_per_gas,
+            rlp.val_at(nonce_pos + 2).map_err(rlp_error)?,
+        )?;
+        nonce_pos + 3
+    } else {
+        check_field(
+            "gas price",
+            tp.gas_price,
+            rlp.val_at(nonce_pos + 1).map_err(rlp_error)?,
+        )?;
+        nonce_pos + 2
+    };
+    check_field("gas", Some(tp.gas), rlp.val_at(gas_pos).map_err(rlp_error)?)?;
+    let to: Vec<u8> = rlp.val_at(gas_pos + 1).map_err(rlp_error)?;
+    let to = match to.len() {
+        0 => None,
+        20 => Some(H160::from_slice(&to)),
+        len => {
+            return Err(SignerError {
+                message: format!("Invalid recipient length {len} in signed transaction"),
+            })
+        }
+    };
+    check_field("recipient", Some(tp.to), to)?;
+    check_field(
+        "value",
+        Some(tp.value),
+        rlp.val_at(gas_pos + 2).map_err(rlp_error)?,
+    )?;
+    check_field(
+        "data",
+        Some(&tp.data.0),
+        &rlp.val_at::<Vec<u8>>(gas_pos + 3).map_err(rlp_error)?,
+    )
+}
+
+/// Rebuilds SignedTransaction from raw transaction returned by remote signer
+/// and checks that it was signed by the expected account and matches requested parameters
+pub fn decode_signed_transaction(
+    raw: &[u8],
+    pub_address: H160,
+    tp: &TransactionParameters,
+) -> Result<SignedTransaction, SignerError> {
+    let (tx_type, payload) = match raw.first() {
+        Some(tx_type) if *tx_type < 0x7f => (Some(*tx_type), &raw[1..]),
+        Some(_) => (None, raw),
+        None => {
+            return Err(SignerError {
+                message: "Remote signer returned empty transaction".to_string(),
+            })
+        }
+    };
+    let rlp = Rlp::new(payload);
+    let item_count = rlp.item_count().map_err(rlp_error)?;
+    if item_count < 4 {
+        return Err(SignerError {
+            message: format!("Signed transaction has only {item_count} fields"),
+        });
+    }
+    let fields = item_count - 3;
+    let v: u64 = rlp.val_at(fields).map_err(rlp_error)?;
+    let r: U256 = rlp.val_at(fields + 1).map_err(rlp_error)?;
+    let s: U256 = rlp.val_at(fields + 2).map_err(rlp_error)?;
+    let legacy_chain_id = (tx_type.is_none() && v >= 35).then(|| (v - 35) / 2);
+    check_signed_fields(&rlp, tx_type, legacy_chain_id, tp)?;
+
+    let mut stream = RlpStream::new();
+    let recovery_id = match tx_type {
+        Some(_) => {
+            stream.begin_list(fields);
+            v
+        }
+        // EIP-155 signature covers chain id followed by two empty fields
+        None if v >= 35 => {
+            stream.begin_list(fields + 3);
+            (v - 35) % 2
+        }
+        None => {
+            stream.begin_list(fields);
+            v.saturating_sub(27)
+        }
+    };
+    for i in 0..fields {
+        stream.append_raw(rlp.at(i).map_err(rlp_error)?.as_raw(), 1);
+    }
+    if tx_type.is_none() && v >= 35 {
+        stream.append(&((v - 35) / 2));
+        stream.append(&0u8);
+        stream.append(&0u8);
+    }
+    let unsigned = match tx_type {
+        Some(tx_type) => [&[tx_type], stream.as_raw()].concat(),
+        None => stream.out().to_vec(),
+    };
+    let message_hash = keccak256(&unsigned);
+
+    let mut r_bytes = [0u8; 32];
+    let mut s_bytes = [0u8; 32];
+    r.to_big_endian(&mut r_bytes);
+    s.to_big_endian(&mut s_bytes);
+    let signer_address = recover(
+        &message_hash,
+        &[r_bytes, s_bytes].concat(),
+        recovery_id as i32,
+    )
+    .map_err(|err| SignerError {
+        message: format!("Cannot recover signer of transaction: {err}"),
+    })?;
+    if signer_address != pub_address {
+        return Err(SignerError {
+            message: format!(
+                "Transaction signed by {:#x} instead of {:#x}",
+                signer_address, pub_address
+            ),
+        });
+    }
+
+    Ok(SignedTransaction {
+        message_hash: H256::from(message_hash),
+        v,
+        r: H256::from(r_bytes),
+        s: H256::from(s_bytes),
+        raw_transaction: Bytes(raw.to_vec()),
+        transaction_hash: H256::from(keccak256(raw)),
+    })
+}
+
+impl Signer for RemoteSigner {
+    fn check_if_sign_possible(&self, pub_address: H160) -> BoxFuture<'_, Result<(), SignerError>> {
+        async move {
+            if self.accounts().await?.contains(&pub_address) {
+                Ok(())
+            } else {
+                Err(SignerError {
+                    message: format!(
+                        "Remote signer {} has no key for address {:#x}",
+                        self.url, pub_address
+                    ),
+                })
+            }
+        }
+        .boxed()
+    }
+
+    fn sign(
+        &self,
+        pub_address: H160,
+        tp: TransactionParameters,
+    ) -> BoxFuture<'_, Result<SignedTransaction, SignerError>> {
+        async move {
+            let res = self
+                .call(
+                    "eth_signTransaction",
+                    vec![transaction_request(pub_address, &tp)],
+                )
+                .await?;
+            // Web3Signer returns raw transaction, geth returns object with raw and decoded tx
+            let raw = res
+                .as_str()
+                .or_else(|| res.get("raw").and_then(Value::as_str))
+                .ok_or_else(|| SignerError {
+                    message: format!("Unexpected eth_signTransaction response: {res}"),
+                })?;
+            let raw = hex::decode(raw.trim_start_matches("0x")).map_err(|err| SignerError {
+                message: format!("Signed transaction is not valid hex: {err}"),
+            })?;
+            decode_signed_transaction(&raw, pub_address, &tp)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth::get_eth_addr_from_secret;
+    use crate::signer::PrivateKeySigner;
+    use secp256k1::SecretKey;
+    use std::str::FromStr;
+    use web3::types::U64;
+
+    #[tokio::test]
+    async fn test_decode_signed_transaction() {
+        let secret_key =
+            SecretKey::from_str("0000000000000000000000000000000000000000000000000000000000000001")
+                .unwrap();
+        let address = get_eth_addr_from_secret(&secret_key);
+        let signer = PrivateKeySigner::new(vec![secret_key]);
+
+        let legacy = TransactionParameters {
+            nonce: Some(U256::from(7)),
+            to: Some(H160::from_low_u64_be(0x1234)),
+            gas: U256::from(21000),
+            gas_price: Some(U256::from(1_000_000_000)),
+            value: U256::from(100),
+            chain_id: Some(17000),
+            ..Default::default()
+        };
+        let eip1559 = TransactionParameters {
+            gas_price: None,
+            transaction_type: Some(U64::from(2)),
+            max_fee_per_gas: Some(U256::from(2_000_000_000)),
+            max_priority_fee_per_gas: Some(U256::from(1_000_000)),
+            data: Bytes(vec![0xa9, 0x05, 0x9c, 0xbb]),
+            ..legacy.clone()
+        };
+        for tp in [legacy, eip1559] {
+            let signed = signer.sign(address, tp.clone()).await.unwrap();
+            let raw = &signed.raw_transaction.0;
+            let decoded = decode_signed_transaction(raw, address, &tp).unwrap();
+            assert_eq!(decoded, signed);
+            assert!(decode_signed_transaction(raw, H160::zero(), &tp).is_err());
+
+            // signer is not allowed to change any of the requested fields
+            let changed = [
+                TransactionParameters {
+                    nonce: Some(U256::from(8)),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    to: Some(H160::from_low_u64_be(0x4321)),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    to: None,
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    value: U256::from(101),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    data: Bytes(vec![0x01]),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    gas: U256::from(21001),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    chain_id: Some(1),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    gas_price: tp.gas_price.map(|fee| fee + 1),
+                    max_fee_per_gas: tp.max_fee_per_gas.map(|fee| fee + 1),
+                    ..tp.clone()
+                },
+                TransactionParameters {
+                    max_priority_fee_per_gas: tp.max_priority_fee_per_gas.map(|fee| fee * 2),
+                    transaction_type: tp.transaction_type.or(Some(U64::from(2))),
+                    ..tp.clone()
+                },
+            ];
+            for changed in changed {
+                assert!(decode_signed_transaction(raw, address, &changed).is_err());
+            }
+        }
+    }
+}
@@ -1,32 +1,36 @@
 use crate::contracts::*;
+use crate::eip712::meta_transaction_struct_hash;
 use crate::error::*;
-use crate::eth::get_eth_addr_from_secret;
+use crate::eth::{
+    get_domain_separator, get_eth_addr_from_secret, get_meta_transaction_nonce, get_token_decimals,
+};
 use crate::multi::pack_transfers_for_multi_contract;
 use crate::runtime::{
     get_token_balance, get_unpaid_token_amount, remove_transaction_force, send_driver_event,
 };
-use crate::signer::Signer;
+use crate::signer::{Signer, SignerAccount};
 use crate::utils::{datetime_from_u256_timestamp, ConversionError, StringConvExt, U256ConvExt};
 use crate::{err_custom_create, err_from};
 use chrono::Utc;
 use erc20_payment_lib_common::model::{
-    ChainTransferDbObj, ChainTxDbObj, TokenTransferDbObj, TxDbObj,
+    ChainTransferDbObj, ChainTxDbObj, TokenTransferDbObj, TokenTransferStatus, TxDbObj,
 };
+use erc20_payment_lib_common::ops::get_token_transfers_by_tx;
 use erc20_payment_lib_common::CantSignContent;
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{
     DriverEvent, DriverEventContent, NoGasDetails, NoTokenDetails, TransactionStuckReason,
 };
-use erc20_rpc_pool::Web3RpcPool;
+use erc20_rpc_pool::{RpcErrorKind, Web3RpcPool};
 use secp256k1::SecretKey;
-use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use web3::transports::Http;
 use web3::types::{
-    Address, BlockId, BlockNumber, Bytes, CallRequest, TransactionId, TransactionParameters, H160,
-    H256, U256, U64,
+    Address, Block, BlockId, BlockNumber, Bytes, CallRequest, Transaction, TransactionId,
+    TransactionParameters, TransactionReceipt, H160, H256, U256, U64,
 };
 use web3::Web3;
 
@@ -136,6 +140,9 @@ pub fn create_token_transfer(
         paid_date: None,
         fee_paid: None,
         error: None,
+        status: TokenTransferStatus::Queued,
+        priority: 0,
+        deadline: None,
     }
 }
 
@@ -178,6 +185,59 @@ pub fn create_erc20_transfer(
     })
 }
 
+pub struct MetaTransferArgs {
+    pub relayer: Address,
+    pub token: Address,
+    pub erc20_to: Address,
+    pub erc20_amount: U256,
+    pub chain_id: u64,
+    pub gas_limit: Option<u64>,
+    /// Meta transaction nonce, read from token contract if not set
+    pub nonce: Option<U256>,
+}
+
+/// Create ERC20 transfer signed (EIP-712) by the sender account, which is sent by the relayer
+/// using executeMetaTransaction, so the sender does not need gas token
+pub async fn create_erc20_transfer_meta(
+    web3: Arc<Web3RpcPool>,
+    sender: &SignerAccount,
+    meta_args: MetaTransferArgs,
+) -> Result<TxDbObj, PaymentError> {
+    let function_signature =
+        encode_erc20_transfer(meta_args.erc20_to, meta_args.erc20_amount).map_err(err_from!())?;
+    let domain_separator = get_domain_separator(web3.clone(), meta_args.token).await?;
+    let nonce = match meta_args.nonce {
+        Some(nonce) => nonce,
+        None => get_meta_transaction_nonce(web3, meta_args.token, sender.address).await?,
+    };
+
+    let struct_hash = meta_transaction_struct_hash(nonce, sender.address, &function_signature);
+    let signature = sender
+        .sign_typed_data(domain_separator, struct_hash)
+        .await?;
+    let sig_v = u8::try_from(signature.v)
+        .map_err(|_| err_custom_create!("Invalid signature v value: {}", signature.v))?;
+
+    Ok(TxDbObj {
+        method: "ERC20.executeMetaTransaction".to_string(),
+        from_addr: format!("{:#x}", meta_args.relayer),
+        to_addr: format!("{:#x}", meta_args.token),
+        chain_id: meta_args.chain_id as i64,
+        gas_limit: meta_args.gas_limit.map(|gas_limit| gas_limit as i64),
+        call_data: Some(hex::encode(
+            encode_execute_meta_transaction(
+                sender.address,
+                function_signature,
+                signature.r,
+                signature.s,
+                sig_v,
+            )
+            .map_err(err_from!())?,
+        )),
+        ..Default::default()
+    })
+}
+
 pub struct SingleTransferDepositArgs {
     pub from: Address,
     pub lock_contract: Address,
@@ -364,6 +424,26 @@ pub fn create_create_deposit(
     })
 }
 
+pub fn create_extend_deposit(
+    from: Address,
+    lock_address: Address,
+    chain_id: u64,
+    gas_limit: Option<u64>,
+    deposit_args: ExtendDepositArgs,
+) -> Result<TxDbObj, PaymentError> {
+    Ok(TxDbObj {
+        method: "LOCK.extendDeposit".to_string(),
+        from_addr: format!("{from:#x}"),
+        to_addr: format!("{lock_address:#x}"),
+        chain_id: chain_id as i64,
+        gas_limit: gas_limit.map(|gas_limit| gas_limit as i64),
+        call_data: Some(hex::encode(
+            encode_extend_deposit(deposit_args).map_err(err_from!())?,
+        )),
+        ..Default::default()
+    })
+}
+
 pub fn create_close_deposit(
     from: Address,
     lock_address: Address,
@@ -426,10 +506,21 @@ pub fn create_erc20_approve(
 
 pub async fn get_no_token_details(
     web3: Arc<Web3RpcPool>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     web3_tx_dao: &TxDbObj,
-    glm_token: Address,
+    default_token: Address,
 ) -> Result<NoTokenDetails, PaymentError> {
+    //transaction is already bound to transfers of single token, check which one
+    let glm_token = match get_token_transfers_by_tx(conn, web3_tx_dao.id)
+        .await
+        .map_err(err_from!())?
+        .into_iter()
+        .find_map(|tt| tt.token_addr)
+    {
+        Some(token_addr) => Address::from_str(&token_addr).map_err(err_from!())?,
+        None => default_token,
+    };
+    let decimals = get_token_decimals(web3.clone(), glm_token).await?;
     Ok(NoTokenDetails {
         tx: web3_tx_dao.clone(),
         sender: Address::from_str(&web3_tx_dao.from_addr).map_err(err_from!())?,
@@ -440,7 +531,7 @@ pub async fn get_no_token_details(
             None,
         )
         .await?
-        .to_eth()
+        .to_token_decimal(decimals)
         .map_err(err_from!())?,
         token_needed: get_unpaid_token_amount(
             conn,
@@ -449,14 +540,14 @@ pub async fn get_no_token_details(
             Address::from_str(&web3_tx_dao.from_addr).map_err(err_from!())?,
         )
         .await?
-        .to_eth()
+        .to_token_decimal(decimals)
         .map_err(err_from!())?,
     })
 }
 
 pub async fn check_transaction(
     event_sender: &Option<mpsc::Sender<DriverEvent>>,
-    conn: &SqlitePool,
+    conn: &DbPool,
     glm_token: Address,
     web3: Arc<Web3RpcPool>,
     web3_tx_dao: &mut TxDbObj,
@@ -472,13 +563,16 @@ pub async fn check_transaction(
         match web3.clone().eth_estimate_gas(loc_call_request, None).await {
             Ok(gas_est) => gas_est,
             Err(e) => {
-                let event = if e.to_string().contains("gas required exceeds allowance") {
+                let kind = RpcErrorKind::from_web3_error(&e);
+                let event = if kind == Some(RpcErrorKind::InsufficientFunds) {
                     log::error!("Gas estimation failed - probably insufficient funds: {}", e);
                     return Err(err_custom_create!(
                         "Gas estimation failed - probably insufficient funds"
                     ));
                 } else if web3_tx_dao.method == "FAUCET.create"
-                    && e.to_string().contains("Cannot acquire more funds")
+                    && kind
+                        .as_ref()
+                        .is_some_and(|k| k.revert_reason_contains("Cannot acquire more funds"))
                 {
                     log::warn!(
                         "Faucet create call failed - probably too much token already minted: {}",
@@ -486,7 +580,10 @@ pub async fn check_transaction(
                     );
                     remove_transaction_force(conn, web3_tx_dao.id).await?;
                     return Ok(None);
-                } else if e.to_string().contains("transfer amount exceeds balance") {
+                } else if kind
+                    .as_ref()
+                    .is_some_and(|k| k.revert_reason_contains("transfer amount exceeds balance"))
+                {
                     log::warn!("Transfer amount exceed balance (chain_id: {}, sender: {:#x}). Getting details...", web3_tx_dao.chain_id, Address::from_str(&web3_tx_dao.from_addr).map_err(err_from!())?);
                     match get_no_token_details(web3, conn, web3_tx_dao, glm_token).await {
                         Ok(stuck_reason) => {
@@ -607,11 +704,12 @@ pub async fn sign_transaction_with_callback(
 }
 
 pub async fn send_transaction(
-    conn: &SqlitePool,
+    conn: &DbPool,
     glm_token: Address,
     event_sender: Option<mpsc::Sender<DriverEvent>>,
     web3: Arc<Web3RpcPool>,
     web3_tx_dao: &mut TxDbObj,
+    broadcast_to_all_endpoints: bool,
 ) -> Result<(), PaymentError> {
     if let Some(signed_raw_data) = web3_tx_dao.signed_raw_data.as_ref() {
         let bytes = Bytes(
@@ -619,17 +717,33 @@ pub async fn send_transaction(
                 .map_err(|_err| ConversionError::from("cannot decode signed_raw_data".to_string()))
                 .map_err(err_from!())?,
         );
-        let result = web3.clone().eth_send_raw_transaction(bytes).await;
+        let result = if broadcast_to_all_endpoints {
+            let tx_hash = H256::from_str(
+                web3_tx_dao
+                    .tx_hash
+                    .as_ref()
+                    .ok_or(err_custom_create!("No tx hash for signed transaction"))?,
+            )
+            .map_err(err_from!())?;
+            let (result, endpoints) = web3
+                .clone()
+                .eth_broadcast_raw_transaction(bytes, tx_hash)
+                .await;
+            web3_tx_dao.broadcast_endpoints = serde_json::to_string(&endpoints).ok();
+            result
+        } else {
+            web3.clone()
+                .eth_send_raw_transaction(bytes)
+                .await
+                .map(|_| ())
+        };
         web3_tx_dao.broadcast_date = Some(chrono::Utc::now());
 
         if let Err(e) = result {
-            //if e.message.contains("insufficient funds") {
-            //    send_driver_event(&event_sender, DriverEvent::InsufficientFunds).await;
-            //
-            match e {
-                web3::Error::Rpc(e) => {
-                    log::error!("Error sending transaction: {:#?}", e);
-                    let event = if e.message.contains("insufficient funds") {
+            match RpcErrorKind::from_web3_error(&e) {
+                Some(kind) => {
+                    log::error!("Error sending transaction ({:?}): {:#?}", kind, e);
+                    let event = if kind == RpcErrorKind::InsufficientFunds {
                         Some(DriverEventContent::TransactionStuck(
                             TransactionStuckReason::NoGas(NoGasDetails {
                                 tx: web3_tx_dao.clone(),
@@ -659,35 +773,13 @@ pub async fn send_transaction(
                                 .map_err(err_from!())?,
                             }),
                         ))
-                    } else if e.message.contains("transfer amount exceeds balance") {
+                    } else if kind.revert_reason_contains("transfer amount exceeds balance") {
                         Some(DriverEventContent::TransactionStuck(
-                            TransactionStuckReason::NoToken(NoTokenDetails {
-                                tx: web3_tx_dao.clone(),
-                                sender: Address::from_str(&web3_tx_dao.from_addr)
-                                    .map_err(err_from!())?,
-                                token_balance: get_token_balance(
-                                    web3,
-                                    glm_token,
-                                    Address::from_str(&web3_tx_dao.from_addr)
-                                        .map_err(err_from!())?,
-                                    None,
-                                )
-                                .await?
-                                .to_eth()
-                                .map_err(err_from!())?,
-                                token_needed: get_unpaid_token_amount(
-                                    conn,
-                                    web3_tx_dao.chain_id,
-                                    glm_token,
-                                    Address::from_str(&web3_tx_dao.from_addr)
-                                        .map_err(err_from!())?,
-                                )
-                                .await?
-                                .to_eth()
-                                .map_err(err_from!())?,
-                            }),
+                            TransactionStuckReason::NoToken(
+                                get_no_token_details(web3, conn, web3_tx_dao, glm_token).await?,
+                            ),
                         ))
-                    } else if e.message.contains("invalid sender") {
+                    } else if kind == RpcErrorKind::InvalidSender {
                         // transaction sent with wrong chain id
                         return Err(err_custom_create!(
                             r#"Invalid sender, seems like transaction is sending to wrong chain. \
@@ -696,7 +788,7 @@ Nonce may be set incorrectly. You can try to fix it by running command (but it m
 erc20processor cleanup --remove-tx-unsafe
 "#
                         ));
-                    } else if e.message.contains("already known") {
+                    } else if kind == RpcErrorKind::AlreadyKnown {
                         //transaction is already in mempool, success!
                         return Ok(());
                     } else {
@@ -707,7 +799,7 @@ erc20processor cleanup --remove-tx-unsafe
                         send_driver_event(&event_sender, event).await;
                     }
                 }
-                _ => {
+                None => {
                     log::error!("Error sending transaction: {:#?}", e);
                 }
             }
@@ -758,6 +850,7 @@ pub async fn find_receipt(
             .map_err(err_from!())?;
         if let Some(receipt) = receipt {
             web3_tx_dao.block_number = receipt.block_number.map(|x| x.as_u64() as i64);
+            web3_tx_dao.block_hash = receipt.block_hash.map(|x| format!("{:#x}", x));
             web3_tx_dao.chain_status = receipt.status.map(|x| x.as_u64() as i64);
             web3_tx_dao.gas_used = receipt.gas_used.map(|x| x.as_u64() as i64);
             web3_tx_dao.effective_gas_price = receipt.effective_gas_price.map(|x| x.to_string());
@@ -789,6 +882,7 @@ pub async fn find_receipt(
             Ok(Some(effective_gas_price))
         } else {
             web3_tx_dao.block_number = None;
+            web3_tx_dao.block_hash = None;
             web3_tx_dao.chain_status = None;
             web3_tx_dao.fee_paid = None;
             Ok(None)
@@ -808,7 +902,134 @@ pub async fn find_receipt_extended(
     web3: Arc<Web3RpcPool>,
     tx_hash: H256,
     chain_id: i64,
-    glm_address: Address,
+    token_addresses: &[Address],
+) -> Result<FindReceiptParseResult, PaymentError> {
+    let receipt = web3
+        .clone()
+        .eth_transaction_receipt(tx_hash)
+        .await
+        .map_err(err_from!())?
+        .ok_or(err_custom_create!("Receipt not found"))?;
+    let tx = web3
+        .clone()
+        .eth_transaction(TransactionId::Hash(tx_hash))
+        .await
+        .map_err(err_from!())?
+        .ok_or(err_custom_create!("Transaction not found"))?;
+    let block_number = receipt
+        .block_number
+        .ok_or(err_custom_create!("Block number is None"))?;
+
+    let block_info = web3
+        .clone()
+        .eth_block(BlockId::Number(BlockNumber::Number(block_number)))
+        .await
+        .map_err(err_from!())?
+        .ok_or(err_custom_create!("Block not found"))?;
+
+    parse_receipt_extended(tx_hash, receipt, tx, block_info, chain_id, token_addresses)
+}
+
+/// Same as find_receipt_extended for many transactions, fetching data with batch requests
+pub async fn find_receipts_extended(
+    web3: Arc<Web3RpcPool>,
+    tx_hashes: &[H256],
+    chain_id: i64,
+    token_addresses: &[Address],
+) -> Result<Vec<Result<FindReceiptParseResult, PaymentError>>, PaymentError> {
+    let receipts = web3
+        .clone()
+        .eth_transaction_receipt_batch(tx_hashes.iter().map(|tx_hash| (*tx_hash,)).collect())
+        .await
+        .map_err(err_from!())?;
+    let txs = web3
+        .clone()
+        .eth_transaction_batch(
+            tx_hashes
+                .iter()
+                .map(|tx_hash| (TransactionId::Hash(*tx_hash),))
+                .collect(),
+        )
+        .await
+        .map_err(err_from!())?;
+
+    let mut block_numbers = receipts
+        .iter()
+        .filter_map(|receipt| receipt.as_ref().ok()?.as_ref()?.block_number)
+        .collect::<Vec<_>>();
+    block_numbers.sort();
+    block_numbers.dedup();
+    let blocks = web3
+        .eth_block_batch(
+            block_numbers
+                .iter()
+                .map(|block_number| (BlockId::Number(BlockNumber::Number(*block_number)),))
+                .collect(),
+        )
+        .await
+        .map_err(err_from!())?;
+    let blocks = block_numbers
+        .into_iter()
+        .zip(blocks)
+        .collect::<HashMap<_, _>>();
+
+    let mut results = Vec::with_capacity(tx_hashes.len());
+    for (tx_hash, (receipt, tx)) in tx_hashes.iter().zip(receipts.into_iter().zip(txs)) {
+        let receipt = match receipt {
+            Ok(Some(receipt)) => receipt,
+            Ok(None) => {
+                results.push(Err(err_custom_create!("Receipt not found")));
+                continue;
+            }
+            Err(e) => {
+                results.push(Err(err_from!()(e)));
+                continue;
+            }
+        };
+        let tx = match tx {
+            Ok(Some(tx)) => tx,
+            Ok(None) => {
+                results.push(Err(err_custom_create!("Transaction not found")));
+                continue;
+            }
+            Err(e) => {
+                results.push(Err(err_from!()(e)));
+                continue;
+            }
+        };
+        let block_info = match receipt
+            .block_number
+            .and_then(|block_number| blocks.get(&block_number))
+        {
+            Some(Ok(Some(block_info))) => block_info.clone(),
+            Some(Err(e)) => {
+                results.push(Err(err_custom_create!("Error getting block: {}", e)));
+                continue;
+            }
+            _ => {
+                results.push(Err(err_custom_create!("Block not found")));
+                continue;
+            }
+        };
+        results.push(parse_receipt_extended(
+            *tx_hash,
+            receipt,
+            tx,
+            block_info,
+            chain_id,
+            token_addresses,
+        ));
+    }
+    Ok(results)
+}
+
+fn parse_receipt_extended(
+    tx_hash: H256,
+    receipt: TransactionReceipt,
+    tx: Transaction,
+    block_info: Block<H256>,
+    chain_id: i64,
+    token_addresses: &[Address],
 ) -> Result<FindReceiptParseResult, PaymentError> {
     let mut chain_tx_dao = ChainTxDbObj {
         id: -1,
@@ -837,32 +1058,11 @@ pub async fn find_receipt_extended(
         balance_glm: None,
     };
 
-    let receipt = web3
-        .clone()
-        .eth_transaction_receipt(tx_hash)
-        .await
-        .map_err(err_from!())?
-        .ok_or(err_custom_create!("Receipt not found"))?;
-    let tx = web3
-        .clone()
-        .eth_transaction(TransactionId::Hash(tx_hash))
-        .await
-        .map_err(err_from!())?
-        .ok_or(err_custom_create!("Transaction not found"))?;
     chain_tx_dao.block_number = receipt
         .block_number
         .map(|x| x.as_u64() as i64)
         .ok_or(err_custom_create!("Block number is None"))?;
 
-    let block_info = web3
-        .clone()
-        .eth_block(BlockId::Number(BlockNumber::Number(U64::from(
-            chain_tx_dao.block_number as u64,
-        ))))
-        .await
-        .map_err(err_from!())?
-        .ok_or(err_custom_create!("Block not found"))?;
-
     //println!("Receipt: {:#?}", receipt);
     chain_tx_dao.checked_date = chrono::Utc::now();
     chain_tx_dao.blockchain_date = datetime_from_u256_timestamp(block_info.timestamp)
@@ -973,7 +1173,7 @@ pub async fn find_receipt_extended(
 
     //check if there is special transfer to contract
     for log in &receipt.logs {
-        if log.address != glm_address {
+        if !token_addresses.contains(&log.address) {
             continue;
         }
         if log.topics.len() == 3 && log.topics[0] == erc20_transfer_event_signature {
@@ -1012,7 +1212,7 @@ pub async fn find_receipt_extended(
     }
 
     for log in &receipt.logs {
-        if log.address != glm_address {
+        if !token_addresses.contains(&log.address) {
             continue;
         }
         if log.topics.len() == 3 && log.topics[0] == erc20_transfer_event_signature {
@@ -1074,7 +1274,7 @@ pub async fn find_receipt_extended(
 
 pub async fn get_erc20_logs(
     web3: Arc<Web3RpcPool>,
-    erc20_address: Address,
+    erc20_addresses: Vec<Address>,
     topic_senders: Option<Vec<H256>>,
     topic_receivers: Option<Vec<H256>>,
     from_block: i64,
@@ -1084,7 +1284,7 @@ pub async fn get_erc20_logs(
         return Err(err_custom_create!("Block number cannot be negative"));
     }
     let filter = web3::types::FilterBuilder::default()
-        .address(vec![erc20_address])
+        .address(erc20_addresses)
         .topics(
             Some(vec![H256::from_str(
                 "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
@@ -1103,7 +1303,7 @@ pub async fn get_erc20_logs(
 
 pub struct ImportErc20TxsArgs {
     pub web3: Arc<Web3RpcPool>,
-    pub erc20_address: Address,
+    pub erc20_addresses: Vec<Address>,
     pub chain_id: i64,
     pub filter_by_senders: Option<Vec<Address>>,
     pub filter_by_receivers: Option<Vec<Address>>,
@@ -1155,7 +1355,7 @@ pub async fn import_erc20_txs(import_args: ImportErc20TxsArgs) -> Result<Vec<H25
     log::debug!("Scanning chain, blocks: {start_block} - {end_block}");
     let logs = get_erc20_logs(
         import_args.web3.clone(),
-        import_args.erc20_address,
+        import_args.erc20_addresses,
         topic_senders.clone(),
         topic_receivers.clone(),
         start_block,
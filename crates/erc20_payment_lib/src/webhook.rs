@@ -0,0 +1,507 @@
+use crate::config::WebhookSettings;
+use crate::error::PaymentError;
+use crate::error::*;
+use crate::{err_custom_create, err_from};
+use chrono::{DateTime, Utc};
+use erc20_payment_lib_common::model::WebhookDeliveryDbObj;
+use erc20_payment_lib_common::ops::{
+    delete_webhook_deliveries_delivered_before, get_next_webhook_attempt_date,
+    get_webhook_deliveries_due, insert_webhook_delivery, postpone_webhook_deliveries,
+    update_webhook_delivery,
+};
+use erc20_payment_lib_common::{DbPool, DriverEvent};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+pub const WEBHOOK_ID_HEADER: &str = "X-Webhook-Id";
+pub const WEBHOOK_EVENT_HEADER: &str = "X-Webhook-Event";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+const DELIVERIES_AT_ONCE: i64 = 100;
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Signature sent in X-Webhook-Signature header (hex encoded)
+pub fn webhook_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}\n").as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delay before the next attempt after given number of failed attempts
+fn retry_delay(settings: &WebhookSettings, attempts: i64) -> Duration {
+    let exp = attempts.saturating_sub(1).clamp(0, 32) as u32;
+    let delay = settings
+        .min_retry_interval
+        .saturating_mul(2u64.saturating_pow(exp));
+    Duration::from_secs(delay.min(settings.max_retry_interval))
+}
+
+/// Stores driver events in webhook_delivery table and sends them to subscribed webhooks
+#[derive(Clone)]
+pub struct WebhookOutbox {
+    conn: DbPool,
+    webhooks: Arc<BTreeMap<String, WebhookSettings>>,
+    notify: Arc<Notify>,
+}
+
+impl WebhookOutbox {
+    /// Returns None if no webhooks are configured
+    pub fn new(conn: DbPool, webhooks: BTreeMap<String, WebhookSettings>) -> Option<Self> {
+        if webhooks.is_empty() {
+            return None;
+        }
+        Some(Self {
+            conn,
+            webhooks: Arc::new(webhooks),
+            notify: Arc::new(Notify::new()),
+        })
+    }
+
+    pub async fn enqueue(&self, event: &DriverEvent) -> Result<(), PaymentError> {
+        let event_type = event.content.event_type();
+        let subscribed = self
+            .webhooks
+            .iter()
+            .filter(|(_, settings)| {
+                settings.events.is_empty() || settings.events.iter().any(|e| e == event_type)
+            })
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if subscribed.is_empty() {
+            return Ok(());
+        }
+        let payload = serde_json::to_string(event)
+            .map_err(|err| err_custom_create!("Cannot serialize event: {}", err))?;
+        let now = Utc::now();
+        let mut db_transaction = self.conn.begin().await.map_err(err_from!())?;
+        for name in subscribed {
+            insert_webhook_delivery(
+                &mut db_transaction,
+                &WebhookDeliveryDbObj {
+                    id: 0,
+                    webhook: name.clone(),
+                    event_type: event_type.to_string(),
+                    payload: payload.clone(),
+                    created_date: now,
+                    attempts: 0,
+                    next_attempt_date: Some(now),
+                    delivered_date: None,
+                    last_error: None,
+                },
+            )
+            .await
+            .map_err(err_from!())?;
+        }
+        db_transaction.commit().await.map_err(err_from!())?;
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        settings: &WebhookSettings,
+        delivery: &WebhookDeliveryDbObj,
+    ) -> Result<(), String> {
+        let timestamp = Utc::now().timestamp();
+        let signature = webhook_signature(&settings.secret, timestamp, delivery.payload.as_bytes());
+        let res = client
+            .post(&settings.url)
+            .timeout(Duration::from_secs(settings.timeout))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID_HEADER, delivery.id.to_string())
+            .header(WEBHOOK_EVENT_HEADER, &delivery.event_type)
+            .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+            .header(WEBHOOK_SIGNATURE_HEADER, signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|err| format!("Request failed: {err}"))?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Receiver responded with {}", res.status()))
+        }
+    }
+
+    /// Returns time of the next attempt if delivery failed
+    async fn deliver(
+        &self,
+        client: &reqwest::Client,
+        mut delivery: WebhookDeliveryDbObj,
+    ) -> Result<Option<DateTime<Utc>>, PaymentError> {
+        let now = Utc::now();
+        delivery.attempts += 1;
+        let result = match self.webhooks.get(&delivery.webhook) {
+            Some(settings) => match self.send(client, settings, &delivery).await {
+                Ok(()) => Ok(()),
+                Err(err) => Err((err, retry_delay(settings, delivery.attempts))),
+            },
+            //webhook removed from config, keep the delivery in case it comes back
+            None => Err((
+                "Webhook not configured".to_string(),
+                Duration::from_secs(3600),
+            )),
+        };
+        match result {
+            Ok(()) => {
+                log::debug!(
+                    "Webhook {} delivered {} event {}",
+                    delivery.webhook,
+                    delivery.event_type,
+                    delivery.id
+                );
+                delivery.next_attempt_date = None;
+                delivery.delivered_date = Some(now);
+                delivery.last_error = None;
+            }
+            Err((err, delay)) => {
+                log::warn!(
+                    "Webhook {} delivery {} failed (attempt {}): {}, retrying in {}s",
+                    delivery.webhook,
+                    delivery.id,
+                    delivery.attempts,
+                    err,
+                    delay.as_secs()
+                );
+                delivery.next_attempt_date =
+                    Some(now + chrono::Duration::from_std(delay).unwrap_or_default());
+                delivery.last_error = Some(err);
+            }
+        }
+        update_webhook_delivery(&self.conn, &delivery)
+            .await
+            .map_err(err_from!())?;
+        Ok(delivery.next_attempt_date)
+    }
+
+    /// Sends due events of one webhook in order. After failed delivery the rest of them
+    /// waits for the same retry time. Returns false if result of some delivery was not stored.
+    async fn deliver_webhook(
+        &self,
+        client: &reqwest::Client,
+        webhook: &str,
+        deliveries: Vec<WebhookDeliveryDbObj>,
+    ) -> bool {
+        let mut stored = true;
+        for delivery in deliveries {
+            let id = delivery.id;
+            match self.deliver(client, delivery).await {
+                Ok(None) => {}
+                Ok(Some(next_attempt)) => {
+                    if let Err(err) =
+                        postpone_webhook_deliveries(&self.conn, webhook, next_attempt).await
+                    {
+                        log::error!("Cannot postpone deliveries of webhook {}: {}", webhook, err);
+                        stored = false;
+                    }
+                    break;
+                }
+                Err(err) => {
+                    log::error!(
+                        "Cannot store result of webhook {} delivery {}: {}",
+                        webhook,
+                        id,
+                        err
+                    );
+                    stored = false;
+                }
+            }
+        }
+        stored
+    }
+
+    /// Webhooks are delivered concurrently, so slow or dead receiver does not delay the others
+    async fn deliver_due(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<Option<DateTime<Utc>>, PaymentError> {
+        loop {
+            let due = get_webhook_deliveries_due(&self.conn, Utc::now(), DELIVERIES_AT_ONCE)
+                .await
+                .map_err(err_from!())?;
+            let count = due.len() as i64;
+            let mut by_webhook = BTreeMap::<String, Vec<WebhookDeliveryDbObj>>::new();
+            for delivery in due {
+                by_webhook
+                    .entry(delivery.webhook.clone())
+                    .or_default()
+                    .push(delivery);
+            }
+            let stored = futures::future::join_all(by_webhook.into_iter().map(
+                |(webhook, deliveries)| async move {
+                    self.deliver_webhook(client, &webhook, deliveries).await
+                },
+            ))
+            .await;
+            if stored.contains(&false) {
+                return Err(err_custom_create!(
+                    "Results of some webhook deliveries were not stored"
+                ));
+            }
+            if count < DELIVERIES_AT_ONCE {
+                break;
+            }
+        }
+        get_next_webhook_attempt_date(&self.conn)
+            .await
+            .map_err(err_from!())
+    }
+
+    async fn prune_delivered(&self) {
+        for (name, settings) in self.webhooks.iter() {
+            if settings.retention == 0 {
+                continue;
+            }
+            let retention =
+                chrono::Duration::try_seconds(settings.retention as i64).unwrap_or_default();
+            match delete_webhook_deliveries_delivered_before(
+                &self.conn,
+                name,
+                Utc::now() - retention,
+            )
+            .await
+            {
+                Ok(0) => {}
+                Ok(deleted) => {
+                    log::debug!("Deleted {} delivered events of webhook {}", deleted, name)
+                }
+                Err(err) => log::warn!(
+                    "Error deleting delivered events of webhook {}: {}",
+                    name,
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Sends pending deliveries, including ones left from previous runs, until the task is aborted.
+    /// Delivered events older than retention are deleted once per hour.
+    pub async fn delivery_loop(self) {
+        let client = reqwest::Client::new();
+        let mut last_prune: Option<Instant> = None;
+        loop {
+            if last_prune.is_none_or(|last| last.elapsed() >= PRUNE_INTERVAL) {
+                self.prune_delivered().await;
+                last_prune = Some(Instant::now());
+            }
+            let wait = match self.deliver_due(&client).await {
+                Ok(Some(next_attempt)) => (next_attempt - Utc::now())
+                    .to_std()
+                    .unwrap_or_default()
+                    .min(MAX_IDLE_WAIT),
+                Ok(None) => MAX_IDLE_WAIT,
+                Err(err) => {
+                    log::error!("Webhook delivery failed: {}", err);
+                    Duration::from_secs(5)
+                }
+            };
+            tokio::select! {
+                _ = self.notify.notified() => {},
+                _ = tokio::time::sleep(wait) => {},
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use erc20_payment_lib_common::DriverEventContent;
+    use std::sync::Mutex;
+
+    fn settings(url: &str) -> WebhookSettings {
+        WebhookSettings {
+            url: url.to_string(),
+            secret: "secret".to_string(),
+            events: vec![],
+            min_retry_interval: 1,
+            max_retry_interval: 60,
+            timeout: 10,
+            retention: 0,
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let settings = WebhookSettings {
+            min_retry_interval: 5,
+            ..settings("http://127.0.0.1")
+        };
+        assert_eq!(retry_delay(&settings, 1), Duration::from_secs(5));
+        assert_eq!(retry_delay(&settings, 2), Duration::from_secs(10));
+        assert_eq!(retry_delay(&settings, 4), Duration::from_secs(40));
+        assert_eq!(retry_delay(&settings, 5), Duration::from_secs(60));
+        assert_eq!(retry_delay(&settings, 1000), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_retry() {
+        use erc20_payment_lib_common::create_sqlite_connection;
+        let conn = create_sqlite_connection(None, None, false, true)
+            .await
+            .unwrap();
+
+        // rejects the first request, accepts the next ones if signed correctly
+        let received = web::Data::new(Mutex::new(Vec::<(String, String)>::new()));
+        let received_ = received.clone();
+        let server = HttpServer::new(move || {
+            App::new().app_data(received_.clone()).route(
+                "/",
+                web::post().to(
+                    |req: HttpRequest,
+                     body: web::Bytes,
+                     received: web::Data<Mutex<Vec<(String, String)>>>| async move {
+                        let header = |name| {
+                            req.headers()
+                                .get(name)
+                                .and_then(|v| v.to_str().ok())
+                                .unwrap_or_default()
+                                .to_string()
+                        };
+                        let timestamp = header(WEBHOOK_TIMESTAMP_HEADER).parse::<i64>().unwrap();
+                        let signature = webhook_signature("secret", timestamp, &body);
+                        let mut received = received.lock().unwrap();
+                        received.push((header(WEBHOOK_EVENT_HEADER), header(WEBHOOK_ID_HEADER)));
+                        if received.len() == 1 || header(WEBHOOK_SIGNATURE_HEADER) != signature {
+                            HttpResponse::InternalServerError().finish()
+                        } else {
+                            HttpResponse::Ok().finish()
+                        }
+                    },
+                ),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        let mut webhooks = BTreeMap::new();
+        webhooks.insert(
+            "accounting".to_string(),
+            WebhookSettings {
+                events: vec!["StatusChanged".to_string()],
+                ..settings(&format!("http://127.0.0.1:{port}/"))
+            },
+        );
+        let outbox = WebhookOutbox::new(conn.clone(), webhooks).unwrap();
+        outbox
+            .enqueue(&DriverEvent::now(DriverEventContent::Alive))
+            .await
+            .unwrap();
+        outbox
+            .enqueue(&DriverEvent::now(DriverEventContent::StatusChanged(vec![])))
+            .await
+            .unwrap();
+        let delivery_loop = tokio::spawn(outbox.clone().delivery_loop());
+
+        for _ in 0..100 {
+            if get_next_webhook_attempt_date(&conn)
+                .await
+                .unwrap()
+                .is_none()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        delivery_loop.abort();
+        handle.stop(false).await;
+
+        assert_eq!(get_next_webhook_attempt_date(&conn).await.unwrap(), None);
+        let received = received.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            vec![
+                ("StatusChanged".to_string(), "1".to_string()),
+                ("StatusChanged".to_string(), "1".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dead_webhook_does_not_block_others() {
+        use erc20_payment_lib_common::create_sqlite_connection;
+        let conn = create_sqlite_connection(None, None, false, true)
+            .await
+            .unwrap();
+
+        // accepts connections but never responds
+        let dead = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let dead_port = dead.local_addr().unwrap().port();
+
+        let received = web::Data::new(Mutex::new(0));
+        let received_ = received.clone();
+        let server = HttpServer::new(move || {
+            App::new().app_data(received_.clone()).route(
+                "/",
+                web::post().to(|received: web::Data<Mutex<i32>>| async move {
+                    *received.lock().unwrap() += 1;
+                    HttpResponse::Ok().finish()
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        let mut webhooks = BTreeMap::new();
+        webhooks.insert(
+            "dead".to_string(),
+            WebhookSettings {
+                timeout: 30,
+                ..settings(&format!("http://127.0.0.1:{dead_port}/"))
+            },
+        );
+        webhooks.insert(
+            "live".to_string(),
+            settings(&format!("http://127.0.0.1:{port}/")),
+        );
+        let outbox = WebhookOutbox::new(conn.clone(), webhooks).unwrap();
+        let delivery_loop = tokio::spawn(outbox.clone().delivery_loop());
+        outbox
+            .enqueue(&DriverEvent::now(DriverEventContent::Alive))
+            .await
+            .unwrap();
+
+        for _ in 0..50 {
+            if *received.lock().unwrap() > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        delivery_loop.abort();
+        handle.stop(false).await;
+
+        assert_eq!(*received.lock().unwrap(), 1);
+        let due = get_webhook_deliveries_due(
+            &conn,
+            Utc::now() + chrono::Duration::try_hours(1).unwrap(),
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].webhook, "dead");
+        drop(dead);
+    }
+}
@@ -0,0 +1,7 @@
+-- Keep the oldest transfer for duplicated payment ids, so the unique index can be created
+UPDATE token_transfer SET payment_id = payment_id || '_duplicate_' || id
+WHERE payment_id IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM token_transfer WHERE payment_id IS NOT NULL GROUP BY chain_id, from_addr, payment_id
+);
+
+CREATE UNIQUE INDEX "idx_token_transfer_payment_id" ON "token_transfer" (chain_id, from_addr, payment_id);
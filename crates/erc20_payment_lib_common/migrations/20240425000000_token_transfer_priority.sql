@@ -0,0 +1,2 @@
+ALTER TABLE token_transfer ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE token_transfer ADD COLUMN deadline TEXT NULL;
@@ -0,0 +1 @@
+ALTER TABLE tx ADD COLUMN block_hash TEXT NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE "webhook_delivery"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    webhook             TEXT        NOT NULL,
+    event_type          TEXT        NOT NULL,
+    payload             TEXT        NOT NULL,
+    created_date        TEXT        NOT NULL,
+    attempts            INTEGER     NOT NULL,
+    next_attempt_date   TEXT        NULL,
+    delivered_date      TEXT        NULL,
+    last_error          TEXT        NULL
+) strict;
+
+CREATE INDEX "idx_webhook_delivery_next_attempt_date" ON "webhook_delivery" ("next_attempt_date");
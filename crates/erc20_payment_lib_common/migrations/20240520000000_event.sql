@@ -0,0 +1,9 @@
+CREATE TABLE "event"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    event_type          TEXT        NOT NULL,
+    create_date         TEXT        NOT NULL,
+    payload             TEXT        NOT NULL
+) strict;
+
+CREATE INDEX "idx_event_create_date" ON "event" ("create_date");
@@ -0,0 +1,3 @@
+ALTER TABLE tx ADD COLUMN final_date TEXT NULL;
+
+UPDATE tx SET final_date = confirm_date WHERE confirm_date IS NOT NULL AND block_hash IS NOT NULL AND signed_raw_data IS NULL;
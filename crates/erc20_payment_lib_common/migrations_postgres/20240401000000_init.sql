@@ -0,0 +1,145 @@
+CREATE TABLE "tx"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    method              TEXT            NOT NULL,
+    from_addr           TEXT            NOT NULL,
+    to_addr             TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    gas_limit           BIGINT          NULL,
+    max_fee_per_gas     TEXT            NULL,
+    priority_fee        TEXT            NULL,
+    val                 TEXT            NOT NULL,
+    nonce               BIGINT          NULL,
+    processing          BIGINT          NOT NULL,
+    call_data           TEXT            NULL,
+    created_date        TIMESTAMPTZ     NOT NULL,
+    first_processed     TIMESTAMPTZ     NULL,
+    tx_hash             TEXT            NULL,
+    signed_raw_data     TEXT            NULL,
+    signed_date         TIMESTAMPTZ     NULL,
+    broadcast_date      TIMESTAMPTZ     NULL,
+    broadcast_count     BIGINT          NOT NULL,
+    first_stuck_date    TIMESTAMPTZ     NULL,
+    confirm_date        TIMESTAMPTZ     NULL,
+    blockchain_date     TIMESTAMPTZ     NULL,
+    gas_used            BIGINT          NULL,
+    block_number        BIGINT          NULL,
+    chain_status        BIGINT          NULL,
+    block_gas_price     TEXT            NULL,
+    effective_gas_price TEXT            NULL,
+    fee_paid            TEXT            NULL,
+    error               TEXT            NULL,
+    orig_tx_id          BIGINT          NULL
+);
+
+CREATE INDEX "idx_tx_created_date" ON "tx" (created_date);
+CREATE INDEX "idx_tx_first_processed" ON "tx" (first_processed);
+CREATE INDEX "idx_tx_processing" ON "tx" (processing);
+
+CREATE TABLE "token_transfer"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    payment_id          TEXT            NULL,
+    from_addr           TEXT            NOT NULL,
+    receiver_addr       TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    token_addr          TEXT            NULL,
+    token_amount        TEXT            NOT NULL,
+    deposit_id          TEXT            NULL,
+    deposit_finish      BIGINT          NOT NULL DEFAULT 0,
+    create_date         TIMESTAMPTZ     NOT NULL,
+    paid_date           TIMESTAMPTZ     NULL,
+    tx_id               BIGINT          NULL,
+    fee_paid            TEXT            NULL,
+    error               TEXT            NULL,
+    CONSTRAINT "fk_token_transfer_tx" FOREIGN KEY ("tx_id") REFERENCES "tx" ("id")
+);
+
+CREATE INDEX "idx_deposit_id" ON "token_transfer" (deposit_id);
+
+CREATE TABLE "allowance"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    owner               TEXT            NOT NULL,
+    token_addr          TEXT            NULL,
+    spender             TEXT            NOT NULL,
+    allowance           TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    tx_id               BIGINT          NULL,
+    fee_paid            TEXT            NULL,
+    confirm_date        TIMESTAMPTZ     NULL,
+    error               TEXT            NULL,
+    CONSTRAINT "fk_allowance_tx" FOREIGN KEY ("tx_id") REFERENCES "tx" ("id")
+);
+
+CREATE TABLE "chain_tx"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    tx_hash             TEXT            NOT NULL,
+    method              TEXT            NOT NULL,
+    from_addr           TEXT            NOT NULL,
+    to_addr             TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    gas_used            BIGINT          NULL,
+    gas_limit           BIGINT          NULL,
+    block_gas_price     TEXT            NULL,
+    effective_gas_price TEXT            NULL,
+    max_fee_per_gas     TEXT            NULL,
+    priority_fee        TEXT            NULL,
+    val                 TEXT            NOT NULL,
+    nonce               BIGINT          NOT NULL,
+    checked_date        TIMESTAMPTZ     NOT NULL,
+    blockchain_date     TIMESTAMPTZ     NOT NULL,
+    block_number        BIGINT          NOT NULL,
+    chain_status        BIGINT          NOT NULL,
+    fee_paid            TEXT            NULL,
+    error               TEXT            NULL,
+    balance_eth         TEXT            NULL,
+    balance_glm         TEXT            NULL
+);
+
+CREATE UNIQUE INDEX "idx_chain_tx_tx_hash" ON "chain_tx" ("tx_hash");
+CREATE INDEX "idx_chain_tx_blockchain_date" ON "chain_tx" ("blockchain_date");
+
+CREATE TABLE "transfer_in"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    payment_id          TEXT            NOT NULL,
+    from_addr           TEXT            NOT NULL,
+    receiver_addr       TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    token_addr          TEXT            NULL,
+    token_amount        TEXT            NOT NULL,
+    tx_hash             TEXT            NULL,
+    requested_date      TIMESTAMPTZ     NOT NULL,
+    received_date       TIMESTAMPTZ     NULL
+);
+
+CREATE TABLE "chain_transfer"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    from_addr           TEXT            NOT NULL,
+    receiver_addr       TEXT            NOT NULL,
+    chain_id            BIGINT          NOT NULL,
+    token_addr          TEXT            NULL,
+    token_amount        TEXT            NOT NULL,
+    chain_tx_id         BIGINT          NOT NULL,
+    fee_paid            TEXT            NOT NULL,
+    blockchain_date     TIMESTAMPTZ     NOT NULL,
+    CONSTRAINT "fk_chain_transfer_tx" FOREIGN KEY ("chain_tx_id") REFERENCES "chain_tx" ("id")
+);
+
+CREATE INDEX "idx_chain_transfer_blockchain_date" ON "chain_transfer" ("blockchain_date");
+CREATE INDEX "idx_chain_transfer_receiver_address" ON "chain_transfer" ("receiver_addr");
+CREATE INDEX "idx_chain_transfer_from_address" ON "chain_transfer" ("from_addr");
+
+CREATE TABLE "scan_info"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    chain_id            BIGINT          NOT NULL,
+    filter              TEXT            NOT NULL,
+    start_block         BIGINT          NOT NULL,
+    last_block          BIGINT          NOT NULL
+);
+
+CREATE UNIQUE INDEX "idx_scan_info_chain_id" ON "scan_info" ("chain_id", "filter");
@@ -0,0 +1 @@
+CREATE UNIQUE INDEX "idx_token_transfer_payment_id" ON "token_transfer" (chain_id, from_addr, payment_id);
@@ -0,0 +1,14 @@
+ALTER TABLE token_transfer ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+
+UPDATE token_transfer SET status = CASE
+    WHEN tx_id IS NULL AND error = 'cancelled' THEN 'cancelled'
+    WHEN error IS NOT NULL THEN 'failed'
+    WHEN fee_paid IS NOT NULL AND (SELECT chain_status FROM tx WHERE tx.id = token_transfer.tx_id) = 0 THEN 'failed'
+    WHEN fee_paid IS NOT NULL THEN 'confirmed'
+    WHEN tx_id IS NULL THEN 'queued'
+    WHEN (SELECT broadcast_date FROM tx WHERE tx.id = token_transfer.tx_id) IS NOT NULL THEN 'broadcast'
+    WHEN (SELECT signed_date FROM tx WHERE tx.id = token_transfer.tx_id) IS NOT NULL THEN 'signed'
+    ELSE 'batched'
+END;
+
+CREATE INDEX "idx_token_transfer_status" ON "token_transfer" (status);
@@ -0,0 +1,2 @@
+ALTER TABLE token_transfer ADD COLUMN priority BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE token_transfer ADD COLUMN deadline TIMESTAMPTZ NULL;
@@ -0,0 +1 @@
+ALTER TABLE tx ADD COLUMN broadcast_endpoints TEXT NULL;
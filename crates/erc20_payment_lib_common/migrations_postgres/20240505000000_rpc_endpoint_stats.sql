@@ -0,0 +1,9 @@
+CREATE TABLE "rpc_endpoint_stats"
+(
+    chain_id            BIGINT          NOT NULL,
+    endpoint            TEXT            NOT NULL,
+    name                TEXT            NOT NULL,
+    info                TEXT            NOT NULL,
+    updated_date        TIMESTAMPTZ     NOT NULL,
+    CONSTRAINT "pk_rpc_endpoint_stats" PRIMARY KEY ("chain_id", "endpoint")
+);
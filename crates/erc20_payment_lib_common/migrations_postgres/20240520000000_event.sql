@@ -0,0 +1,9 @@
+CREATE TABLE "event"
+(
+    id                  BIGSERIAL       NOT NULL    PRIMARY KEY,
+    event_type          TEXT            NOT NULL,
+    create_date         TIMESTAMPTZ     NOT NULL,
+    payload             TEXT            NOT NULL
+);
+
+CREATE INDEX "idx_event_create_date" ON "event" ("create_date");
@@ -0,0 +1,332 @@
+//! Request and response bodies of the processor HTTP API (/api scope),
+//! shared by the server and erc20_payment_client.
+use crate::model::{AllowanceDbObj, EventDbObj, TokenTransferDbObj, TransferInDbObj, TxDbObj};
+use crate::SharedInfoTx;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+pub const API_CLIENT_HEADER: &str = "X-Api-Client";
+pub const API_TIMESTAMP_HEADER: &str = "X-Api-Timestamp";
+pub const API_SIGNATURE_HEADER: &str = "X-Api-Signature";
+/// Unique value of every signed request, server rejects nonces it has already seen
+pub const API_NONCE_HEADER: &str = "X-Api-Nonce";
+pub const API_MAX_NONCE_LENGTH: usize = 128;
+
+/// Signature expected in X-Api-Signature header (hex encoded)
+pub fn hmac_signature(
+    secret: &str,
+    timestamp: i64,
+    nonce: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}\n{nonce}\n{method}\n{path_and_query}\n").as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Body of every error response (4xx and 5xx)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    pub error: String,
+    /// Existing transfer when the request conflicts with it (409)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<Box<TokenTransferDbObj>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowancesResponse {
+    pub allowances: Vec<AllowanceDbObj>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxResponse {
+    pub tx: TxDbObj,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsResponse {
+    pub txs: Vec<TxDbObj>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsFeedResponse {
+    pub txs: Vec<TxDbObj>,
+    /// Engine messages of transactions being processed, by tx id
+    pub current: BTreeMap<i64, SharedInfoTx>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsCountResponse {
+    pub transfers_queued: u64,
+    pub transfers_processing: u64,
+    pub transfers_done: u64,
+    pub tx_queued: u64,
+    pub tx_done: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkipTxResponse {
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransfersResponse {
+    pub transfers: Vec<TokenTransferDbObj>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferResponse {
+    pub transfer: TokenTransferDbObj,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTransferRequest {
+    pub from: String,
+    pub to: String,
+    /// Token symbol or address, gas transfer if not set
+    pub token: Option<String>,
+    /// Amount in wei (decimal string)
+    pub amount: String,
+    pub chain: i64,
+    /// RFC 3339 date
+    pub due_date: Option<String>,
+    /// Generated if not set
+    pub payment_id: Option<String>,
+    /// Hex encoded id of deposit to pay from
+    pub deposit_id: Option<String>,
+    /// Higher is sent first, from MIN_TRANSFER_PRIORITY to MAX_TRANSFER_PRIORITY
+    pub priority: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTransferResponse {
+    pub payment_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferBatchRowResult {
+    /// Row number counted from 1 (CSV header is not counted)
+    pub row: usize,
+    pub payment_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Returned with 200 when all transfers are inserted, with 400 when nothing is inserted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferBatchResponse {
+    pub inserted: usize,
+    pub invalid: usize,
+    pub results: Vec<TransferBatchRowResult>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelTransferRequest {
+    pub from: String,
+    pub chain: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmendTransferRequest {
+    pub from: String,
+    pub chain: i64,
+    pub to: Option<String>,
+    pub amount: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsTransferRequest {
+    /// Receiver address or "all"
+    pub receiver: Option<String>,
+    /// Unix timestamp
+    pub from: Option<String>,
+    /// Unix timestamp
+    pub to: Option<String>,
+    /// Chain id
+    pub chain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsTransferResponse {
+    pub request_time: f64,
+    pub transfers: Vec<ChainTransferRespObj>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainTransferRespObj {
+    pub id: i64,
+    pub from_addr: String,
+    pub receiver_addr: String,
+    pub chain_id: i64,
+    pub token_addr: Option<String>,
+    pub token_amount: String,
+    pub tx_hash: String,
+    pub block_number: i64,
+    pub fee_paid: Option<String>,
+    pub block_date: DateTime<Utc>,
+    pub block_timestamp: i64,
+    pub to_addr: String,
+    pub caller_addr: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountsResponse {
+    pub public_addr: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountResponse {
+    pub account: String,
+    pub allowances: Vec<AllowanceDbObj>,
+    pub transfers_queued: u64,
+    pub transfers_processing: u64,
+    pub transfers_done: u64,
+    pub received_transfers: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPaymentsInResponse {
+    pub transfers_in: Vec<TransferInDbObj>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountBalanceRequest {
+    /// Token symbol or address, GLM if not set
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountBalanceResponse {
+    pub network_id: i64,
+    pub account: String,
+    pub gas_balance: String,
+    pub token_symbol: String,
+    pub token_address: String,
+    pub token_decimals: u8,
+    pub token_balance: String,
+    pub token_balance_decimal: String,
+    pub block_number: u64,
+    pub block_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsRequest {
+    /// Id of the last event seen by the client, 0 to read from the oldest stored event
+    #[serde(default)]
+    pub after: i64,
+    pub limit: Option<i64>,
+    /// How long to wait for new events (in seconds) if there are none after given id
+    pub timeout: Option<u64>,
+}
+
+/// Driver event as returned by /api/events and resumed websocket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredEvent {
+    pub id: i64,
+    pub event_type: String,
+    /// createDate and content of DriverEvent
+    #[serde(flatten)]
+    pub event: serde_json::Value,
+}
+
+impl TryFrom<EventDbObj> for StoredEvent {
+    type Error = String;
+
+    fn try_from(event: EventDbObj) -> Result<Self, Self::Error> {
+        //flattened payload has to be an object
+        match serde_json::from_str(&event.payload) {
+            Ok(payload @ serde_json::Value::Object(_)) => Ok(StoredEvent {
+                id: event.id,
+                event_type: event.event_type,
+                event: payload,
+            }),
+            Ok(_) => Err(format!("Event {} payload is not an object", event.id)),
+            Err(err) => Err(format!("Event {} has invalid payload: {}", event.id, err)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsResponse {
+    pub events: Vec<StoredEvent>,
+    /// Pass as after in the next request
+    pub last_event_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcPoolNetwork {
+    pub chain_id: i64,
+    pub chain_network: String,
+    /// Params and stats of every endpoint in the pool
+    pub endpoints: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcPoolResponse {
+    pub networks: Vec<RpcPoolNetwork>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigResponse {
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugResponse {
+    pub shared_state: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaucetStatusResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetTransferResponse {
+    pub transfer_gas_id: i64,
+    pub transfer_gas_payment_id: Option<String>,
+    pub transfer_glm_id: i64,
+    pub transfer_glm_payment_id: Option<String>,
+}
@@ -1,5 +1,6 @@
 use crate::error::PaymentError;
 use crate::error::*;
+use crate::DbPool;
 use crate::{err_custom_create, err_from};
 use sqlx::migrate::Migrator;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -11,6 +12,8 @@ use std::sync::atomic;
 use std::time::Duration;
 
 static MIGRATOR: Migrator = sqlx::migrate!();
+#[cfg(feature = "postgres")]
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations_postgres");
 
 static MEMORY_DATABASE_NUMBER: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
 
@@ -19,6 +22,18 @@ pub async fn create_sqlite_connection(
     memory_name: Option<&str>,
     read_only: bool,
     run_migrations: bool,
+) -> Result<DbPool, PaymentError> {
+    Ok(DbPool::Sqlite(
+        create_sqlite_pool(path, memory_name, read_only, run_migrations).await?,
+    ))
+}
+
+/// Same as create_sqlite_connection, for callers that need sqlite specific pool
+pub async fn create_sqlite_pool(
+    path: Option<&Path>,
+    memory_name: Option<&str>,
+    read_only: bool,
+    run_migrations: bool,
 ) -> Result<SqlitePool, PaymentError> {
     let url = if let Some(path) = path {
         format!(
@@ -59,3 +74,165 @@ pub async fn create_sqlite_connection(
 
     Ok(pool)
 }
+
+#[cfg(feature = "postgres")]
+pub async fn create_postgres_connection(
+    url: &str,
+    run_migrations: bool,
+) -> Result<DbPool, PaymentError> {
+    let max_connections = match env::var("ERC20_LIB_POSTGRES_MAX_CONNECTIONS") {
+        Ok(val) => u32::from_str(&val).map_err(err_from!())?,
+        Err(_) => 10,
+    };
+
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(max_connections)
+        .acquire_timeout(Duration::from_secs(30))
+        .connect(url)
+        .await
+        .map_err(err_from!())?;
+
+    if run_migrations {
+        POSTGRES_MIGRATOR.run(&pool).await.map_err(err_from!())?;
+    }
+
+    Ok(DbPool::Postgres(pool))
+}
+
+/// Connect to the database given by url. `postgres://` and `postgresql://` urls use Postgres
+/// (requires `postgres` feature), anything else is a path to sqlite file.
+pub async fn create_db_connection(
+    db_url: &str,
+    read_only: bool,
+    run_migrations: bool,
+) -> Result<DbPool, PaymentError> {
+    if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        {
+            if read_only {
+                log::warn!("Read only connection is not supported for Postgres, ignoring");
+            }
+            create_postgres_connection(db_url, run_migrations).await
+        }
+        #[cfg(not(feature = "postgres"))]
+        {
+            Err(err_custom_create!(
+                "Postgres database url given, but library is built without postgres feature"
+            ))
+        }
+    } else {
+        let path = db_url.strip_prefix("sqlite://").unwrap_or(db_url);
+        create_sqlite_connection(Some(Path::new(path)), None, read_only, run_migrations).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::TokenTransferStatus;
+    use std::borrow::Cow;
+
+    const TOKEN_TRANSFER_STATUS_MIGRATION: i64 = 20240420000000;
+
+    #[tokio::test]
+    async fn test_token_transfer_status_backfill() {
+        let pool = create_sqlite_pool(None, None, false, false).await.unwrap();
+        // database as it was before status column was added
+        let pre_status_migrator = Migrator {
+            migrations: Cow::Owned(
+                MIGRATOR
+                    .migrations
+                    .iter()
+                    .filter(|m| m.version < TOKEN_TRANSFER_STATUS_MIGRATION)
+                    .cloned()
+                    .collect(),
+            ),
+            ignore_missing: false,
+            locking: true,
+        };
+        pre_status_migrator.run(&pool).await.unwrap();
+
+        // tx 1 succeeded, tx 2 failed on chain, tx 3 broadcast, tx 4 signed, tx 5 only created
+        for (id, chain_status, signed_date, broadcast_date) in [
+            (
+                1,
+                Some(1),
+                Some("2024-04-01T00:00:00Z"),
+                Some("2024-04-01T00:00:00Z"),
+            ),
+            (
+                2,
+                Some(0),
+                Some("2024-04-01T00:00:00Z"),
+                Some("2024-04-01T00:00:00Z"),
+            ),
+            (
+                3,
+                None,
+                Some("2024-04-01T00:00:00Z"),
+                Some("2024-04-01T00:00:00Z"),
+            ),
+            (4, None, Some("2024-04-01T00:00:00Z"), None),
+            (5, None, None, None),
+        ] {
+            sqlx::query(
+                r"INSERT INTO tx (id, method, from_addr, to_addr, chain_id, val, processing, created_date, broadcast_count, chain_status, signed_date, broadcast_date)
+VALUES ($1, 'ERC20.transfer', '0x01', '0x02', 987789, '0', 0, '2024-04-01T00:00:00Z', 0, $2, $3, $4)",
+            )
+            .bind(id)
+            .bind(chain_status)
+            .bind(signed_date)
+            .bind(broadcast_date)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let transfers = [
+            (Some(1), Some("1000"), None, TokenTransferStatus::Confirmed),
+            (
+                None,
+                None,
+                Some("not enough gas"),
+                TokenTransferStatus::Failed,
+            ),
+            (
+                None,
+                None,
+                Some("cancelled"),
+                TokenTransferStatus::Cancelled,
+            ),
+            (None, None, None, TokenTransferStatus::Queued),
+            (Some(2), Some("1000"), None, TokenTransferStatus::Failed),
+            (Some(3), None, None, TokenTransferStatus::Broadcast),
+            (Some(4), None, None, TokenTransferStatus::Signed),
+            (Some(5), None, None, TokenTransferStatus::Batched),
+        ];
+        for (tx_id, fee_paid, error, _) in transfers {
+            sqlx::query(
+                r"INSERT INTO token_transfer (from_addr, receiver_addr, chain_id, token_amount, create_date, tx_id, fee_paid, error)
+VALUES ('0x01', '0x03', 987789, '1', '2024-04-01T00:00:00Z', $1, $2, $3)",
+            )
+            .bind(tx_id)
+            .bind(fee_paid)
+            .bind(error)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        MIGRATOR.run(&pool).await.unwrap();
+
+        let statuses: Vec<TokenTransferStatus> =
+            sqlx::query_scalar("SELECT status FROM token_transfer ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            statuses,
+            transfers
+                .iter()
+                .map(|(_, _, _, status)| *status)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,3 +1,127 @@
 pub mod connection;
 pub mod model;
 pub mod ops;
+
+#[cfg(feature = "postgres")]
+use sqlx::{PgConnection, PgPool, Postgres};
+use sqlx::{Sqlite, SqliteConnection, SqlitePool};
+
+/// Database connection pool. Backend is chosen at runtime from the connection url,
+/// Postgres is available when built with `postgres` feature.
+#[derive(Clone, Debug)]
+pub enum DbPool {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(PgPool),
+}
+
+impl DbPool {
+    pub async fn begin(&self) -> Result<DbTransaction<'static>, sqlx::Error> {
+        match self {
+            DbPool::Sqlite(pool) => Ok(DbTransaction::Sqlite(pool.begin().await?)),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => Ok(DbTransaction::Postgres(Box::new(pool.begin().await?))),
+        }
+    }
+
+    pub async fn close(&self) {
+        match self {
+            DbPool::Sqlite(pool) => pool.close().await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    pub fn is_postgres(&self) -> bool {
+        !matches!(self, DbPool::Sqlite(_))
+    }
+}
+
+impl From<SqlitePool> for DbPool {
+    fn from(pool: SqlitePool) -> Self {
+        DbPool::Sqlite(pool)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<PgPool> for DbPool {
+    fn from(pool: PgPool) -> Self {
+        DbPool::Postgres(pool)
+    }
+}
+
+/// Database transaction started with [`DbPool::begin`]
+pub enum DbTransaction<'c> {
+    Sqlite(sqlx::Transaction<'c, Sqlite>),
+    #[cfg(feature = "postgres")]
+    Postgres(Box<sqlx::Transaction<'c, Postgres>>),
+}
+
+impl DbTransaction<'_> {
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            DbTransaction::Sqlite(transaction) => transaction.commit().await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(transaction) => (*transaction).commit().await,
+        }
+    }
+
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        match self {
+            DbTransaction::Sqlite(transaction) => transaction.rollback().await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(transaction) => (*transaction).rollback().await,
+        }
+    }
+}
+
+/// Anything db ops can be run on: the pool itself or an open transaction
+pub enum DbExecutor<'a> {
+    SqlitePool(&'a SqlitePool),
+    Sqlite(&'a mut SqliteConnection),
+    #[cfg(feature = "postgres")]
+    PgPool(&'a PgPool),
+    #[cfg(feature = "postgres")]
+    Postgres(&'a mut PgConnection),
+}
+
+impl DbExecutor<'_> {
+    pub fn is_postgres(&self) -> bool {
+        !matches!(self, DbExecutor::SqlitePool(_) | DbExecutor::Sqlite(_))
+    }
+}
+
+impl<'a> From<&'a DbPool> for DbExecutor<'a> {
+    fn from(pool: &'a DbPool) -> Self {
+        match pool {
+            DbPool::Sqlite(pool) => DbExecutor::SqlitePool(pool),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => DbExecutor::PgPool(pool),
+        }
+    }
+}
+
+impl<'a> From<&'a mut DbTransaction<'_>> for DbExecutor<'a> {
+    fn from(transaction: &'a mut DbTransaction<'_>) -> Self {
+        match transaction {
+            DbTransaction::Sqlite(transaction) => DbExecutor::Sqlite(transaction),
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(transaction) => DbExecutor::Postgres(transaction),
+        }
+    }
+}
+
+/// Run the same sqlx query on whichever backend the executor points to
+macro_rules! db_exec {
+    ($executor:ident, $query:expr) => {
+        match Into::<$crate::db::DbExecutor>::into($executor) {
+            $crate::db::DbExecutor::SqlitePool($executor) => $query,
+            $crate::db::DbExecutor::Sqlite($executor) => $query,
+            #[cfg(feature = "postgres")]
+            $crate::db::DbExecutor::PgPool($executor) => $query,
+            #[cfg(feature = "postgres")]
+            $crate::db::DbExecutor::Postgres($executor) => $query,
+        }
+    };
+}
+pub(crate) use db_exec;
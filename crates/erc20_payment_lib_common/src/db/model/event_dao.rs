@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EventDbObj {
+    pub id: i64,
+    pub event_type: String,
+    pub create_date: DateTime<Utc>,
+    /// DriverEvent serialized to json
+    pub payload: String,
+}
@@ -1,15 +1,24 @@
 mod allowance_dao;
 mod chain_transfer_dao;
 mod chain_tx_dao;
+mod event_dao;
+mod rpc_endpoint_stats_dao;
 mod scan_dao;
 mod token_transfer_dao;
 mod transfer_in_dao;
 mod tx_dao;
+mod webhook_delivery_dao;
 
 pub use allowance_dao::AllowanceDbObj;
 pub use chain_transfer_dao::{ChainTransferDbObj, ChainTransferDbObjExt};
 pub use chain_tx_dao::ChainTxDbObj;
+pub use event_dao::EventDbObj;
+pub use rpc_endpoint_stats_dao::RpcEndpointStatsDbObj;
 pub use scan_dao::ScanDaoDbObj;
-pub use token_transfer_dao::TokenTransferDbObj;
+pub use token_transfer_dao::{
+    check_transfer_priority, TokenTransferDbObj, TokenTransferStatus, MAX_TRANSFER_PRIORITY,
+    MIN_TRANSFER_PRIORITY,
+};
 pub use transfer_in_dao::TransferInDbObj;
 pub use tx_dao::TxDbObj;
+pub use webhook_delivery_dao::WebhookDeliveryDbObj;
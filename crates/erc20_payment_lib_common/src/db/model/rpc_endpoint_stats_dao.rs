@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcEndpointStatsDbObj {
+    pub chain_id: i64,
+    pub endpoint: String,
+    pub name: String,
+    /// Web3RpcInfo of the endpoint serialized to json
+    pub info: String,
+    pub updated_date: DateTime<Utc>,
+}
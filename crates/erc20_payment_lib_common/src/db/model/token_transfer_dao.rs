@@ -1,5 +1,111 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Lifecycle of the transfer:
+/// queued -> batched -> signed -> broadcast -> confirmed/failed,
+/// queued -> cancelled/failed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenTransferStatus {
+    #[default]
+    Queued,
+    Batched,
+    Signed,
+    Broadcast,
+    Confirmed,
+    Failed,
+    Cancelled,
+}
+
+impl TokenTransferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenTransferStatus::Queued => "queued",
+            TokenTransferStatus::Batched => "batched",
+            TokenTransferStatus::Signed => "signed",
+            TokenTransferStatus::Broadcast => "broadcast",
+            TokenTransferStatus::Confirmed => "confirmed",
+            TokenTransferStatus::Failed => "failed",
+            TokenTransferStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl Display for TokenTransferStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TokenTransferStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(TokenTransferStatus::Queued),
+            "batched" => Ok(TokenTransferStatus::Batched),
+            "signed" => Ok(TokenTransferStatus::Signed),
+            "broadcast" => Ok(TokenTransferStatus::Broadcast),
+            "confirmed" => Ok(TokenTransferStatus::Confirmed),
+            "failed" => Ok(TokenTransferStatus::Failed),
+            "cancelled" => Ok(TokenTransferStatus::Cancelled),
+            _ => Err(format!("Unknown token transfer status: {s}")),
+        }
+    }
+}
+
+// Stored as plain text column, so it works the same way on every backend
+impl<DB: sqlx::Database> sqlx::Type<DB> for TokenTransferStatus
+where
+    str: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: sqlx::Database> sqlx::Encode<'q, DB> for TokenTransferStatus
+where
+    &'q str: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as sqlx::database::HasArguments<'q>>::ArgumentBuffer,
+    ) -> sqlx::encode::IsNull {
+        <&str as sqlx::Encode<'q, DB>>::encode(self.as_str(), buf)
+    }
+}
+
+impl<'r, DB: sqlx::Database> sqlx::Decode<'r, DB> for TokenTransferStatus
+where
+    &'r str: sqlx::Decode<'r, DB>,
+{
+    fn decode(
+        value: <DB as sqlx::database::HasValueRef<'r>>::ValueRef,
+    ) -> Result<Self, sqlx::error::BoxDynError> {
+        let value = <&str as sqlx::Decode<'r, DB>>::decode(value)?;
+        Ok(TokenTransferStatus::from_str(value)?)
+    }
+}
+
+/// Allowed range of transfer priority
+pub const MIN_TRANSFER_PRIORITY: i64 = -1_000_000;
+pub const MAX_TRANSFER_PRIORITY: i64 = 1_000_000;
+
+pub fn check_transfer_priority(priority: i64) -> Result<(), String> {
+    if !(MIN_TRANSFER_PRIORITY..=MAX_TRANSFER_PRIORITY).contains(&priority) {
+        return Err(format!(
+            "Priority {priority} out of range {MIN_TRANSFER_PRIORITY}..={MAX_TRANSFER_PRIORITY}"
+        ));
+    }
+    Ok(())
+}
 
 #[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -21,4 +127,86 @@ pub struct TokenTransferDbObj {
     pub paid_date: Option<DateTime<Utc>>,
     pub fee_paid: Option<String>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub status: TokenTransferStatus,
+    /// Higher values are sent first, negative values can be held to build bigger batches
+    #[serde(default)]
+    pub priority: i64,
+    /// Batch containing the transfer is sent before this time
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_sqlite_pool;
+
+    const ALL_STATUSES: [TokenTransferStatus; 7] = [
+        TokenTransferStatus::Queued,
+        TokenTransferStatus::Batched,
+        TokenTransferStatus::Signed,
+        TokenTransferStatus::Broadcast,
+        TokenTransferStatus::Confirmed,
+        TokenTransferStatus::Failed,
+        TokenTransferStatus::Cancelled,
+    ];
+
+    #[test]
+    fn test_status_serde_round_trip() {
+        for status in ALL_STATUSES {
+            let value = serde_json::to_value(status).unwrap();
+            assert_eq!(value, serde_json::json!(status.as_str()));
+            assert_eq!(
+                serde_json::from_value::<TokenTransferStatus>(value).unwrap(),
+                status
+            );
+            assert_eq!(TokenTransferStatus::from_str(status.as_str()), Ok(status));
+        }
+        assert!(serde_json::from_str::<TokenTransferStatus>("\"paid\"").is_err());
+        assert!(TokenTransferStatus::from_str("Queued").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_status_sqlx_round_trip() {
+        let pool = create_sqlite_pool(None, None, false, false).await.unwrap();
+        for status in ALL_STATUSES {
+            let (decoded, text): (TokenTransferStatus, String) = sqlx::query_as("SELECT $1, $1")
+                .bind(status)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(decoded, status);
+            assert_eq!(text, status.as_str());
+        }
+        assert!(
+            sqlx::query_scalar::<_, TokenTransferStatus>("SELECT 'paid'")
+                .fetch_one(&pool)
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    #[ignore = "needs Postgres instance given by ERC20_LIB_TEST_POSTGRES_URL"]
+    async fn test_status_sqlx_round_trip_postgres() {
+        use crate::{create_db_connection, DbPool};
+
+        let url = std::env::var("ERC20_LIB_TEST_POSTGRES_URL")
+            .expect("ERC20_LIB_TEST_POSTGRES_URL has to be set to run Postgres tests");
+        let DbPool::Postgres(pool) = create_db_connection(&url, false, false).await.unwrap() else {
+            panic!("Postgres connection expected");
+        };
+        for status in ALL_STATUSES {
+            let (decoded, text): (TokenTransferStatus, String) =
+                sqlx::query_as("SELECT $1::TEXT, $1::TEXT")
+                    .bind(status)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(decoded, status);
+            assert_eq!(text, status.as_str());
+        }
+    }
 }
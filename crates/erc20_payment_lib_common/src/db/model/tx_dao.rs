@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct TxDbObj {
     pub id: i64,
@@ -15,12 +15,12 @@ pub struct TxDbObj {
     pub val: String,
     pub nonce: Option<i64>,
     pub processing: i64,
-    #[serde(skip_serializing)]
+    #[serde(skip_serializing, default)]
     pub call_data: Option<String>,
     pub created_date: DateTime<Utc>,
     pub first_processed: Option<DateTime<Utc>>,
     pub tx_hash: Option<String>,
-    #[serde(skip_serializing)]
+    #[serde(skip_serializing, default)]
     pub signed_raw_data: Option<String>,
     pub signed_date: Option<DateTime<Utc>>,
     pub broadcast_date: Option<DateTime<Utc>>,
@@ -30,15 +30,23 @@ pub struct TxDbObj {
     pub blockchain_date: Option<DateTime<Utc>>,
     pub gas_used: Option<i64>,
     pub block_number: Option<i64>,
+    /// Hash of the block the transaction was confirmed in, used to detect reorgs
+    pub block_hash: Option<String>,
+    /// Set when the block containing the transaction is deep enough that reorgs are no longer checked
+    pub final_date: Option<DateTime<Utc>>,
     pub chain_status: Option<i64>,
     pub block_gas_price: Option<String>,
     pub effective_gas_price: Option<String>,
     pub fee_paid: Option<String>,
     pub error: Option<String>,
     pub orig_tx_id: Option<i64>,
+    /// JSON list of endpoints the transaction was broadcast to, with their answers
+    pub broadcast_endpoints: Option<String>,
     #[sqlx(default)]
+    #[serde(default)]
     pub engine_message: Option<String>,
     #[sqlx(default)]
+    #[serde(default)]
     pub engine_error: Option<String>,
 }
 
@@ -69,12 +77,15 @@ impl Default for TxDbObj {
             blockchain_date: None,
             gas_used: None,
             block_number: None,
+            block_hash: None,
+            final_date: None,
             chain_status: None,
             block_gas_price: None,
             effective_gas_price: None,
             fee_paid: None,
             error: None,
             orig_tx_id: None,
+            broadcast_endpoints: None,
             engine_message: None,
             engine_error: None,
         }
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryDbObj {
+    pub id: i64,
+    /// Name of the webhook from config
+    pub webhook: String,
+    pub event_type: String,
+    /// DriverEvent serialized to json, sent as request body
+    pub payload: String,
+    pub created_date: DateTime<Utc>,
+    pub attempts: i64,
+    /// None when delivered
+    pub next_attempt_date: Option<DateTime<Utc>>,
+    pub delivered_date: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
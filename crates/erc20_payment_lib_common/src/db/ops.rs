@@ -1,21 +1,27 @@
 mod allowance_ops;
 mod chain_transfer_ops;
 mod chain_tx_ops;
+mod event_ops;
+mod rpc_endpoint_stats_ops;
 mod scan_ops;
 mod token_transfer_ops;
 mod transfer_in_ops;
 mod tx_ops;
+mod webhook_delivery_ops;
 
 use super::model;
 pub use allowance_ops::*;
 pub use chain_transfer_ops::*;
 pub use chain_tx_ops::*;
+pub use event_ops::*;
+pub use rpc_endpoint_stats_ops::*;
 pub use scan_ops::*;
 use std::future::Future;
 use std::time::Duration;
 pub use token_transfer_ops::*;
 pub use transfer_in_ops::*;
 pub use tx_ops::*;
+pub use webhook_delivery_ops::*;
 
 const LOCKED_TIMEOUT: Duration = std::time::Duration::from_secs(300);
 
@@ -1,88 +1,98 @@
 use super::model::ChainTxDbObj;
+use crate::db::{db_exec, DbExecutor, DbPool};
 use chrono::{DateTime, Utc};
-use sqlx::Executor;
-use sqlx::Sqlite;
-use sqlx::SqlitePool;
 
 pub async fn insert_chain_tx<'c, E>(
     executor: E,
     tx: &ChainTxDbObj,
 ) -> Result<ChainTxDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let res = sqlx::query_as::<_, ChainTxDbObj>(
-        r"INSERT INTO chain_tx
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, ChainTxDbObj>(
+            r"INSERT INTO chain_tx
 (tx_hash, method, from_addr, to_addr, chain_id, gas_used, gas_limit, block_gas_price, effective_gas_price, max_fee_per_gas, priority_fee, val, nonce, checked_date, blockchain_date, block_number, chain_status, fee_paid, error, balance_eth, balance_glm)
 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21) RETURNING *",
-    )
-    .bind(&tx.tx_hash)
-    .bind(&tx.method)
-    .bind(&tx.from_addr)
-    .bind(&tx.to_addr)
-    .bind(tx.chain_id)
-    .bind(tx.gas_used)
-    .bind(tx.gas_limit)
-    .bind(&tx.block_gas_price)
-    .bind(&tx.effective_gas_price)
-    .bind(&tx.max_fee_per_gas)
-    .bind(&tx.priority_fee)
-    .bind(&tx.val)
-    .bind(tx.nonce)
-    .bind(tx.checked_date)
-    .bind(tx.blockchain_date)
-    .bind(tx.block_number)
-    .bind(tx.chain_status)
-    .bind(&tx.fee_paid)
-    .bind(&tx.error)
-    .bind(&tx.balance_eth)
-    .bind(&tx.balance_glm)
-    .fetch_one(executor)
-    .await?;
+        )
+        .bind(&tx.tx_hash)
+        .bind(&tx.method)
+        .bind(&tx.from_addr)
+        .bind(&tx.to_addr)
+        .bind(tx.chain_id)
+        .bind(tx.gas_used)
+        .bind(tx.gas_limit)
+        .bind(&tx.block_gas_price)
+        .bind(&tx.effective_gas_price)
+        .bind(&tx.max_fee_per_gas)
+        .bind(&tx.priority_fee)
+        .bind(&tx.val)
+        .bind(tx.nonce)
+        .bind(tx.checked_date)
+        .bind(tx.blockchain_date)
+        .bind(tx.block_number)
+        .bind(tx.chain_status)
+        .bind(&tx.fee_paid)
+        .bind(&tx.error)
+        .bind(&tx.balance_eth)
+        .bind(&tx.balance_glm)
+        .fetch_one(executor)
+        .await
+    )?;
     Ok(res)
 }
 
 pub async fn get_chain_txs_by_chain_id(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     limit: Option<i64>,
 ) -> Result<Vec<ChainTxDbObj>, sqlx::Error> {
     let limit = limit.unwrap_or(i64::MAX);
-    let rows = sqlx::query_as::<_, ChainTxDbObj>(
-        r"SELECT * FROM chain_tx WHERE chain_id = $1 ORDER by id DESC LIMIT $2",
-    )
-    .bind(chain_id)
-    .bind(limit)
-    .fetch_all(conn)
-    .await?;
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, ChainTxDbObj>(
+            r"SELECT * FROM chain_tx WHERE chain_id = $1 ORDER by id DESC LIMIT $2",
+        )
+        .bind(chain_id)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
 pub async fn get_chain_txs_by_chain_id_and_dates(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     from: DateTime<Utc>,
     to: DateTime<Utc>,
     limit: Option<i64>,
 ) -> Result<Vec<ChainTxDbObj>, sqlx::Error> {
     let limit = limit.unwrap_or(i64::MAX);
-    let rows = sqlx::query_as::<_, ChainTxDbObj>(
-        r"SELECT * FROM chain_tx WHERE chain_id = $1 AND blockchain_date >= $2 AND blockchain_date <= $3 ORDER by id DESC LIMIT $4",
-    )
-    .bind(chain_id)
-    .bind(from)
-    .bind(to)
-    .bind(limit)
-    .fetch_all(conn)
-    .await?;
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, ChainTxDbObj>(
+            r"SELECT * FROM chain_tx WHERE chain_id = $1 AND blockchain_date >= $2 AND blockchain_date <= $3 ORDER by id DESC LIMIT $4",
+        )
+        .bind(chain_id)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
-pub async fn get_chain_tx(conn: &SqlitePool, id: i64) -> Result<ChainTxDbObj, sqlx::Error> {
-    let row = sqlx::query_as::<_, ChainTxDbObj>(r"SELECT * FROM chain_tx WHERE id = $1")
-        .bind(id)
-        .fetch_one(conn)
-        .await?;
+pub async fn get_chain_tx(conn: &DbPool, id: i64) -> Result<ChainTxDbObj, sqlx::Error> {
+    let row = db_exec!(
+        conn,
+        sqlx::query_as::<_, ChainTxDbObj>(r"SELECT * FROM chain_tx WHERE id = $1")
+            .bind(id)
+            .fetch_one(conn)
+            .await
+    )?;
     Ok(row)
 }
 
@@ -91,12 +101,15 @@ pub async fn get_chain_tx_hash<'c, E>(
     tx_hash: String,
 ) -> Result<Option<ChainTxDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let row = sqlx::query_as::<_, ChainTxDbObj>(r"SELECT * FROM chain_tx WHERE tx_hash = $1")
-        .bind(tx_hash)
-        .fetch_optional(executor)
-        .await?;
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, ChainTxDbObj>(r"SELECT * FROM chain_tx WHERE tx_hash = $1")
+            .bind(tx_hash)
+            .fetch_optional(executor)
+            .await
+    )?;
     Ok(row)
 }
 
@@ -105,12 +118,15 @@ pub async fn get_last_scanned_block<'c, E>(
     chain_id: i64,
 ) -> Result<Option<i64>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    sqlx::query_scalar::<_, i64>(r"SELECT MAX(block_number) FROM chain_tx WHERE chain_id = $1")
-        .bind(chain_id)
-        .fetch_optional(executor)
-        .await
+    db_exec!(
+        executor,
+        sqlx::query_scalar::<_, i64>(r"SELECT MAX(block_number) FROM chain_tx WHERE chain_id = $1")
+            .bind(chain_id)
+            .fetch_optional(executor)
+            .await
+    )
 }
 
 #[tokio::test]
@@ -0,0 +1,125 @@
+use super::model::EventDbObj;
+use crate::db::{db_exec, DbExecutor};
+use chrono::{DateTime, Utc};
+
+pub async fn insert_event<'c, E>(executor: E, event: &EventDbObj) -> Result<EventDbObj, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, EventDbObj>(
+            r"INSERT INTO event
+(event_type, create_date, payload)
+VALUES ($1, $2, $3) RETURNING *;
+",
+        )
+        .bind(&event.event_type)
+        .bind(event.create_date)
+        .bind(&event.payload)
+        .fetch_one(executor)
+        .await
+    )?;
+    Ok(res)
+}
+
+/// Events with id greater than after_id in order they were stored
+pub async fn get_events_after<'c, E>(
+    executor: E,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<EventDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let rows = db_exec!(
+        executor,
+        sqlx::query_as::<_, EventDbObj>(
+            r"SELECT * FROM event WHERE id > $1 ORDER BY id ASC LIMIT $2",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(executor)
+        .await
+    )?;
+    Ok(rows)
+}
+
+pub async fn get_last_event_id<'c, E>(executor: E) -> Result<i64, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, EventDbObj>(r"SELECT * FROM event ORDER BY id DESC LIMIT 1")
+            .fetch_optional(executor)
+            .await
+    )?;
+    Ok(row.map(|row| row.id).unwrap_or_default())
+}
+
+pub async fn delete_events_older_than<'c, E>(
+    executor: E,
+    date: DateTime<Utc>,
+) -> Result<u64, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query(r"DELETE FROM event WHERE create_date < $1")
+            .bind(date)
+            .execute(executor)
+            .await
+            .map(|res| res.rows_affected())
+    )?;
+    Ok(res)
+}
+
+#[tokio::test]
+async fn event_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    assert_eq!(get_last_event_id(&conn).await?, 0);
+    let mut events = Vec::new();
+    for (idx, event_type) in ["TransferFinished", "StatusChanged", "CantSign"]
+        .iter()
+        .enumerate()
+    {
+        events.push(
+            insert_event(
+                &conn,
+                &EventDbObj {
+                    id: 0,
+                    event_type: event_type.to_string(),
+                    create_date: DateTime::from_timestamp(1716000000 + idx as i64, 0).unwrap(),
+                    payload: "{}".to_string(),
+                },
+            )
+            .await?,
+        );
+    }
+    assert!(events[0].id < events[1].id && events[1].id < events[2].id);
+    assert_eq!(get_last_event_id(&conn).await?, events[2].id);
+    assert_eq!(get_events_after(&conn, 0, 100).await?, events);
+    assert_eq!(
+        get_events_after(&conn, events[0].id, 1).await?,
+        vec![events[1].clone()]
+    );
+    assert!(get_events_after(&conn, events[2].id, 100).await?.is_empty());
+
+    assert_eq!(
+        delete_events_older_than(&conn, events[2].create_date).await?,
+        2
+    );
+    assert_eq!(
+        get_events_after(&conn, 0, 100).await?,
+        vec![events[2].clone()]
+    );
+    //ids are not reused after delete
+    assert_eq!(get_last_event_id(&conn).await?, events[2].id);
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use super::model::RpcEndpointStatsDbObj;
+use crate::db::{db_exec, DbExecutor};
+
+pub async fn get_rpc_endpoint_stats<'c, E>(
+    executor: E,
+    chain_id: i64,
+) -> Result<Vec<RpcEndpointStatsDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let rows = db_exec!(
+        executor,
+        sqlx::query_as::<_, RpcEndpointStatsDbObj>(
+            r"SELECT * FROM rpc_endpoint_stats WHERE chain_id = $1",
+        )
+        .bind(chain_id)
+        .fetch_all(executor)
+        .await
+    )?;
+    Ok(rows)
+}
+
+pub async fn upsert_rpc_endpoint_stats<'c, E>(
+    executor: E,
+    stats: &RpcEndpointStatsDbObj,
+) -> Result<(), sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query(
+            r"INSERT INTO rpc_endpoint_stats
+(chain_id, endpoint, name, info, updated_date)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (chain_id, endpoint) DO UPDATE SET name = EXCLUDED.name, info = EXCLUDED.info, updated_date = EXCLUDED.updated_date
+",
+        )
+        .bind(stats.chain_id)
+        .bind(&stats.endpoint)
+        .bind(&stats.name)
+        .bind(&stats.info)
+        .bind(stats.updated_date)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn rpc_endpoint_stats_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let mut stats = RpcEndpointStatsDbObj {
+        chain_id: 987789,
+        endpoint: "http://127.0.0.1:8545".to_string(),
+        name: "local".to_string(),
+        info: "{}".to_string(),
+        updated_date: chrono::DateTime::from_timestamp(1714000000, 0).unwrap(),
+    };
+    upsert_rpc_endpoint_stats(&conn, &stats).await?;
+    stats.info = r#"{"penaltyFromErrors":10}"#.to_string();
+    stats.updated_date = chrono::DateTime::from_timestamp(1714000060, 0).unwrap();
+    upsert_rpc_endpoint_stats(&conn, &stats).await?;
+
+    assert_eq!(get_rpc_endpoint_stats(&conn, 987789).await?, vec![stats]);
+    assert!(get_rpc_endpoint_stats(&conn, 1).await?.is_empty());
+    Ok(())
+}
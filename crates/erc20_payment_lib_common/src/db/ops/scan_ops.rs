@@ -1,5 +1,5 @@
 use super::model::ScanDaoDbObj;
-use sqlx::{Executor, Sqlite};
+use crate::db::{db_exec, DbExecutor};
 
 pub async fn get_scan_info<'c, E>(
     executor: E,
@@ -7,15 +7,18 @@ pub async fn get_scan_info<'c, E>(
     filter: &str,
 ) -> Result<Option<ScanDaoDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let row = sqlx::query_as::<_, ScanDaoDbObj>(
-        r"SELECT * FROM scan_info WHERE chain_id = $1 AND filter = $2",
-    )
-    .bind(chain_id)
-    .bind(filter)
-    .fetch_optional(executor)
-    .await?;
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, ScanDaoDbObj>(
+            r"SELECT * FROM scan_info WHERE chain_id = $1 AND filter = $2",
+        )
+        .bind(chain_id)
+        .bind(filter)
+        .fetch_optional(executor)
+        .await
+    )?;
     Ok(row)
 }
 
@@ -25,13 +28,17 @@ pub async fn delete_scan_info<'c, E>(
     filter: &str,
 ) -> Result<(), sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    sqlx::query(r"DELETE FROM scan_info WHERE chain_id = $1 AND filter = $2")
-        .bind(chain_id)
-        .bind(filter)
-        .execute(executor)
-        .await?;
+    db_exec!(
+        executor,
+        sqlx::query(r"DELETE FROM scan_info WHERE chain_id = $1 AND filter = $2")
+            .bind(chain_id)
+            .bind(filter)
+            .execute(executor)
+            .await
+            .map(|res| res.rows_affected())
+    )?;
     Ok(())
 }
 
@@ -40,20 +47,32 @@ pub async fn upsert_scan_info<'c, E>(
     scan_dao: &ScanDaoDbObj,
 ) -> Result<ScanDaoDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let res = sqlx::query_as::<_, ScanDaoDbObj>(
+    let executor: DbExecutor = executor.into();
+    let query = if executor.is_postgres() {
+        r"INSERT INTO scan_info
+(chain_id, filter, start_block, last_block)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (chain_id, filter) DO UPDATE SET start_block = EXCLUDED.start_block, last_block = EXCLUDED.last_block
+RETURNING *;
+"
+    } else {
         r"INSERT OR REPLACE INTO scan_info
 (chain_id, filter, start_block, last_block)
 VALUES ($1, $2, $3, $4) RETURNING *;
-",
-    )
-    .bind(scan_dao.chain_id)
-    .bind(&scan_dao.filter)
-    .bind(scan_dao.start_block)
-    .bind(scan_dao.last_block)
-    .fetch_one(executor)
-    .await?;
+"
+    };
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, ScanDaoDbObj>(query)
+            .bind(scan_dao.chain_id)
+            .bind(&scan_dao.filter)
+            .bind(scan_dao.start_block)
+            .bind(scan_dao.last_block)
+            .fetch_one(executor)
+            .await
+    )?;
     Ok(res)
 }
 #[tokio::test]
@@ -1,12 +1,10 @@
-use super::model::TokenTransferDbObj;
+use super::model::{TokenTransferDbObj, TokenTransferStatus};
 use crate::db::ops::get_chain_transfers_by_chain_id;
+use crate::db::{db_exec, DbExecutor, DbPool, DbTransaction};
 use crate::error::PaymentError;
 use crate::error::*;
-use crate::{err_custom_create, err_from};
+use crate::{err_create, err_custom_create, err_from};
 use chrono::{DateTime, Duration, Utc};
-use sqlx::Executor;
-use sqlx::Sqlite;
-use sqlx::SqlitePool;
 use std::collections::{BTreeMap, HashSet};
 use std::ops::AddAssign;
 use std::str::FromStr;
@@ -18,15 +16,18 @@ pub async fn check_if_deposit_closed<'c, E>(
     deposit_id: &str,
 ) -> Result<bool, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let finished_count = sqlx::query_as::<_, (i64,)>(
-        "SELECT COUNT(*) FROM token_transfer WHERE chain_id=$1 AND deposit_id=$2 AND deposit_finish=1",
-    )
-    .bind(chain_id)
-    .bind(deposit_id)
-    .fetch_one(executor)
-    .await?;
+    let finished_count = db_exec!(
+        executor,
+        sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM token_transfer WHERE chain_id=$1 AND deposit_id=$2 AND deposit_finish=1",
+        )
+        .bind(chain_id)
+        .bind(deposit_id)
+        .fetch_one(executor)
+        .await
+    )?;
     Ok(finished_count.0 > 0)
 }
 
@@ -35,36 +36,79 @@ pub async fn insert_token_transfer<'c, E>(
     token_transfer: &TokenTransferDbObj,
 ) -> Result<TokenTransferDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    sqlx::query_as::<_, TokenTransferDbObj>(
-        r"INSERT INTO token_transfer
-(payment_id, from_addr, receiver_addr, chain_id, token_addr, token_amount, deposit_id, deposit_finish, create_date, tx_id, paid_date, fee_paid, error)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, strftime('%Y-%m-%dT%H:%M:%f', 'now'), $9, $10, $11, $12) RETURNING *;
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"INSERT INTO token_transfer
+(payment_id, from_addr, receiver_addr, chain_id, token_addr, token_amount, deposit_id, deposit_finish, create_date, tx_id, paid_date, fee_paid, error, status, priority, deadline)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $13, $9, $10, $11, $12, $14, $15, $16) RETURNING *;
 ",
+        )
+        .bind(&token_transfer.payment_id)
+        .bind(&token_transfer.from_addr)
+        .bind(&token_transfer.receiver_addr)
+        .bind(token_transfer.chain_id)
+        .bind(&token_transfer.token_addr)
+        .bind(&token_transfer.token_amount)
+        .bind(&token_transfer.deposit_id)
+        .bind(token_transfer.deposit_finish)
+        .bind(token_transfer.tx_id)
+        .bind(token_transfer.paid_date)
+        .bind(&token_transfer.fee_paid)
+        .bind(&token_transfer.error)
+        .bind(Utc::now())
+        .bind(token_transfer.status)
+        .bind(token_transfer.priority)
+        .bind(token_transfer.deadline)
+        .fetch_one(executor)
+        .await
     )
-    .bind(&token_transfer.payment_id)
-    .bind(&token_transfer.from_addr)
-    .bind(&token_transfer.receiver_addr)
-    .bind(token_transfer.chain_id)
-    .bind(&token_transfer.token_addr)
-    .bind(&token_transfer.token_amount)
-    .bind(&token_transfer.deposit_id)
-    .bind(token_transfer.deposit_finish)
-    .bind(token_transfer.tx_id)
-    .bind(token_transfer.paid_date)
-    .bind(&token_transfer.fee_paid)
-    .bind(&token_transfer.error)
-    .fetch_one(executor)
-    .await
 }
 
-pub async fn insert_token_transfer_with_deposit_check(
-    conn: &SqlitePool,
+pub async fn get_token_transfer_by_payment_id<'c, E>(
+    executor: E,
+    chain_id: i64,
+    from_addr: &str,
+    payment_id: &str,
+) -> Result<Option<TokenTransferDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer WHERE chain_id = $1 AND from_addr = $2 AND payment_id = $3",
+        )
+        .bind(chain_id)
+        .bind(from_addr)
+        .bind(payment_id)
+        .fetch_optional(executor)
+        .await
+    )
+}
+
+async fn check_token_transfer_insertable(
+    transaction: &mut DbTransaction<'_>,
     token_transfer: &TokenTransferDbObj,
-) -> Result<TokenTransferDbObj, PaymentError> {
+) -> Result<(), PaymentError> {
+    if let Some(payment_id) = token_transfer.payment_id.as_ref() {
+        if let Some(existing) = get_token_transfer_by_payment_id(
+            &mut *transaction,
+            token_transfer.chain_id,
+            &token_transfer.from_addr,
+            payment_id,
+        )
+        .await
+        .map_err(err_from!())?
+        {
+            return Err(err_create!(ErrorBag::TransferAlreadyExists(Box::new(
+                existing
+            ))));
+        }
+    }
     if let Some(deposit_id) = token_transfer.deposit_id.as_ref() {
-        let mut transaction = conn.begin().await.map_err(err_from!())?;
         let is_finished =
             check_if_deposit_closed(&mut *transaction, token_transfer.chain_id, deposit_id)
                 .await
@@ -74,33 +118,67 @@ pub async fn insert_token_transfer_with_deposit_check(
                 "Cannot add token_transfer to already finished deposit"
             ));
         }
-        let res = sqlx::query_as::<_, TokenTransferDbObj>(
-            r"INSERT INTO token_transfer
-(payment_id, from_addr, receiver_addr, chain_id, token_addr, token_amount, deposit_id, deposit_finish, create_date, tx_id, paid_date, fee_paid, error)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, strftime('%Y-%m-%dT%H:%M:%f', 'now'), $9, $10, $11, $12) RETURNING *;
-",
-        )
-            .bind(&token_transfer.payment_id)
-            .bind(&token_transfer.from_addr)
-            .bind(&token_transfer.receiver_addr)
-            .bind(token_transfer.chain_id)
-            .bind(&token_transfer.token_addr)
-            .bind(&token_transfer.token_amount)
-            .bind(&token_transfer.deposit_id)
-            .bind(token_transfer.deposit_finish)
-            .bind(token_transfer.tx_id)
-            .bind(token_transfer.paid_date)
-            .bind(&token_transfer.fee_paid)
-            .bind(&token_transfer.error)
-            .fetch_one(&mut *transaction)
-            .await.map_err(err_from!())?;
-        transaction.commit().await.map_err(err_from!())?;
-        Ok(res)
-    } else {
-        insert_token_transfer(conn, token_transfer)
-            .await
-            .map_err(err_from!())
     }
+    Ok(())
+}
+
+/// Insert transfer checking that payment_id is not used yet (per chain and sender)
+/// and that deposit is not closed. Existing transfer is returned as TransferAlreadyExists error.
+pub async fn insert_token_transfer_with_deposit_check(
+    conn: &DbPool,
+    token_transfer: &TokenTransferDbObj,
+) -> Result<TokenTransferDbObj, PaymentError> {
+    let mut transaction = conn.begin().await.map_err(err_from!())?;
+    check_token_transfer_insertable(&mut transaction, token_transfer).await?;
+    let res = match insert_token_transfer(&mut transaction, token_transfer).await {
+        Ok(res) => res,
+        Err(err) => {
+            let is_unique_violation = err
+                .as_database_error()
+                .map(|db_err| db_err.is_unique_violation())
+                .unwrap_or(false);
+            drop(transaction);
+            // transfer with the same payment_id was inserted concurrently
+            if let (true, Some(payment_id)) = (is_unique_violation, &token_transfer.payment_id) {
+                if let Some(existing) = get_token_transfer_by_payment_id(
+                    conn,
+                    token_transfer.chain_id,
+                    &token_transfer.from_addr,
+                    payment_id,
+                )
+                .await
+                .map_err(err_from!())?
+                {
+                    return Err(err_create!(ErrorBag::TransferAlreadyExists(Box::new(
+                        existing
+                    ))));
+                }
+            }
+            return Err(err).map_err(err_from!());
+        }
+    };
+    transaction.commit().await.map_err(err_from!())?;
+    Ok(res)
+}
+
+/// Same checks as insert_token_transfer_with_deposit_check, but all transfers are inserted
+/// in one database transaction. Nothing is inserted if any of them fails.
+pub async fn insert_token_transfers_with_deposit_check(
+    conn: &DbPool,
+    token_transfers: &[TokenTransferDbObj],
+) -> Result<Vec<TokenTransferDbObj>, PaymentError> {
+    let mut transaction = conn.begin().await.map_err(err_from!())?;
+    let mut inserted = Vec::with_capacity(token_transfers.len());
+    for token_transfer in token_transfers {
+        check_token_transfer_insertable(&mut transaction, token_transfer).await?;
+        inserted.push(
+            insert_token_transfer(&mut transaction, token_transfer)
+                .await
+                .map_err(err_from!())?,
+        );
+    }
+    transaction.commit().await.map_err(err_from!())?;
+    Ok(inserted)
 }
 
 pub async fn remap_token_transfer_tx<'c, E>(
@@ -109,37 +187,47 @@ pub async fn remap_token_transfer_tx<'c, E>(
     new_tx_id: i64,
 ) -> Result<(), sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE token_transfer SET
+    let _res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE token_transfer SET
             tx_id = $2
             WHERE tx_id = $1
         ",
-    )
-    .bind(old_tx_id)
-    .bind(new_tx_id)
-    .execute(executor)
-    .await?;
+        )
+        .bind(old_tx_id)
+        .bind(new_tx_id)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
     Ok(())
 }
 
 pub async fn cleanup_token_transfer_tx<'c, E>(executor: E, tx_id: i64) -> Result<(), sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE token_transfer SET
+    let _res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE token_transfer SET
             tx_id = NULL,
             fee_paid = NULL,
             error = NULL,
-            paid_date = NULL
+            paid_date = NULL,
+            status = $2
             WHERE tx_id = $1
         ",
-    )
-    .bind(tx_id)
-    .execute(executor)
-    .await?;
+        )
+        .bind(tx_id)
+        .bind(TokenTransferStatus::Queued)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
     Ok(())
 }
 
@@ -148,10 +236,12 @@ pub async fn update_token_transfer<'c, E>(
     token_transfer: &TokenTransferDbObj,
 ) -> Result<TokenTransferDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE token_transfer SET
+    let _res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE token_transfer SET
 payment_id = $2,
 from_addr = $3,
 receiver_addr = $4,
@@ -163,55 +253,193 @@ deposit_finish = $9,
 tx_id = $10,
 paid_date = $11,
 fee_paid = $12,
-error = $13
+error = $13,
+status = $14,
+priority = $15,
+deadline = $16
 WHERE id = $1
 ",
-    )
-    .bind(token_transfer.id)
-    .bind(&token_transfer.payment_id)
-    .bind(&token_transfer.from_addr)
-    .bind(&token_transfer.receiver_addr)
-    .bind(token_transfer.chain_id)
-    .bind(&token_transfer.token_addr)
-    .bind(&token_transfer.token_amount)
-    .bind(&token_transfer.deposit_id)
-    .bind(token_transfer.deposit_finish)
-    .bind(token_transfer.tx_id)
-    .bind(token_transfer.paid_date)
-    .bind(&token_transfer.fee_paid)
-    .bind(&token_transfer.error)
-    .execute(executor)
-    .await?;
+        )
+        .bind(token_transfer.id)
+        .bind(&token_transfer.payment_id)
+        .bind(&token_transfer.from_addr)
+        .bind(&token_transfer.receiver_addr)
+        .bind(token_transfer.chain_id)
+        .bind(&token_transfer.token_addr)
+        .bind(&token_transfer.token_amount)
+        .bind(&token_transfer.deposit_id)
+        .bind(token_transfer.deposit_finish)
+        .bind(token_transfer.tx_id)
+        .bind(token_transfer.paid_date)
+        .bind(&token_transfer.fee_paid)
+        .bind(&token_transfer.error)
+        .bind(token_transfer.status)
+        .bind(token_transfer.priority)
+        .bind(token_transfer.deadline)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
     Ok(token_transfer.clone())
 }
 
+/// Marks queued transfer as cancelled. Returns None if transfer is not queued anymore
+pub async fn cancel_token_transfer<'c, E>(
+    executor: E,
+    id: i64,
+) -> Result<Option<TokenTransferDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"UPDATE token_transfer SET status = $2
+WHERE id = $1 AND status = $3
+RETURNING *
+",
+        )
+        .bind(id)
+        .bind(TokenTransferStatus::Cancelled)
+        .bind(TokenTransferStatus::Queued)
+        .fetch_optional(executor)
+        .await
+    )
+}
+
+/// Changes receiver and amount of queued transfer. Returns None if transfer is not queued anymore
+pub async fn amend_token_transfer<'c, E>(
+    executor: E,
+    id: i64,
+    receiver_addr: &str,
+    token_amount: &str,
+) -> Result<Option<TokenTransferDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"UPDATE token_transfer SET receiver_addr = $2, token_amount = $3
+WHERE id = $1 AND status = $4
+RETURNING *
+",
+        )
+        .bind(id)
+        .bind(receiver_addr)
+        .bind(token_amount)
+        .bind(TokenTransferStatus::Queued)
+        .fetch_optional(executor)
+        .await
+    )
+}
+
+/// Sets tx_id of the transfer, only if it wasn't cancelled or amended since it was read.
+/// Returns false if transfer was changed in the meantime.
+pub async fn assign_token_transfer_to_tx<'c, E>(
+    executor: E,
+    token_transfer: &TokenTransferDbObj,
+    tx_id: i64,
+) -> Result<bool, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE token_transfer SET tx_id = $2, status = $5
+WHERE id = $1 AND status = $6 AND receiver_addr = $3 AND token_amount = $4
+",
+        )
+        .bind(token_transfer.id)
+        .bind(tx_id)
+        .bind(&token_transfer.receiver_addr)
+        .bind(&token_transfer.token_amount)
+        .bind(TokenTransferStatus::Batched)
+        .bind(TokenTransferStatus::Queued)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(res == 1)
+}
+
+/// Moves transfers of the transaction to the new status, unless they are already finished
+pub async fn update_token_transfers_status_by_tx<'c, E>(
+    executor: E,
+    tx_id: i64,
+    status: TokenTransferStatus,
+) -> Result<(), sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE token_transfer SET status = $2
+WHERE tx_id = $1 AND fee_paid IS NULL AND error IS NULL
+",
+        )
+        .bind(tx_id)
+        .bind(status)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(())
+}
+
+pub async fn get_token_transfers_by_payment_id<'c, E>(
+    executor: E,
+    payment_id: &str,
+) -> Result<Vec<TokenTransferDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer WHERE payment_id = $1 ORDER by id ASC",
+        )
+        .bind(payment_id)
+        .fetch_all(executor)
+        .await
+    )
+}
+
 pub async fn get_all_token_transfers(
-    conn: &SqlitePool,
+    conn: &DbPool,
     limit: Option<i64>,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error> {
     let limit = limit.unwrap_or(i64::MAX);
-    let rows = sqlx::query_as::<_, TokenTransferDbObj>(
-        r"SELECT * FROM token_transfer ORDER by id DESC LIMIT $1",
-    )
-    .bind(limit)
-    .fetch_all(conn)
-    .await?;
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer ORDER by id DESC LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
 pub async fn get_token_transfers_by_chain_id(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     limit: Option<i64>,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error> {
     let limit = limit.unwrap_or(i64::MAX);
-    let rows = sqlx::query_as::<_, TokenTransferDbObj>(
-        r"SELECT * FROM token_transfer WHERE chain_id = $1 ORDER by id DESC LIMIT $2",
-    )
-    .bind(chain_id)
-    .bind(limit)
-    .fetch_all(conn)
-    .await?;
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer WHERE chain_id = $1 ORDER by id DESC LIMIT $2",
+        )
+        .bind(chain_id)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
@@ -221,55 +449,116 @@ pub async fn get_token_transfers_by_deposit_id<'c, E>(
     deposit_id: &str,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let rows = sqlx::query_as::<_, TokenTransferDbObj>(
-        r"SELECT * FROM token_transfer WHERE chain_id = $1 AND deposit_id = $2 ORDER by id DESC",
-    )
-    .bind(chain_id)
-    .bind(deposit_id)
-    .fetch_all(conn)
-    .await?;
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer WHERE chain_id = $1 AND deposit_id = $2 ORDER by id DESC",
+        )
+        .bind(chain_id)
+        .bind(deposit_id)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
 pub async fn get_pending_token_transfers(
-    conn: &SqlitePool,
+    conn: &DbPool,
     account: Address,
     chain_id: i64,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, TokenTransferDbObj>(
-        r"SELECT * FROM token_transfer
-WHERE tx_id is null
-AND error is null
+    let rows = db_exec!(
+        conn,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer
+WHERE status = 'queued'
 AND from_addr = $1
 AND chain_id = $2
 ORDER by id ASC
 ",
-    )
-    .bind(format!("{:#x}", account))
-    .bind(chain_id)
-    .fetch_all(conn)
-    .await?;
+        )
+        .bind(format!("{:#x}", account))
+        .bind(chain_id)
+        .fetch_all(conn)
+        .await
+    )?;
     Ok(rows)
 }
 
+/// Deadlines of transfers waiting for gathering
+pub async fn get_pending_token_transfer_deadlines(
+    conn: &DbPool,
+    account: Address,
+    chain_id: i64,
+) -> Result<Vec<DateTime<Utc>>, sqlx::Error> {
+    let rows = db_exec!(
+        conn,
+        sqlx::query_scalar::<_, DateTime<Utc>>(
+            r"SELECT deadline FROM token_transfer
+WHERE status = 'queued'
+AND deadline is not null
+AND from_addr = $1
+AND chain_id = $2
+",
+        )
+        .bind(format!("{:#x}", account))
+        .bind(chain_id)
+        .fetch_all(conn)
+        .await
+    )?;
+    Ok(rows)
+}
+
+/// Count meta transactions (sent by relayer) that are still processed for given sender
+pub async fn get_meta_transactions_in_progress_count<'c, E>(
+    executor: E,
+    sender: Address,
+    chain_id: i64,
+) -> Result<usize, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let count = db_exec!(
+        executor,
+        sqlx::query_scalar::<_, i64>(
+            r"SELECT COUNT(DISTINCT tx.id) FROM tx
+JOIN token_transfer ON token_transfer.tx_id = tx.id
+WHERE token_transfer.from_addr = $1
+AND token_transfer.chain_id = $2
+AND tx.method = 'ERC20.executeMetaTransaction'
+AND tx.processing > 0
+",
+        )
+        .bind(format!("{:#x}", sender))
+        .bind(chain_id)
+        .fetch_one(executor)
+        .await
+    )?;
+    Ok(count as usize)
+}
+
 pub async fn get_unpaid_token_transfers(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     sender: Address,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error> {
-    sqlx::query_as::<_, TokenTransferDbObj>(
-        r"SELECT * FROM token_transfer
+    db_exec!(
+        conn,
+        sqlx::query_as::<_, TokenTransferDbObj>(
+            r"SELECT * FROM token_transfer
 WHERE fee_paid is null
+AND status <> 'cancelled'
 AND chain_id = $1
 AND from_addr = $2
 ",
+        )
+        .bind(chain_id)
+        .bind(format!("{:#x}", sender))
+        .fetch_all(conn)
+        .await
     )
-    .bind(chain_id)
-    .bind(format!("{:#x}", sender))
-    .fetch_all(conn)
-    .await
 }
 
 pub async fn get_token_transfers_by_tx<'c, E>(
@@ -277,20 +566,23 @@ pub async fn get_token_transfers_by_tx<'c, E>(
     tx_id: i64,
 ) -> Result<Vec<TokenTransferDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let rows =
+    let rows = db_exec!(
+        executor,
         sqlx::query_as::<_, TokenTransferDbObj>(r"SELECT * FROM token_transfer WHERE tx_id=$1")
             .bind(tx_id)
             .fetch_all(executor)
-            .await?;
+            .await
+    )?;
     Ok(rows)
 }
 
 pub const TRANSFER_FILTER_ALL: &str = "(id >= 0)";
-pub const TRANSFER_FILTER_QUEUED: &str = "(tx_id is null AND error is null)";
+pub const TRANSFER_FILTER_QUEUED: &str = "(status = 'queued')";
 pub const TRANSFER_FILTER_PROCESSING: &str = "(tx_id is not null AND fee_paid is null)";
 pub const TRANSFER_FILTER_DONE: &str = "(fee_paid is not null)";
+pub const TRANSFER_FILTER_CANCELLED: &str = "(status = 'cancelled')";
 
 #[derive(Debug, Clone, Default)]
 pub struct TransferStatsPart {
@@ -322,7 +614,7 @@ pub struct TransferStats {
 }
 
 pub async fn get_transfer_stats_from_blockchain(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     limit: Option<i64>,
 ) -> Result<TransferStats, PaymentError> {
@@ -386,7 +678,7 @@ pub async fn get_transfer_stats_from_blockchain(
 }
 
 pub async fn get_transfer_stats(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     limit: Option<i64>,
 ) -> Result<TransferStats, PaymentError> {
@@ -440,7 +732,7 @@ pub async fn get_transfer_stats(
             if let Some(tx_id) = t.tx_id {
                 ts.transaction_ids.insert(tx_id);
             }
-            if t.tx_id.is_none() && t.error.is_none() {
+            if t.status == TokenTransferStatus::Queued {
                 ts.queued_count += 1;
             }
             if t.tx_id.is_some() && t.fee_paid.is_none() {
@@ -468,7 +760,7 @@ pub async fn get_transfer_stats(
 }
 
 pub async fn get_transfer_count(
-    conn: &SqlitePool,
+    conn: &DbPool,
     transfer_filter: Option<&str>,
     sender: Option<&str>,
     receiver: Option<&str>,
@@ -476,27 +768,275 @@ pub async fn get_transfer_count(
     let transfer_filter = transfer_filter.unwrap_or(TRANSFER_FILTER_ALL);
 
     let count = if let Some(sender) = sender {
-        sqlx::query_scalar::<_, i64>(
-            format!(
-                r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter} AND from_addr = $1"
+        db_exec!(
+            conn,
+            sqlx::query_scalar::<_, i64>(
+                format!(
+                    r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter} AND from_addr = $1"
+                )
+                .as_str(),
             )
-            .as_str(),
-        )
-        .bind(sender)
-        .fetch_one(conn)
-        .await?
-    } else if let Some(receiver) = receiver {
-        sqlx::query_scalar::<_, i64>(format!(r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter} AND receiver_addr = $1").as_str())
-            .bind(receiver)
+            .bind(sender)
             .fetch_one(conn)
-            .await?
+            .await
+        )?
+    } else if let Some(receiver) = receiver {
+        db_exec!(
+            conn,
+            sqlx::query_scalar::<_, i64>(format!(r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter} AND receiver_addr = $1").as_str())
+                .bind(receiver)
+                .fetch_one(conn)
+                .await
+        )?
     } else {
-        sqlx::query_scalar::<_, i64>(
-            format!(r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter}").as_str(),
-        )
-        .fetch_one(conn)
-        .await?
+        db_exec!(
+            conn,
+            sqlx::query_scalar::<_, i64>(
+                format!(r"SELECT COUNT(*) FROM token_transfer WHERE {transfer_filter}").as_str(),
+            )
+            .fetch_one(conn)
+            .await
+        )?
     };
 
     Ok(count as usize)
 }
+
+#[tokio::test]
+async fn token_transfer_payment_id_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    use crate::error::ErrorBag;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let token_transfer = TokenTransferDbObj {
+        id: -1,
+        payment_id: Some("payment_1".to_string()),
+        from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+        receiver_addr: "0xbcfe9736a4f5bf2e43620061ff3001ea0d003c0f".to_string(),
+        chain_id: 987789,
+        token_addr: None,
+        token_amount: "1000".to_string(),
+        deposit_id: None,
+        deposit_finish: 0,
+        create_date: Utc::now(),
+        tx_id: None,
+        paid_date: None,
+        fee_paid: None,
+        error: None,
+        status: TokenTransferStatus::Queued,
+        priority: 0,
+        deadline: None,
+    };
+
+    let inserted = insert_token_transfer_with_deposit_check(&conn, &token_transfer)
+        .await
+        .unwrap();
+
+    //second insert with the same payment_id returns stored record
+    match insert_token_transfer_with_deposit_check(&conn, &token_transfer).await {
+        Err(err) => match *err.inner {
+            ErrorBag::TransferAlreadyExists(existing) => assert_eq!(existing.id, inserted.id),
+            _ => panic!("Unexpected error: {err}"),
+        },
+        Ok(_) => panic!("Duplicated payment_id should not be inserted"),
+    }
+    //unique index protects from inserts skipping the check
+    assert!(insert_token_transfer(&conn, &token_transfer).await.is_err());
+
+    //the same payment_id is allowed for different sender or chain
+    let mut other_chain = token_transfer.clone();
+    other_chain.chain_id = 987790;
+    insert_token_transfer_with_deposit_check(&conn, &other_chain)
+        .await
+        .unwrap();
+
+    assert_eq!(get_transfer_count(&conn, None, None, None).await?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_transfers_batch_insert_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    use crate::error::ErrorBag;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let token_transfer = TokenTransferDbObj {
+        id: -1,
+        payment_id: Some("payment_1".to_string()),
+        from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+        receiver_addr: "0xbcfe9736a4f5bf2e43620061ff3001ea0d003c0f".to_string(),
+        chain_id: 987789,
+        token_addr: None,
+        token_amount: "1000".to_string(),
+        deposit_id: None,
+        deposit_finish: 0,
+        create_date: Utc::now(),
+        tx_id: None,
+        paid_date: None,
+        fee_paid: None,
+        error: None,
+        status: TokenTransferStatus::Queued,
+        priority: 0,
+        deadline: None,
+    };
+    let mut second = token_transfer.clone();
+    second.payment_id = Some("payment_2".to_string());
+
+    let inserted =
+        insert_token_transfers_with_deposit_check(&conn, &[token_transfer.clone(), second.clone()])
+            .await
+            .unwrap();
+    assert_eq!(inserted.len(), 2);
+    assert_eq!(inserted[1].payment_id, Some("payment_2".to_string()));
+
+    //batch containing already used payment_id is rejected as a whole
+    let mut third = token_transfer.clone();
+    third.payment_id = Some("payment_3".to_string());
+    match insert_token_transfers_with_deposit_check(&conn, &[third.clone(), second]).await {
+        Err(err) => match *err.inner {
+            ErrorBag::TransferAlreadyExists(existing) => assert_eq!(existing.id, inserted[1].id),
+            _ => panic!("Unexpected error: {err}"),
+        },
+        Ok(_) => panic!("Duplicated payment_id should not be inserted"),
+    }
+    //duplicates within one batch are rejected too
+    assert!(
+        insert_token_transfers_with_deposit_check(&conn, &[third.clone(), third])
+            .await
+            .is_err()
+    );
+
+    assert_eq!(get_transfer_count(&conn, None, None, None).await?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_transfer_cancel_amend_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let mut token_transfer = TokenTransferDbObj {
+        id: -1,
+        payment_id: Some("payment_1".to_string()),
+        from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+        receiver_addr: "0xbcfe9736a4f5bf2e43620061ff3001ea0d003c0f".to_string(),
+        chain_id: 987789,
+        token_addr: None,
+        token_amount: "1000".to_string(),
+        deposit_id: None,
+        deposit_finish: 0,
+        create_date: Utc::now(),
+        tx_id: None,
+        paid_date: None,
+        fee_paid: None,
+        error: None,
+        status: TokenTransferStatus::Queued,
+        priority: 0,
+        deadline: None,
+    };
+    let first = insert_token_transfer(&conn, &token_transfer).await?;
+    token_transfer.payment_id = Some("payment_2".to_string());
+    let second = insert_token_transfer(&conn, &token_transfer).await?;
+
+    let amended = amend_token_transfer(&conn, first.id, &first.receiver_addr, "2000")
+        .await?
+        .unwrap();
+    assert_eq!(amended.token_amount, "2000");
+    //transfer read before amend cannot be assigned to tx
+    assert!(!assign_token_transfer_to_tx(&conn, &first, 1).await?);
+
+    let cancelled = cancel_token_transfer(&conn, second.id).await?.unwrap();
+    assert_eq!(cancelled.error, None);
+    assert_eq!(cancelled.status, TokenTransferStatus::Cancelled);
+    assert!(cancel_token_transfer(&conn, second.id).await?.is_none());
+    assert!(!assign_token_transfer_to_tx(&conn, &second, 1).await?);
+    assert_eq!(
+        get_transfer_count(&conn, Some(TRANSFER_FILTER_CANCELLED), None, None).await?,
+        1
+    );
+    assert_eq!(
+        get_transfer_count(&conn, Some(TRANSFER_FILTER_QUEUED), None, None).await?,
+        1
+    );
+    //cancelled transfer is not gathered again
+    let pending = get_pending_token_transfers(
+        &conn,
+        Address::from_str(&first.from_addr).unwrap(),
+        first.chain_id,
+    )
+    .await?;
+    assert_eq!(
+        pending.iter().map(|t| t.id).collect::<Vec<_>>(),
+        vec![first.id]
+    );
+
+    let tx = crate::ops::insert_tx(
+        &conn,
+        &crate::model::TxDbObj {
+            from_addr: amended.from_addr.clone(),
+            chain_id: amended.chain_id,
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert!(assign_token_transfer_to_tx(&conn, &amended, tx.id).await?);
+    update_token_transfers_status_by_tx(&conn, tx.id, TokenTransferStatus::Signed).await?;
+    let signed = get_token_transfers_by_tx(&conn, tx.id).await?;
+    assert_eq!(signed[0].status, TokenTransferStatus::Signed);
+    cleanup_token_transfer_tx(&conn, tx.id).await?;
+    let requeued = get_token_transfers_by_payment_id(&conn, "payment_1").await?;
+    assert_eq!(requeued[0].status, TokenTransferStatus::Queued);
+    assert_eq!(requeued[0].tx_id, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_transfer_pending_deadlines_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let sender = Address::from_str("0x001066290077e38f222cc6009c0c7a91d5192303").unwrap();
+    let deadline = Utc::now() + Duration::try_seconds(3600).unwrap();
+    let mut token_transfer = TokenTransferDbObj {
+        id: -1,
+        payment_id: None,
+        from_addr: format!("{:#x}", sender),
+        receiver_addr: "0xbcfe9736a4f5bf2e43620061ff3001ea0d003c0f".to_string(),
+        chain_id: 987789,
+        token_addr: None,
+        token_amount: "1000".to_string(),
+        deposit_id: None,
+        deposit_finish: 0,
+        create_date: Utc::now(),
+        tx_id: None,
+        paid_date: None,
+        fee_paid: None,
+        error: None,
+        status: TokenTransferStatus::Queued,
+        priority: 0,
+        deadline: None,
+    };
+    insert_token_transfer(&conn, &token_transfer).await?;
+    token_transfer.deadline = Some(deadline);
+    insert_token_transfer(&conn, &token_transfer).await?;
+    //failed transfers are not gathered
+    token_transfer.error = Some("failed".to_string());
+    token_transfer.status = TokenTransferStatus::Failed;
+    insert_token_transfer(&conn, &token_transfer).await?;
+
+    let deadlines = get_pending_token_transfer_deadlines(&conn, sender, 987789).await?;
+    assert_eq!(deadlines.len(), 1);
+    assert_eq!(deadlines[0].timestamp(), deadline.timestamp());
+    assert!(get_pending_token_transfer_deadlines(&conn, sender, 987790)
+        .await?
+        .is_empty());
+    Ok(())
+}
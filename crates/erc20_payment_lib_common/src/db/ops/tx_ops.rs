@@ -1,7 +1,6 @@
 use super::model::TxDbObj;
-use sqlx::Sqlite;
-use sqlx::SqlitePool;
-use sqlx::{Executor, Transaction};
+use crate::db::{db_exec, DbExecutor, DbPool, DbTransaction};
+use chrono::{DateTime, Utc};
 use web3::types::Address;
 
 pub const TRANSACTION_FILTER_QUEUED: &str = "processing > 0 AND first_processed IS NULL";
@@ -19,17 +18,20 @@ pub async fn get_next_transaction<'c, E>(
     account: &str,
 ) -> Result<Option<TxDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let res = sqlx::query_as::<_, TxDbObj>(
-        r"SELECT * FROM tx
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(
+            r"SELECT * FROM tx
         WHERE chain_id = $1 AND from_addr = $2 AND processing > 0 AND first_processed IS NULL
         ORDER BY id ASC LIMIT 1",
-    )
-    .bind(chain_id)
-    .bind(account)
-    .fetch_optional(executor)
-    .await?;
+        )
+        .bind(chain_id)
+        .bind(account)
+        .fetch_optional(executor)
+        .await
+    )?;
     Ok(res)
 }
 
@@ -42,7 +44,7 @@ pub async fn get_transactions<'c, E>(
     chain_id: Option<i64>,
 ) -> Result<Vec<TxDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
     let limit = limit.unwrap_or(i64::MAX);
     let filter = filter.unwrap_or(TRANSACTION_FILTER_ALL);
@@ -55,22 +57,28 @@ where
         Some(chain_id) => format!("chain_id = {}", chain_id),
         None => "1 = 1".to_string(),
     };
-    let rows = sqlx::query_as::<_, TxDbObj>(
-        format!(r"SELECT * FROM tx WHERE ({filter_chain}) AND ({filter_account}) AND ({filter}) ORDER BY {order} LIMIT {limit}").as_str(),
-    )
-    .fetch_all(executor)
-    .await?;
+    let rows = db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(
+            format!(r"SELECT * FROM tx WHERE ({filter_chain}) AND ({filter_account}) AND ({filter}) ORDER BY {order} LIMIT {limit}").as_str(),
+        )
+        .fetch_all(executor)
+        .await
+    )?;
     Ok(rows)
 }
 
 pub async fn get_transaction<'c, E>(executor: E, tx_id: i64) -> Result<TxDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let row = sqlx::query_as::<_, TxDbObj>(r"SELECT * FROM tx WHERE id = $1")
-        .bind(tx_id)
-        .fetch_one(executor)
-        .await?;
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(r"SELECT * FROM tx WHERE id = $1")
+            .bind(tx_id)
+            .fetch_one(executor)
+            .await
+    )?;
     Ok(row)
 }
 
@@ -79,27 +87,27 @@ pub async fn get_last_unsent_tx<'c, E>(
     tx_id: i64,
 ) -> Result<Option<TxDbObj>, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let row = sqlx::query_as::<_, TxDbObj>(r"SELECT * FROM tx WHERE broadcast_date is NULL AND signed_date is NULL ORDER BY id DESC LIMIT 1")
-        .bind(tx_id)
-        .fetch_optional(executor)
-        .await?;
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(r"SELECT * FROM tx WHERE broadcast_date is NULL AND signed_date is NULL ORDER BY id DESC LIMIT 1")
+            .bind(tx_id)
+            .fetch_optional(executor)
+            .await
+    )?;
     Ok(row)
 }
 
 //call in transaction
 pub async fn get_transaction_chain(
-    executor: &mut Transaction<'_, Sqlite>,
+    executor: &mut DbTransaction<'_>,
     tx_id: i64,
 ) -> Result<Vec<TxDbObj>, sqlx::Error> {
     let mut current_id = Some(tx_id);
     let mut res = vec![];
     while let Some(id) = current_id {
-        let row = sqlx::query_as::<_, TxDbObj>(r"SELECT * FROM tx WHERE id = $1")
-            .bind(id)
-            .fetch_one(&mut **executor)
-            .await?;
+        let row = get_transaction(&mut *executor, id).await?;
         current_id = row.orig_tx_id;
         res.push(row);
     }
@@ -108,66 +116,131 @@ pub async fn get_transaction_chain(
 
 pub async fn delete_tx<'c, E>(executor: E, tx_id: i64) -> Result<(), sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    sqlx::query(r"DELETE FROM tx WHERE id = $1")
-        .bind(tx_id)
-        .execute(executor)
-        .await?;
+    db_exec!(
+        executor,
+        sqlx::query(r"DELETE FROM tx WHERE id = $1")
+            .bind(tx_id)
+            .execute(executor)
+            .await
+            .map(|res| res.rows_affected())
+    )?;
     Ok(())
 }
 
 pub async fn get_transaction_highest_block(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     from_addr: &str,
 ) -> Result<Option<i64>, sqlx::Error> {
-    sqlx::query_scalar::<_, Option<i64>>(
-        r"SELECT MAX(block_number) FROM tx WHERE confirm_date
+    db_exec!(
+        conn,
+        sqlx::query_scalar::<_, Option<i64>>(
+            r"SELECT MAX(block_number) FROM tx WHERE confirm_date
          IS NOT NULL
          AND chain_id = $1
          AND from_addr = $2
          ",
+        )
+        .bind(chain_id)
+        .bind(from_addr)
+        .fetch_one(conn)
+        .await
     )
-    .bind(chain_id)
-    .bind(from_addr)
-    .fetch_one(conn)
-    .await
 }
 
 pub async fn get_transaction_highest_nonce(
-    conn: &SqlitePool,
+    conn: &DbPool,
     chain_id: i64,
     from_addr: &str,
 ) -> Result<Option<i64>, sqlx::Error> {
-    sqlx::query_scalar::<_, Option<i64>>(
-        r"SELECT MAX(nonce) FROM tx WHERE confirm_date
+    db_exec!(
+        conn,
+        sqlx::query_scalar::<_, Option<i64>>(
+            r"SELECT MAX(nonce) FROM tx WHERE confirm_date
          IS NOT NULL
          AND chain_id = $1
          AND from_addr = $2
          ",
+        )
+        .bind(chain_id)
+        .bind(from_addr)
+        .fetch_one(conn)
+        .await
+    )
+}
+
+/// Transactions of the account confirmed since given date and transactions sent but not confirmed yet
+pub async fn get_transactions_for_fee_budget(
+    conn: &DbPool,
+    chain_id: i64,
+    from_addr: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<TxDbObj>, sqlx::Error> {
+    db_exec!(
+        conn,
+        sqlx::query_as::<_, TxDbObj>(
+            r"SELECT * FROM tx WHERE chain_id = $1
+         AND from_addr = $2
+         AND (confirm_date >= $3
+            OR (confirm_date IS NULL AND broadcast_date IS NOT NULL AND error IS NULL))
+         ",
+        )
+        .bind(chain_id)
+        .bind(from_addr)
+        .bind(since)
+        .fetch_all(conn)
+        .await
+    )
+}
+
+/// Confirmed transactions of the account that can still be reorged out.
+/// Signed data is kept until the transaction is final, so it can be broadcast again.
+pub async fn get_transactions_awaiting_finality<'c, E>(
+    executor: E,
+    chain_id: i64,
+    from_addr: &str,
+) -> Result<Vec<TxDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(
+            r"SELECT * FROM tx WHERE chain_id = $1
+         AND from_addr = $2
+         AND confirm_date IS NOT NULL
+         AND block_hash IS NOT NULL
+         AND final_date IS NULL
+         ORDER BY id
+         ",
+        )
+        .bind(chain_id)
+        .bind(from_addr)
+        .fetch_all(executor)
+        .await
     )
-    .bind(chain_id)
-    .bind(from_addr)
-    .fetch_one(conn)
-    .await
 }
 
 pub async fn get_transaction_count(
-    conn: &SqlitePool,
+    conn: &DbPool,
     transaction_filter: Option<&str>,
 ) -> Result<usize, sqlx::Error> {
     let transaction_filter = transaction_filter.unwrap_or(TRANSACTION_FILTER_ALL);
-    let count = sqlx::query_scalar::<_, i64>(
-        format!(r"SELECT COUNT(*) FROM tx WHERE {transaction_filter}").as_str(),
-    )
-    .fetch_one(conn)
-    .await?;
+    let count = db_exec!(
+        conn,
+        sqlx::query_scalar::<_, i64>(
+            format!(r"SELECT COUNT(*) FROM tx WHERE {transaction_filter}").as_str(),
+        )
+        .fetch_one(conn)
+        .await
+    )?;
     Ok(count as usize)
 }
 
 pub async fn get_next_transactions_to_process(
-    conn: &SqlitePool,
+    conn: &DbPool,
     account: Option<Address>,
     limit: i64,
     chain_id: i64,
@@ -183,86 +256,104 @@ pub async fn get_next_transactions_to_process(
     .await
 }
 
-pub async fn force_tx_error(conn: &SqlitePool, tx: &TxDbObj) -> Result<(), sqlx::Error> {
-    sqlx::query(r"UPDATE tx SET error = 'forced error' WHERE id = $1")
-        .bind(tx.id)
-        .execute(conn)
-        .await?;
+pub async fn force_tx_error(conn: &DbPool, tx: &TxDbObj) -> Result<(), sqlx::Error> {
+    db_exec!(
+        conn,
+        sqlx::query(r"UPDATE tx SET error = 'forced error' WHERE id = $1")
+            .bind(tx.id)
+            .execute(conn)
+            .await
+            .map(|res| res.rows_affected())
+    )?;
     Ok(())
 }
 
 pub async fn insert_tx<'c, E>(executor: E, tx: &TxDbObj) -> Result<TxDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let res = sqlx::query_as::<_, TxDbObj>(
-        r"INSERT INTO tx
-(method, from_addr, to_addr, chain_id, gas_limit, max_fee_per_gas, priority_fee, val, nonce, processing, call_data, created_date, first_processed, tx_hash, signed_raw_data, signed_date, broadcast_date, broadcast_count, first_stuck_date, confirm_date, blockchain_date, gas_used, block_number, chain_status, block_gas_price, effective_gas_price, fee_paid, error, orig_tx_id)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29) RETURNING *;
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, TxDbObj>(
+            r"INSERT INTO tx
+(method, from_addr, to_addr, chain_id, gas_limit, max_fee_per_gas, priority_fee, val, nonce, processing, call_data, created_date, first_processed, tx_hash, signed_raw_data, signed_date, broadcast_date, broadcast_count, first_stuck_date, confirm_date, blockchain_date, gas_used, block_number, chain_status, block_gas_price, effective_gas_price, fee_paid, error, orig_tx_id, broadcast_endpoints, block_hash, final_date)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32) RETURNING *;
 ",
-    )
-        .bind(&tx.method)
-        .bind(&tx.from_addr)
-        .bind(&tx.to_addr)
-        .bind( tx.chain_id)
-        .bind( tx.gas_limit)
-        .bind( &tx.max_fee_per_gas)
-        .bind( &tx.priority_fee)
-        .bind( &tx.val)
-        .bind( tx.nonce)
-        .bind( tx.processing)
-        .bind( &tx.call_data)
-        .bind( tx.created_date)
-        .bind( tx.first_processed)
-        .bind( &tx.tx_hash)
-        .bind( &tx.signed_raw_data)
-        .bind( tx.signed_date)
-        .bind( tx.broadcast_date)
-        .bind( tx.broadcast_count)
-        .bind( tx.first_stuck_date)
-        .bind( tx.confirm_date)
-        .bind( tx.blockchain_date)
-        .bind( tx.gas_used)
-        .bind( tx.block_number)
-        .bind( tx.chain_status)
-        .bind( &tx.block_gas_price)
-        .bind( &tx.effective_gas_price)
-        .bind( &tx.fee_paid)
-        .bind(&tx.error)
-        .bind( tx.orig_tx_id)
-        .fetch_one(executor)
-        .await?;
+        )
+            .bind(&tx.method)
+            .bind(&tx.from_addr)
+            .bind(&tx.to_addr)
+            .bind( tx.chain_id)
+            .bind( tx.gas_limit)
+            .bind( &tx.max_fee_per_gas)
+            .bind( &tx.priority_fee)
+            .bind( &tx.val)
+            .bind( tx.nonce)
+            .bind( tx.processing)
+            .bind( &tx.call_data)
+            .bind( tx.created_date)
+            .bind( tx.first_processed)
+            .bind( &tx.tx_hash)
+            .bind( &tx.signed_raw_data)
+            .bind( tx.signed_date)
+            .bind( tx.broadcast_date)
+            .bind( tx.broadcast_count)
+            .bind( tx.first_stuck_date)
+            .bind( tx.confirm_date)
+            .bind( tx.blockchain_date)
+            .bind( tx.gas_used)
+            .bind( tx.block_number)
+            .bind( tx.chain_status)
+            .bind( &tx.block_gas_price)
+            .bind( &tx.effective_gas_price)
+            .bind( &tx.fee_paid)
+            .bind(&tx.error)
+            .bind( tx.orig_tx_id)
+            .bind(&tx.broadcast_endpoints)
+            .bind(&tx.block_hash)
+            .bind(tx.final_date)
+            .fetch_one(executor)
+            .await
+    )?;
     Ok(res)
 }
 
+/// Marks transaction as taken for processing. Only succeeds if no other processor
+/// took it in the meantime, returns false otherwise.
 pub async fn update_processing_and_first_processed_tx<'c, E>(
     executor: E,
     tx: &TxDbObj,
-) -> Result<TxDbObj, sqlx::Error>
+) -> Result<bool, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE tx SET
+    let res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE tx SET
 processing = $2,
 first_processed = $3
-WHERE id = $1
+WHERE id = $1 AND first_processed IS NULL
 ",
-    )
-    .bind(tx.id)
-    .bind(tx.processing)
-    .bind(tx.first_processed)
-    .execute(executor)
-    .await?;
-    Ok(tx.clone())
+        )
+        .bind(tx.id)
+        .bind(tx.processing)
+        .bind(tx.first_processed)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(res == 1)
 }
 
 pub async fn update_tx<'c, E>(executor: E, tx: &TxDbObj) -> Result<TxDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE tx SET
+    let _res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE tx SET
 method = $2,
 from_addr = $3,
 to_addr = $4,
@@ -291,59 +382,71 @@ block_gas_price = $26,
 effective_gas_price = $27,
 fee_paid = $28,
 error = $29,
-orig_tx_id = $30
+orig_tx_id = $30,
+broadcast_endpoints = $31,
+block_hash = $32,
+final_date = $33
 WHERE id = $1
 ",
-    )
-    .bind(tx.id)
-    .bind(&tx.method)
-    .bind(&tx.from_addr)
-    .bind(&tx.to_addr)
-    .bind(tx.chain_id)
-    .bind(tx.gas_limit)
-    .bind(&tx.max_fee_per_gas)
-    .bind(&tx.priority_fee)
-    .bind(&tx.val)
-    .bind(tx.nonce)
-    .bind(tx.processing)
-    .bind(&tx.call_data)
-    .bind(tx.created_date)
-    .bind(tx.first_processed)
-    .bind(&tx.tx_hash)
-    .bind(&tx.signed_raw_data)
-    .bind(tx.signed_date)
-    .bind(tx.broadcast_date)
-    .bind(tx.broadcast_count)
-    .bind(tx.first_stuck_date)
-    .bind(tx.confirm_date)
-    .bind(tx.blockchain_date)
-    .bind(tx.gas_used)
-    .bind(tx.block_number)
-    .bind(tx.chain_status)
-    .bind(&tx.block_gas_price)
-    .bind(&tx.effective_gas_price)
-    .bind(&tx.fee_paid)
-    .bind(&tx.error)
-    .bind(tx.orig_tx_id)
-    .execute(executor)
-    .await?;
+        )
+        .bind(tx.id)
+        .bind(&tx.method)
+        .bind(&tx.from_addr)
+        .bind(&tx.to_addr)
+        .bind(tx.chain_id)
+        .bind(tx.gas_limit)
+        .bind(&tx.max_fee_per_gas)
+        .bind(&tx.priority_fee)
+        .bind(&tx.val)
+        .bind(tx.nonce)
+        .bind(tx.processing)
+        .bind(&tx.call_data)
+        .bind(tx.created_date)
+        .bind(tx.first_processed)
+        .bind(&tx.tx_hash)
+        .bind(&tx.signed_raw_data)
+        .bind(tx.signed_date)
+        .bind(tx.broadcast_date)
+        .bind(tx.broadcast_count)
+        .bind(tx.first_stuck_date)
+        .bind(tx.confirm_date)
+        .bind(tx.blockchain_date)
+        .bind(tx.gas_used)
+        .bind(tx.block_number)
+        .bind(tx.chain_status)
+        .bind(&tx.block_gas_price)
+        .bind(&tx.effective_gas_price)
+        .bind(&tx.fee_paid)
+        .bind(&tx.error)
+        .bind(tx.orig_tx_id)
+        .bind(&tx.broadcast_endpoints)
+        .bind(&tx.block_hash)
+        .bind(tx.final_date)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
     Ok(tx.clone())
 }
 
 pub async fn update_tx_stuck_date<'c, E>(executor: E, tx: &TxDbObj) -> Result<TxDbObj, sqlx::Error>
 where
-    E: Executor<'c, Database = Sqlite>,
+    E: Into<DbExecutor<'c>>,
 {
-    let _res = sqlx::query(
-        r"UPDATE tx SET
+    let _res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE tx SET
 first_stuck_date = $2
 WHERE id = $1
 ",
-    )
-    .bind(tx.id)
-    .bind(tx.first_stuck_date)
-    .execute(executor)
-    .await?;
+        )
+        .bind(tx.id)
+        .bind(tx.first_stuck_date)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
     Ok(tx.clone())
 }
 
@@ -381,12 +484,19 @@ async fn tx_test() -> sqlx::Result<()> {
         call_data: None,
         created_date: chrono::Utc::now(),
         block_number: Some(119677),
+        block_hash: Some(
+            "0x5c9f2d8a1e8f1b6c2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293".to_string(),
+        ),
+        final_date: None,
         chain_status: Some(1),
         block_gas_price: Some("557034000005500".to_string()),
         effective_gas_price: Some("103434000005500".to_string()),
         fee_paid: Some("83779300533141".to_string()),
         error: Some("Test error message".to_string()),
         orig_tx_id: None,
+        broadcast_endpoints: Some(
+            r#"[{"endpoint":"endp1","accepted":true,"message":null}]"#.to_string(),
+        ),
         engine_message: None,
         engine_error: None,
         first_processed: None,
@@ -409,5 +519,160 @@ async fn tx_test() -> sqlx::Result<()> {
     let tx_from_dao = get_transaction(&conn, tx_from_insert.id).await?;
     assert_eq!(tx_update, tx_from_dao);
 
+    //only one processor can take the transaction
+    let mut tx_taken = tx_from_dao.clone();
+    tx_taken.first_processed = Some(chrono::Utc::now());
+    assert!(update_processing_and_first_processed_tx(&conn, &tx_taken).await?);
+    assert!(!update_processing_and_first_processed_tx(&conn, &tx_taken).await?);
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+#[ignore = "needs Postgres instance given by ERC20_LIB_TEST_POSTGRES_URL"]
+async fn tx_test_postgres() -> sqlx::Result<()> {
+    use super::{
+        get_scan_info, get_token_transfers_by_tx, insert_token_transfer, upsert_scan_info,
+    };
+    use crate::create_db_connection;
+    use crate::model::{ScanDaoDbObj, TokenTransferDbObj};
+    use chrono::SubsecRound;
+
+    let url = std::env::var("ERC20_LIB_TEST_POSTGRES_URL")
+        .expect("ERC20_LIB_TEST_POSTGRES_URL has to be set to run Postgres tests");
+    let conn = create_db_connection(&url, false, true).await.unwrap();
+    assert!(conn.is_postgres());
+
+    // Postgres stores timestamps with microsecond precision
+    let now = chrono::Utc::now().trunc_subsecs(6);
+    let chain_id = 987789 + fastrand::i64(0..1000000);
+
+    let mut tx_to_insert = TxDbObj {
+        id: -1,
+        method: "ERC20.transfer".to_string(),
+        from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+        to_addr: "0xbcfe9736a4f5bf2e43620061ff3001ea0d003c0f".to_string(),
+        chain_id,
+        gas_limit: Some(100000),
+        max_fee_per_gas: Some("110000000000".to_string()),
+        priority_fee: Some("5110000000000".to_string()),
+        val: "0".to_string(),
+        nonce: Some(1),
+        processing: 1,
+        call_data: None,
+        created_date: now,
+        first_processed: Some(now),
+        tx_hash: None,
+        signed_raw_data: None,
+        signed_date: None,
+        broadcast_date: None,
+        broadcast_count: 0,
+        first_stuck_date: None,
+        confirm_date: None,
+        blockchain_date: None,
+        gas_used: None,
+        block_number: None,
+        block_hash: None,
+        final_date: None,
+        chain_status: None,
+        block_gas_price: None,
+        effective_gas_price: None,
+        fee_paid: None,
+        error: None,
+        orig_tx_id: None,
+        broadcast_endpoints: None,
+        engine_message: None,
+        engine_error: None,
+    };
+
+    let tx_from_insert = insert_tx(&conn, &tx_to_insert).await?;
+    tx_to_insert.id = tx_from_insert.id;
+    let tx_from_dao = get_transaction(&conn, tx_from_insert.id).await?;
+    assert_eq!(tx_to_insert, tx_from_dao);
+
+    let token_transfer = TokenTransferDbObj {
+        id: -1,
+        payment_id: Some("postgres_test".to_string()),
+        from_addr: tx_to_insert.from_addr.clone(),
+        receiver_addr: tx_to_insert.to_addr.clone(),
+        chain_id,
+        token_addr: None,
+        token_amount: "1000".to_string(),
+        deposit_id: None,
+        deposit_finish: 0,
+        create_date: now,
+        tx_id: Some(tx_from_insert.id),
+        paid_date: None,
+        fee_paid: None,
+        error: None,
+        status: Default::default(),
+        priority: 1,
+        deadline: Some(now),
+    };
+    let transfer_from_insert = insert_token_transfer(&conn, &token_transfer).await?;
+    let transfers = get_token_transfers_by_tx(&conn, tx_from_insert.id).await?;
+    assert_eq!(transfers.len(), 1);
+    assert_eq!(transfers[0].id, transfer_from_insert.id);
+    assert_eq!(transfers[0].priority, 1);
+    assert!(transfers[0].deadline.is_some());
+    assert_eq!(transfers[0].payment_id, token_transfer.payment_id);
+
+    let mut scan_info = ScanDaoDbObj {
+        id: -1,
+        chain_id,
+        filter: "filter".to_string(),
+        start_block: 77,
+        last_block: 6666,
+    };
+    let scan_info_from_insert = upsert_scan_info(&conn, &scan_info).await?;
+    scan_info.last_block = 7777;
+    let scan_info_from_update = upsert_scan_info(&conn, &scan_info).await?;
+    assert_eq!(scan_info_from_insert.id, scan_info_from_update.id);
+    assert_eq!(
+        get_scan_info(&conn, chain_id, "filter")
+            .await?
+            .unwrap()
+            .last_block,
+        7777
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tx_awaiting_finality_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let from_addr = "0x001066290077e38f222cc6009c0c7a91d5192303";
+    let confirmed = TxDbObj {
+        from_addr: from_addr.to_string(),
+        chain_id: 987789,
+        signed_raw_data: Some("0x00".to_string()),
+        confirm_date: Some(chrono::Utc::now()),
+        block_number: Some(100),
+        block_hash: Some(format!("{:#x}", web3::types::H256::repeat_byte(1))),
+        ..Default::default()
+    };
+    let confirmed = insert_tx(&conn, &confirmed).await?;
+    let final_tx = TxDbObj {
+        final_date: Some(chrono::Utc::now()),
+        ..confirmed.clone()
+    };
+    insert_tx(&conn, &final_tx).await?;
+    let pending = TxDbObj {
+        confirm_date: None,
+        block_hash: None,
+        ..confirmed.clone()
+    };
+    insert_tx(&conn, &pending).await?;
+
+    assert_eq!(
+        get_transactions_awaiting_finality(&conn, 987789, from_addr).await?,
+        vec![confirmed]
+    );
     Ok(())
 }
@@ -0,0 +1,232 @@
+use super::model::WebhookDeliveryDbObj;
+use crate::db::{db_exec, DbExecutor};
+use chrono::{DateTime, Utc};
+
+pub async fn insert_webhook_delivery<'c, E>(
+    executor: E,
+    delivery: &WebhookDeliveryDbObj,
+) -> Result<WebhookDeliveryDbObj, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query_as::<_, WebhookDeliveryDbObj>(
+            r"INSERT INTO webhook_delivery
+(webhook, event_type, payload, created_date, attempts, next_attempt_date, delivered_date, last_error)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *;
+",
+        )
+        .bind(&delivery.webhook)
+        .bind(&delivery.event_type)
+        .bind(&delivery.payload)
+        .bind(delivery.created_date)
+        .bind(delivery.attempts)
+        .bind(delivery.next_attempt_date)
+        .bind(delivery.delivered_date)
+        .bind(&delivery.last_error)
+        .fetch_one(executor)
+        .await
+    )?;
+    Ok(res)
+}
+
+pub async fn update_webhook_delivery<'c, E>(
+    executor: E,
+    delivery: &WebhookDeliveryDbObj,
+) -> Result<(), sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE webhook_delivery SET
+attempts = $2,
+next_attempt_date = $3,
+delivered_date = $4,
+last_error = $5
+WHERE id = $1
+",
+        )
+        .bind(delivery.id)
+        .bind(delivery.attempts)
+        .bind(delivery.next_attempt_date)
+        .bind(delivery.delivered_date)
+        .bind(&delivery.last_error)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(())
+}
+
+/// Undelivered webhooks that should be sent now, oldest first
+pub async fn get_webhook_deliveries_due<'c, E>(
+    executor: E,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<WebhookDeliveryDbObj>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let rows = db_exec!(
+        executor,
+        sqlx::query_as::<_, WebhookDeliveryDbObj>(
+            r"SELECT * FROM webhook_delivery WHERE next_attempt_date <= $1 ORDER BY id ASC LIMIT $2",
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(executor)
+        .await
+    )?;
+    Ok(rows)
+}
+
+/// Earliest time any undelivered webhook should be sent
+pub async fn get_next_webhook_attempt_date<'c, E>(
+    executor: E,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let row = db_exec!(
+        executor,
+        sqlx::query_as::<_, WebhookDeliveryDbObj>(
+            r"SELECT * FROM webhook_delivery WHERE next_attempt_date IS NOT NULL ORDER BY next_attempt_date ASC LIMIT 1",
+        )
+        .fetch_optional(executor)
+        .await
+    )?;
+    Ok(row.and_then(|row| row.next_attempt_date))
+}
+
+/// Moves next attempt of all undelivered events of the webhook to given date (if earlier),
+/// so they are not sent while the receiver is failing
+pub async fn postpone_webhook_deliveries<'c, E>(
+    executor: E,
+    webhook: &str,
+    next_attempt_date: DateTime<Utc>,
+) -> Result<u64, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query(
+            r"UPDATE webhook_delivery SET next_attempt_date = $2
+WHERE webhook = $1 AND next_attempt_date IS NOT NULL AND next_attempt_date < $2
+",
+        )
+        .bind(webhook)
+        .bind(next_attempt_date)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(res)
+}
+
+pub async fn delete_webhook_deliveries_delivered_before<'c, E>(
+    executor: E,
+    webhook: &str,
+    date: DateTime<Utc>,
+) -> Result<u64, sqlx::Error>
+where
+    E: Into<DbExecutor<'c>>,
+{
+    let res = db_exec!(
+        executor,
+        sqlx::query(
+            r"DELETE FROM webhook_delivery WHERE webhook = $1 AND delivered_date IS NOT NULL AND delivered_date < $2",
+        )
+        .bind(webhook)
+        .bind(date)
+        .execute(executor)
+        .await
+        .map(|res| res.rows_affected())
+    )?;
+    Ok(res)
+}
+
+#[tokio::test]
+async fn webhook_delivery_test() -> sqlx::Result<()> {
+    use crate::create_sqlite_connection;
+    let conn = create_sqlite_connection(None, None, false, true)
+        .await
+        .unwrap();
+
+    let now = chrono::DateTime::from_timestamp(1715000000, 0).unwrap();
+    let mut delivery = insert_webhook_delivery(
+        &conn,
+        &WebhookDeliveryDbObj {
+            id: 0,
+            webhook: "accounting".to_string(),
+            event_type: "TransferFinished".to_string(),
+            payload: "{}".to_string(),
+            created_date: now,
+            attempts: 0,
+            next_attempt_date: Some(now),
+            delivered_date: None,
+            last_error: None,
+        },
+    )
+    .await?;
+    assert!(delivery.id > 0);
+    assert_eq!(get_next_webhook_attempt_date(&conn).await?, Some(now));
+    assert_eq!(
+        get_webhook_deliveries_due(&conn, now, 10).await?,
+        vec![delivery.clone()]
+    );
+
+    delivery.attempts = 1;
+    delivery.last_error = Some("HTTP 500".to_string());
+    delivery.next_attempt_date = Some(now + chrono::Duration::try_seconds(10).unwrap());
+    update_webhook_delivery(&conn, &delivery).await?;
+    assert!(get_webhook_deliveries_due(&conn, now, 10).await?.is_empty());
+
+    delivery.attempts = 2;
+    delivery.next_attempt_date = None;
+    delivery.delivered_date = Some(now + chrono::Duration::try_seconds(10).unwrap());
+    update_webhook_delivery(&conn, &delivery).await?;
+    assert!(get_webhook_deliveries_due(
+        &conn,
+        now + chrono::Duration::try_seconds(60).unwrap(),
+        10
+    )
+    .await?
+    .is_empty());
+    assert_eq!(get_next_webhook_attempt_date(&conn).await?, None);
+
+    let pending = insert_webhook_delivery(
+        &conn,
+        &WebhookDeliveryDbObj {
+            id: 0,
+            delivered_date: None,
+            next_attempt_date: Some(now),
+            ..delivery.clone()
+        },
+    )
+    .await?;
+    let later = now + chrono::Duration::try_seconds(30).unwrap();
+    assert_eq!(postpone_webhook_deliveries(&conn, "other", later).await?, 0);
+    assert_eq!(
+        postpone_webhook_deliveries(&conn, "accounting", later).await?,
+        1
+    );
+    assert_eq!(get_next_webhook_attempt_date(&conn).await?, Some(later));
+
+    // only delivered events are deleted
+    assert_eq!(
+        delete_webhook_deliveries_delivered_before(&conn, "accounting", later).await?,
+        1
+    );
+    assert_eq!(
+        get_webhook_deliveries_due(&conn, later, 10).await?,
+        vec![WebhookDeliveryDbObj {
+            next_attempt_date: Some(later),
+            ..pending
+        }]
+    );
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use super::{CustomError, TransactionFailedError};
 use crate::error::allowance::AllowanceRequest;
+use crate::model::TokenTransferDbObj;
 use crate::utils::ConversionError;
 use rustc_hex::FromHexError;
 use std::fmt::Display;
@@ -24,6 +25,10 @@ pub enum ErrorBag {
     NoAllowanceFound(AllowanceRequest),
     FromDecStrErr(FromDecStrErr),
     TimeLimitReached(std::time::Duration),
+    TransferAlreadyExists(Box<TokenTransferDbObj>),
+    /// Payment id of the missing transfer
+    TransferNotFound(String),
+    TransferNotQueued(Box<TokenTransferDbObj>),
 }
 
 impl Display for ErrorBag {
@@ -44,6 +49,21 @@ impl Display for ErrorBag {
             ErrorBag::NoAllowanceFound(allowance_request) => write!(f, "{allowance_request:?}"),
             ErrorBag::FromDecStrErr(from_dec_str_err) => write!(f, "{from_dec_str_err:?}"),
             ErrorBag::TimeLimitReached(duration) => write!(f, "Time limit reached: {duration:?}"),
+            ErrorBag::TransferAlreadyExists(token_transfer) => write!(
+                f,
+                "Transfer with payment id {} already exists (id: {})",
+                token_transfer.payment_id.as_deref().unwrap_or_default(),
+                token_transfer.id
+            ),
+            ErrorBag::TransferNotFound(payment_id) => {
+                write!(f, "Transfer {payment_id} not found")
+            }
+            ErrorBag::TransferNotQueued(token_transfer) => write!(
+                f,
+                "Transfer {} is not queued (status: {})",
+                token_transfer.payment_id.as_deref().unwrap_or_default(),
+                token_transfer.status
+            ),
         }
     }
 }
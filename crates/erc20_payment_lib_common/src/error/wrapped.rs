@@ -3,9 +3,10 @@ use std::error::Error;
 
 /// Error type build over ErrorBag, containing source code location and optional message
 /// Note that only creating via macro is possible to catch line and file
+/// Inner error is boxed to keep Result<_, PaymentError> small
 #[derive(Debug)]
 pub struct PaymentError {
-    pub inner: ErrorBag,
+    pub inner: Box<ErrorBag>,
     pub msg: Option<String>,
     pub file: &'static str,
     pub line: u32,
@@ -14,7 +15,7 @@ pub struct PaymentError {
 
 impl Error for PaymentError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.inner)
+        Some(self.inner.as_ref())
     }
 }
 
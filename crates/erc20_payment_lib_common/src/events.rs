@@ -1,7 +1,7 @@
 use crate::model::{AllowanceDbObj, TokenTransferDbObj, TxDbObj};
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use web3::types::Address;
 
@@ -29,9 +29,16 @@ pub enum StatusProperty {
     TxStuck {
         chain_id: i64,
     },
+    FeeBudgetExhausted {
+        chain_id: i64,
+        address: String,
+        period: String,
+        budget: Decimal,
+        spent: Decimal,
+    },
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SharedInfoTx {
     pub message: String,
     pub error: Option<String>,
@@ -62,6 +69,18 @@ pub struct NoGasDetails {
     pub gas_needed: Decimal,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeBudgetDetails {
+    pub tx: TxDbObj,
+    /// daily or monthly
+    pub period: String,
+    pub budget: Decimal,
+    /// Fees paid in the period and fees that pending transactions can still take
+    pub spent: Decimal,
+    pub tx_max_fee: Decimal,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NoTokenDetails {
@@ -79,6 +98,7 @@ pub enum TransactionStuckReason {
     NoToken(NoTokenDetails),
     GasPriceLow(GasLowInfo),
     RPCEndpointProblems(String),
+    FeeBudgetExceeded(FeeBudgetDetails),
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -94,6 +114,16 @@ pub struct TransactionFinishedInfo {
     pub tx_dao: TxDbObj,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReorgedInfo {
+    /// Transaction reverted to pending state, it will be broadcast again
+    pub tx_dao: TxDbObj,
+    /// Block the transaction was confirmed in before the reorg
+    pub block_number: i64,
+    pub block_hash: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Web3RpcPoolContent {
@@ -139,6 +169,7 @@ impl CantSignContent {
 pub enum DriverEventContent {
     Alive,
     TransactionConfirmed(TxDbObj),
+    TransactionReorged(TransactionReorgedInfo),
     TransferFinished(TransactionFinishedInfo),
     ApproveFinished(AllowanceDbObj),
     TransactionStuck(TransactionStuckReason),
@@ -155,6 +186,24 @@ pub struct DriverEvent {
     pub content: DriverEventContent,
 }
 
+impl DriverEventContent {
+    /// Name of the variant, used to subscribe to selected events
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DriverEventContent::Alive => "Alive",
+            DriverEventContent::TransactionConfirmed(_) => "TransactionConfirmed",
+            DriverEventContent::TransactionReorged(_) => "TransactionReorged",
+            DriverEventContent::TransferFinished(_) => "TransferFinished",
+            DriverEventContent::ApproveFinished(_) => "ApproveFinished",
+            DriverEventContent::TransactionStuck(_) => "TransactionStuck",
+            DriverEventContent::TransactionFailed(_) => "TransactionFailed",
+            DriverEventContent::CantSign(_) => "CantSign",
+            DriverEventContent::StatusChanged(_) => "StatusChanged",
+            DriverEventContent::Web3RpcMessage(_) => "Web3RpcMessage",
+        }
+    }
+}
+
 impl DriverEvent {
     pub fn now(content: DriverEventContent) -> Self {
         DriverEvent {
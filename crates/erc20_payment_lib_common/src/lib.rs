@@ -1,3 +1,4 @@
+pub mod api;
 mod db;
 pub mod error;
 mod events;
@@ -5,6 +6,6 @@ mod metrics;
 pub mod utils;
 
 pub use crate::metrics::*;
-pub use db::connection::create_sqlite_connection;
+pub use db::connection::*;
 pub use db::*;
 pub use events::*;
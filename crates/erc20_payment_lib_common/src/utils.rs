@@ -180,6 +180,7 @@ pub trait U256ConvExt {
     fn to_eth_str(&self) -> String;
     fn to_gwei_str_with_precision(&self, precision: u8) -> String;
     fn to_eth_str_with_precision(&self, precision: u8) -> String;
+    fn to_token_decimal(&self, decimals: u8) -> Result<Decimal, ConversionError>;
 }
 
 impl U256ConvExt for U256 {
@@ -207,11 +208,15 @@ impl U256ConvExt for U256 {
     fn to_eth_str_with_precision(&self, precision: u8) -> String {
         u256_to_decimal_string(*self, Decimals::Eighteen, Some(precision as usize))
     }
+    fn to_token_decimal(&self, decimals: u8) -> Result<Decimal, ConversionError> {
+        u256_to_rust_dec(*self, Some(decimals as u32))
+    }
 }
 
 pub trait StringConvExt {
     fn to_gwei(&self) -> Result<Decimal, ConversionError>;
     fn to_eth(&self) -> Result<Decimal, ConversionError>;
+    fn to_token_decimal(&self, decimals: u8) -> Result<Decimal, ConversionError>;
     fn to_u256(&self) -> Result<U256, ConversionError>;
 }
 impl StringConvExt for String {
@@ -221,6 +226,9 @@ impl StringConvExt for String {
     fn to_eth(&self) -> Result<Decimal, ConversionError> {
         self.to_u256()?.to_eth()
     }
+    fn to_token_decimal(&self, decimals: u8) -> Result<Decimal, ConversionError> {
+        self.to_u256()?.to_token_decimal(decimals)
+    }
 
     fn to_u256(&self) -> Result<U256, ConversionError> {
         U256::from_dec_str(self).map_err(|err| {
@@ -232,6 +240,7 @@ impl StringConvExt for String {
 pub trait DecimalConvExt {
     fn to_u256_from_gwei(&self) -> Result<U256, ConversionError>;
     fn to_u256_from_eth(&self) -> Result<U256, ConversionError>;
+    fn to_u256_from_token_decimal(&self, decimals: u8) -> Result<U256, ConversionError>;
 }
 
 impl DecimalConvExt for Decimal {
@@ -241,6 +250,9 @@ impl DecimalConvExt for Decimal {
     fn to_u256_from_eth(&self) -> Result<U256, ConversionError> {
         rust_dec_to_u256_strict(*self, Some(18))
     }
+    fn to_u256_from_token_decimal(&self, decimals: u8) -> Result<U256, ConversionError> {
+        rust_dec_to_u256_strict(*self, Some(decimals as u32))
+    }
 }
 
 fn u256_to_eth(amount: U256) -> Result<Decimal, ConversionError> {
@@ -458,4 +470,18 @@ mod tests {
         );
         assert_eq!(res, U256::from(2514264337593543950335_u128));
     }
+
+    #[test]
+    fn test_token_decimal_conversion() {
+        let amount = Decimal::from_str("12.345678").unwrap();
+        let res = amount.to_u256_from_token_decimal(6).unwrap();
+        assert_eq!(res, U256::from(12345678));
+        assert_eq!(res.to_token_decimal(6).unwrap(), amount);
+        assert_eq!(res.to_string().to_token_decimal(6).unwrap(), amount);
+
+        let res = Decimal::from_str("0.0000001")
+            .unwrap()
+            .to_u256_from_token_decimal(6);
+        assert!(res.err().unwrap().msg.contains("fractional"));
+    }
 }
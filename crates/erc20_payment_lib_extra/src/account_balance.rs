@@ -1,5 +1,5 @@
 use erc20_payment_lib::config;
-use erc20_payment_lib::eth::get_balance;
+use erc20_payment_lib::eth::{get_balances, get_token_decimals};
 use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib_common::err_custom_create;
 use erc20_payment_lib_common::error::*;
@@ -14,6 +14,9 @@ use stream_rate_limiter::{RateLimitOptions, StreamRateLimitExt};
 use structopt::StructOpt;
 use web3::types::Address;
 
+/// Accounts checked in one job, balances of them are fetched using batch requests
+const ACCOUNTS_PER_JOB: usize = 100;
+
 #[derive(Clone, StructOpt)]
 #[structopt(about = "Payment statistics options")]
 pub struct BalanceOptions {
@@ -30,12 +33,18 @@ pub struct BalanceOptions {
     #[structopt(long = "hide-token")]
     pub hide_token: bool,
 
+    ///token symbol or address, default token of the chain if not set
+    #[structopt(long = "token")]
+    pub token: Option<String>,
+
     #[structopt(long = "block-number")]
     pub block_number: Option<u64>,
 
+    ///number of jobs (up to 100 accounts each) running at once
     #[structopt(long = "tasks", default_value = "1")]
     pub tasks: usize,
 
+    ///min interval between jobs in seconds
     #[structopt(long = "interval")]
     pub interval: Option<f64>,
 }
@@ -67,10 +76,23 @@ pub async fn account_balance(
 
     let web3 = payment_setup.get_provider(chain_cfg.chain_id)?;
 
-    let token = if !account_balance_options.hide_token {
-        Some(chain_cfg.token.address)
+    let token_cfg = match &account_balance_options.token {
+        Some(token) => chain_cfg.find_token(token).ok_or(err_custom_create!(
+            "Token {} not found in config for chain {}",
+            token,
+            account_balance_options.chain_name
+        ))?,
+        None => &chain_cfg.token,
+    };
+
+    let (token, token_decimals) = if !account_balance_options.hide_token {
+        let decimals = match token_cfg.decimals {
+            Some(decimals) => decimals,
+            None => get_token_decimals(web3.clone(), token_cfg.address).await?,
+        };
+        (Some(token_cfg.address), decimals)
     } else {
-        None
+        (None, 18)
     };
 
     //deduplicate accounts using hashset
@@ -102,57 +124,81 @@ pub async fn account_balance(
         RateLimitOptions::empty()
     };
 
+    let multicall_address = chain_cfg.multicall_contract.as_ref().map(|m| m.address);
+    let jobs = jobs.chunks(ACCOUNTS_PER_JOB).collect::<Vec<_>>();
+    let first_error = Rc::new(RefCell::new(None::<PaymentError>));
+    let first_error_ = first_error.clone();
     stream::iter(0..jobs.len())
         .rate_limit(rate_limit_options)
         .for_each_concurrent(account_balance_options.tasks, |i| {
             let job = jobs[i];
             let result_map = result_map_.clone();
+            let first_error = first_error_.clone();
             let web3 = web3.clone();
             async move {
-                log::debug!("Getting balance for account: {:#x}", job);
-                let balance =
-                    get_balance(web3, token, job, !account_balance_options.hide_gas, None)
-                        .await
-                        .unwrap();
-
-                let gas_balance = balance.gas_balance.map(|b| b.to_string());
-                let token_balance = balance.token_balance.map(|b| b.to_string());
-                log::debug!("{:#x} gas: {:?}", job, gas_balance);
-                log::debug!("{:#x} token: {:?}", job, token_balance);
-                let gas_balance_decimal = balance
-                    .gas_balance
-                    .map(|v| v.to_eth().unwrap_or_default().to_string());
-                let token_balance_decimal = balance
-                    .token_balance
-                    .map(|v| v.to_eth().unwrap_or_default().to_string());
-                let gas_balance_human = gas_balance_decimal.clone().map(|v| {
-                    format!(
-                        "{:.03} {}",
-                        (f64::from_str(&v).unwrap_or(0.0) * 1000.0).floor() / 1000.0,
-                        &chain_cfg.currency_symbol
-                    )
-                });
-                let token_balance_human = token_balance_decimal.clone().map(|v| {
-                    format!(
-                        "{:.03} {}",
-                        (f64::from_str(&v).unwrap_or(0.0) * 1000.0).floor() / 1000.0,
-                        &chain_cfg.token.symbol
-                    )
-                });
-                result_map.borrow_mut().insert(
-                    format!("{:#x}", job),
-                    BalanceResult {
-                        gas: gas_balance,
-                        gas_decimal: gas_balance_decimal,
-                        gas_human: gas_balance_human,
-                        token: token_balance,
-                        token_decimal: token_balance_decimal,
-                        token_human: token_balance_human,
-                    },
-                );
+                log::debug!("Getting balance for {} accounts", job.len());
+                let balances = match get_balances(
+                    web3,
+                    multicall_address,
+                    token,
+                    job,
+                    !account_balance_options.hide_gas,
+                    account_balance_options.block_number,
+                )
+                .await
+                {
+                    Ok(balances) => balances,
+                    Err(err) => {
+                        first_error.borrow_mut().get_or_insert(err);
+                        return;
+                    }
+                };
+
+                for (account, balance) in job.iter().zip(balances) {
+                    let gas_balance = balance.gas_balance.map(|b| b.to_string());
+                    let token_balance = balance.token_balance.map(|b| b.to_string());
+                    log::debug!("{:#x} gas: {:?}", account, gas_balance);
+                    log::debug!("{:#x} token: {:?}", account, token_balance);
+                    let gas_balance_decimal = balance
+                        .gas_balance
+                        .map(|v| v.to_eth().unwrap_or_default().to_string());
+                    let token_balance_decimal = balance.token_balance.map(|v| {
+                        v.to_token_decimal(token_decimals)
+                            .unwrap_or_default()
+                            .to_string()
+                    });
+                    let gas_balance_human = gas_balance_decimal.clone().map(|v| {
+                        format!(
+                            "{:.03} {}",
+                            (f64::from_str(&v).unwrap_or(0.0) * 1000.0).floor() / 1000.0,
+                            &chain_cfg.currency_symbol
+                        )
+                    });
+                    let token_balance_human = token_balance_decimal.clone().map(|v| {
+                        format!(
+                            "{:.03} {}",
+                            (f64::from_str(&v).unwrap_or(0.0) * 1000.0).floor() / 1000.0,
+                            &token_cfg.symbol
+                        )
+                    });
+                    result_map.borrow_mut().insert(
+                        format!("{:#x}", account),
+                        BalanceResult {
+                            gas: gas_balance,
+                            gas_decimal: gas_balance_decimal,
+                            gas_human: gas_balance_human,
+                            token: token_balance,
+                            token_decimal: token_balance_decimal,
+                            token_human: token_balance_human,
+                        },
+                    );
+                }
             }
         })
         .await;
 
+    if let Some(err) = first_error.take() {
+        return Err(err);
+    }
     Ok(result_map.take())
 }
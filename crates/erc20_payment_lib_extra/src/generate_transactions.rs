@@ -6,10 +6,10 @@ use erc20_payment_lib_common::error::*;
 use erc20_payment_lib_common::ops::insert_token_transfer;
 
 use erc20_payment_lib::config;
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::*;
 use futures_util::StreamExt;
 use futures_util::TryStreamExt;
-use sqlx::SqlitePool;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
@@ -63,7 +63,7 @@ pub async fn generate_test_payments(
     generate_options: GenerateOptions,
     config: &config::Config,
     from_addrs: Vec<Address>,
-    sqlite_pool: Option<SqlitePool>,
+    sqlite_pool: Option<DbPool>,
 ) -> Result<(), PaymentError> {
     let chain_cfg = config
         .chain
@@ -166,7 +166,7 @@ pub async fn generate_test_payments(
             if let Some(conn) = conn {
                 let mut t = conn.begin().await.unwrap();
 
-                insert_token_transfer(&mut *t, &token_transfer)
+                insert_token_transfer(&mut t, &token_transfer)
                     .await
                     .map_err(|err| {
                         err_custom_create!(
@@ -183,7 +183,7 @@ pub async fn generate_test_payments(
         Ok(_) => {
             log::info!("All transactions generated successfully");
         }
-        Err(err) => match err.inner {
+        Err(err) => match *err.inner {
             ErrorBag::TimeLimitReached(d) => {
                 log::info!("Time limit reached: {} seconds, exiting", d.as_secs_f64());
             }
@@ -1,10 +1,9 @@
 use erc20_payment_lib::config;
 use erc20_payment_lib::config::{Chain, Config, Engine, MultiContractSettings, RpcSettings, Token};
-use erc20_payment_lib_common::create_sqlite_connection;
 use erc20_payment_lib_common::utils::get_env_bool_value;
+use erc20_payment_lib_common::{create_sqlite_connection, DbPool};
 use rust_decimal::prelude::FromPrimitive;
 use rust_decimal::Decimal;
-use sqlx::SqlitePool;
 use std::collections::BTreeMap;
 use std::env;
 use std::path::Path;
@@ -24,9 +23,15 @@ pub async fn create_default_config_setup(proxy_url_base: &str, proxy_key: &str)
             backup_level: None,
             verify_interval_secs: None,
             min_interval_ms: None,
+            max_requests_per_second: None,
+            daily_request_quota: None,
             max_timeout_ms: None,
             allowed_head_behind_secs: Some(200000000000),
             max_consecutive_errors: None,
+            max_batch_size: None,
+            quorum_size: None,
+            quorum_min_agree: None,
+            quorum_methods: None,
         }],
         currency_symbol: "tETH".to_string(),
         priority_fee: Decimal::from_f64(1.1).unwrap(),
@@ -34,27 +39,37 @@ pub async fn create_default_config_setup(proxy_url_base: &str, proxy_key: &str)
         token: Token {
             symbol: "tGLM".to_string(),
             address: Address::from_str("0xfff17584d526aba263025eE7fEF517E4A31D4246").unwrap(),
+            decimals: Some(18),
             faucet: None,
         },
+        tokens: vec![],
         multi_contract: Some(MultiContractSettings {
             address: Address::from_str("0xF9861F83766CD507E0d2749B60d4fD6C68E5B96C").unwrap(),
             max_at_once: 10,
         }),
         mint_contract: None,
         lock_contract: None,
+        multicall_contract: None,
+        meta_transaction: None,
+        fee_policy: None,
         faucet_client: None,
         transaction_timeout: 25,
         confirmation_blocks: 1,
+        finality_blocks: None,
         faucet_eth_amount: Some(Decimal::from_f64(10.0).unwrap()),
         faucet_glm_amount: Some(Decimal::from_f64(20.0).unwrap()),
         block_explorer_url: Some("http://127.0.0.1:4000".to_string()),
         replacement_timeout: Some(1.0),
         external_source_check_interval: None,
+        broadcast_to_all_endpoints: None,
     };
     let mut chain_map = BTreeMap::new();
     chain_map.insert("dev".to_string(), chain);
     Config {
         chain: chain_map,
+        remote_signer: BTreeMap::new(),
+        api_auth: None,
+        webhook: BTreeMap::new(),
         engine: Engine {
             process_interval: 1,
             process_interval_after_error: 1,
@@ -68,12 +83,15 @@ pub async fn create_default_config_setup(proxy_url_base: &str, proxy_key: &str)
             automatic_recover: false,
             gather_at_start: false,
             ignore_deadlines: false,
+            batching: Default::default(),
+            rpc_stats: Default::default(),
+            event_log: Default::default(),
         },
     }
 }
 
 /// Convenient function for use in testing
-pub async fn setup_random_memory_sqlite_conn() -> SqlitePool {
+pub async fn setup_random_memory_sqlite_conn() -> DbPool {
     use rand::distributions::Alphanumeric;
     use rand::Rng;
 
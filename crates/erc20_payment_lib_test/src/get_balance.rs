@@ -13,6 +13,7 @@ pub async fn test_get_balance(
         accounts: Some(accounts.to_string()),
         hide_gas: false,
         hide_token: false,
+        token: None,
         block_number: None,
         tasks: 4,
         interval: Some(0.001),
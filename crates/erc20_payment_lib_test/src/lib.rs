@@ -3,6 +3,7 @@ mod blockchain_setup;
 mod config_setup;
 mod durabily2;
 mod get_balance;
+mod local_signer;
 mod multi_erc20_transfer;
 mod multi_test_one_docker_helper;
 mod one_docker_per_test_helper;
@@ -13,6 +14,7 @@ pub use config_setup::create_default_config_setup;
 pub use config_setup::setup_random_memory_sqlite_conn;
 pub use durabily2::test_durability2;
 pub use get_balance::test_get_balance;
+pub use local_signer::LocalSignerServer;
 pub use multi_erc20_transfer::test_durability;
 pub use multi_test_one_docker_helper::common_geth_init;
 pub use one_docker_per_test_helper::exclusive_geth_init;
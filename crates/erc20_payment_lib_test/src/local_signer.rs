@@ -0,0 +1,131 @@
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use erc20_payment_lib::eth::get_eth_addr_from_secret;
+use erc20_payment_lib::signer::{PrivateKeySigner, Signer};
+use secp256k1::SecretKey;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use web3::types::{AccessList, Address, Bytes, TransactionParameters, U256, U64};
+
+#[derive(Deserialize)]
+struct JsonRpcRequest {
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignTransactionRequest {
+    from: Address,
+    to: Option<Address>,
+    gas: U256,
+    gas_price: Option<U256>,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    value: U256,
+    data: Bytes,
+    nonce: U256,
+    chain_id: U256,
+    #[serde(rename = "type")]
+    transaction_type: Option<U64>,
+    access_list: Option<AccessList>,
+}
+
+struct LocalSignerState {
+    accounts: Vec<Address>,
+    signer: PrivateKeySigner,
+}
+
+async fn handle_request(
+    state: &LocalSignerState,
+    method: &str,
+    params: Vec<Value>,
+) -> Result<Value, String> {
+    match method {
+        "eth_accounts" => Ok(json!(state.accounts)),
+        "eth_signTransaction" => {
+            let req: SignTransactionRequest =
+                serde_json::from_value(params.into_iter().next().ok_or("Missing transaction")?)
+                    .map_err(|err| format!("Invalid transaction: {err}"))?;
+            let tp = TransactionParameters {
+                nonce: Some(req.nonce),
+                to: req.to,
+                gas: req.gas,
+                gas_price: req.gas_price,
+                value: req.value,
+                data: req.data,
+                chain_id: Some(req.chain_id.as_u64()),
+                transaction_type: req.transaction_type,
+                access_list: req.access_list,
+                max_fee_per_gas: req.max_fee_per_gas,
+                max_priority_fee_per_gas: req.max_priority_fee_per_gas,
+            };
+            let signed = state
+                .signer
+                .sign(req.from, tp)
+                .await
+                .map_err(|err| err.message)?;
+            Ok(json!(signed.raw_transaction))
+        }
+        _ => Err(format!("Method {method} not supported")),
+    }
+}
+
+async fn json_rpc(
+    state: web::Data<LocalSignerState>,
+    body: web::Json<JsonRpcRequest>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    match handle_request(&state, &body.method, body.params).await {
+        Ok(result) => HttpResponse::Ok().json(json!({
+            "jsonrpc": "2.0",
+            "id": body.id,
+            "result": result,
+        })),
+        Err(message) => HttpResponse::Ok().json(json!({
+            "jsonrpc": "2.0",
+            "id": body.id,
+            "error": { "code": -32000, "message": message },
+        })),
+    }
+}
+
+/// Stand-in for Web3Signer holding keys in memory, used to test remote signing
+pub struct LocalSignerServer {
+    pub url: String,
+    handle: ServerHandle,
+}
+
+impl LocalSignerServer {
+    pub async fn start(secret_keys: Vec<SecretKey>) -> Result<Self, anyhow::Error> {
+        let state = web::Data::new(LocalSignerState {
+            accounts: secret_keys.iter().map(get_eth_addr_from_secret).collect(),
+            signer: PrivateKeySigner::new(secret_keys),
+        });
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .route("/", web::post().to(json_rpc))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))?;
+        let port = server
+            .addrs()
+            .first()
+            .ok_or(anyhow::anyhow!("Local signer not bound"))?
+            .port();
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        Ok(Self {
+            url: format!("http://127.0.0.1:{port}"),
+            handle,
+        })
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
@@ -1,6 +1,10 @@
 mod rpc_pool;
 
 pub use rpc_pool::resolve_txt_record_to_string_array;
+pub use rpc_pool::EndpointBroadcastResult;
+pub use rpc_pool::EndpointRateLimiter;
+pub use rpc_pool::RateLimitBudget;
+pub use rpc_pool::RpcErrorKind;
 pub use rpc_pool::VerifyEndpointResult;
 pub use rpc_pool::VerifyEndpointStatus;
 pub use rpc_pool::Web3EndpointParams;
@@ -14,4 +18,7 @@ pub use rpc_pool::Web3RpcEndpoint;
 pub use rpc_pool::Web3RpcInfo;
 pub use rpc_pool::Web3RpcParams;
 pub use rpc_pool::Web3RpcPool;
+pub use rpc_pool::Web3RpcQuorum;
 pub use rpc_pool::Web3RpcSingleParams;
+pub use rpc_pool::Web3Transport;
+pub use rpc_pool::DEFAULT_MAX_BATCH_SIZE;
@@ -0,0 +1,147 @@
+use crate::rpc_pool::rpc_error::RpcErrorKind;
+use crate::rpc_pool::VerifyEndpointResult;
+use crate::Web3RpcPool;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use web3::types::{Bytes, TransactionId, H256};
+
+const METHOD: &str = "send_raw_transaction";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointBroadcastResult {
+    pub endpoint: String,
+    pub accepted: bool,
+    pub message: Option<String>,
+}
+
+impl Web3RpcPool {
+    /// Sends signed transaction to all allowed endpoints at once.
+    /// Endpoint accepted the transaction if it returned hash, reported it as already known
+    /// or reported nonce too low while knowing the transaction with the same hash.
+    /// Result is Ok if at least one endpoint accepted the transaction.
+    pub async fn eth_broadcast_raw_transaction(
+        self: Arc<Self>,
+        rlp: Bytes,
+        tx_hash: H256,
+    ) -> (Result<(), web3::Error>, Vec<EndpointBroadcastResult>) {
+        let endpoints = self.clone().choose_best_endpoints().await.allowed_endpoints;
+        if endpoints.is_empty() {
+            log::warn!(
+                "No valid endpoints found for chain id {}, cannot broadcast transaction",
+                self.chain_id
+            );
+            return (Err(web3::Error::Unreachable), Vec::new());
+        }
+
+        let calls = endpoints.iter().filter_map(|idx| {
+            let idx = *idx;
+            let web3 = self.get_web3(idx)?;
+            let rlp = rlp.clone();
+            // request timeout is applied by the transport, Unreachable is returned when it passes
+            Some(async move {
+                let res = match web3.eth().send_raw_transaction(rlp).await {
+                    Err(web3::Error::Rpc(e))
+                        if RpcErrorKind::from_rpc_error(&e) == RpcErrorKind::NonceTooLow =>
+                    {
+                        match web3.eth().transaction(TransactionId::Hash(tx_hash)).await {
+                            Ok(Some(_)) => Ok(tx_hash),
+                            _ => Err(web3::Error::Rpc(e)),
+                        }
+                    }
+                    res => res,
+                };
+                (idx, res)
+            })
+        });
+
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        for (idx, res) in futures::future::join_all(calls).await {
+            let name = self.get_name(idx);
+            let result = match res {
+                Ok(_) => {
+                    self.mark_rpc_success(idx, METHOD.to_string());
+                    EndpointBroadcastResult {
+                        endpoint: name,
+                        accepted: true,
+                        message: None,
+                    }
+                }
+                Err(web3::Error::Rpc(e))
+                    if RpcErrorKind::from_rpc_error(&e) == RpcErrorKind::AlreadyKnown =>
+                {
+                    self.mark_rpc_success(idx, METHOD.to_string());
+                    EndpointBroadcastResult {
+                        endpoint: name,
+                        accepted: true,
+                        message: Some(e.message),
+                    }
+                }
+                Err(web3::Error::Rpc(e)) => {
+                    let kind = RpcErrorKind::from_rpc_error(&e);
+                    log::warn!("Endpoint {} rejected transaction ({:?}): {}", name, kind, e);
+                    if !kind.is_endpoint_fault() {
+                        self.mark_rpc_success(idx, METHOD.to_string());
+                    } else {
+                        self.mark_rpc_error(
+                            idx,
+                            METHOD.to_string(),
+                            VerifyEndpointResult::RpcWeb3Error(e.to_string()),
+                        );
+                    }
+                    let message = Some(e.message.clone());
+                    errors.push(web3::Error::Rpc(e));
+                    EndpointBroadcastResult {
+                        endpoint: name,
+                        accepted: false,
+                        message,
+                    }
+                }
+                Err(web3::Error::Unreachable) => {
+                    log::warn!("Timeout when sending transaction to endpoint {}", name);
+                    self.mark_rpc_error(idx, METHOD.to_string(), VerifyEndpointResult::Unreachable);
+                    errors.push(web3::Error::Unreachable);
+                    EndpointBroadcastResult {
+                        endpoint: name,
+                        accepted: false,
+                        message: Some("Timeout".to_string()),
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Error sending transaction to endpoint {}: {}", name, e);
+                    self.mark_rpc_error(
+                        idx,
+                        METHOD.to_string(),
+                        VerifyEndpointResult::OtherNetworkError(e.to_string()),
+                    );
+                    let message = Some(e.to_string());
+                    errors.push(e);
+                    EndpointBroadcastResult {
+                        endpoint: name,
+                        accepted: false,
+                        message,
+                    }
+                }
+            };
+            results.push(result);
+        }
+
+        let accepted = results.iter().filter(|r| r.accepted).count();
+        log::info!(
+            "Transaction {:#x} accepted by {} of {} endpoints",
+            tx_hash,
+            accepted,
+            results.len()
+        );
+        if accepted > 0 {
+            return (Ok(()), results);
+        }
+        // rpc errors carry the reason of the rejection, so prefer them over network errors
+        let err = match errors.iter().position(|e| matches!(e, web3::Error::Rpc(_))) {
+            Some(pos) => errors.swap_remove(pos),
+            None => errors.pop().unwrap_or(web3::Error::Unreachable),
+        };
+        (Err(err), results)
+    }
+}
@@ -34,4 +34,11 @@ impl Web3RpcPool {
             (block,)
         ).await
     }
+
+    pub async fn eth_block_batch(
+        self: Arc<Self>,
+        args: Vec<(BlockId,)>,
+    ) -> Result<Vec<Result<Option<Block<H256>>, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthBlock>(args).await
+    }
 }
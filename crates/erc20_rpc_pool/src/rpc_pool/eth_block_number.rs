@@ -34,4 +34,11 @@ impl Web3RpcPool {
             ()
         ).await
     }
+
+    pub async fn eth_block_number_batch(
+        self: Arc<Self>,
+        args: Vec<()>,
+    ) -> Result<Vec<Result<U64, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthBlockNumber>(args).await
+    }
 }
@@ -35,4 +35,11 @@ impl Web3RpcPool {
             (call_data.clone(), block)
         ).await
     }
+
+    pub async fn eth_call_batch(
+        self: Arc<Self>,
+        args: Vec<(CallRequest, Option<BlockId>)>,
+    ) -> Result<Vec<Result<Bytes, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthCall>(args).await
+    }
 }
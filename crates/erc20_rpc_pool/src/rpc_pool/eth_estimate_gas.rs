@@ -35,4 +35,11 @@ impl Web3RpcPool {
             (call_data.clone(), block)
         ).await
     }
+
+    pub async fn eth_estimate_gas_batch(
+        self: Arc<Self>,
+        args: Vec<(CallRequest, Option<BlockNumber>)>,
+    ) -> Result<Vec<Result<U256, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthEstimateGas>(args).await
+    }
 }
@@ -0,0 +1,46 @@
+// Wrapper generated using python gen_methods.py
+// Do not modify this file directly
+
+use super::eth_generic_call::EthMethod;
+use super::Web3RpcPool;
+use std::sync::Arc;
+use web3::api::Eth;
+use web3::helpers::CallFuture;
+use web3::types::*;
+
+pub struct EthFeeHistory;
+
+#[rustfmt::skip]
+impl<T: web3::Transport> EthMethod<T> for EthFeeHistory {
+    const METHOD: &'static str = "fee_history";
+    type Args = (U256, BlockNumber, Option<Vec<f64>>);
+    type Return = FeeHistory;
+
+    fn do_call(
+        eth: Eth<T>,
+        args: Self::Args,
+    ) -> CallFuture<Self::Return, <T as web3::Transport>::Out> {
+        eth.fee_history(args.0, args.1, args.2)
+    }
+}
+
+#[rustfmt::skip]
+impl Web3RpcPool {
+    pub async fn eth_fee_history(
+        self: Arc<Self>,
+        block_count: U256,
+        newest_block: BlockNumber,
+        reward_percentiles: Option<Vec<f64>>,
+    ) -> Result<FeeHistory, web3::Error> {
+        self.eth_generic_call::<EthFeeHistory>(
+            (block_count, newest_block, reward_percentiles)
+        ).await
+    }
+
+    pub async fn eth_fee_history_batch(
+        self: Arc<Self>,
+        args: Vec<(U256, BlockNumber, Option<Vec<f64>>)>,
+    ) -> Result<Vec<Result<FeeHistory, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthFeeHistory>(args).await
+    }
+}
@@ -0,0 +1,177 @@
+use crate::rpc_pool::eth_generic_call::EthMethod;
+use crate::rpc_pool::rpc_error::RpcErrorKind;
+use crate::rpc_pool::transport::Web3Transport;
+use crate::rpc_pool::VerifyEndpointResult;
+use crate::Web3RpcPool;
+use std::sync::Arc;
+use std::time::Duration;
+use web3::transports::Batch;
+use web3::Web3;
+
+type CallResult<T> = Result<T, web3::Error>;
+
+/// Result of the call is final if endpoint answered properly, even if the answer is an error
+fn is_final<T>(res: &CallResult<T>) -> bool {
+    match res {
+        Ok(_) => true,
+        Err(err) => {
+            RpcErrorKind::from_web3_error(err).is_some_and(|kind| !kind.is_endpoint_fault())
+        }
+    }
+}
+
+impl Web3RpcPool {
+    /// Sends single batch request to the endpoint, Err if whole batch failed
+    async fn eth_batch_call_on_endpoint<EthMethodCall: EthMethod<Batch<Web3Transport>>>(
+        &self,
+        web3: &Web3<Web3Transport>,
+        args: Vec<EthMethodCall::Args>,
+    ) -> CallResult<Vec<CallResult<EthMethodCall::Return>>> {
+        let batch = Batch::new(web3.transport().clone());
+        let eth = Web3::new(batch.clone()).eth();
+        let calls = args
+            .into_iter()
+            .map(|args| EthMethodCall::do_call(eth.clone(), args))
+            .collect::<Vec<_>>();
+        // timeout is applied by the transport, it returns Unreachable when it passes
+        batch.submit_batch().await?;
+        Ok(futures::future::join_all(calls).await)
+    }
+
+    /// Calls method for every element of args using JSON-RPC batches limited by max batch size
+    /// of the endpoint. Calls failing because of the endpoint (timeouts, rate limits, etc.)
+    /// are repeated on other endpoints, errors caused by the call itself are returned
+    /// in place of the result.
+    pub async fn eth_generic_batch_call<EthMethodCall: EthMethod<Batch<Web3Transport>>>(
+        self: Arc<Self>,
+        args: Vec<EthMethodCall::Args>,
+    ) -> Result<Vec<CallResult<EthMethodCall::Return>>, web3::Error> {
+        if args.is_empty() {
+            return Ok(Vec::new());
+        }
+        let method = EthMethodCall::METHOD;
+        let mut results: Vec<Option<CallResult<EthMethodCall::Return>>> =
+            args.iter().map(|_| None).collect();
+        let mut last_error = web3::Error::Unreachable;
+        let mut loop_no = 0;
+        const LOOP_COUNT: usize = 4;
+        loop {
+            let resp = self.clone().choose_best_endpoints().await;
+            if resp.allowed_endpoints.is_empty() && !resp.is_resolving {
+                log::warn!(
+                    "No valid endpoints found for chain id {}, wait until next check",
+                    self.chain_id
+                );
+                return Err(web3::Error::Unreachable);
+            }
+            if let Some(idx_chosen) = resp.allowed_endpoints.first() {
+                self.mark_rpc_chosen(*idx_chosen);
+            }
+
+            for idx in resp.allowed_endpoints {
+                let Some(web3) = self.get_web3(idx) else {
+                    continue;
+                };
+                let pending = (0..results.len())
+                    .filter(|pos| results[*pos].is_none())
+                    .collect::<Vec<_>>();
+                if pending.is_empty() {
+                    break;
+                }
+                let max_batch_size = self.get_max_batch_size(idx).max(1);
+                for chunk in pending.chunks(max_batch_size) {
+                    let chunk_args = chunk.iter().map(|pos| args[*pos].clone()).collect();
+                    let chunk_results = match self
+                        .eth_batch_call_on_endpoint::<EthMethodCall>(&web3, chunk_args)
+                        .await
+                    {
+                        Ok(chunk_results) => chunk_results,
+                        Err(err) => {
+                            log::warn!(
+                                "Batch call {} ({} calls) failed on endpoint {}: {}",
+                                method,
+                                chunk.len(),
+                                self.get_name(idx),
+                                err
+                            );
+                            let verify_result = match &err {
+                                web3::Error::Unreachable => VerifyEndpointResult::Unreachable,
+                                web3::Error::Rpc(e) => {
+                                    VerifyEndpointResult::RpcWeb3Error(e.to_string())
+                                }
+                                e => VerifyEndpointResult::OtherNetworkError(e.to_string()),
+                            };
+                            self.mark_rpc_error(idx, method.to_string(), verify_result);
+                            last_error = err;
+                            break;
+                        }
+                    };
+
+                    let mut endpoint_error = None;
+                    for (pos, res) in chunk.iter().zip(chunk_results) {
+                        if is_final(&res) {
+                            results[*pos] = Some(res);
+                        } else if let Err(err) = res {
+                            endpoint_error = Some(err);
+                        }
+                    }
+                    if let Some(err) = endpoint_error {
+                        log::warn!(
+                            "Batch call {} on endpoint {} partially failed: {}",
+                            method,
+                            self.get_name(idx),
+                            err
+                        );
+                        self.mark_rpc_error(
+                            idx,
+                            method.to_string(),
+                            VerifyEndpointResult::RpcWeb3Error(err.to_string()),
+                        );
+                        last_error = err;
+                        break;
+                    }
+                    self.mark_rpc_success(idx, method.to_string());
+                }
+            }
+
+            if results.iter().all(|res| res.is_some()) {
+                return Ok(results.into_iter().flatten().collect());
+            }
+            if loop_no >= LOOP_COUNT {
+                log::warn!(
+                    "Batch call {} failed on all endpoints - chain id: {}",
+                    method,
+                    self.chain_id
+                );
+                return Ok(results
+                    .into_iter()
+                    .map(|res| res.unwrap_or_else(|| Err(last_error.clone())))
+                    .collect());
+            }
+            // sleep for 800, 1200, 2000, 2800 ms - total max sleep time is 6800 ms
+            let sleep_times: [u64; LOOP_COUNT] = [800, 1200, 2000, 2800];
+            tokio::time::sleep(Duration::from_millis(sleep_times[loop_no])).await;
+            loop_no += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_result_is_final() {
+        let revert = jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(3),
+            message: "execution reverted".to_string(),
+            data: None,
+        };
+        assert!(is_final(&Ok(())));
+        assert!(is_final::<()>(&Err(web3::Error::Rpc(revert))));
+        assert!(!is_final::<()>(&Err(web3::Error::Rpc(
+            jsonrpc_core::Error::internal_error()
+        ))));
+        assert!(!is_final::<()>(&Err(web3::Error::Unreachable)));
+    }
+}
@@ -1,10 +1,12 @@
-use crate::rpc_pool::web3_error_list::check_if_proper_rpc_error;
+use crate::rpc_pool::rpc_error::RpcErrorKind;
+use crate::rpc_pool::transport::Web3Transport;
 use crate::rpc_pool::VerifyEndpointResult;
 use crate::Web3RpcPool;
 use erc20_payment_lib_common::{
     DriverEvent, DriverEventContent, Web3RpcPoolContent, Web3RpcPoolInfo,
 };
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::sync::Arc;
 use std::time::Duration;
 use web3::{api::Eth, helpers::CallFuture};
@@ -12,13 +14,13 @@ use web3::{api::Eth, helpers::CallFuture};
 pub trait EthMethod<T: web3::Transport> {
     const METHOD: &'static str;
     type Args: Clone;
-    type Return: DeserializeOwned;
+    type Return: DeserializeOwned + Serialize;
 
     fn do_call(eth: Eth<T>, args: Self::Args) -> CallFuture<Self::Return, T::Out>;
 }
 
 impl Web3RpcPool {
-    pub async fn eth_generic_call<EthMethodCall: EthMethod<web3::transports::Http>>(
+    pub async fn eth_generic_call<EthMethodCall: EthMethod<Web3Transport>>(
         self: Arc<Self>,
         args: EthMethodCall::Args,
     ) -> Result<EthMethodCall::Return, web3::Error> {
@@ -63,12 +65,44 @@ impl Web3RpcPool {
                 continue;
             }
 
-            for idx in idx_vec {
+            if let Some(quorum) = self.get_quorum_for_method(EthMethodCall::METHOD) {
+                let err = match self
+                    .eth_quorum_call::<EthMethodCall>(&idx_vec, &quorum, args.clone())
+                    .await
+                {
+                    Ok(res) => return Ok(res),
+                    Err(err) => err,
+                };
+                if loop_no >= LOOP_COUNT {
+                    if let Some(event_sender) =
+                        self.event_sender.clone().and_then(|es| es.upgrade())
+                    {
+                        let _ = event_sender
+                            .send(DriverEvent {
+                                create_date: chrono::Utc::now(),
+                                content: DriverEventContent::Web3RpcMessage(Web3RpcPoolInfo {
+                                    chain_id: self.chain_id,
+                                    content: Web3RpcPoolContent::Error(format!(
+                                        "Web3 rpc quorum call failed {}",
+                                        err
+                                    )),
+                                }),
+                            })
+                            .await;
+                    }
+                    return Err(err);
+                }
+                let sleep_times: [u64; LOOP_COUNT] = [800, 1200, 2000, 2800];
+                tokio::time::sleep(Duration::from_millis(sleep_times[loop_no])).await;
+                loop_no += 1;
+                continue;
+            }
+
+            let endpoint_count = idx_vec.len();
+            for (pos, idx) in idx_vec.into_iter().enumerate() {
+                // request timeout is applied by the transport after waiting for the rate limiter
                 let res = match self.get_web3(idx) {
-                    Some(web3) => tokio::time::timeout(
-                        self.get_max_timeout(idx),
-                        EthMethodCall::do_call(web3.eth(), args.clone()),
-                    ),
+                    Some(web3) => EthMethodCall::do_call(web3.eth(), args.clone()),
                     None => {
                         //this case is possible if endpoint is removed from pool, just skip it and try next one
                         log::warn!("No web3 instance found on specified index");
@@ -77,7 +111,7 @@ impl Web3RpcPool {
                 };
 
                 let err = match res.await {
-                    Ok(Ok(balance)) => {
+                    Ok(balance) => {
                         self.mark_rpc_success(idx, EthMethodCall::METHOD.to_string());
                         if let Some(event_sender) =
                             self.event_sender.clone().and_then(|es| es.upgrade())
@@ -94,10 +128,17 @@ impl Web3RpcPool {
                         }
                         return Ok(balance);
                     }
-                    Ok(Err(e)) => match e {
+                    Err(e) => match e {
                         web3::Error::Rpc(e) => {
-                            let proper = check_if_proper_rpc_error(e.to_string());
-                            if proper {
+                            let kind = RpcErrorKind::from_rpc_error(&e);
+                            if !kind.is_endpoint_fault() {
+                                log::debug!(
+                                    "Call {} - endpoint {} returned {:?}: {}",
+                                    EthMethodCall::METHOD,
+                                    self.get_name(idx),
+                                    kind,
+                                    e
+                                );
                                 self.mark_rpc_success(idx, EthMethodCall::METHOD.to_string());
                                 if let Some(event_sender) =
                                     self.event_sender.clone().and_then(|es| es.upgrade())
@@ -116,7 +157,7 @@ impl Web3RpcPool {
                                 }
                                 return Err(web3::Error::Rpc(e));
                             } else {
-                                log::warn!("Unknown RPC error: {}", e);
+                                log::warn!("RPC error ({:?}): {}", kind, e);
                                 self.mark_rpc_error(
                                     idx,
                                     EthMethodCall::METHOD.to_string(),
@@ -125,6 +166,18 @@ impl Web3RpcPool {
                                 web3::Error::Rpc(e)
                             }
                         }
+                        web3::Error::Unreachable => {
+                            log::warn!(
+                                "Timeout when getting data from endpoint {}",
+                                self.get_name(idx)
+                            );
+                            self.mark_rpc_error(
+                                idx,
+                                EthMethodCall::METHOD.to_string(),
+                                VerifyEndpointResult::Unreachable,
+                            );
+                            web3::Error::Unreachable
+                        }
                         _ => {
                             log::warn!(
                                 "Error doing call {} from endpoint {}: {}",
@@ -140,20 +193,13 @@ impl Web3RpcPool {
                             e
                         }
                     },
-                    Err(e) => {
-                        log::warn!(
-                            "Timeout when getting data from endpoint {}: {}",
-                            self.get_name(idx),
-                            e
-                        );
-                        self.mark_rpc_error(
-                            idx,
-                            EthMethodCall::METHOD.to_string(),
-                            VerifyEndpointResult::Unreachable,
-                        );
-                        web3::Error::Unreachable
-                    }
                 };
+                // rate limited endpoint is backing off, try next one without waiting
+                if RpcErrorKind::from_web3_error(&err) == Some(RpcErrorKind::RateLimited)
+                    && pos + 1 < endpoint_count
+                {
+                    continue;
+                }
                 if loop_no >= LOOP_COUNT {
                     if let Some(event_sender) =
                         self.event_sender.clone().and_then(|es| es.upgrade())
@@ -181,3 +227,102 @@ impl Web3RpcPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Web3EndpointParams, Web3RpcInfo, Web3RpcSingleParams, DEFAULT_MAX_BATCH_SIZE};
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    fn endpoint_params(url: String, max_requests_per_second: Option<f64>) -> Web3RpcSingleParams {
+        Web3RpcSingleParams {
+            chain_id: 1,
+            name: url.clone(),
+            endpoint: url,
+            web3_endpoint_params: Web3EndpointParams {
+                backup_level: 0,
+                skip_validation: true,
+                max_number_of_consecutive_errors: 5,
+                verify_interval_secs: 120,
+                min_interval_requests_ms: None,
+                max_requests_per_second,
+                daily_request_quota: None,
+                max_head_behind_secs: None,
+                max_response_time_ms: 200,
+                max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            },
+            source_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_bucket_does_not_eat_timeout() {
+        let server = HttpServer::new(|| {
+            App::new().default_service(web::to(|body: web::Json<serde_json::Value>| async move {
+                HttpResponse::Ok().json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": body["id"],
+                    "result": "0x2a"
+                }))
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        tokio::spawn(server.run());
+
+        let limited_url = format!("http://127.0.0.1:{port}/limited");
+        let pool = Web3RpcPool::new(
+            1,
+            vec![
+                endpoint_params(limited_url.clone(), Some(0.1)),
+                endpoint_params(format!("http://127.0.0.1:{port}/free"), None),
+            ],
+            vec![],
+            vec![],
+            None,
+            Duration::from_secs(10),
+            Duration::from_secs(300),
+        );
+        // limited endpoint has the best score, so it is tried first
+        let info = Web3RpcInfo {
+            is_allowed: true,
+            ..Default::default()
+        };
+        assert!(pool.restore_endpoint_info(
+            &limited_url,
+            info,
+            chrono::Utc::now(),
+            chrono::Duration::try_hours(1).unwrap()
+        ));
+        let (limited_idx, _, _) = pool
+            .get_endpoints_info()
+            .into_iter()
+            .find(|(_, params, _)| params.endpoint == limited_url)
+            .unwrap();
+        let limiter = pool
+            .get_web3(limited_idx)
+            .unwrap()
+            .transport()
+            .rate_limiter()
+            .clone();
+        // empty the bucket, next token is available in 10 s, much later than the 200 ms timeout
+        limiter.acquire(1, Duration::ZERO).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let block_number = pool.clone().eth_block_number().await.unwrap();
+        assert_eq!(block_number.as_u64(), 42);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // endpoint was tried, but not penalised for waiting on its own limiter
+        assert!(limiter.is_limited());
+        let (_, _, info) = pool
+            .get_endpoints_info()
+            .into_iter()
+            .find(|(idx, _, _)| *idx == limited_idx)
+            .unwrap();
+        assert_eq!(info.web3_rpc_stats.request_count_total_error, 0);
+        assert_eq!(info.endpoint_consecutive_errors, 0);
+    }
+}
@@ -34,4 +34,11 @@ impl Web3RpcPool {
             (filter.clone(),)
         ).await
     }
+
+    pub async fn eth_logs_batch(
+        self: Arc<Self>,
+        args: Vec<(Filter,)>,
+    ) -> Result<Vec<Result<Vec<Log>, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthLogs>(args).await
+    }
 }
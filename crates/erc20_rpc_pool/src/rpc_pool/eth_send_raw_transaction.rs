@@ -34,4 +34,11 @@ impl Web3RpcPool {
             (rlp.clone(),)
         ).await
     }
+
+    pub async fn eth_send_raw_transaction_batch(
+        self: Arc<Self>,
+        args: Vec<(Bytes,)>,
+    ) -> Result<Vec<Result<H256, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthSendRawTransaction>(args).await
+    }
 }
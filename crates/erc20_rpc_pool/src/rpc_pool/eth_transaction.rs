@@ -34,4 +34,11 @@ impl Web3RpcPool {
             (id.clone(),)
         ).await
     }
+
+    pub async fn eth_transaction_batch(
+        self: Arc<Self>,
+        args: Vec<(TransactionId,)>,
+    ) -> Result<Vec<Result<Option<Transaction>, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthTransaction>(args).await
+    }
 }
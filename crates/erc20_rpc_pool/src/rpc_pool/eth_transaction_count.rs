@@ -35,4 +35,11 @@ impl Web3RpcPool {
             (address, block)
         ).await
     }
+
+    pub async fn eth_transaction_count_batch(
+        self: Arc<Self>,
+        args: Vec<(Address, Option<BlockNumber>)>,
+    ) -> Result<Vec<Result<U256, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthTransactionCount>(args).await
+    }
 }
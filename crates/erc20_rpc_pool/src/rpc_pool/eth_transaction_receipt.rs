@@ -34,4 +34,11 @@ impl Web3RpcPool {
             (hash,)
         ).await
     }
+
+    pub async fn eth_transaction_receipt_batch(
+        self: Arc<Self>,
+        args: Vec<(H256,)>,
+    ) -> Result<Vec<Result<Option<TransactionReceipt>, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<EthTransactionReceipt>(args).await
+    }
 }
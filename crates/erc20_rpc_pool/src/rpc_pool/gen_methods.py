@@ -34,6 +34,13 @@ impl Web3RpcPool {
             (%%PARAMS_IN%%)
         ).await
     }
+
+    pub async fn eth_%%METHOD%%_batch(
+        self: Arc<Self>,
+        args: Vec<%%PARAMS_TUPLE%%>,
+    ) -> Result<Vec<Result<%%PARAMS_OUT%%, web3::Error>>, web3::Error> {
+        self.eth_generic_batch_call::<Eth%%METHOD2%%>(args).await
+    }
 }
 """
 
@@ -127,6 +134,15 @@ methods = [
         "params_in": "address, block",
         "params_out": "U256",
         "tuple_args": "args.0, args.1",
+    },
+    {
+        "name": "fee_history",
+        "name2": "FeeHistory",
+        "params_in_full": "block_count: U256,\n        newest_block: BlockNumber,\n        reward_percentiles: Option<Vec<f64>>,",
+        "params_tuple": "(U256, BlockNumber, Option<Vec<f64>>)",
+        "params_in": "block_count, newest_block, reward_percentiles",
+        "params_out": "FeeHistory",
+        "tuple_args": "args.0, args.1, args.2",
     }
 
 
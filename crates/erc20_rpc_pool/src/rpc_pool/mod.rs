@@ -1,8 +1,11 @@
+mod broadcast;
 mod eth_balance;
 mod eth_block;
 mod eth_block_number;
 mod eth_call;
 mod eth_estimate_gas;
+mod eth_fee_history;
+mod eth_generic_batch_call;
 mod eth_generic_call;
 mod eth_logs;
 mod eth_send_raw_transaction;
@@ -10,9 +13,17 @@ mod eth_transaction;
 mod eth_transaction_count;
 mod eth_transaction_receipt;
 mod pool;
+mod quorum;
+mod rate_limit;
+mod rpc_error;
+mod transport;
 mod utils;
 mod verify;
-mod web3_error_list;
 
+pub use broadcast::EndpointBroadcastResult;
 pub use pool::*;
+pub use quorum::Web3RpcQuorum;
+pub use rate_limit::{EndpointRateLimiter, RateLimitBudget};
+pub use rpc_error::RpcErrorKind;
+pub use transport::Web3Transport;
 pub use verify::*;
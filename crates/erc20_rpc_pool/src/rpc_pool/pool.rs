@@ -3,11 +3,17 @@ mod verifier;
 
 use crate::rpc_pool::pool::resolver::ExternalSourceResolver;
 use crate::rpc_pool::pool::verifier::EndpointsVerifier;
-use crate::rpc_pool::verify::{ReqStats, Web3EndpointParams, Web3RpcSingleParams};
+use crate::rpc_pool::quorum::Web3RpcQuorum;
+use crate::rpc_pool::rate_limit::{EndpointRateLimiter, RateLimitBudget};
+use crate::rpc_pool::transport::Web3Transport;
+use crate::rpc_pool::verify::{
+    ReqStats, Web3EndpointParams, Web3RpcSingleParams, DEFAULT_MAX_BATCH_SIZE,
+};
 use crate::rpc_pool::VerifyEndpointResult;
 use crate::Web3RpcInfo;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use erc20_payment_lib_common::DriverEvent;
+use futures::{FutureExt, StreamExt};
 use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -17,7 +23,8 @@ use thunderdome::{Arena, Index};
 use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 use trust_dns_resolver::TokioAsyncResolver;
 use uuid::Uuid;
-use web3::transports::Http;
+use web3::api::SubscriptionStream;
+use web3::types::BlockHeader;
 use web3::Web3;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,7 +64,7 @@ pub struct Web3ExternalSources {
 #[serde(rename_all = "camelCase")]
 pub struct Web3RpcEndpoint {
     #[serde(skip)]
-    pub web3: Option<Web3<Http>>,
+    pub web3: Option<Web3<Web3Transport>>,
     pub web3_rpc_params: Web3RpcSingleParams,
     pub web3_rpc_info: Web3RpcInfo,
 }
@@ -100,6 +107,32 @@ impl Web3RpcEndpoint {
     pub fn is_removed(&self) -> bool {
         self.web3_rpc_info.removed_date.is_some()
     }
+
+    /// Endpoint is backing off after rate limit response or its daily quota is used up
+    pub fn is_rate_limited(&self) -> bool {
+        self.web3
+            .as_ref()
+            .is_some_and(|web3| web3.transport().rate_limiter().is_limited())
+    }
+
+    pub fn rate_limit_budget(&self) -> Option<RateLimitBudget> {
+        self.web3
+            .as_ref()
+            .map(|web3| web3.transport().rate_limiter().budget())
+    }
+}
+
+struct NewHeadsSubscription {
+    idx: Index,
+    stream: SubscriptionStream<Web3Transport, BlockHeader>,
+}
+
+impl std::fmt::Debug for NewHeadsSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NewHeadsSubscription")
+            .field("idx", &self.idx)
+            .finish()
+    }
 }
 
 pub type Web3PoolType = Arc<Mutex<Arena<Arc<RwLock<Web3RpcEndpoint>>>>>;
@@ -120,6 +153,9 @@ pub struct Web3RpcPool {
 
     pub external_sources_resolver: Arc<ExternalSourceResolver>,
     pub endpoint_verifier: Arc<EndpointsVerifier>,
+    pub quorum: RwLock<Option<Web3RpcQuorum>>,
+    /// newHeads subscription used by wait_for_new_head, kept between calls
+    new_heads: tokio::sync::Mutex<Option<NewHeadsSubscription>>,
 }
 
 pub async fn resolve_txt_record_to_string_array(record: &str) -> std::io::Result<Vec<String>> {
@@ -161,8 +197,21 @@ impl Web3RpcPool {
                 );
                 continue;
             }
-            let http = Http::new(&endpoint_params.endpoint).unwrap();
-            let web3 = Web3::new(http);
+            let rate_limiter = Arc::new(EndpointRateLimiter::from_params(
+                &endpoint_params.web3_endpoint_params,
+            ));
+            let transport = match Web3Transport::new(
+                &endpoint_params.endpoint,
+                rate_limiter,
+                Duration::from_millis(endpoint_params.web3_endpoint_params.max_response_time_ms),
+            ) {
+                Ok(transport) => transport,
+                Err(err) => {
+                    log::error!("Skipping endpoint {}: {}", endpoint_params.name, err);
+                    continue;
+                }
+            };
+            let web3 = Web3::new(transport);
             let endpoint = Web3RpcEndpoint {
                 web3: Some(web3),
                 web3_rpc_params: endpoint_params,
@@ -201,6 +250,8 @@ impl Web3RpcPool {
             check_external_sources_interval: external_sources_interval_check,
             external_sources_resolver: Arc::new(ExternalSourceResolver::new()),
             endpoint_verifier: Arc::new(Default::default()),
+            quorum: RwLock::new(None),
+            new_heads: tokio::sync::Mutex::new(None),
         });
 
         if !s.external_json_sources.is_empty() || !s.external_dns_sources.is_empty() {
@@ -224,8 +275,11 @@ impl Web3RpcPool {
                     max_number_of_consecutive_errors: 5,
                     verify_interval_secs: 120,
                     min_interval_requests_ms: None,
+                    max_requests_per_second: None,
+                    daily_request_quota: None,
                     max_head_behind_secs: Some(120),
                     max_response_time_ms: 5000,
+                    max_batch_size: DEFAULT_MAX_BATCH_SIZE,
                 },
                 source_id: None,
             })
@@ -258,8 +312,21 @@ impl Web3RpcPool {
                 return;
             }
         }
-        let http = Http::new(&endpoint.endpoint).unwrap();
-        let web3 = Web3::new(http);
+        let rate_limiter = Arc::new(EndpointRateLimiter::from_params(
+            &endpoint.web3_endpoint_params,
+        ));
+        let transport = match Web3Transport::new(
+            &endpoint.endpoint,
+            rate_limiter,
+            Duration::from_millis(endpoint.web3_endpoint_params.max_response_time_ms),
+        ) {
+            Ok(transport) => transport,
+            Err(err) => {
+                log::error!("Skipping endpoint {}: {}", endpoint.name, err);
+                return;
+            }
+        };
+        let web3 = Web3::new(transport);
         let endpoint = Web3RpcEndpoint {
             web3: Some(web3),
             web3_rpc_params: endpoint,
@@ -368,12 +435,15 @@ impl Web3RpcPool {
             .map(|(idx, _element)| idx)
             .collect::<Vec<Index>>();
 
+        // rate limited endpoints are tried last, they are not penalized
         allowed_endpoints.sort_by_key(|idx| {
-            (endpoints_copy[*idx]
+            let endpoint = endpoints_copy[*idx]
                 .try_read_for(Duration::from_secs(5))
-                .unwrap()
-                .get_score()
-                * 1000.0) as i64
+                .unwrap();
+            (
+                !endpoint.is_rate_limited(),
+                (endpoint.get_score() * 1000.0) as i64,
+            )
         });
         allowed_endpoints.reverse();
 
@@ -438,7 +508,7 @@ impl Web3RpcPool {
         }
     }
 
-    pub fn get_web3(&self, idx: Index) -> Option<Web3<Http>> {
+    pub fn get_web3(&self, idx: Index) -> Option<Web3<Web3Transport>> {
         let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
         endpoints.get(idx).map(|el| {
             el.try_read_for(Duration::from_secs(5))
@@ -449,6 +519,104 @@ impl Web3RpcPool {
         })
     }
 
+    /// Waits for the next block announced over newHeads subscription (ws:// or IPC endpoint),
+    /// falls back to plain sleep when there is no such endpoint in the pool.
+    /// Subscription is kept between calls, blocks announced since the last call return at once.
+    pub async fn wait_for_new_head(self: Arc<Self>, max_wait: std::time::Duration) {
+        let deadline = tokio::time::Instant::now() + max_wait;
+        let mut new_heads = self.new_heads.lock().await;
+
+        let current_idx = new_heads.as_ref().map(|sub| sub.idx);
+        let current_allowed = current_idx.is_some_and(|idx| {
+            let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
+            endpoints.get(idx).is_some_and(|el| {
+                el.try_read_for(Duration::from_secs(5))
+                    .unwrap()
+                    .is_allowed()
+            })
+        });
+        if !current_allowed {
+            *new_heads = None;
+            let best_duplex = {
+                let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
+                endpoints
+                    .iter()
+                    .filter_map(|(idx, el)| {
+                        let el = el.try_read_for(Duration::from_secs(5)).unwrap();
+                        match &el.web3 {
+                            Some(web3)
+                                if el.is_allowed() && web3.transport().supports_subscriptions() =>
+                            {
+                                Some((idx, web3.transport().clone(), el.get_score()))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .max_by_key(|(_idx, _transport, score)| (score * 1000.0) as i64)
+            };
+            let Some((idx, transport, _score)) = best_duplex else {
+                tokio::time::sleep_until(deadline).await;
+                return;
+            };
+            match tokio::time::timeout_at(deadline, transport.subscribe_new_heads()).await {
+                Ok(Ok(stream)) => *new_heads = Some(NewHeadsSubscription { idx, stream }),
+                Ok(Err(err)) => {
+                    log::warn!(
+                        "Failed to subscribe to new heads on endpoint {}: {}",
+                        self.get_name(idx),
+                        err
+                    );
+                    self.mark_rpc_error(
+                        idx,
+                        "subscribe_new_heads".to_string(),
+                        VerifyEndpointResult::OtherNetworkError(err.to_string()),
+                    );
+                    tokio::time::sleep_until(deadline).await;
+                    return;
+                }
+                Err(_) => return,
+            }
+        }
+        let Some(NewHeadsSubscription { idx, stream }) = new_heads.as_mut() else {
+            return;
+        };
+        let idx = *idx;
+
+        let mut announced = false;
+        while let Some(header) = stream.next().now_or_never() {
+            match header {
+                Some(Ok(_)) => announced = true,
+                _ => {
+                    // stream is closed or broken, subscribe again in the next call
+                    *new_heads = None;
+                    return;
+                }
+            }
+        }
+        if announced {
+            return;
+        }
+        match tokio::time::timeout_at(deadline, stream.next()).await {
+            Ok(Some(Ok(header))) => {
+                log::debug!(
+                    "New head {} from endpoint {}",
+                    header.number.unwrap_or_default(),
+                    self.get_name(idx)
+                );
+            }
+            Ok(Some(Err(err))) => {
+                log::warn!("Error in new heads subscription: {}", err);
+                *new_heads = None;
+                tokio::time::sleep_until(deadline).await;
+            }
+            Ok(None) => {
+                *new_heads = None;
+                tokio::time::sleep_until(deadline).await;
+            }
+            Err(_) => {}
+        }
+    }
+
     pub fn get_name(&self, idx: Index) -> String {
         let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
         if let Some(el) = endpoints.get(idx) {
@@ -462,6 +630,19 @@ impl Web3RpcPool {
         }
     }
 
+    pub fn get_max_batch_size(&self, idx: Index) -> usize {
+        let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
+        if let Some(el) = endpoints.get(idx) {
+            el.try_read_for(Duration::from_secs(5))
+                .unwrap()
+                .web3_rpc_params
+                .web3_endpoint_params
+                .max_batch_size
+        } else {
+            1
+        }
+    }
+
     pub fn get_max_timeout(&self, idx: Index) -> std::time::Duration {
         let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
         Duration::from_millis(if let Some(el) = endpoints.get(idx) {
@@ -544,16 +725,26 @@ impl Web3RpcPool {
 
     pub fn mark_rpc_error(&self, idx: Index, method: String, verify_result: VerifyEndpointResult) {
         // use read lock before write lock to avoid deadlock
-        let params = self
-            .endpoints
-            .try_lock_for(Duration::from_secs(5))
-            .unwrap()
-            .get(idx)
-            .unwrap()
-            .try_read_for(Duration::from_secs(5))
-            .unwrap()
-            .web3_rpc_params
-            .clone();
+        let (params, is_rate_limited) = {
+            let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
+            let endpoint = endpoints
+                .get(idx)
+                .unwrap()
+                .try_read_for(Duration::from_secs(5))
+                .unwrap();
+            (endpoint.web3_rpc_params.clone(), endpoint.is_rate_limited())
+        };
+
+        // rate limiting is temporary, the endpoint is skipped until back-off passes instead
+        if is_rate_limited {
+            log::info!(
+                "Endpoint {} is rate limited ({}), backing off",
+                params.name,
+                method
+            );
+            metrics::counter!("web3_rpc_rate_limited", 1, "chain_id" => self.chain_id.to_string(), "endpoint" => params.name);
+            return;
+        }
 
         {
             // lock stats for writing, do not use read lock here
@@ -597,6 +788,34 @@ impl Web3RpcPool {
         } // stats lock is released here
     }
 
+    /// Restores info of the endpoint saved before restart, so the pool does not start from scratch.
+    /// Returns false if there is no such endpoint in the pool.
+    pub fn restore_endpoint_info(
+        &self,
+        endpoint: &str,
+        mut info: Web3RpcInfo,
+        saved_date: DateTime<Utc>,
+        penalty_half_life: chrono::Duration,
+    ) -> bool {
+        info.decay_penalties(Utc::now() - saved_date, penalty_half_life);
+        info.bonus_from_last_chosen = 0;
+        info.removed_date = None;
+        let endpoints = self.endpoints.try_lock_for(Duration::from_secs(5)).unwrap();
+        for (_idx, el) in endpoints.iter() {
+            let mut el = el.try_write_for(Duration::from_secs(5)).unwrap();
+            if !el.is_removed() && el.web3_rpc_params.endpoint == endpoint {
+                log::debug!(
+                    "Restored info of endpoint {} saved at {}",
+                    el.web3_rpc_params.name,
+                    saved_date
+                );
+                el.web3_rpc_info = info;
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn get_endpoints_info(&self) -> Vec<(Index, Web3RpcSingleParams, Web3RpcInfo)> {
         self.endpoints
             .try_lock_for(Duration::from_secs(5))
@@ -0,0 +1,276 @@
+use crate::rpc_pool::eth_generic_call::EthMethod;
+use crate::rpc_pool::rpc_error::RpcErrorKind;
+use crate::rpc_pool::transport::Web3Transport;
+use crate::rpc_pool::VerifyEndpointResult;
+use crate::Web3RpcPool;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thunderdome::Index;
+
+/// Methods checked by quorum when they are not given explicitly
+pub const DEFAULT_QUORUM_METHODS: [&str; 3] = ["transaction_receipt", "transaction_count", "block"];
+
+/// Read only pool methods that can be checked by quorum
+pub const QUORUM_CAPABLE_METHODS: [&str; 10] = [
+    "balance",
+    "block",
+    "block_number",
+    "call",
+    "estimate_gas",
+    "fee_history",
+    "logs",
+    "transaction",
+    "transaction_count",
+    "transaction_receipt",
+];
+
+fn normalize_method(method: &str) -> String {
+    method.trim().trim_start_matches("eth_").to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Web3RpcQuorum {
+    /// Number of endpoints asked on every call
+    pub size: usize,
+    /// Number of endpoints that have to return the same result
+    pub min_agree: usize,
+    /// Pool method names (without eth_ prefix) called with quorum
+    pub methods: Vec<String>,
+}
+
+impl Web3RpcQuorum {
+    pub fn new(size: usize, min_agree: Option<usize>, methods: Option<Vec<String>>) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            min_agree: min_agree.unwrap_or(size / 2 + 1).clamp(1, size),
+            methods: methods
+                .map(|methods| methods.iter().map(|m| normalize_method(m)).collect())
+                .unwrap_or_else(|| {
+                    DEFAULT_QUORUM_METHODS
+                        .iter()
+                        .map(|m| m.to_string())
+                        .collect()
+                }),
+        }
+    }
+
+    /// Checks method names given in config, eth_ prefix is optional
+    pub fn check_methods(methods: &[String]) -> Result<(), String> {
+        for method in methods {
+            if !QUORUM_CAPABLE_METHODS.contains(&normalize_method(method).as_str()) {
+                return Err(format!(
+                    "Unknown quorum method {method}, allowed: {}",
+                    QUORUM_CAPABLE_METHODS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn applies_to(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m == method)
+    }
+}
+
+/// Groups responses by value, returns positions of the responses in each group, largest group first
+fn group_responses(responses: &[serde_json::Value]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(&serde_json::Value, Vec<usize>)> = Vec::new();
+    for (pos, response) in responses.iter().enumerate() {
+        match groups.iter_mut().find(|(value, _)| *value == response) {
+            Some((_, positions)) => positions.push(pos),
+            None => groups.push((response, vec![pos])),
+        }
+    }
+    let mut groups = groups
+        .into_iter()
+        .map(|(_, positions)| positions)
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|positions| std::cmp::Reverse(positions.len()));
+    groups
+}
+
+impl Web3RpcPool {
+    pub fn set_quorum(&self, quorum: Option<Web3RpcQuorum>) {
+        *self.quorum.write() = quorum;
+    }
+
+    pub fn get_quorum_for_method(&self, method: &str) -> Option<Web3RpcQuorum> {
+        self.quorum
+            .read()
+            .as_ref()
+            .filter(|quorum| quorum.applies_to(method))
+            .cloned()
+    }
+
+    /// Asks best endpoints concurrently and returns result only if enough of them agree,
+    /// endpoints returning different result are penalised
+    pub(crate) async fn eth_quorum_call<EthMethodCall: EthMethod<Web3Transport>>(
+        self: &Arc<Self>,
+        endpoints: &[Index],
+        quorum: &Web3RpcQuorum,
+        args: EthMethodCall::Args,
+    ) -> Result<EthMethodCall::Return, web3::Error> {
+        let method = EthMethodCall::METHOD;
+        let chosen = endpoints
+            .iter()
+            .take(quorum.size)
+            .copied()
+            .collect::<Vec<_>>();
+        if chosen.len() < quorum.min_agree {
+            log::warn!(
+                "Not enough endpoints for quorum call {} - chain id: {}, available: {}, required: {}",
+                method,
+                self.chain_id,
+                chosen.len(),
+                quorum.min_agree
+            );
+            return Err(web3::Error::Unreachable);
+        }
+
+        let calls = chosen.iter().filter_map(|idx| {
+            let idx = *idx;
+            let web3 = self.get_web3(idx)?;
+            let args = args.clone();
+            Some(async move { (idx, EthMethodCall::do_call(web3.eth(), args).await) })
+        });
+
+        let mut answered = Vec::new();
+        let mut values = Vec::new();
+        let mut responses = Vec::new();
+        // error caused by the request itself, returned if no endpoint gave an answer
+        let mut request_error = None;
+        for (idx, res) in futures::future::join_all(calls).await {
+            match res {
+                Ok(value) => match serde_json::to_value(&value) {
+                    Ok(response) => {
+                        answered.push(idx);
+                        values.push(value);
+                        responses.push(response);
+                    }
+                    Err(err) => {
+                        log::error!("Failed to serialize response of {}: {}", method, err);
+                    }
+                },
+                Err(web3::Error::Rpc(e)) => {
+                    let kind = RpcErrorKind::from_rpc_error(&e);
+                    if !kind.is_endpoint_fault() {
+                        log::debug!(
+                            "Quorum call {} - endpoint {} returned {:?}: {}",
+                            method,
+                            self.get_name(idx),
+                            kind,
+                            e
+                        );
+                        request_error.get_or_insert(e);
+                    } else {
+                        log::warn!("RPC error ({:?}): {}", kind, e);
+                        self.mark_rpc_error(
+                            idx,
+                            method.to_string(),
+                            VerifyEndpointResult::RpcWeb3Error(e.to_string()),
+                        );
+                    }
+                }
+                Err(web3::Error::Unreachable) => {
+                    log::warn!(
+                        "Timeout when getting data from endpoint {}",
+                        self.get_name(idx)
+                    );
+                    self.mark_rpc_error(idx, method.to_string(), VerifyEndpointResult::Unreachable);
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Error doing call {} from endpoint {}: {}",
+                        method,
+                        self.get_name(idx),
+                        e
+                    );
+                    self.mark_rpc_error(
+                        idx,
+                        method.to_string(),
+                        VerifyEndpointResult::OtherNetworkError(e.to_string()),
+                    );
+                }
+            }
+        }
+
+        if responses.is_empty() {
+            if let Some(e) = request_error {
+                return Err(web3::Error::Rpc(e));
+            }
+        }
+        let groups = group_responses(&responses);
+        let Some(agreed) = groups.first().filter(|g| g.len() >= quorum.min_agree) else {
+            log::warn!(
+                "No quorum for call {} - chain id: {}, {} of {} endpoints agreed, required: {}",
+                method,
+                self.chain_id,
+                groups.first().map(|g| g.len()).unwrap_or(0),
+                chosen.len(),
+                quorum.min_agree
+            );
+            return Err(web3::Error::InvalidResponse(format!(
+                "No quorum reached for {method}"
+            )));
+        };
+
+        for group in groups.iter().skip(1) {
+            for pos in group {
+                let idx = answered[*pos];
+                log::warn!(
+                    "Endpoint {} returned result of {} different than quorum",
+                    self.get_name(idx),
+                    method
+                );
+                self.mark_rpc_error(
+                    idx,
+                    method.to_string(),
+                    VerifyEndpointResult::QuorumMismatch(responses[*pos].to_string()),
+                );
+            }
+        }
+        for pos in agreed {
+            self.mark_rpc_success(answered[*pos], method.to_string());
+        }
+        Ok(values.swap_remove(agreed[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_group_responses() {
+        let responses = vec![json!("0x1"), json!("0x2"), json!("0x2"), json!(null)];
+        assert_eq!(
+            group_responses(&responses),
+            vec![vec![1, 2], vec![0], vec![3]]
+        );
+        assert!(group_responses(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_quorum_params() {
+        let quorum = Web3RpcQuorum::new(3, None, None);
+        assert_eq!(quorum.min_agree, 2);
+        assert!(quorum.applies_to("transaction_receipt"));
+        assert!(!quorum.applies_to("balance"));
+        assert!(quorum.applies_to("transaction_count"));
+        assert!(quorum.applies_to("block"));
+
+        let quorum = Web3RpcQuorum::new(2, Some(5), Some(vec!["eth_balance".to_string()]));
+        assert_eq!(quorum.min_agree, 2);
+        assert!(quorum.applies_to("balance"));
+        assert!(!quorum.applies_to("block"));
+
+        assert!(
+            Web3RpcQuorum::check_methods(&["eth_block".to_string(), " logs".to_string()]).is_ok()
+        );
+        assert!(Web3RpcQuorum::check_methods(&["send_raw_transaction".to_string()]).is_err());
+        assert!(Web3RpcQuorum::check_methods(&["blocks".to_string()]).is_err());
+    }
+}
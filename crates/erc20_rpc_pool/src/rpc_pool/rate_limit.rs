@@ -0,0 +1,279 @@
+use crate::rpc_pool::verify::Web3EndpointParams;
+use chrono::{NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use web3::error::TransportError;
+
+/// Back-off used when endpoint does not send Retry-After, doubled for every consecutive rate limit
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitBudget {
+    /// Requests that can be sent right now, None if requests per second are not limited
+    pub requests_available: Option<f64>,
+    pub daily_requests_used: u64,
+    /// None if there is no daily quota
+    pub daily_requests_remaining: Option<u64>,
+    pub backoff_remaining_ms: u64,
+    pub rate_limited_count: u64,
+}
+
+#[derive(Debug)]
+struct RateLimitState {
+    tokens: f64,
+    last_refill: Instant,
+    day: NaiveDate,
+    daily_used: u64,
+    backoff_until: Option<Instant>,
+    /// Set when token bucket needs longer wait than request timeout allows
+    busy_until: Option<Instant>,
+    consecutive_rate_limits: u32,
+    rate_limited_count: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Acquire {
+    Ready,
+    Wait(Duration),
+    Limited,
+}
+
+/// Token bucket and daily quota of single endpoint, shared by all requests sent to it.
+/// When endpoint answers with rate limit error it is not used until back-off time passes.
+#[derive(Debug)]
+pub struct EndpointRateLimiter {
+    requests_per_second: Option<f64>,
+    burst: f64,
+    daily_quota: Option<u64>,
+    state: Mutex<RateLimitState>,
+}
+
+impl Default for EndpointRateLimiter {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Parses Retry-After header value, given either in seconds or as HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+impl EndpointRateLimiter {
+    pub fn new(requests_per_second: Option<f64>, daily_quota: Option<u64>) -> Self {
+        let requests_per_second = requests_per_second.filter(|rps| *rps > 0.0);
+        // allow bursts of one second worth of requests
+        let burst = requests_per_second.map(|rps| rps.max(1.0)).unwrap_or(1.0);
+        Self {
+            requests_per_second,
+            burst,
+            daily_quota,
+            state: Mutex::new(RateLimitState {
+                tokens: burst,
+                last_refill: Instant::now(),
+                day: Utc::now().date_naive(),
+                daily_used: 0,
+                backoff_until: None,
+                busy_until: None,
+                consecutive_rate_limits: 0,
+                rate_limited_count: 0,
+            }),
+        }
+    }
+
+    /// Stricter of max requests per second and min interval between requests is used
+    pub fn from_params(params: &Web3EndpointParams) -> Self {
+        let from_interval = params
+            .min_interval_requests_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| 1000.0 / ms as f64);
+        let requests_per_second = match (params.max_requests_per_second, from_interval) {
+            (Some(rps), Some(from_interval)) => Some(rps.min(from_interval)),
+            (rps, from_interval) => rps.or(from_interval),
+        };
+        Self::new(requests_per_second, params.daily_request_quota)
+    }
+
+    fn refill(&self, state: &mut RateLimitState, now: Instant) {
+        if let Some(rps) = self.requests_per_second {
+            let elapsed = now.saturating_duration_since(state.last_refill);
+            state.tokens = (state.tokens + elapsed.as_secs_f64() * rps).min(self.burst);
+        }
+        state.last_refill = now;
+        let today = Utc::now().date_naive();
+        if state.day != today {
+            state.day = today;
+            state.daily_used = 0;
+        }
+    }
+
+    fn try_acquire_at(&self, cost: u64, now: Instant) -> Acquire {
+        let mut state = self.state.lock();
+        self.refill(&mut state, now);
+        if state.backoff_until.is_some_and(|until| until > now) {
+            return Acquire::Limited;
+        }
+        if self
+            .daily_quota
+            .is_some_and(|quota| state.daily_used + cost > quota)
+        {
+            return Acquire::Limited;
+        }
+        if let Some(rps) = self.requests_per_second {
+            if state.tokens < 1.0 {
+                return Acquire::Wait(Duration::from_secs_f64((1.0 - state.tokens) / rps));
+            }
+            // batch can take the bucket below zero, following requests will wait longer
+            state.tokens -= cost as f64;
+        }
+        state.daily_used += cost;
+        Acquire::Ready
+    }
+
+    /// Waits until request of given cost can be sent, at most max_wait.
+    /// Fails with HTTP 429 error if endpoint is backing off, its daily quota is used up
+    /// or the wait would be longer, so the caller can use another endpoint.
+    pub async fn acquire(&self, cost: u64, max_wait: Duration) -> web3::Result<()> {
+        loop {
+            let now = Instant::now();
+            match self.try_acquire_at(cost, now) {
+                Acquire::Ready => return Ok(()),
+                Acquire::Wait(wait) if wait > max_wait => {
+                    self.state.lock().busy_until = Some(now + wait);
+                    return Err(web3::Error::Transport(TransportError::Code(429)));
+                }
+                Acquire::Wait(wait) => tokio::time::sleep(wait).await,
+                Acquire::Limited => {
+                    return Err(web3::Error::Transport(TransportError::Code(429)));
+                }
+            }
+        }
+    }
+
+    fn on_rate_limited_at(&self, retry_after: Option<Duration>, now: Instant) -> Duration {
+        let mut state = self.state.lock();
+        state.rate_limited_count += 1;
+        state.consecutive_rate_limits += 1;
+        let backoff = retry_after
+            .unwrap_or_else(|| {
+                DEFAULT_BACKOFF * 2u32.pow(state.consecutive_rate_limits.min(16) - 1)
+            })
+            .min(MAX_BACKOFF);
+        let until = now + backoff;
+        state.backoff_until = Some(state.backoff_until.map_or(until, |prev| prev.max(until)));
+        backoff
+    }
+
+    /// Endpoint refused request because of rate limit, stop using it for a while
+    pub fn on_rate_limited(&self, retry_after: Option<Duration>) -> Duration {
+        self.on_rate_limited_at(retry_after, Instant::now())
+    }
+
+    pub fn on_success(&self) {
+        self.state.lock().consecutive_rate_limits = 0;
+    }
+
+    /// True if requests cannot be sent to the endpoint now
+    /// (back-off, daily quota used up or token bucket empty for longer than request timeout)
+    pub fn is_limited(&self) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        self.refill(&mut state, now);
+        state.backoff_until.is_some_and(|until| until > now)
+            || state.busy_until.is_some_and(|until| until > now)
+            || self
+                .daily_quota
+                .is_some_and(|quota| state.daily_used >= quota)
+    }
+
+    pub fn budget(&self) -> RateLimitBudget {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        self.refill(&mut state, now);
+        RateLimitBudget {
+            requests_available: self.requests_per_second.map(|_| state.tokens),
+            daily_requests_used: state.daily_used,
+            daily_requests_remaining: self
+                .daily_quota
+                .map(|quota| quota.saturating_sub(state.daily_used)),
+            backoff_remaining_ms: state
+                .backoff_until
+                .map(|until| until.saturating_duration_since(now).as_millis() as u64)
+                .unwrap_or_default(),
+            rate_limited_count: state.rate_limited_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_and_quota() {
+        let limiter = EndpointRateLimiter::new(Some(2.0), Some(5));
+        let now = Instant::now();
+        assert_eq!(limiter.try_acquire_at(1, now), Acquire::Ready);
+        assert_eq!(limiter.try_acquire_at(1, now), Acquire::Ready);
+        assert_eq!(
+            limiter.try_acquire_at(1, now),
+            Acquire::Wait(Duration::from_millis(500))
+        );
+        let now = now + Duration::from_millis(500);
+        assert_eq!(limiter.try_acquire_at(1, now), Acquire::Ready);
+        // batch of two after one second
+        let now = now + Duration::from_secs(1);
+        assert_eq!(limiter.try_acquire_at(2, now), Acquire::Ready);
+        let budget = limiter.budget();
+        assert_eq!(budget.daily_requests_used, 5);
+        assert_eq!(budget.daily_requests_remaining, Some(0));
+        assert!(limiter.is_limited());
+        assert_eq!(
+            limiter.try_acquire_at(1, now + Duration::from_secs(10)),
+            Acquire::Limited
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_backoff() {
+        let limiter = EndpointRateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.try_acquire_at(1, now), Acquire::Ready);
+        assert_eq!(
+            limiter.on_rate_limited_at(Some(Duration::from_secs(30)), now),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            limiter.try_acquire_at(1, now + Duration::from_secs(29)),
+            Acquire::Limited
+        );
+        assert_eq!(
+            limiter.try_acquire_at(1, now + Duration::from_secs(30)),
+            Acquire::Ready
+        );
+        // without Retry-After back-off is doubled for consecutive rate limits
+        assert_eq!(limiter.on_rate_limited_at(None, now), DEFAULT_BACKOFF * 2);
+        limiter.on_success();
+        assert_eq!(limiter.on_rate_limited_at(None, now), DEFAULT_BACKOFF);
+        assert_eq!(limiter.budget().rate_limited_count, 3);
+
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,238 @@
+use jsonrpc_core as rpc;
+use serde::{Deserialize, Serialize};
+use web3::error::TransportError;
+use web3::ethabi::ParamType;
+
+/// Selector of Error(string) used by solidity require/revert
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of Panic(uint256) used by solidity assert, overflow checks etc.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Kind of error returned by the node, independent of the node implementation wording.
+/// Only errors that say something about the endpoint itself (rate limits, internal failures,
+/// unrecognized responses) should lower the endpoint score.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "reason")]
+pub enum RpcErrorKind {
+    /// Execution reverted, reason decoded from revert data or message if available
+    Revert(Option<String>),
+    NonceTooLow,
+    ReplacementUnderpriced,
+    InsufficientFunds,
+    AlreadyKnown,
+    /// Transaction signed for different chain
+    InvalidSender,
+    RateLimited,
+    NodeInternal,
+    Other,
+}
+
+fn decode_revert_data(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, params) = data.split_at(4);
+    if selector == ERROR_STRING_SELECTOR {
+        web3::ethabi::decode(&[ParamType::String], params)
+            .ok()?
+            .pop()?
+            .into_string()
+    } else if selector == PANIC_SELECTOR {
+        let code = web3::ethabi::decode(&[ParamType::Uint(256)], params)
+            .ok()?
+            .pop()?
+            .into_uint()?;
+        Some(format!("Panic({code:#x})"))
+    } else {
+        None
+    }
+}
+
+fn revert_reason(err: &rpc::Error) -> Option<String> {
+    if let Some(rpc::Value::String(data)) = &err.data {
+        if let Ok(bytes) = hex::decode(data.trim_start_matches("0x")) {
+            if let Some(reason) = decode_revert_data(&bytes) {
+                return Some(reason);
+            }
+        }
+    }
+    let message = err.message.as_str();
+    if let Some(pos) = message.find("reverted with reason string '") {
+        let reason = &message[pos + "reverted with reason string '".len()..];
+        return Some(reason.trim_end_matches('\'').to_string());
+    }
+    message
+        .split_once("reverted: ")
+        .or_else(|| message.split_once("revert "))
+        .map(|(_, reason)| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty())
+}
+
+impl RpcErrorKind {
+    pub fn from_rpc_error(err: &rpc::Error) -> Self {
+        let code = err.code.code();
+        let message = err.message.to_lowercase();
+        if code == 3 || message.contains("revert") {
+            Self::Revert(revert_reason(err))
+        } else if message.contains("nonce too low") || message.contains("oldnonce") {
+            Self::NonceTooLow
+        } else if message.contains("replacement transaction underpriced") {
+            Self::ReplacementUnderpriced
+        } else if message.contains("insufficient funds")
+            || message.contains("gas required exceeds allowance")
+        {
+            Self::InsufficientFunds
+        } else if message.contains("already known") || message.contains("known transaction") {
+            Self::AlreadyKnown
+        } else if message.contains("invalid sender") {
+            Self::InvalidSender
+        } else if code == -32005
+            || code == 429
+            || message.contains("rate limit")
+            || message.contains("too many requests")
+        {
+            Self::RateLimited
+        } else if message.contains("transfer amount exceeds balance") {
+            // some nodes drop the "execution reverted" prefix
+            Self::Revert(Some(err.message.clone()))
+        } else if code == rpc::ErrorCode::InternalError.code() || message.contains("internal error")
+        {
+            Self::NodeInternal
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Returns None for errors not coming from the node, like timeouts or connection failures
+    pub fn from_web3_error(err: &web3::Error) -> Option<Self> {
+        match err {
+            web3::Error::Rpc(err) => Some(Self::from_rpc_error(err)),
+            web3::Error::Transport(TransportError::Code(429)) => Some(Self::RateLimited),
+            _ => None,
+        }
+    }
+
+    /// If false the node answered properly and the error is caused by the request itself
+    pub fn is_endpoint_fault(&self) -> bool {
+        matches!(self, Self::RateLimited | Self::NodeInternal | Self::Other)
+    }
+
+    pub fn revert_reason_contains(&self, text: &str) -> bool {
+        matches!(self, Self::Revert(Some(reason)) if reason.contains(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rpc_error(code: i64, message: &str, data: Option<rpc::Value>) -> rpc::Error {
+        rpc::Error {
+            code: rpc::ErrorCode::ServerError(code),
+            message: message.to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn test_classify_rpc_errors() {
+        let cases = [
+            ("nonce too low", RpcErrorKind::NonceTooLow),
+            (
+                "replacement transaction underpriced",
+                RpcErrorKind::ReplacementUnderpriced,
+            ),
+            (
+                "insufficient funds for gas * price + value",
+                RpcErrorKind::InsufficientFunds,
+            ),
+            (
+                "gas required exceeds allowance (0)",
+                RpcErrorKind::InsufficientFunds,
+            ),
+            ("already known", RpcErrorKind::AlreadyKnown),
+            ("Known transaction: 0x1234", RpcErrorKind::AlreadyKnown),
+            ("invalid sender", RpcErrorKind::InvalidSender),
+            (
+                "daily request count exceeded, request rate limited",
+                RpcErrorKind::RateLimited,
+            ),
+            ("header not found", RpcErrorKind::Other),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(
+                RpcErrorKind::from_rpc_error(&rpc_error(-32000, message, None)),
+                kind,
+                "{message}"
+            );
+        }
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc_error(-32005, "limit exceeded", None)),
+            RpcErrorKind::RateLimited
+        );
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc::Error::internal_error()),
+            RpcErrorKind::NodeInternal
+        );
+        assert_eq!(
+            RpcErrorKind::from_web3_error(&web3::Error::Transport(TransportError::Code(429))),
+            Some(RpcErrorKind::RateLimited)
+        );
+        assert_eq!(
+            RpcErrorKind::from_web3_error(&web3::Error::Unreachable),
+            None
+        );
+        assert!(!RpcErrorKind::NonceTooLow.is_endpoint_fault());
+        assert!(RpcErrorKind::NodeInternal.is_endpoint_fault());
+    }
+
+    #[test]
+    fn test_decode_revert_reason() {
+        // Error("ERC20: transfer amount exceeds balance")
+        let data = "0x08c379a0\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000026\
+            45524332303a207472616e7366657220616d6f756e7420657863656564732062\
+            616c616e63650000000000000000000000000000000000000000000000000000";
+        let kind =
+            RpcErrorKind::from_rpc_error(&rpc_error(3, "execution reverted", Some(json!(data))));
+        assert_eq!(
+            kind,
+            RpcErrorKind::Revert(Some("ERC20: transfer amount exceeds balance".to_string()))
+        );
+        assert!(kind.revert_reason_contains("transfer amount exceeds balance"));
+        assert!(!kind.is_endpoint_fault());
+
+        // Panic(0x11) - arithmetic overflow
+        let data = "0x4e487b71\
+            0000000000000000000000000000000000000000000000000000000000000011";
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc_error(3, "execution reverted", Some(json!(data)))),
+            RpcErrorKind::Revert(Some("Panic(0x11)".to_string()))
+        );
+
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc_error(
+                -32000,
+                "execution reverted: Cannot acquire more funds",
+                None
+            )),
+            RpcErrorKind::Revert(Some("Cannot acquire more funds".to_string()))
+        );
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc_error(
+                -32603,
+                "Error: VM Exception while processing transaction: reverted with reason string 'ERC20: transfer amount exceeds balance'",
+                None
+            )),
+            RpcErrorKind::Revert(Some(
+                "ERC20: transfer amount exceeds balance".to_string()
+            ))
+        );
+        assert_eq!(
+            RpcErrorKind::from_rpc_error(&rpc_error(-32000, "execution reverted", None)),
+            RpcErrorKind::Revert(None)
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use crate::rpc_pool::rate_limit::{parse_retry_after, EndpointRateLimiter};
+use crate::rpc_pool::rpc_error::RpcErrorKind;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{BoxStream, StreamExt};
+use jsonrpc_core as rpc;
+use parking_lot::Mutex;
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use web3::api::{EthSubscribe, Namespace, SubscriptionId, SubscriptionStream};
+use web3::error::TransportError;
+use web3::transports::{Either, Ipc, WebSocket};
+use web3::types::BlockHeader;
+use web3::{helpers, BatchTransport, DuplexTransport, RequestId, Transport};
+
+pub type DuplexConnection = Either<WebSocket, Ipc>;
+
+#[derive(Debug, Clone)]
+pub enum DuplexTarget {
+    WebSocket(String),
+    Ipc(PathBuf),
+}
+
+/// WebSocket or IPC connection, opened on first use and reopened after connection errors
+#[derive(Debug, Clone)]
+pub struct LazyDuplexConnection {
+    target: DuplexTarget,
+    id: Arc<AtomicUsize>,
+    connection: Arc<Mutex<Option<DuplexConnection>>>,
+    connect_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[cfg(unix)]
+async fn connect_ipc(path: &Path) -> web3::Result<Ipc> {
+    Ipc::new(path).await
+}
+
+#[cfg(not(unix))]
+async fn connect_ipc(path: &Path) -> web3::Result<Ipc> {
+    Err(web3::Error::Transport(TransportError::Message(format!(
+        "IPC endpoint {} is not supported on this platform",
+        path.display()
+    ))))
+}
+
+fn is_connection_error(err: &web3::Error) -> bool {
+    matches!(
+        err,
+        web3::Error::Unreachable
+            | web3::Error::Transport(_)
+            | web3::Error::Io(_)
+            | web3::Error::Internal
+    )
+}
+
+impl LazyDuplexConnection {
+    fn new(target: DuplexTarget) -> Self {
+        Self {
+            target,
+            id: Arc::new(AtomicUsize::new(1)),
+            connection: Arc::new(Mutex::new(None)),
+            connect_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    fn current(&self) -> Option<DuplexConnection> {
+        self.connection.lock().clone()
+    }
+
+    pub async fn connect(&self) -> web3::Result<DuplexConnection> {
+        if let Some(connection) = self.current() {
+            return Ok(connection);
+        }
+        let _connect_guard = self.connect_lock.lock().await;
+        if let Some(connection) = self.current() {
+            return Ok(connection);
+        }
+        let connection = match &self.target {
+            DuplexTarget::WebSocket(url) => Either::Left(WebSocket::new(url).await?),
+            DuplexTarget::Ipc(path) => Either::Right(connect_ipc(path).await?),
+        };
+        log::debug!("Connected to {:?}", self.target);
+        *self.connection.lock() = Some(connection.clone());
+        Ok(connection)
+    }
+
+    fn disconnect(&self) {
+        if self.connection.lock().take().is_some() {
+            log::debug!("Dropped connection to {:?}", self.target);
+        }
+    }
+
+    fn disconnect_on_error<T>(&self, res: &web3::Result<T>) {
+        if let Err(err) = res {
+            if is_connection_error(err) {
+                self.disconnect();
+            }
+        }
+    }
+
+    async fn send(&self, id: RequestId, request: rpc::Call) -> web3::Result<rpc::Value> {
+        let res = self.connect().await?.send(id, request).await;
+        self.disconnect_on_error(&res);
+        res
+    }
+
+    async fn send_batch(
+        &self,
+        requests: Vec<(RequestId, rpc::Call)>,
+    ) -> web3::Result<Vec<web3::Result<rpc::Value>>> {
+        let res = self.connect().await?.send_batch(requests).await;
+        self.disconnect_on_error(&res);
+        res
+    }
+}
+
+/// Plain http connection. Unlike web3 Http transport it reads Retry-After
+/// of rate limited responses and passes it to the endpoint rate limiter.
+#[derive(Debug, Clone)]
+pub struct HttpConnection {
+    client: reqwest::Client,
+    url: reqwest::Url,
+    id: Arc<AtomicUsize>,
+    rate_limiter: Arc<EndpointRateLimiter>,
+}
+
+impl HttpConnection {
+    fn new(endpoint: &str, rate_limiter: Arc<EndpointRateLimiter>) -> web3::Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent("web3.rs")
+            .build()
+            .map_err(|err| {
+                web3::Error::Transport(TransportError::Message(format!(
+                    "failed to build client: {err}"
+                )))
+            })?;
+        let url = endpoint.parse::<reqwest::Url>().map_err(|err| {
+            web3::Error::Transport(TransportError::Message(format!(
+                "Endpoint {endpoint}: invalid url: {err}"
+            )))
+        })?;
+        Ok(Self {
+            client,
+            url,
+            id: Arc::new(AtomicUsize::new(0)),
+            rate_limiter,
+        })
+    }
+
+    async fn execute(&self, request: &rpc::Request) -> web3::Result<rpc::Response> {
+        let body = serde_json::to_vec(request)
+            .map_err(|err| web3::Error::Decoder(format!("failed to serialize request: {err}")))?;
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| {
+                web3::Error::Transport(TransportError::Message(format!(
+                    "failed to send request: {err}"
+                )))
+            })?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            let backoff = self.rate_limiter.on_rate_limited(retry_after);
+            log::debug!("Request rate limited, backing off for {:?}", backoff);
+            return Err(web3::Error::Transport(TransportError::Code(
+                status.as_u16(),
+            )));
+        }
+        let response = response.bytes().await.map_err(|err| {
+            web3::Error::Transport(TransportError::Message(format!(
+                "failed to read response bytes: {err}"
+            )))
+        })?;
+        if !status.is_success() {
+            return Err(web3::Error::Transport(TransportError::Code(
+                status.as_u16(),
+            )));
+        }
+        helpers::arbitrary_precision_deserialize_workaround(&response).map_err(|err| {
+            web3::Error::Transport(TransportError::Message(format!(
+                "failed to deserialize response: {}: {}",
+                err,
+                String::from_utf8_lossy(&response)
+            )))
+        })
+    }
+
+    async fn send(&self, request: rpc::Call) -> web3::Result<rpc::Value> {
+        match self.execute(&rpc::Request::Single(request)).await? {
+            rpc::Response::Single(output) => helpers::to_result_from_output(output),
+            rpc::Response::Batch(_) => Err(web3::Error::InvalidResponse(
+                "Batch response for single request".to_string(),
+            )),
+        }
+    }
+
+    async fn send_batch(
+        &self,
+        requests: Vec<(RequestId, rpc::Call)>,
+    ) -> web3::Result<Vec<web3::Result<rpc::Value>>> {
+        let (ids, calls): (Vec<_>, Vec<_>) = requests.into_iter().unzip();
+        let outputs = match self.execute(&rpc::Request::Batch(calls)).await? {
+            rpc::Response::Batch(outputs) => outputs,
+            // node can answer with single error for the whole batch
+            rpc::Response::Single(rpc::Output::Failure(failure)) => {
+                return Err(web3::Error::Rpc(failure.error))
+            }
+            rpc::Response::Single(output) => {
+                return Err(web3::Error::InvalidResponse(format!(
+                    "Invalid response for batched request: {:?}",
+                    output
+                )))
+            }
+        };
+        // batch responses can be returned in any order
+        let mut outputs = outputs
+            .into_iter()
+            .map(|output| (output.id().clone(), helpers::to_result_from_output(output)))
+            .collect::<HashMap<_, _>>();
+        ids.iter()
+            .map(|id| {
+                outputs.remove(&rpc::Id::Num(*id as u64)).ok_or_else(|| {
+                    web3::Error::InvalidResponse(format!("batch response is missing id {id}"))
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Web3Connection {
+    Http(HttpConnection),
+    Duplex(LazyDuplexConnection),
+}
+
+/// Transport used by the pool, chosen by the endpoint url scheme:
+/// http(s):// - plain http, ws:// - WebSocket, ipc://path - IPC socket.
+/// wss:// is not supported, TLS WebSocket transport of web3 needs native-tls (OpenSSL)
+/// while the rest of the project uses rustls only.
+/// Every request waits for the endpoint rate limiter first, request timeout starts after that,
+/// so waiting for the limiter is not counted as slow response of the endpoint.
+#[derive(Debug, Clone)]
+pub struct Web3Transport {
+    connection: Web3Connection,
+    rate_limiter: Arc<EndpointRateLimiter>,
+    request_timeout: Duration,
+}
+
+impl Web3Transport {
+    pub fn new(
+        endpoint: &str,
+        rate_limiter: Arc<EndpointRateLimiter>,
+        request_timeout: Duration,
+    ) -> web3::Result<Self> {
+        let duplex = |target| Web3Connection::Duplex(LazyDuplexConnection::new(target));
+        let connection = if let Some(path) = endpoint.strip_prefix("ipc://") {
+            duplex(DuplexTarget::Ipc(PathBuf::from(path)))
+        } else if let Some((scheme, _)) = endpoint.split_once("://") {
+            match scheme.to_lowercase().as_str() {
+                "http" | "https" => {
+                    Web3Connection::Http(HttpConnection::new(endpoint, rate_limiter.clone())?)
+                }
+                "ws" => duplex(DuplexTarget::WebSocket(endpoint.to_string())),
+                "wss" => {
+                    return Err(web3::Error::Transport(TransportError::Message(format!(
+                        "Endpoint {endpoint}: wss:// is not supported (TLS WebSocket needs native-tls), use https:// or ws://"
+                    ))))
+                }
+                _ => {
+                    return Err(web3::Error::Transport(TransportError::Message(format!(
+                        "Endpoint {endpoint}: unsupported url scheme {scheme}"
+                    ))))
+                }
+            }
+        } else {
+            // plain path is not accepted, so a mistyped url does not become IPC endpoint
+            return Err(web3::Error::Transport(TransportError::Message(format!(
+                "Endpoint {endpoint}: missing url scheme, use http(s)://, ws:// or ipc://"
+            ))));
+        };
+        Ok(Self {
+            connection,
+            rate_limiter,
+            request_timeout,
+        })
+    }
+
+    pub fn rate_limiter(&self) -> &Arc<EndpointRateLimiter> {
+        &self.rate_limiter
+    }
+
+    pub fn supports_subscriptions(&self) -> bool {
+        matches!(self.connection, Web3Connection::Duplex(_))
+    }
+
+    pub async fn subscribe_new_heads(
+        &self,
+    ) -> web3::Result<SubscriptionStream<Web3Transport, BlockHeader>> {
+        match &self.connection {
+            Web3Connection::Http(_) => Err(web3::Error::Transport(TransportError::Message(
+                "Subscriptions require ws:// or IPC endpoint".to_string(),
+            ))),
+            Web3Connection::Duplex(duplex) => {
+                duplex.connect().await?;
+                EthSubscribe::new(self.clone()).subscribe_new_heads().await
+            }
+        }
+    }
+
+    /// Rate limit errors returned in JSON-RPC response (not as HTTP 429) also start back-off
+    fn check_rate_limited(&self, err: &web3::Error) -> bool {
+        if matches!(err, web3::Error::Rpc(_))
+            && RpcErrorKind::from_web3_error(err) == Some(RpcErrorKind::RateLimited)
+        {
+            let backoff = self.rate_limiter.on_rate_limited(None);
+            log::debug!("Request rate limited, backing off for {:?}", backoff);
+            return true;
+        }
+        false
+    }
+}
+
+impl Transport for Web3Transport {
+    type Out = BoxFuture<'static, web3::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        // ids have to be unique for the whole connection, so they are kept by the connection
+        let id = match &self.connection {
+            Web3Connection::Http(http) => http.id.fetch_add(1, Ordering::AcqRel),
+            Web3Connection::Duplex(duplex) => duplex.id.fetch_add(1, Ordering::AcqRel),
+        };
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let transport = self.clone();
+        async move {
+            transport
+                .rate_limiter
+                .acquire(1, transport.request_timeout)
+                .await?;
+            let res = tokio::time::timeout(transport.request_timeout, async {
+                match &transport.connection {
+                    Web3Connection::Http(http) => http.send(request).await,
+                    Web3Connection::Duplex(duplex) => duplex.send(id, request).await,
+                }
+            })
+            .await
+            .unwrap_or(Err(web3::Error::Unreachable));
+            match &res {
+                Ok(_) => transport.rate_limiter.on_success(),
+                Err(err) => {
+                    transport.check_rate_limited(err);
+                }
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
+impl BatchTransport for Web3Transport {
+    type Batch = BoxFuture<'static, web3::Result<Vec<web3::Result<rpc::Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let mut requests = requests.into_iter().collect::<Vec<_>>();
+        // batch of one is sent as plain request, so batch size 1 works with endpoints not supporting batches
+        if requests.len() == 1 {
+            let (id, request) = requests.remove(0);
+            return self.send(id, request).map(|res| Ok(vec![res])).boxed();
+        }
+        let transport = self.clone();
+        async move {
+            // providers count every call of the batch
+            transport
+                .rate_limiter
+                .acquire(requests.len() as u64, transport.request_timeout)
+                .await?;
+            let res = tokio::time::timeout(transport.request_timeout, async {
+                match &transport.connection {
+                    Web3Connection::Http(http) => http.send_batch(requests).await,
+                    Web3Connection::Duplex(duplex) => duplex.send_batch(requests).await,
+                }
+            })
+            .await
+            .unwrap_or(Err(web3::Error::Unreachable));
+            let rate_limited = match &res {
+                Ok(results) => results
+                    .iter()
+                    .filter_map(|res| res.as_ref().err())
+                    .any(|err| transport.check_rate_limited(err)),
+                Err(err) => transport.check_rate_limited(err),
+            };
+            if !rate_limited && res.is_ok() {
+                transport.rate_limiter.on_success();
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
+impl DuplexTransport for Web3Transport {
+    type NotificationStream = BoxStream<'static, rpc::Value>;
+
+    fn subscribe(&self, id: SubscriptionId) -> web3::Result<Self::NotificationStream> {
+        match &self.connection {
+            Web3Connection::Http(_) => Err(web3::Error::Transport(TransportError::Message(
+                "Subscriptions require ws:// or IPC endpoint".to_string(),
+            ))),
+            Web3Connection::Duplex(duplex) => Ok(duplex
+                .current()
+                .ok_or(web3::Error::Unreachable)?
+                .subscribe(id)?
+                .boxed()),
+        }
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> web3::Result<()> {
+        match &self.connection {
+            Web3Connection::Http(_) => Ok(()),
+            Web3Connection::Duplex(duplex) => match duplex.current() {
+                Some(connection) => connection.unsubscribe(id),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_from_endpoint() {
+        let new =
+            |endpoint| Web3Transport::new(endpoint, Default::default(), Duration::from_secs(5));
+        assert!(matches!(
+            new("https://polygon-rpc.com").unwrap().connection,
+            Web3Connection::Http(_)
+        ));
+        assert!(matches!(
+            new("ws://127.0.0.1:8546").unwrap().connection,
+            Web3Connection::Duplex(LazyDuplexConnection {
+                target: DuplexTarget::WebSocket(_),
+                ..
+            })
+        ));
+        let transport = new("ipc:///tmp/geth.ipc").unwrap();
+        assert!(transport.supports_subscriptions());
+        assert!(matches!(
+            transport.connection,
+            Web3Connection::Duplex(LazyDuplexConnection {
+                target: DuplexTarget::Ipc(path),
+                ..
+            }) if path == Path::new("/tmp/geth.ipc")
+        ));
+        assert!(new("/tmp/geth.ipc").is_err());
+        assert!(new("polygon-rpc.com").is_err());
+        assert!(new("wss://127.0.0.1:8546").is_err());
+        assert!(new("ftp://127.0.0.1").is_err());
+    }
+}
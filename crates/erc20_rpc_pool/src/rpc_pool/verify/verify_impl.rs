@@ -1,3 +1,4 @@
+use crate::rpc_pool::transport::Web3Transport;
 use crate::rpc_pool::utils::datetime_from_u256_timestamp;
 use crate::rpc_pool::verify::{VerifyEndpointParams, VerifyEndpointStatus};
 use crate::rpc_pool::VerifyEndpointResult;
@@ -7,12 +8,11 @@ use parking_lot::RwLock;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::select;
-use web3::transports::Http;
 use web3::types::{BlockId, BlockNumber, U256};
 use web3::Web3;
 
 async fn verify_endpoint_int(
-    web3: &Web3<Http>,
+    web3: &Web3<Web3Transport>,
     name: &str,
     vep: VerifyEndpointParams,
 ) -> VerifyEndpointResult {
@@ -107,6 +107,15 @@ pub async fn verify_endpoint(chain_id: u64, m: Arc<RwLock<Web3RpcEndpoint>>, for
         }
     }
 
+    // rate limited endpoint would fail verification, keep previous result until back-off passes
+    if web3.transport().rate_limiter().is_limited() {
+        log::debug!(
+            "Verification of rate limited endpoint {} postponed",
+            web3_rpc_params.name
+        );
+        return;
+    }
+
     let verify_result = verify_endpoint_int(
         &web3,
         &web3_rpc_params.name,
@@ -117,6 +126,13 @@ pub async fn verify_endpoint(chain_id: u64, m: Arc<RwLock<Web3RpcEndpoint>>, for
         },
     )
     .await;
+    if web3.transport().rate_limiter().is_limited() {
+        log::debug!(
+            "Endpoint {} rate limited during verification",
+            web3_rpc_params.name
+        );
+        return;
+    }
 
     let mut web3_rpc_info = m
         .try_read_for(std::time::Duration::from_secs(5))
@@ -151,6 +167,7 @@ pub async fn verify_endpoint(chain_id: u64, m: Arc<RwLock<Web3RpcEndpoint>>, for
             VerifyEndpointResult::OtherNetworkError(_) => {}
             VerifyEndpointResult::HeadBehind(_) => {}
             VerifyEndpointResult::Unreachable => {}
+            VerifyEndpointResult::QuorumMismatch(_) => {}
         }
     }
     m.try_write_for(std::time::Duration::from_secs(5))
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
+/// Used when max batch size is not configured for the endpoint
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 20;
+
 pub struct VerifyEndpointParams {
     pub chain_id: u64,
     pub allow_max_head_behind_secs: Option<u64>,
@@ -26,6 +29,7 @@ pub enum VerifyEndpointResult {
     OtherNetworkError(String),
     HeadBehind(DateTime<Utc>),
     Unreachable,
+    QuorumMismatch(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,10 +46,23 @@ pub struct Web3EndpointParams {
     pub verify_interval_secs: u64,
     /// rate limit endpoint
     pub min_interval_requests_ms: Option<u64>,
+    /// token bucket limit of requests sent to the endpoint
+    #[serde(default)]
+    pub max_requests_per_second: Option<f64>,
+    /// max number of requests sent to the endpoint during UTC day
+    #[serde(default)]
+    pub daily_request_quota: Option<u64>,
     /// if head is behind this time mark endpoint as not available
     pub max_head_behind_secs: Option<u64>,
     /// limit response timeout
     pub max_response_time_ms: u64,
+    /// max number of calls sent in one JSON-RPC batch request, 1 disables batching
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+}
+
+fn default_max_batch_size() -> usize {
+    DEFAULT_MAX_BATCH_SIZE
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -140,4 +157,47 @@ pub struct Web3RpcInfo {
     pub removed_date: Option<DateTime<Utc>>,
 }
 
-impl Web3RpcInfo {}
+impl Web3RpcInfo {
+    /// Penalties are halved for every half_life elapsed, zero half_life clears them
+    pub fn decay_penalties(&mut self, elapsed: chrono::Duration, half_life: chrono::Duration) {
+        let factor = if half_life <= chrono::Duration::zero() {
+            0.0
+        } else {
+            0.5f64.powf(
+                elapsed.num_milliseconds().max(0) as f64 / half_life.num_milliseconds() as f64,
+            )
+        };
+        let decay = |penalty: i64| (penalty as f64 * factor) as i64;
+        self.penalty_from_last_critical_error = decay(self.penalty_from_last_critical_error);
+        self.penalty_from_errors = decay(self.penalty_from_errors);
+        self.penalty_from_head_behind = decay(self.penalty_from_head_behind);
+        self.penalty_from_ms = decay(self.penalty_from_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay_penalties() {
+        let mut info = Web3RpcInfo {
+            penalty_from_last_critical_error: 100,
+            penalty_from_errors: 40,
+            penalty_from_head_behind: 8,
+            penalty_from_ms: 3,
+            ..Default::default()
+        };
+        info.decay_penalties(
+            chrono::Duration::try_hours(2).unwrap(),
+            chrono::Duration::try_hours(1).unwrap(),
+        );
+        assert_eq!(info.penalty_from_last_critical_error, 25);
+        assert_eq!(info.penalty_from_errors, 10);
+        assert_eq!(info.penalty_from_head_behind, 2);
+        assert_eq!(info.penalty_from_ms, 0);
+
+        info.decay_penalties(chrono::Duration::zero(), chrono::Duration::zero());
+        assert_eq!(info.penalty_from_last_critical_error, 0);
+    }
+}
@@ -48,7 +48,12 @@ const EventBox = (_props: EventBoxProps) => {
     const { backendSettings } = useContext(BackendSettingsContext);
     const [events, setEvents] = React.useState<BalanceEvent[]>([]);
 
-    useWebSocket(backendSettings.backendUrl.replace("http://", "ws://") + "/event_stream", {
+    //browsers cannot set headers of websocket request, token is passed in query instead
+    let eventStreamUrl = backendSettings.backendUrl.replace("http://", "ws://") + "/event_stream";
+    if (backendSettings.enableBearerToken) {
+        eventStreamUrl += "?access_token=" + encodeURIComponent(backendSettings.bearerToken);
+    }
+    useWebSocket(eventStreamUrl, {
         onOpen: () => {
             console.log("WebSocket connection established.");
         },
@@ -20,6 +20,7 @@ interface Web3VerifyEndpointResult {
     otherNetworkError?: string;
     headBehind?: string;
     Unreachable?: string;
+    quorumMismatch?: string;
 }
 
 interface Web3RpcInfo {
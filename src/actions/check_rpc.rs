@@ -4,7 +4,7 @@ use erc20_payment_lib_common::err_custom_create;
 use erc20_payment_lib_common::error::PaymentError;
 use erc20_rpc_pool::{
     resolve_txt_record_to_string_array, Web3EndpointParams, Web3ExternalEndpointList, Web3RpcPool,
-    Web3RpcSingleParams,
+    Web3RpcSingleParams, DEFAULT_MAX_BATCH_SIZE,
 };
 use std::collections::HashSet;
 use std::time::Duration;
@@ -55,6 +55,11 @@ pub async fn check_rpc_local(
                             .max_consecutive_errors
                             .unwrap_or(5),
                         min_interval_requests_ms: rpc_settings.min_interval_ms,
+                        max_requests_per_second: rpc_settings.max_requests_per_second,
+                        daily_request_quota: rpc_settings.daily_request_quota,
+                        max_batch_size: rpc_settings
+                            .max_batch_size
+                            .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                     },
                     source_id: None,
                 };
@@ -112,6 +117,11 @@ pub async fn check_rpc_local(
                             .max_consecutive_errors
                             .unwrap_or(5),
                         min_interval_requests_ms: rpc_settings.min_interval_ms,
+                        max_requests_per_second: rpc_settings.max_requests_per_second,
+                        daily_request_quota: rpc_settings.daily_request_quota,
+                        max_batch_size: rpc_settings
+                            .max_batch_size
+                            .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                     },
                     source_id: None,
                 });
@@ -163,6 +173,11 @@ pub async fn check_rpc_local(
                             .max_consecutive_errors
                             .unwrap_or(5),
                         min_interval_requests_ms: rpc_settings.min_interval_ms,
+                        max_requests_per_second: rpc_settings.max_requests_per_second,
+                        daily_request_quota: rpc_settings.daily_request_quota,
+                        max_batch_size: rpc_settings
+                            .max_batch_size
+                            .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
                     },
                     source_id: None,
                 });
@@ -1,4 +1,135 @@
 pub mod close;
 pub mod create;
 pub mod details;
+pub mod extend;
 pub mod terminate;
+
+use chrono::Utc;
+use erc20_payment_lib::config::{Chain, Config};
+use erc20_payment_lib::eth::check_allowance;
+use erc20_payment_lib::process_allowance;
+use erc20_payment_lib::runtime::deposit_token;
+use erc20_payment_lib::setup::PaymentSetup;
+use erc20_payment_lib::signer::PrivateKeySigner;
+use erc20_payment_lib::utils::DecimalConvExt;
+use erc20_payment_lib_common::error::ErrorBag;
+use erc20_payment_lib_common::error::{AllowanceRequest, PaymentError};
+use erc20_payment_lib_common::DbPool;
+use erc20_payment_lib_common::{err_custom_create, err_from};
+use erc20_rpc_pool::Web3RpcPool;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use web3::types::{Address, U256};
+
+/// Account, chain, lock contract and its token used by deposit commands
+pub struct DepositContext<'a> {
+    pub public_addr: Address,
+    pub chain_cfg: &'a Chain,
+    pub lock_contract_address: Address,
+    pub token_address: Address,
+    pub token_decimals: u8,
+    pub payment_setup: PaymentSetup,
+    pub web3: Arc<Web3RpcPool>,
+}
+
+impl<'a> DepositContext<'a> {
+    pub async fn new(
+        config: &'a Config,
+        chain_name: &str,
+        address: Option<Address>,
+        account_no: Option<usize>,
+        public_addrs: &[Address],
+    ) -> Result<Self, PaymentError> {
+        let public_addr = if let Some(address) = address {
+            address
+        } else if let Some(account_no) = account_no {
+            *public_addrs
+                .get(account_no)
+                .expect("No public adss found with specified account_no")
+        } else {
+            *public_addrs.first().expect("No public adss found")
+        };
+        let chain_cfg = config.chain.get(chain_name).ok_or(err_custom_create!(
+            "Chain {} not found in config file",
+            chain_name
+        ))?;
+        let lock_contract_address =
+            chain_cfg
+                .lock_contract
+                .as_ref()
+                .map(|c| c.address)
+                .ok_or(err_custom_create!(
+                    "No lock contract found for chain {}",
+                    chain_name
+                ))?;
+
+        let payment_setup = PaymentSetup::new_empty(config)?;
+        let web3 = payment_setup.get_provider(chain_cfg.chain_id)?;
+        let (token_address, token_decimals) =
+            deposit_token(web3.clone(), lock_contract_address).await?;
+
+        Ok(Self {
+            public_addr,
+            chain_cfg,
+            lock_contract_address,
+            token_address,
+            token_decimals,
+            payment_setup,
+            web3,
+        })
+    }
+
+    /// Make sure lock contract is allowed to take given amount of deposit token
+    pub async fn ensure_allowance(
+        &self,
+        conn: &DbPool,
+        amount: Decimal,
+        signer: PrivateKeySigner,
+    ) -> Result<(), PaymentError> {
+        let allowance = check_allowance(
+            self.web3.clone(),
+            self.public_addr,
+            self.token_address,
+            self.lock_contract_address,
+        )
+        .await?;
+
+        if amount
+            .to_u256_from_token_decimal(self.token_decimals)
+            .map_err(err_from!())?
+            > allowance
+        {
+            let allowance_request = AllowanceRequest {
+                owner: format!("{:#x}", self.public_addr),
+                token_addr: format!("{:#x}", self.token_address),
+                spender_addr: format!("{:#x}", self.lock_contract_address),
+                chain_id: self.chain_cfg.chain_id,
+                amount: U256::MAX,
+            };
+
+            let _ = process_allowance(
+                conn,
+                &self.payment_setup,
+                &allowance_request,
+                Arc::new(Box::new(signer)),
+                None,
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
+
+/// Deposit valid to timestamp from --block-for or --block-until options
+pub fn deposit_timestamp(
+    block_for: Option<u64>,
+    block_until: Option<chrono::DateTime<Utc>>,
+) -> Option<u64> {
+    if let Some(block_for) = block_for {
+        let date_fut = Utc::now()
+            + chrono::Duration::try_seconds(block_for as i64).expect("Invalid value block_for");
+        Some(date_fut.timestamp() as u64)
+    } else {
+        block_until.map(|block_until| block_until.timestamp() as u64)
+    }
+}
@@ -1,9 +1,9 @@
+use crate::actions::deposit::DepositContext;
 use erc20_payment_lib::config::Config;
 use erc20_payment_lib::runtime::{close_deposit, CloseDepositOptionsInt};
-use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib_common::err_custom_create;
 use erc20_payment_lib_common::error::PaymentError;
-use sqlx::SqlitePool;
+use erc20_payment_lib_common::DbPool;
 use std::str::FromStr;
 use structopt::StructOpt;
 use web3::types::{Address, U256};
@@ -28,50 +28,34 @@ pub struct CloseDepositOptions {
 }
 
 pub async fn close_deposit_local(
-    conn: SqlitePool,
+    conn: DbPool,
     close_deposit_options: CloseDepositOptions,
     config: Config,
     public_addrs: &[Address],
 ) -> Result<(), PaymentError> {
-    log::info!("Making deposit...");
-    let public_addr = if let Some(address) = close_deposit_options.address {
-        address
-    } else if let Some(account_no) = close_deposit_options.account_no {
-        *public_addrs
-            .get(account_no)
-            .expect("No public adss found with specified account_no")
-    } else {
-        *public_addrs.first().expect("No public adss found")
-    };
-    let chain_cfg =
-        config
-            .chain
-            .get(&close_deposit_options.chain_name)
-            .ok_or(err_custom_create!(
-                "Chain {} not found in config file",
-                close_deposit_options.chain_name
-            ))?;
-
-    let payment_setup = PaymentSetup::new_empty(&config)?;
-    let web3 = payment_setup.get_provider(chain_cfg.chain_id)?;
+    log::info!("Closing deposit...");
+    let ctx = DepositContext::new(
+        &config,
+        &close_deposit_options.chain_name,
+        close_deposit_options.address,
+        close_deposit_options.account_no,
+        public_addrs,
+    )
+    .await?;
 
     let deposit_id = U256::from_str(&close_deposit_options.deposit_id)
         .map_err(|e| err_custom_create!("Invalid deposit id: {}", e))?;
 
     close_deposit(
-        web3,
+        ctx.web3,
         &conn,
-        chain_cfg.chain_id as u64,
-        public_addr,
+        ctx.chain_cfg.chain_id as u64,
+        ctx.public_addr,
         CloseDepositOptionsInt {
-            lock_contract_address: chain_cfg
-                .lock_contract
-                .clone()
-                .map(|c| c.address)
-                .expect("No lock contract found"),
+            lock_contract_address: ctx.lock_contract_address,
             deposit_id,
             skip_deposit_check: close_deposit_options.skip_check,
-            token_address: chain_cfg.token.address,
+            token_address: ctx.token_address,
         },
     )
     .await?;
@@ -1,20 +1,16 @@
 use crate::actions::check_address_name;
+use crate::actions::deposit::{deposit_timestamp, DepositContext};
 use chrono::Utc;
 use erc20_payment_lib::config::Config;
-use erc20_payment_lib::eth::{check_allowance, deposit_id_from_nonce};
-use erc20_payment_lib::process_allowance;
+use erc20_payment_lib::eth::deposit_id_from_nonce;
 use erc20_payment_lib::runtime::{make_deposit, CreateDepositOptionsInt};
-use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib::signer::PrivateKeySigner;
-use erc20_payment_lib::utils::DecimalConvExt;
-use erc20_payment_lib_common::error::ErrorBag;
-use erc20_payment_lib_common::error::{AllowanceRequest, PaymentError};
-use erc20_payment_lib_common::{err_custom_create, err_from};
+use erc20_payment_lib_common::err_custom_create;
+use erc20_payment_lib_common::error::PaymentError;
+use erc20_payment_lib_common::DbPool;
 use rand::Rng;
-use sqlx::SqlitePool;
-use std::sync::Arc;
 use structopt::StructOpt;
-use web3::types::{Address, U256};
+use web3::types::Address;
 
 #[derive(StructOpt)]
 #[structopt(about = "Create deposit for use by spender")]
@@ -56,7 +52,11 @@ pub struct CreateDepositOptions {
     #[structopt(long = "block-until", help = "Block until specified date")]
     pub block_until: Option<chrono::DateTime<Utc>>,
 
-    #[structopt(long = "block-for", help = "Block for number of seconds")]
+    #[structopt(
+        long = "block-for",
+        conflicts_with = "block-until",
+        help = "Block for number of seconds"
+    )]
     pub block_for: Option<u64>,
 
     #[structopt(
@@ -70,100 +70,36 @@ pub struct CreateDepositOptions {
 }
 
 pub async fn make_deposit_local(
-    conn: SqlitePool,
+    conn: DbPool,
     make_deposit_options: CreateDepositOptions,
     config: Config,
     public_addrs: &[Address],
     signer: PrivateKeySigner,
 ) -> Result<(), PaymentError> {
     log::info!("Making deposit...");
-    let public_addr = if let Some(address) = make_deposit_options.address {
-        address
-    } else if let Some(account_no) = make_deposit_options.account_no {
-        *public_addrs
-            .get(account_no)
-            .expect("No public adss found with specified account_no")
-    } else {
-        *public_addrs.first().expect("No public adss found")
-    };
-    let chain_cfg =
-        config
-            .chain
-            .get(&make_deposit_options.chain_name)
-            .ok_or(err_custom_create!(
-                "Chain {} not found in config file",
-                make_deposit_options.chain_name
-            ))?;
-
-    if make_deposit_options.block_for.is_some() && make_deposit_options.block_until.is_some() {
-        return Err(err_custom_create!(
-            "Cannot specify both block-for and block-until"
-        ));
-    }
+    let ctx = DepositContext::new(
+        &config,
+        &make_deposit_options.chain_name,
+        make_deposit_options.address,
+        make_deposit_options.account_no,
+        public_addrs,
+    )
+    .await?;
 
-    let timestamp = if let Some(block_for) = make_deposit_options.block_for {
-        let now = Utc::now();
-        let date_fut =
-            now + chrono::Duration::try_seconds(block_for as i64).expect("Invalid value block_for");
-        date_fut.timestamp() as u64
-    } else if let Some(block_until) = make_deposit_options.block_until {
-        block_until.timestamp() as u64
-    } else {
-        let now = Utc::now();
-        now.timestamp() as u64
-    };
-
-    let payment_setup = PaymentSetup::new_empty(&config)?;
-    let web3 = payment_setup.get_provider(chain_cfg.chain_id)?;
+    let timestamp = deposit_timestamp(
+        make_deposit_options.block_for,
+        make_deposit_options.block_until,
+    )
+    .unwrap_or_else(|| Utc::now().timestamp() as u64);
 
     if !make_deposit_options.skip_allowance {
-        let allowance = check_allowance(
-            web3.clone(),
-            public_addr,
-            chain_cfg.token.address,
-            chain_cfg
-                .lock_contract
-                .clone()
-                .map(|c| c.address)
-                .expect("No lock contract found"),
+        ctx.ensure_allowance(
+            &conn,
+            make_deposit_options.fee_amount.unwrap_or_default()
+                + make_deposit_options.amount.unwrap_or_default(),
+            signer,
         )
         .await?;
-
-        if (make_deposit_options.fee_amount.unwrap_or_default()
-            + make_deposit_options.amount.unwrap_or_default())
-        .to_u256_from_eth()
-        .map_err(err_from!())?
-            > allowance
-        {
-            let allowance_request = AllowanceRequest {
-                owner: format!("{:#x}", public_addr),
-                token_addr: format!("{:#x}", chain_cfg.token.address),
-                spender_addr: format!(
-                    "{:#x}",
-                    chain_cfg
-                        .lock_contract
-                        .clone()
-                        .map(|c| c.address)
-                        .expect("No mint contract")
-                ),
-                chain_id: chain_cfg.chain_id,
-                amount: U256::MAX,
-            };
-
-            let _ = process_allowance(
-                &conn.clone(),
-                &payment_setup,
-                &allowance_request,
-                Arc::new(Box::new(signer)),
-                None,
-            )
-            .await;
-            /*return Err(err_custom_create!(
-                "Not enough allowance, required: {}, available: {}",
-                deposit_tokens_options.amount.unwrap(),
-                allowance
-            ));*/
-        }
     }
 
     let deposit_nonce = make_deposit_options.deposit_nonce.unwrap_or_else(|| {
@@ -180,17 +116,12 @@ pub async fn make_deposit_local(
     })?;
 
     make_deposit(
-        web3,
+        ctx.web3,
         &conn,
-        chain_cfg.chain_id as u64,
-        public_addr,
-        chain_cfg.token.address,
+        ctx.chain_cfg.chain_id as u64,
+        ctx.public_addr,
         CreateDepositOptionsInt {
-            lock_contract_address: chain_cfg
-                .lock_contract
-                .clone()
-                .map(|c| c.address)
-                .expect("No lock contract found"),
+            lock_contract_address: ctx.lock_contract_address,
             spender,
             skip_balance_check: make_deposit_options.skip_balance_check,
             amount: make_deposit_options.amount,
@@ -202,7 +133,7 @@ pub async fn make_deposit_local(
     )
     .await?;
 
-    let deposit_id = deposit_id_from_nonce(public_addr, deposit_nonce);
+    let deposit_id = deposit_id_from_nonce(ctx.public_addr, deposit_nonce);
     println!(
         "make_deposit added to queue successfully nonce: {}, deposit_id: {:#x}",
         deposit_nonce, deposit_id
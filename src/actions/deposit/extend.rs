@@ -0,0 +1,130 @@
+use crate::actions::deposit::{deposit_timestamp, DepositContext};
+use chrono::Utc;
+use erc20_payment_lib::config::Config;
+use erc20_payment_lib::eth::deposit_id_from_nonce;
+use erc20_payment_lib::runtime::{extend_deposit, ExtendDepositOptionsInt};
+use erc20_payment_lib::signer::PrivateKeySigner;
+use erc20_payment_lib_common::err_custom_create;
+use erc20_payment_lib_common::error::PaymentError;
+use erc20_payment_lib_common::DbPool;
+use std::str::FromStr;
+use structopt::StructOpt;
+use web3::types::{Address, U256};
+
+#[derive(StructOpt)]
+#[structopt(about = "Top up or prolong deposit if you are funder")]
+pub struct ExtendDepositOptions {
+    #[structopt(short = "c", long = "chain-name", default_value = "holesky")]
+    pub chain_name: String,
+
+    #[structopt(long = "address", help = "Address (has to have private key)")]
+    pub address: Option<Address>,
+
+    #[structopt(long = "account-no", help = "Address by index (for convenience)")]
+    pub account_no: Option<usize>,
+
+    #[structopt(long = "deposit-id", help = "Deposit id to extend.")]
+    pub deposit_id: Option<String>,
+
+    #[structopt(long = "deposit-nonce", help = "Deposit nonce to extend.")]
+    pub deposit_nonce: Option<u64>,
+
+    #[structopt(
+        short = "a",
+        long = "amount",
+        help = "Extra amount (decimal, full precision, i.e. 0.01)"
+    )]
+    pub amount: Option<rust_decimal::Decimal>,
+
+    #[structopt(
+        long = "fee-amount",
+        help = "Extra fee amount (decimal, full precision, i.e. 0.01)"
+    )]
+    pub fee_amount: Option<rust_decimal::Decimal>,
+
+    #[structopt(long = "block-until", help = "Block until specified date")]
+    pub block_until: Option<chrono::DateTime<Utc>>,
+
+    #[structopt(
+        long = "block-for",
+        conflicts_with = "block-until",
+        help = "Block for number of seconds from now"
+    )]
+    pub block_for: Option<u64>,
+
+    #[structopt(long = "skip-check", help = "Skip check deposit")]
+    pub skip_check: bool,
+
+    #[structopt(long = "skip-balance", help = "Skip balance check")]
+    pub skip_balance_check: bool,
+
+    #[structopt(long = "skip-allowance", help = "Skip allowance check")]
+    pub skip_allowance: bool,
+}
+
+pub async fn extend_deposit_local(
+    conn: DbPool,
+    extend_deposit_options: ExtendDepositOptions,
+    config: Config,
+    public_addrs: &[Address],
+    signer: PrivateKeySigner,
+) -> Result<(), PaymentError> {
+    log::info!("Extending deposit...");
+    let ctx = DepositContext::new(
+        &config,
+        &extend_deposit_options.chain_name,
+        extend_deposit_options.address,
+        extend_deposit_options.account_no,
+        public_addrs,
+    )
+    .await?;
+
+    let timestamp = deposit_timestamp(
+        extend_deposit_options.block_for,
+        extend_deposit_options.block_until,
+    );
+
+    let deposit_id = match (
+        extend_deposit_options.deposit_id,
+        extend_deposit_options.deposit_nonce,
+    ) {
+        (Some(deposit_id), None) => U256::from_str(&deposit_id)
+            .map_err(|e| err_custom_create!("Invalid deposit id: {}", e))?,
+        (None, Some(deposit_nonce)) => deposit_id_from_nonce(ctx.public_addr, deposit_nonce),
+        (Some(_), Some(_)) => {
+            return Err(err_custom_create!("Invalid parameters: only one of `deposit_id` or `deposit_nonce` should be provided to extend a deposit"));
+        }
+        (None, None) => {
+            return Err(err_custom_create!("Missing required parameters: either `deposit_id` or `deposit_nonce` must be provided to extend a deposit"));
+        }
+    };
+
+    let extra_total = extend_deposit_options.fee_amount.unwrap_or_default()
+        + extend_deposit_options.amount.unwrap_or_default();
+    if !extend_deposit_options.skip_allowance && !extra_total.is_zero() {
+        ctx.ensure_allowance(&conn, extra_total, signer).await?;
+    }
+
+    extend_deposit(
+        ctx.web3,
+        &conn,
+        ctx.chain_cfg.chain_id as u64,
+        ctx.public_addr,
+        ExtendDepositOptionsInt {
+            lock_contract_address: ctx.lock_contract_address,
+            skip_deposit_check: extend_deposit_options.skip_check,
+            skip_balance_check: extend_deposit_options.skip_balance_check,
+            deposit_id,
+            extra_amount: extend_deposit_options.amount,
+            extra_fee: extend_deposit_options.fee_amount,
+            timestamp,
+        },
+    )
+    .await?;
+
+    println!(
+        "extend_deposit added to queue successfully deposit id: {:#x}",
+        deposit_id,
+    );
+    Ok(())
+}
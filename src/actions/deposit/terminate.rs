@@ -1,10 +1,10 @@
+use crate::actions::deposit::DepositContext;
 use erc20_payment_lib::config::Config;
 use erc20_payment_lib::eth::deposit_id_from_nonce;
 use erc20_payment_lib::runtime::{terminate_deposit, TerminateDepositOptionsInt};
-use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib_common::err_custom_create;
 use erc20_payment_lib_common::error::PaymentError;
-use sqlx::SqlitePool;
+use erc20_payment_lib_common::DbPool;
 use std::str::FromStr;
 use structopt::StructOpt;
 use web3::types::{Address, U256};
@@ -32,31 +32,20 @@ pub struct TerminateDepositOptions {
 }
 
 pub async fn terminate_deposit_local(
-    conn: SqlitePool,
+    conn: DbPool,
     terminate_deposit_options: TerminateDepositOptions,
     config: Config,
     public_addrs: &[Address],
 ) -> Result<(), PaymentError> {
-    log::info!("Making deposit...");
-    let public_addr = if let Some(address) = terminate_deposit_options.address {
-        address
-    } else if let Some(account_no) = terminate_deposit_options.account_no {
-        *public_addrs
-            .get(account_no)
-            .expect("No public adss found with specified account_no")
-    } else {
-        *public_addrs.first().expect("No public adss found")
-    };
-    let chain_cfg = config
-        .chain
-        .get(&terminate_deposit_options.chain_name)
-        .ok_or(err_custom_create!(
-            "Chain {} not found in config file",
-            terminate_deposit_options.chain_name
-        ))?;
-
-    let payment_setup = PaymentSetup::new_empty(&config)?;
-    let web3 = payment_setup.get_provider(chain_cfg.chain_id)?;
+    log::info!("Terminating deposit...");
+    let ctx = DepositContext::new(
+        &config,
+        &terminate_deposit_options.chain_name,
+        terminate_deposit_options.address,
+        terminate_deposit_options.account_no,
+        public_addrs,
+    )
+    .await?;
 
     if terminate_deposit_options.deposit_id.is_some()
         && terminate_deposit_options.deposit_nonce.is_some()
@@ -70,7 +59,7 @@ pub async fn terminate_deposit_local(
     ) {
         (Some(deposit_id), None) => U256::from_str(&deposit_id)
             .map_err(|e| err_custom_create!("Invalid deposit id: {}", e))?,
-        (None, Some(deposit_nonce)) => deposit_id_from_nonce(public_addr, deposit_nonce),
+        (None, Some(deposit_nonce)) => deposit_id_from_nonce(ctx.public_addr, deposit_nonce),
         (Some(_), Some(_)) => {
             return Err(err_custom_create!("Invalid parameters: only one of `deposit_id` or `deposit_nonce` should be provided to terminate a deposit"));
         }
@@ -80,16 +69,12 @@ pub async fn terminate_deposit_local(
     };
 
     terminate_deposit(
-        web3,
+        ctx.web3,
         &conn,
-        chain_cfg.chain_id as u64,
-        public_addr,
+        ctx.chain_cfg.chain_id as u64,
+        ctx.public_addr,
         TerminateDepositOptionsInt {
-            lock_contract_address: chain_cfg
-                .lock_contract
-                .clone()
-                .map(|c| c.address)
-                .expect("No lock contract found"),
+            lock_contract_address: ctx.lock_contract_address,
             deposit_id,
             skip_deposit_check: terminate_deposit_options.skip_check,
         },
@@ -1,21 +1,21 @@
 use crate::options::ScanBlockchainOptions;
 use erc20_payment_lib::config::{Chain, Config};
-use erc20_payment_lib::service::transaction_from_chain_and_into_db;
+use erc20_payment_lib::service::transactions_from_chain_and_into_db;
 use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib::transaction::{import_erc20_txs, ImportErc20TxsArgs};
 use erc20_payment_lib_common::error::ErrorBag;
 use erc20_payment_lib_common::error::PaymentError;
 use erc20_payment_lib_common::model::ScanDaoDbObj;
 use erc20_payment_lib_common::ops::{delete_scan_info, get_scan_info, upsert_scan_info};
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{err_custom_create, err_from};
 use erc20_rpc_pool::Web3RpcPool;
-use sqlx::SqlitePool;
 use std::str::FromStr;
 use std::sync::Arc;
 use web3::types::Address;
 
 async fn scan_int(
-    conn: SqlitePool,
+    conn: DbPool,
     scan_blockchain_options: &ScanBlockchainOptions,
     chain_cfg: Chain,
     web3: Arc<Web3RpcPool>,
@@ -25,7 +25,7 @@ async fn scan_int(
 ) -> Result<(), PaymentError> {
     let txs = import_erc20_txs(ImportErc20TxsArgs {
         web3: web3.clone(),
-        erc20_address: chain_cfg.token.address,
+        erc20_addresses: chain_cfg.all_tokens().map(|t| t.address).collect(),
         chain_id: chain_cfg.chain_id,
         filter_by_senders: sender.map(|sender| [sender].to_vec()),
         filter_by_receivers: None,
@@ -39,18 +39,29 @@ async fn scan_int(
         e
     })?;
 
+    let results = match transactions_from_chain_and_into_db(
+        web3.clone(),
+        &conn.clone(),
+        chain_cfg.chain_id,
+        &txs,
+        &chain_cfg
+            .all_tokens()
+            .map(|t| t.address)
+            .collect::<Vec<_>>(),
+        scan_blockchain_options.import_balances,
+    )
+    .await
+    {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!("Error when getting transactions from chain: {}", e);
+            Vec::new()
+        }
+    };
+
     let mut max_block_from_tx = None;
-    for tx in &txs {
-        match transaction_from_chain_and_into_db(
-            web3.clone(),
-            &conn.clone(),
-            chain_cfg.chain_id,
-            &format!("{tx:#x}"),
-            chain_cfg.token.address,
-            scan_blockchain_options.import_balances,
-        )
-        .await
-        {
+    for result in results {
+        match result {
             Ok(Some(chain_tx)) => {
                 if chain_tx.block_number > max_block_from_tx.unwrap_or(0) {
                     max_block_from_tx = Some(chain_tx.block_number);
@@ -69,7 +80,7 @@ async fn scan_int(
 
 #[allow(clippy::too_many_arguments)]
 async fn scan_auto_step(
-    conn: SqlitePool,
+    conn: DbPool,
     scan_blockchain_options: &ScanBlockchainOptions,
     chain_cfg: Chain,
     web3: Arc<Web3RpcPool>,
@@ -185,7 +196,7 @@ async fn scan_auto_step(
 }
 
 pub async fn scan_blockchain_local(
-    conn: SqlitePool,
+    conn: DbPool,
     scan_blockchain_options: ScanBlockchainOptions,
     config: Config,
 ) -> Result<(), PaymentError> {
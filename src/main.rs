@@ -8,10 +8,10 @@ use actix_web::{web, App, HttpServer};
 use csv::ReaderBuilder;
 use erc20_payment_lib::config::{AdditionalOptions, RpcSettings};
 use erc20_payment_lib::signer::PrivateKeySigner;
-use erc20_payment_lib_common::create_sqlite_connection;
+use erc20_payment_lib_common::create_db_connection;
 use erc20_payment_lib_common::error::*;
 use erc20_payment_lib_common::ops::{
-    get_next_transactions_to_process, insert_token_transfer,
+    get_next_transactions_to_process, get_token_transfer_by_payment_id,
     insert_token_transfer_with_deposit_check, update_token_transfer,
 };
 use erc20_payment_lib_common::*;
@@ -30,18 +30,22 @@ use crate::actions::check_rpc::check_rpc_local;
 use crate::actions::deposit::close::close_deposit_local;
 use crate::actions::deposit::create::make_deposit_local;
 use crate::actions::deposit::details::deposit_details_local;
+use crate::actions::deposit::extend::extend_deposit_local;
 use crate::actions::deposit::terminate::terminate_deposit_local;
 use crate::stats::{export_stats, run_stats};
 use erc20_payment_lib::faucet_client::faucet_donate;
 use erc20_payment_lib::misc::gen_private_keys;
 use erc20_payment_lib::runtime::{
-    get_token_balance, mint_golem_token, remove_last_unsent_transactions, remove_transaction_force,
+    amend_transfer, cancel_transfer, get_token_balance, mint_golem_token,
+    remove_last_unsent_transactions, remove_transaction_force, AmendTransferArgs,
     PaymentRuntimeArgs,
 };
 use erc20_payment_lib::server::web::{runtime_web_scope, ServerData};
 use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib_common::init_metrics;
-use erc20_payment_lib_common::model::TokenTransferDbObj;
+use erc20_payment_lib_common::model::{
+    check_transfer_priority, TokenTransferDbObj, TokenTransferStatus,
+};
 use erc20_payment_lib_common::utils::{DecimalConvExt, StringConvExt};
 use erc20_payment_lib_extra::{account_balance, generate_test_payments};
 use rust_decimal::Decimal;
@@ -75,6 +79,12 @@ async fn main_internal() -> Result<(), PaymentError> {
         PaymentCommands::MintTestTokens { .. } => {}
         PaymentCommands::Deposit { .. } => {}
         PaymentCommands::Transfer { .. } => {}
+        PaymentCommands::CancelTransfer { .. } => {
+            private_key_load_needed = false;
+        }
+        PaymentCommands::AmendTransfer { .. } => {
+            private_key_load_needed = false;
+        }
         PaymentCommands::Balance { .. } => {}
         PaymentCommands::ImportPayments { .. } => {}
         PaymentCommands::ScanBlockchain { .. } => {}
@@ -96,7 +106,7 @@ async fn main_internal() -> Result<(), PaymentError> {
 
     let mut config = match config::Config::load("config-payments.toml").await {
         Ok(c) => c,
-        Err(err) => match err.inner {
+        Err(err) => match *err.inner {
             ErrorBag::IoError(_) => {
                 log::info!("No local config found, using default config");
                 config::Config::default_config()
@@ -128,10 +138,16 @@ async fn main_internal() -> Result<(), PaymentError> {
                     skip_validation: None,
                     verify_interval_secs: None,
                     min_interval_ms: None,
+                    max_requests_per_second: None,
+                    daily_request_quota: None,
                     max_timeout_ms: None,
                     allowed_head_behind_secs: None,
                     backup_level: None,
                     max_consecutive_errors: None,
+                    max_batch_size: None,
+                    quorum_size: None,
+                    quorum_min_agree: None,
+                    quorum_methods: None,
                     dns_source: None,
                     json_source: None,
                 })
@@ -162,27 +178,31 @@ async fn main_internal() -> Result<(), PaymentError> {
 
     let db_filename = cli.sqlite_db_file;
     let conn = if db_connection_needed {
-        if cli.sqlite_read_only {
-            log::info!(
-                "Connecting read only to db: {} (journal mode: {})",
-                db_filename.display(),
-                cli.sqlite_journal
-            );
+        let conn = if let Some(db_url) = &cli.db_url {
+            log::info!("Connecting to db given by url");
+            create_db_connection(db_url, cli.sqlite_read_only, !cli.skip_migrations).await?
         } else {
-            log::info!(
-                "Connecting read/write connection to db: {} (journal mode: {})",
-                db_filename.display(),
-                cli.sqlite_journal
-            );
-        }
-        env::set_var("ERC20_LIB_SQLITE_JOURNAL_MODE", cli.sqlite_journal);
-        let conn = create_sqlite_connection(
-            Some(&db_filename),
-            None,
-            cli.sqlite_read_only,
-            !cli.skip_migrations,
-        )
-        .await?;
+            if cli.sqlite_read_only {
+                log::info!(
+                    "Connecting read only to db: {} (journal mode: {})",
+                    db_filename.display(),
+                    cli.sqlite_journal
+                );
+            } else {
+                log::info!(
+                    "Connecting read/write connection to db: {} (journal mode: {})",
+                    db_filename.display(),
+                    cli.sqlite_journal
+                );
+            }
+            env::set_var("ERC20_LIB_SQLITE_JOURNAL_MODE", cli.sqlite_journal);
+            create_db_connection(
+                &db_filename.to_string_lossy(),
+                cli.sqlite_read_only,
+                !cli.skip_migrations,
+            )
+            .await?
+        };
         Some(conn)
     } else {
         None
@@ -234,6 +254,7 @@ async fn main_internal() -> Result<(), PaymentError> {
                     db_connection: Arc::new(Mutex::new(conn.clone().unwrap())),
                     payment_setup: sp.setup.clone(),
                     payment_runtime: sp,
+                    api_nonces: Default::default(),
                 }));
 
                 let server = HttpServer::new(move || {
@@ -385,6 +406,18 @@ async fn main_internal() -> Result<(), PaymentError> {
                 )
                 .await?;
             }
+            DepositCommands::Extend {
+                extend_deposit_options,
+            } => {
+                extend_deposit_local(
+                    conn.clone().unwrap(),
+                    extend_deposit_options,
+                    config,
+                    &public_addrs,
+                    signer,
+                )
+                .await?;
+            }
             DepositCommands::Check {
                 check_deposit_options,
             } => {
@@ -418,12 +451,14 @@ async fn main_internal() -> Result<(), PaymentError> {
 
             #[allow(clippy::if_same_then_else)]
             let token = if single_transfer_options.token == "glm" {
-                Some(format!("{:#x}", chain_cfg.token.address))
+                Some(chain_cfg.token.address)
             } else if single_transfer_options.token == "eth" {
                 None
             } else if single_transfer_options.token == "matic" {
                 //matic is the same as eth
                 None
+            } else if let Some(token) = chain_cfg.find_token(&single_transfer_options.token) {
+                Some(token.address)
             } else {
                 return Err(err_custom_create!(
                     "Unknown token: {}",
@@ -444,24 +479,38 @@ async fn main_internal() -> Result<(), PaymentError> {
             };
             //let mut db_transaction = conn.clone().unwrap().begin().await.unwrap();
 
+            let payment_setup = PaymentSetup::new_empty(&config)?;
+            let decimals = if let Some(token) = token {
+                payment_setup
+                    .chain_setup
+                    .get(&chain_cfg.chain_id)
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in setup",
+                        chain_cfg.chain_id
+                    ))?
+                    .token_decimals(token)
+                    .await?
+            } else {
+                18
+            };
+
             let amount_str = if let Some(amount) = single_transfer_options.amount {
-                amount.to_u256_from_eth().unwrap().to_string()
+                amount
+                    .to_u256_from_token_decimal(decimals)
+                    .map_err(err_from!())?
+                    .to_string()
             } else if single_transfer_options.all {
-                let payment_setup = PaymentSetup::new_empty(&config)?;
                 {
-                    #[allow(clippy::if_same_then_else)]
-                    if single_transfer_options.token == "glm" {
+                    if let Some(token) = token {
                         get_token_balance(
                             payment_setup.get_provider(chain_cfg.chain_id)?,
-                            chain_cfg.token.address,
+                            token,
                             public_addr,
                             None,
                         )
                         .await?
                         .to_string()
-                    } else if single_transfer_options.token == "eth"
-                        || single_transfer_options.token == "matic"
-                    {
+                    } else {
                         let val = payment_setup
                             .get_provider(chain_cfg.chain_id)?
                             .eth_balance(public_addr, None)
@@ -479,17 +528,14 @@ async fn main_internal() -> Result<(), PaymentError> {
                             ));
                         }
                         (val - gas_val).to_string()
-                    } else {
-                        return Err(err_custom_create!(
-                            "Unknown token: {}",
-                            single_transfer_options.token
-                        ));
                     }
                 }
             } else {
                 return Err(err_custom_create!("No amount specified"));
             };
-            let amount_decimal = amount_str.to_eth().unwrap();
+            let amount_decimal = amount_str.to_token_decimal(decimals).map_err(err_from!())?;
+            check_transfer_priority(single_transfer_options.priority)
+                .map_err(|err| err_custom_create!("{err}"))?;
 
             let mut tt = insert_token_transfer_with_deposit_check(
                 &conn.clone().unwrap(),
@@ -499,7 +545,7 @@ async fn main_internal() -> Result<(), PaymentError> {
                     from_addr: format!("{:#x}", public_addr),
                     receiver_addr: format!("{:#x}", recipient),
                     chain_id: chain_cfg.chain_id,
-                    token_addr: token,
+                    token_addr: token.map(|token| format!("{:#x}", token)),
                     token_amount: amount_str,
                     deposit_id: single_transfer_options.deposit_id,
                     deposit_finish: 0,
@@ -508,6 +554,9 @@ async fn main_internal() -> Result<(), PaymentError> {
                     paid_date: None,
                     fee_paid: None,
                     error: None,
+                    status: TokenTransferStatus::Queued,
+                    priority: single_transfer_options.priority,
+                    deadline: None,
                 },
             )
             .await
@@ -525,6 +574,93 @@ async fn main_internal() -> Result<(), PaymentError> {
                 payment_id
             );
         }
+        PaymentCommands::CancelTransfer {
+            cancel_transfer_options,
+        } => {
+            let chain_cfg = config
+                .chain
+                .get(&cancel_transfer_options.chain_name)
+                .ok_or(err_custom_create!(
+                    "Chain {} not found in config file",
+                    cancel_transfer_options.chain_name
+                ))?;
+            let transfer = cancel_transfer(
+                &conn.clone().unwrap(),
+                chain_cfg.chain_id,
+                cancel_transfer_options.address,
+                &cancel_transfer_options.payment_id,
+            )
+            .await?;
+            println!(
+                "Transfer {} cancelled, id: {}",
+                cancel_transfer_options.payment_id, transfer.id
+            );
+        }
+        PaymentCommands::AmendTransfer {
+            amend_transfer_options,
+        } => {
+            let chain_cfg =
+                config
+                    .chain
+                    .get(&amend_transfer_options.chain_name)
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in config file",
+                        amend_transfer_options.chain_name
+                    ))?;
+            let receiver = match &amend_transfer_options.recipient {
+                Some(recipient) => Some(check_address_name(recipient).map_err(err_from!())?),
+                None => None,
+            };
+            let amount = if let Some(amount) = amend_transfer_options.amount {
+                let transfer = get_token_transfer_by_payment_id(
+                    &conn.clone().unwrap(),
+                    chain_cfg.chain_id,
+                    &format!("{:#x}", amend_transfer_options.address),
+                    &amend_transfer_options.payment_id,
+                )
+                .await
+                .map_err(err_from!())?
+                .ok_or(err_custom_create!(
+                    "Transfer {} not found",
+                    amend_transfer_options.payment_id
+                ))?;
+                let decimals = if let Some(token_addr) = &transfer.token_addr {
+                    let payment_setup = PaymentSetup::new_empty(&config)?;
+                    payment_setup
+                        .chain_setup
+                        .get(&transfer.chain_id)
+                        .ok_or(err_custom_create!(
+                            "Chain {} not found in setup",
+                            transfer.chain_id
+                        ))?
+                        .token_decimals(
+                            web3::types::Address::from_str(token_addr).map_err(err_from!())?,
+                        )
+                        .await?
+                } else {
+                    18
+                };
+                Some(
+                    amount
+                        .to_u256_from_token_decimal(decimals)
+                        .map_err(err_from!())?,
+                )
+            } else {
+                None
+            };
+            let transfer = amend_transfer(
+                &conn.clone().unwrap(),
+                chain_cfg.chain_id,
+                amend_transfer_options.address,
+                &amend_transfer_options.payment_id,
+                AmendTransferArgs { receiver, amount },
+            )
+            .await?;
+            println!(
+                "Transfer {} amended, receiver: {}, amount: {}",
+                amend_transfer_options.payment_id, transfer.receiver_addr, transfer.token_amount
+            );
+        }
         PaymentCommands::Balance {
             account_balance_options,
         } => {
@@ -570,7 +706,7 @@ async fn main_internal() -> Result<(), PaymentError> {
         } => scan_blockchain_local(conn.clone().unwrap(), scan_blockchain_options, config).await?,
         PaymentCommands::ImportPayments { import_options } => {
             log::info!("importing payments from file: {}", import_options.file);
-            if !cli.sqlite_read_only {
+            if cli.sqlite_read_only {
                 return Err(err_custom_create!(
                     "Cannot import payments in read-only mode"
                 ));
@@ -595,18 +731,20 @@ async fn main_internal() -> Result<(), PaymentError> {
                             ))?;
 
                         if let Some(token_addr) = &token_transfer.token_addr {
-                            if format!("{:#x}", chain_cfg.token.address)
-                                != token_addr.to_lowercase()
-                            {
+                            if chain_cfg.find_token(token_addr).is_none() {
                                 return Err(err_custom_create!(
-                                    "Token address in line {} is different from default token address {} != {:#x}",
+                                    "Token address in line {} is not configured for chain {}: {}",
                                     line_no,
-                                    token_addr.to_lowercase(),
-                                    chain_cfg.token.address
+                                    token_transfer.chain_id,
+                                    token_addr.to_lowercase()
                                 ));
                             }
                         }
 
+                        check_transfer_priority(token_transfer.priority).map_err(|err| {
+                            err_custom_create!("Invalid priority in line {}: {}", line_no, err)
+                        })?;
+
                         token_transfer_list.push(token_transfer);
                     }
                     Err(e) => {
@@ -620,10 +758,33 @@ async fn main_internal() -> Result<(), PaymentError> {
                 token_transfer_list.len(),
                 import_options.file
             );
+            let mut already_exists_count = 0;
             for token_transfer in token_transfer_list {
-                insert_token_transfer(&conn.clone().unwrap(), &token_transfer)
-                    .await
-                    .map_err(err_from!())?;
+                match insert_token_transfer_with_deposit_check(
+                    &conn.clone().unwrap(),
+                    &token_transfer,
+                )
+                .await
+                {
+                    Ok(_) => {}
+                    Err(err) => match *err.inner {
+                        ErrorBag::TransferAlreadyExists(existing) => {
+                            log::warn!(
+                                "Transfer with payment id {} already exists, skipping: {:?}",
+                                existing.payment_id.as_deref().unwrap_or_default(),
+                                existing
+                            );
+                            already_exists_count += 1;
+                        }
+                        _ => return Err(err),
+                    },
+                }
+            }
+            if already_exists_count > 0 {
+                log::info!(
+                    "Skipped {} transfers that already exist in db",
+                    already_exists_count
+                );
             }
         }
         PaymentCommands::DecryptKeyStore { decrypt_options } => {
@@ -1,6 +1,7 @@
 use crate::options::{ExportHistoryStatsOptions, PaymentStatsOptions};
 use erc20_payment_lib::config::Config;
-use erc20_payment_lib_common::create_sqlite_connection;
+use erc20_payment_lib::setup::PaymentSetup;
+use erc20_payment_lib_common::create_sqlite_pool;
 use erc20_payment_lib_common::error::ErrorBag;
 use erc20_payment_lib_common::error::PaymentError;
 use erc20_payment_lib_common::model::ChainTxDbObj;
@@ -9,16 +10,17 @@ use erc20_payment_lib_common::ops::{
     get_transfer_stats_from_blockchain, TransferStatsPart,
 };
 use erc20_payment_lib_common::utils::{u256_eth_from_str, U256ConvExt};
+use erc20_payment_lib_common::DbPool;
 use erc20_payment_lib_common::{err_custom_create, err_from};
 use itertools::Itertools;
 use rust_decimal::Decimal;
-use sqlx::{Executor, SqlitePool};
+use sqlx::Executor;
 use std::collections::HashMap;
 use std::{env, fs};
-use web3::types::{H160, U256};
+use web3::types::H160;
 
 pub async fn export_stats(
-    conn: SqlitePool,
+    conn: DbPool,
     payment_stats_options: ExportHistoryStatsOptions,
     config: &Config,
 ) -> Result<(), PaymentError> {
@@ -34,7 +36,7 @@ pub async fn export_stats(
     let time_started = chrono::Utc::now();
 
     env::set_var("ERC20_LIB_SQLITE_JOURNAL_MODE", "Wal");
-    let export_conn = create_sqlite_connection(
+    let export_conn = create_sqlite_pool(
         Some(&payment_stats_options.export_sqlite_file),
         None,
         false,
@@ -114,7 +116,7 @@ pub async fn export_stats(
 }
 
 pub async fn run_stats(
-    conn: SqlitePool,
+    conn: DbPool,
     payment_stats_options: PaymentStatsOptions,
     config: &Config,
 ) -> Result<(), PaymentError> {
@@ -127,6 +129,39 @@ pub async fn run_stats(
                 payment_stats_options.chain_name
             ))?;
 
+    // decimals are taken from config, chain is asked only for tokens without them
+    let mut payment_setup = None;
+    let mut tokens = Vec::new();
+    for token in chain_cfg.all_tokens() {
+        let decimals = match token.decimals {
+            Some(decimals) => decimals,
+            None => {
+                if payment_setup.is_none() {
+                    payment_setup = Some(PaymentSetup::new_empty(config)?);
+                }
+                let chain_setup = payment_setup
+                    .as_ref()
+                    .and_then(|setup| setup.chain_setup.get(&chain_cfg.chain_id))
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in setup",
+                        chain_cfg.chain_id
+                    ))?;
+                chain_setup
+                    .token_decimals(token.address)
+                    .await
+                    .map_err(|err| {
+                        err_custom_create!(
+                            "Cannot get decimals of token {} ({:#x}), set decimals in config: {}",
+                            token.symbol,
+                            token.address,
+                            err
+                        )
+                    })?
+            }
+        };
+        tokens.push((token, decimals));
+    }
+
     let mut metrics = String::new();
 
     println!(
@@ -169,20 +204,26 @@ pub async fn run_stats(
             stats.per_receiver.len(),
         );
 
-        let token_transferred = stats
-            .all
-            .erc20_token_transferred
-            .get(&chain_cfg.token.address)
-            .copied();
-
-        metrics += &format!(
-            "{}\n{}\nerc20_transferred{{chain_id=\"{}\", sender=\"{:#x}\"}} {}\n",
-            "# HELP erc20_transferred Number of distinct receivers",
-            "# TYPE erc20_transferred counter",
-            chain_cfg.chain_id,
-            sender,
-            token_transferred.unwrap_or_default().to_eth().unwrap(),
-        );
+        for (token, decimals) in &tokens {
+            let token_transferred = stats
+                .all
+                .erc20_token_transferred
+                .get(&token.address)
+                .copied();
+
+            metrics += &format!(
+                "{}\n{}\nerc20_transferred{{chain_id=\"{}\", sender=\"{:#x}\", token=\"{}\"}} {}\n",
+                "# HELP erc20_transferred Number of distinct receivers",
+                "# TYPE erc20_transferred counter",
+                chain_cfg.chain_id,
+                sender,
+                token.symbol,
+                token_transferred
+                    .unwrap_or_default()
+                    .to_token_decimal(*decimals)
+                    .unwrap(),
+            );
+        }
 
         metrics += &format!(
             "{}\n{}\npayment_count{{chain_id=\"{}\", sender=\"{:#x}\"}} {}\n",
@@ -267,16 +308,22 @@ pub async fn run_stats(
         "Native token sent: {}",
         main_sender.1.all.native_token_transferred.to_eth().unwrap()
     );
-    let token_transferred = main_sender
-        .1
-        .all
-        .erc20_token_transferred
-        .get(&chain_cfg.token.address)
-        .copied();
-    println!(
-        "Erc20 token sent: {}",
-        token_transferred.unwrap_or_default().to_eth().unwrap()
-    );
+    for (token, decimals) in &tokens {
+        let token_transferred = main_sender
+            .1
+            .all
+            .erc20_token_transferred
+            .get(&token.address)
+            .copied();
+        println!(
+            "Erc20 token sent ({}): {}",
+            token.symbol,
+            token_transferred
+                .unwrap_or_default()
+                .to_token_decimal(*decimals)
+                .unwrap()
+        );
+    }
 
     let per_receiver = main_sender.1.per_receiver.clone();
     let mut per_receiver: Vec<(H160, TransferStatsPart)> = per_receiver.into_iter().collect();
@@ -292,8 +339,16 @@ pub async fn run_stats(
         });
     } else if payment_stats_options.order_by == "token_sent" {
         per_receiver.sort_by(|r, b| {
-            let left = *r.1.erc20_token_transferred.iter().next().unwrap().1;
-            let right = *b.1.erc20_token_transferred.iter().next().unwrap().1;
+            let left =
+                r.1.erc20_token_transferred
+                    .get(&chain_cfg.token.address)
+                    .copied()
+                    .unwrap_or_default();
+            let right =
+                b.1.erc20_token_transferred
+                    .get(&chain_cfg.token.address)
+                    .copied()
+                    .unwrap_or_default();
             right.cmp(&left)
         });
     } else if payment_stats_options.order_by == "gas_paid"
@@ -320,10 +375,22 @@ pub async fn run_stats(
             println!("... and more (max {} receivers shown)", el_no);
             break;
         }
-        let ts = match receiver.1.erc20_token_transferred.iter().next() {
-            None => U256::zero(),
-            Some(x) => *x.1,
-        };
+        let ts = tokens
+            .iter()
+            .filter_map(|(token, decimals)| {
+                receiver
+                    .1
+                    .erc20_token_transferred
+                    .get(&token.address)
+                    .map(|amount| {
+                        format!(
+                            "{} {}",
+                            amount.to_token_decimal(*decimals).unwrap(),
+                            token.symbol
+                        )
+                    })
+            })
+            .join(", ");
 
         println!(
             "Receiver: {:#x}\n  count (payment/web3): {}/{}, gas: {}, native token sent: {}, token sent: {}",
@@ -332,7 +399,7 @@ pub async fn run_stats(
             receiver.1.transaction_ids.len(),
             receiver.1.fee_paid.to_eth().unwrap(),
             receiver.1.native_token_transferred.to_eth().unwrap(),
-            ts.to_eth().unwrap(),
+            if ts.is_empty() { "0".to_string() } else { ts },
         );
         println!(
             "  First transfer requested at {}",
@@ -0,0 +1,140 @@
+use actix_web::{web, App, HttpServer, Scope};
+use erc20_payment_client::{ClientError, PaymentClient};
+use erc20_payment_lib::config::{AdditionalOptions, Config};
+use erc20_payment_lib::runtime::{PaymentRuntime, PaymentRuntimeArgs};
+use erc20_payment_lib::server::web::{runtime_web_scope, ServerData};
+use erc20_payment_lib::signer::PrivateKeySigner;
+use erc20_payment_lib_common::api::{AmendTransferRequest, CancelTransferRequest};
+use erc20_payment_lib_common::create_sqlite_connection;
+use erc20_payment_lib_common::model::{TokenTransferDbObj, TokenTransferStatus};
+use erc20_payment_lib_common::ops::insert_token_transfer;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[tokio::test]
+async fn test_client_with_runtime_web_scope() -> Result<(), anyhow::Error> {
+    let conn = create_sqlite_connection(None, None, false, true).await?;
+
+    //payment id with characters that change the route if not encoded
+    let payment_id = "order/1?part=2#3 %";
+    insert_token_transfer(
+        &conn,
+        &TokenTransferDbObj {
+            id: 0,
+            payment_id: Some(payment_id.to_string()),
+            from_addr: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+            receiver_addr: "0x0000000000000000000000000000000000000001".to_string(),
+            chain_id: 17000,
+            token_addr: None,
+            token_amount: "1000".to_string(),
+            deposit_id: None,
+            deposit_finish: 0,
+            create_date: chrono::Utc::now(),
+            tx_id: None,
+            paid_date: None,
+            fee_paid: None,
+            error: None,
+            status: TokenTransferStatus::Queued,
+            priority: 0,
+            deadline: None,
+        },
+    )
+    .await?;
+
+    let payment_runtime = PaymentRuntime::new(
+        PaymentRuntimeArgs {
+            secret_keys: vec![],
+            db_filename: Default::default(),
+            config: Config::default_config(),
+            conn: Some(conn.clone()),
+            options: Some(AdditionalOptions {
+                skip_service_loop: true,
+                ..Default::default()
+            }),
+            broadcast_sender: None,
+            mspc_sender: None,
+            extra_testing: None,
+        },
+        Arc::new(Box::new(PrivateKeySigner::new(vec![]))),
+    )
+    .await?;
+    let server_data = web::Data::new(Box::new(ServerData {
+        shared_state: payment_runtime.shared_state.clone(),
+        db_connection: Arc::new(Mutex::new(conn.clone())),
+        payment_setup: payment_runtime.setup.clone(),
+        payment_runtime,
+        api_nonces: Default::default(),
+    }));
+    let server = HttpServer::new(move || {
+        App::new().service(runtime_web_scope(
+            Scope::new("erc20"),
+            server_data.clone(),
+            false,
+            true,
+            false,
+            false,
+        ))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))?;
+    let port = server.addrs()[0].port();
+    let server = server.run();
+    let handle = server.handle();
+    tokio::spawn(server);
+
+    let client = PaymentClient::new(&format!("http://127.0.0.1:{port}/erc20/api"));
+    assert_eq!(client.version().await?.name, "erc20_payment_lib");
+
+    let cancel_request = CancelTransferRequest {
+        from: "0x001066290077e38f222cc6009c0c7a91d5192303".to_string(),
+        chain: 17000,
+    };
+    match client.cancel_transfer("order/2", &cancel_request).await {
+        Err(ClientError::Api { status, error }) => {
+            assert_eq!(status.as_u16(), 404);
+            assert!(error.error.contains("order/2 not found"), "{}", error.error);
+        }
+        res => panic!("Unexpected result {res:?}"),
+    }
+    //payment id is only unique for given chain and sender
+    let other_chain = CancelTransferRequest {
+        chain: 80002,
+        ..cancel_request.clone()
+    };
+    match client.cancel_transfer(payment_id, &other_chain).await {
+        Err(ClientError::Api { status, .. }) => assert_eq!(status.as_u16(), 404),
+        res => panic!("Unexpected result {res:?}"),
+    }
+
+    let transfer = client
+        .cancel_transfer(payment_id, &cancel_request)
+        .await?
+        .transfer;
+    assert_eq!(transfer.payment_id.as_deref(), Some(payment_id));
+    assert_eq!(transfer.status, TokenTransferStatus::Cancelled);
+    //cancelled transfer cannot be changed anymore
+    match client
+        .amend_transfer(
+            payment_id,
+            &AmendTransferRequest {
+                from: cancel_request.from.clone(),
+                chain: cancel_request.chain,
+                amount: Some("2000".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Err(ClientError::Api { status, error }) => {
+            assert_eq!(status.as_u16(), 409);
+            assert_eq!(
+                error.transfer.map(|t| t.status),
+                Some(TokenTransferStatus::Cancelled)
+            );
+        }
+        res => panic!("Unexpected result {res:?}"),
+    }
+
+    handle.stop(false).await;
+    Ok(())
+}
@@ -74,9 +74,15 @@ async fn test_rpc_pool() -> Result<(), anyhow::Error> {
             skip_validation: None,
             verify_interval_secs: Some(10),
             min_interval_ms: None,
+            max_requests_per_second: None,
+            daily_request_quota: None,
             max_timeout_ms: None,
             allowed_head_behind_secs: Some(-1),
             max_consecutive_errors: None,
+            max_batch_size: None,
+            quorum_size: None,
+            quorum_min_agree: None,
+            quorum_methods: None,
             dns_source: None,
             json_source: None,
         },
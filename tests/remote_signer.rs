@@ -0,0 +1,47 @@
+use erc20_payment_lib::eth::get_eth_addr_from_secret;
+use erc20_payment_lib::signer::{PrivateKeySigner, RemoteSigner, Signer, SignerAccount};
+use erc20_payment_lib_test::LocalSignerServer;
+use secp256k1::SecretKey;
+use std::str::FromStr;
+use std::sync::Arc;
+use web3::types::{Bytes, TransactionParameters, H160, U256, U64};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_remote_signer() -> Result<(), anyhow::Error> {
+    let secret_key =
+        SecretKey::from_str("5f5ab0de8b1b4a4e9e2b8c5b2e8d1a9b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f")?;
+    let address = get_eth_addr_from_secret(&secret_key);
+    let server = LocalSignerServer::start(vec![secret_key]).await?;
+
+    let remote_signer: Arc<Box<dyn Signer + Send + Sync>> =
+        Arc::new(Box::new(RemoteSigner::new(&server.url).unwrap()));
+    let account = SignerAccount::new(address, remote_signer.clone());
+    account.check_if_sign_possible().await?;
+    assert!(SignerAccount::new(H160::from_low_u64_be(1), remote_signer)
+        .check_if_sign_possible()
+        .await
+        .is_err());
+
+    let tp = TransactionParameters {
+        nonce: Some(U256::from(3)),
+        to: Some(H160::from_low_u64_be(0x1234)),
+        gas: U256::from(60000),
+        gas_price: None,
+        value: U256::zero(),
+        data: Bytes(vec![0xa9, 0x05, 0x9c, 0xbb]),
+        chain_id: Some(987789),
+        transaction_type: Some(U64::from(2)),
+        max_fee_per_gas: Some(U256::from(20_000_000_000u64)),
+        max_priority_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+        ..Default::default()
+    };
+    let signed = account.sign(tp.clone()).await?;
+    let expected = PrivateKeySigner::new(vec![secret_key])
+        .sign(address, tp)
+        .await
+        .unwrap();
+    assert_eq!(signed, expected);
+
+    server.stop().await;
+    Ok(())
+}